@@ -17,8 +17,12 @@ mod ui;
 use error::{GrafenCliError, Result};
 use ui::read_configuration;
 
+use grafen::coord::Coord;
 use grafen::database::{read_database, ComponentEntry, DataBase};
 use grafen::read_conf::ReadConf;
+use grafen::recipe::{read_recipe_file, Recipe};
+use grafen::session::read_session_file;
+use grafen::system::{BoxSetting, System};
 
 use colored::*;
 use std::env::{current_dir, home_dir, var, var_os};
@@ -28,6 +32,7 @@ use std::path::PathBuf;
 use structopt::StructOpt;
 
 const DEFAULT_DBNAME: &str = "database.json";
+const DEFAULT_OUTPUT: &str = "conf.gro";
 
 /// The program run configuration.
 pub struct Config {
@@ -39,30 +44,122 @@ pub struct Config {
     pub components: Vec<ComponentEntry>,
     /// Database of residue and substrate definitions.
     pub database: DataBase,
+    /// Setting for the box size of the system.
+    pub box_setting: BoxSetting,
+    /// Seed for reproducible construction of stochastic components.
+    pub seed: Option<u64>,
+    /// Number of component seeds which have been derived in a restored session.
+    pub num_seeds: u64,
+    /// Center the input components in the box.
+    pub center: bool,
+    /// Path to the session which was restored.
+    pub session_path: Option<PathBuf>,
+    /// Recipe to build the system from, with the box setting and centering
+    /// of the command line.
+    pub recipe: Option<Recipe>,
 }
 
 impl Config {
     /// Parse the input command line arguments and read the `DataBase`.
     ///
+    /// If a session is given the system is restored from it. Options which are set
+    /// on the command line take precedence over those of a recipe, which in turn
+    /// take precedence over those of the session.
+    ///
     /// # Errors
-    /// Returns an error if the `DataBase` (if given as an input) could not be read.
+    /// Returns an error if the `DataBase`, session or recipe (if given as an input)
+    /// could not be read.
     fn new() -> Result<Config> {
         eprintln!("{} {}\n", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
 
         let options = CliOptions::from_args();
 
-        let output_path = options.output;
-        let title = options.title.unwrap_or("System created by grafen".into());
+        let session_path = options.session;
+
+        let session = match session_path {
+            Some(ref path) => {
+                eprintln!("Restoring session from '{}'", path.display());
+                Some(read_session_file(&path).map_err(|err| GrafenCliError::RunError(
+                    format!("Could not read session '{}' ({})", path.display(), err)
+                ))?)
+            },
+            None => None,
+        };
 
-        let mut database = match options.database {
-            Some(path) => read_database(&path).map_err(|err| GrafenCliError::from(err)),
-            None => read_or_create_default_database(),
+        let mut recipe = match options.recipe {
+            Some(ref path) => {
+                eprintln!("Reading recipe from '{}'", path.display());
+                Some(read_recipe_file(&path)?)
+            },
+            None => None,
+        };
+
+        let output_path = options.output
+            .or(recipe.as_ref().and_then(|recipe| recipe.output_path.clone()))
+            .or(session.as_ref().map(|system| system.output_path.clone()))
+            .unwrap_or(PathBuf::from(DEFAULT_OUTPUT));
+        let title = options.title
+            .or(recipe.as_ref().and_then(|recipe| recipe.title.clone()))
+            .or(session.as_ref().map(|system| system.title.clone()))
+            .unwrap_or("System created by grafen".into());
+
+        let database_path = options.database
+            .or(recipe.as_ref().and_then(|recipe| recipe.database.clone()));
+
+        let mut database = match (database_path, session.as_ref()) {
+            (Some(path), _) => read_database(&path).map_err(|err| GrafenCliError::from(err)),
+            (None, Some(system)) => Ok(system.database.clone()),
+            (None, None) => read_or_create_default_database(),
         }?;
 
-        let (components, mut entries) = read_input_configurations(options.input_confs);
+        let (mut components, mut entries) = read_input_configurations(options.input_confs);
         database.component_defs.append(&mut entries);
 
-        Ok(Config { title, output_path, components, database })
+        let box_setting = match (options.box_size, options.padding) {
+            (Some(_), Some(_)) => {
+                return Err(GrafenCliError::RunError(
+                    "A box size and a box padding cannot both be set".to_string()
+                ));
+            },
+            (Some(size), None) => BoxSetting::Fixed(size),
+            (None, Some(padding)) => BoxSetting::Auto { padding },
+            (None, None) => {
+                recipe.as_ref()
+                    .and_then(|recipe| recipe.box_setting)
+                    .or(session.as_ref().map(|system| system.box_setting))
+                    .unwrap_or_default()
+            },
+        };
+
+        let seed = options.seed
+            .or(recipe.as_ref().and_then(|recipe| recipe.seed))
+            .or(session.as_ref().and_then(|system| system.seed));
+        let num_seeds = session.as_ref().map(|system| system.num_seeds).unwrap_or(0);
+
+        if let Some(ref mut recipe) = recipe {
+            recipe.box_setting = Some(box_setting);
+            recipe.center = recipe.center || options.center;
+            recipe.seed = seed;
+        }
+
+        if let Some(system) = session {
+            let mut session_components = system.components;
+            session_components.append(&mut components);
+            components = session_components;
+        }
+
+        Ok(Config {
+            title,
+            output_path,
+            components,
+            database,
+            box_setting,
+            seed,
+            num_seeds,
+            center: options.center,
+            session_path,
+            recipe,
+        })
     }
 }
 
@@ -73,24 +170,95 @@ struct CliOptions {
     #[structopt(short = "t", long = "title")]
     /// Title of output system
     title: Option<String>,
-    #[structopt(short = "o", long = "output", default_value = "conf.gro", parse(from_os_str))]
-    /// Output configuration file
-    output: PathBuf,
+    #[structopt(short = "o", long = "output", parse(from_os_str))]
+    /// Output configuration file (.gro or .pdb) [default: conf.gro]
+    output: Option<PathBuf>,
     #[structopt(short = "d", long = "database", parse(from_os_str))]
     /// Path to residue and component database
     database: Option<PathBuf>,
     #[structopt(short = "c", long = "conf", parse(from_os_str))]
     /// Path to input configuration files to add as components
     input_confs: Vec<PathBuf>,
+    #[structopt(long = "box", parse(try_from_str = "parse_coord"))]
+    /// Fixed box size of the system ("x,y,z" in nm)
+    box_size: Option<Coord>,
+    #[structopt(long = "padding", parse(try_from_str = "parse_coord"))]
+    /// Vacuum padding added to the box enclosing all components ("x,y,z" in nm)
+    padding: Option<Coord>,
+    #[structopt(long = "center")]
+    /// Center the input components in the box
+    center: bool,
+    #[structopt(long = "seed")]
+    /// Seed for the random generation of components, which makes systems reproducible
+    seed: Option<u64>,
+    #[structopt(short = "s", long = "session", parse(from_os_str))]
+    /// Path to a saved session to continue
+    session: Option<PathBuf>,
+    #[structopt(short = "r", long = "recipe", parse(from_os_str))]
+    /// Path to a recipe to build the system from, which is then saved without opening the menu
+    recipe: Option<PathBuf>,
+}
+
+/// Parse a coordinate from a string of three comma or whitespace separated values.
+fn parse_coord(input: &str) -> ::std::result::Result<Coord, String> {
+    let values = input
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|s| !s.is_empty())
+        .map(|s| s.parse::<f64>())
+        .collect::<::std::result::Result<Vec<_>, _>>()
+        .map_err(|err| format!("could not parse '{}' as a coordinate ({})", input, err))?;
+
+    if values.len() == 3 {
+        Ok(Coord::new(values[0], values[1], values[2]))
+    } else {
+        Err(format!("a coordinate needs three values, got '{}'", input))
+    }
 }
 
 fn main() {
-    if let Err(err) = Config::new().and_then(|conf| ui::user_menu(conf)) {
+    if let Err(err) = Config::new().and_then(|conf| run(conf)) {
         eprintln!("{}", err);
         process::exit(1);
     }
 }
 
+/// Build and save the system from the recipe if one was given, otherwise open the menu.
+fn run(mut config: Config) -> Result<()> {
+    match config.recipe.take() {
+        Some(recipe) => build_from_recipe(config, &recipe),
+        None => ui::user_menu(config),
+    }
+}
+
+/// Apply the steps of a recipe to the system and write it to disk.
+fn build_from_recipe(config: Config, recipe: &Recipe) -> Result<()> {
+    let mut system = System {
+        title: config.title,
+        output_path: config.output_path,
+        database: config.database,
+        components: config.components,
+        box_setting: config.box_setting,
+        seed: config.seed,
+        num_seeds: config.num_seeds,
+    };
+
+    recipe.apply(&mut system)?;
+    system.print_state();
+
+    let num_outside = system.num_atoms_outside_box();
+
+    if num_outside > 0 {
+        eprintln!("{}", format!(
+            "Warning: {} atoms lie outside of the box", num_outside
+        ).color("yellow"));
+    }
+
+    output::write_system(&system)?;
+    eprintln!("Saved system to '{}'", system.output_path.display());
+
+    Ok(())
+}
+
 fn read_input_configurations(confs: Vec<PathBuf>) -> (Vec<ComponentEntry>, Vec<ComponentEntry>) {
     let mut configurations = Vec::new();
 
@@ -110,6 +278,7 @@ fn read_input_configurations(confs: Vec<PathBuf>) -> (Vec<ComponentEntry>, Vec<C
             conf: None,
             path: current_dir.join(&conf.path),
             backup_conf: None,
+            operations: Vec::new(),
             description: conf.description.clone(),
             volume_type: conf.volume_type.clone(),
         })
@@ -252,6 +421,14 @@ pub mod tests {
         assert_eq!(result, priority_list);
     }
 
+    #[test]
+    fn parse_coord_from_comma_or_whitespace_separated_values() {
+        assert_eq!(parse_coord("1,2.5,-3"), Ok(Coord::new(1.0, 2.5, -3.0)));
+        assert_eq!(parse_coord("1 2.5 -3"), Ok(Coord::new(1.0, 2.5, -3.0)));
+        assert!(parse_coord("1,2").is_err());
+        assert!(parse_coord("1,2,a").is_err());
+    }
+
     #[test]
     #[cfg(any(unix, windows))]
     fn default_database_path_adds_grafen_directory_and_database_path() {
@@ -7,6 +7,18 @@ use grafen::system::{Component, System};
 use std::fs::File;
 use std::io::{BufWriter, Write};
 
+/// Output a system to disk, as a PDB formatted file if the output path has
+/// a .pdb extension and otherwise as a GROMOS formatted file.
+///
+/// # Errors
+/// Returns an error if the file could not be written to.
+pub fn write_system(system: &System) -> Result<()> {
+    match system.output_path.extension().and_then(|ext| ext.to_str()) {
+        Some("pdb") => write_pdb(system),
+        _ => write_gromos(system),
+    }
+}
+
 /// Output a system to disk as a GROMOS formatted file.
 /// The filename extension is adjusted to .gro.
 ///
@@ -47,8 +59,78 @@ pub fn write_gromos(system: &System) -> Result<()> {
         }
     }
 
-    let (dx, dy, dz) = system.box_size().to_tuple();
-    writer.write_fmt(format_args!("{:12.8} {:12.8} {:12.8}\n", dx, dy, dz))?;
+    // Triclinic boxes are written with all nine values in the GROMOS order
+    let vectors = system.box_vectors();
+    let (a, b, c) = (vectors.a, vectors.b, vectors.c);
+
+    if vectors.is_rectangular() {
+        writer.write_fmt(format_args!("{:12.8} {:12.8} {:12.8}\n", a.x, b.y, c.z))?;
+    } else {
+        writer.write_fmt(format_args!(
+            "{:12.8} {:12.8} {:12.8} {:12.8} {:12.8} {:12.8} {:12.8} {:12.8} {:12.8}\n",
+            a.x, b.y, c.z, a.y, a.z, b.x, b.z, c.x, c.y))?;
+    }
+
+    Ok(())
+}
+
+/// Output a system to disk as a PDB formatted file.
+/// The filename extension is adjusted to .pdb.
+///
+/// # Errors
+/// Returns an error if the file could not be written to.
+pub fn write_pdb(system: &System) -> Result<()> {
+    let path = system.output_path.with_extension("pdb");
+    let file = File::create(path)?;
+    let mut writer = BufWriter::new(file);
+
+    // PDB files use Ångström instead of nm
+    let nm_to_angstrom = 10.0;
+
+    let vectors = system.box_vectors();
+    let (a, b, c) = (vectors.lengths() * nm_to_angstrom).to_tuple();
+    let (alpha, beta, gamma) = vectors.angles();
+
+    writer.write_fmt(format_args!("TITLE     {}\n", system.title))?;
+    writer.write_fmt(format_args!(
+        "CRYST1{:>9.3}{:>9.3}{:>9.3}{:>7.2}{:>7.2}{:>7.2} P 1           1\n",
+        a, b, c, alpha, beta, gamma))?;
+
+    let mut res_num_total = 1;
+    let mut atom_num_total = 1;
+
+    for component in &system.components {
+        let origin = component.get_origin();
+
+        for residue in component.iter_residues() {
+            let res_name = residue.get_residue();
+
+            for (atom_name, position) in residue.get_atoms() {
+                // PDB loops the atom indices at five and residue indices at four digits.
+                let res_num = res_num_total % 10_000;
+                let atom_num = atom_num_total % 100_000;
+
+                // Atom names shorter than four characters start in the second column
+                let name = if atom_name.borrow().len() < 4 {
+                    format!(" {}", atom_name.borrow())
+                } else {
+                    atom_name.borrow().to_string()
+                };
+
+                let (x, y, z) = ((origin + position) * nm_to_angstrom).to_tuple();
+
+                write!(&mut writer,
+                    "ATOM  {:>5} {:<4} {:<4} {:>4}    {:>8.3}{:>8.3}{:>8.3}{:>6.2}{:>6.2}\n",
+                    atom_num, name, res_name.borrow(), res_num, x, y, z, 1.0, 0.0)?;
+
+                atom_num_total += 1;
+            }
+
+            res_num_total += 1;
+        }
+    }
+
+    writer.write_fmt(format_args!("END\n"))?;
 
     Ok(())
 }
@@ -0,0 +1,134 @@
+//! Construct a channel of two parallel walls with an optional fluid between them.
+
+use error::{GrafenCliError, Result, UIErrorKind};
+use ui::{read_configuration, resolve_database_path, resolve_height_map_path,
+         select_num_coords_or_density_with_default};
+use ui::history::{Change, History};
+use ui::utils::{MenuResult, get_value_from_user, get_value_or_default_from_user,
+                select_command, select_item};
+
+use grafen::channel::{ChannelFluid, ChannelParameters, construct_channel};
+use grafen::database::ComponentEntry;
+use grafen::read_conf::ReadConf;
+use grafen::surface::{LatticeType, Sheet};
+use grafen::system::{BoxSetting, System};
+
+#[derive(Clone, Copy, Debug)]
+enum FluidSelect {
+    Empty,
+    Residue,
+    Configuration,
+}
+
+/// Prompt the user for a wall definition, the channel size and a fluid, then add
+/// the walls and fluid to the system and set the box to make the walls periodic in-plane.
+pub fn user_menu(system: &mut System, history: &mut History) -> MenuResult {
+    let sheets = system.database.component_defs
+        .iter()
+        .filter_map(|def| match def {
+            &ComponentEntry::SurfaceSheet(ref sheet) => Some(sheet.clone()),
+            _ => None,
+        })
+        .collect::<Vec<Sheet>>();
+
+    if sheets.is_empty() {
+        return Err(GrafenCliError::from(
+            UIErrorKind::from("no sheet definitions exist in the database")
+        ));
+    }
+
+    let mut wall = select_item(&sheets, Some("Select wall definition"))?.clone();
+    resolve_height_map_path(&mut wall, system.database.path.as_ref());
+
+    let length = get_value_from_user::<f64>("Length ΔX (nm)")?;
+    let width = get_value_from_user::<f64>("Width ΔY (nm)")?;
+    let height = get_value_from_user::<f64>("Channel height between the walls (nm)")?;
+
+    if let LatticeType::BlueNoise { ref mut number } = wall.lattice {
+        *number = get_value_from_user::<u64>("Number of residues per wall")?;
+    }
+
+    let wall_distance = get_value_or_default_from_user::<f64>(
+        "Distance between the walls and the fluid (nm)", "0.3")?;
+    let vacuum = get_value_or_default_from_user::<f64>(
+        "Vacuum above the top wall (nm)", "3.0")?;
+
+    let fluid = select_fluid(system)?;
+
+    let params = ChannelParameters {
+        wall,
+        length,
+        width,
+        height,
+        wall_distance,
+        vacuum,
+        fluid,
+        seed: system.component_seed(),
+    };
+
+    let channel = construct_channel(&params)
+        .map_err(|err| GrafenCliError::RunError(format!("Could not construct channel: {}", err)))?;
+
+    let num_before = system.components.len();
+    let box_before = system.box_setting;
+
+    system.components.push(ComponentEntry::from(channel.bottom));
+    system.components.push(ComponentEntry::from(channel.top));
+
+    if let Some(fluid) = channel.fluid {
+        system.components.push(fluid);
+    }
+
+    system.box_setting = BoxSetting::Fixed(channel.box_size);
+
+    history.push("Construct a channel", Change::Multiple(vec![
+        Change::Truncate(num_before),
+        Change::BoxSetting(box_before),
+    ]));
+
+    Ok(Some(format!("Added channel and set the box size to {}", channel.box_size)))
+}
+
+/// Ask the user for the fluid which fills the channel.
+fn select_fluid(system: &System) -> Result<Option<ChannelFluid>> {
+    let (commands, item_texts) = create_menu_items![
+        (FluidSelect::Empty, "Empty channel"),
+        (FluidSelect::Residue, "Fill with a residue"),
+        (FluidSelect::Configuration, "Fill with a configuration from the database")
+    ];
+
+    eprintln!("Fluid between the walls:");
+    match select_command(item_texts, commands)? {
+        FluidSelect::Empty => Ok(None),
+        FluidSelect::Residue => {
+            let residue = select_item(&system.database.residue_defs, Some("Select residue"))?
+                .clone();
+            let fill_type = select_num_coords_or_density_with_default(None, Some(&residue))?;
+
+            Ok(Some(ChannelFluid::Residue { residue, fill_type }))
+        },
+        FluidSelect::Configuration => {
+            let confs = system.database.component_defs
+                .iter()
+                .filter_map(|def| match def {
+                    &ComponentEntry::ConfigurationFile(ref conf) => Some(conf.clone()),
+                    _ => None,
+                })
+                .collect::<Vec<ReadConf>>();
+
+            if confs.is_empty() {
+                return Err(GrafenCliError::from(
+                    UIErrorKind::from("no configuration definitions exist in the database")
+                ));
+            }
+
+            let conf = select_item(&confs, Some("Select configuration"))?.clone();
+            let path = resolve_database_path(conf.path, system.database.path.as_ref());
+
+            let mut new_conf = read_configuration(&path)?;
+            new_conf.description = conf.description;
+
+            Ok(Some(ChannelFluid::Configuration(new_conf)))
+        },
+    }
+}
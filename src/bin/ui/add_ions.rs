@@ -0,0 +1,93 @@
+//! Add ions to a constructed system.
+
+use error::{GrafenCliError, UIErrorKind};
+use ui::history::{Change, History};
+use ui::utils::{MenuResult, YesOrNo, get_value_or_default_from_user, select_command,
+                select_item, select_item_index};
+
+use grafen::database::ComponentEntry;
+use grafen::ions::{IonParameters, IonPlacement, add_ions, collect_atom_positions};
+use grafen::system::{Component, System};
+
+use std::mem;
+
+/// Prompt the user for ion residues, a concentration and a solvent component,
+/// then add ions which neutralize the system.
+pub fn user_menu(system: &mut System, history: &mut History) -> MenuResult {
+    if system.components.is_empty() {
+        return Err(GrafenCliError::from(UIErrorKind::from("no components have been constructed")));
+    }
+
+    let cation = select_item(&system.database.residue_defs, Some("Select cation residue"))?
+        .clone();
+    let anion = select_item(&system.database.residue_defs, Some("Select anion residue"))?
+        .clone();
+
+    eprintln!("Select solvent component:");
+    let index = select_item_index(&system.components, 0)?;
+
+    let concentration = get_value_or_default_from_user::<f64>("Salt concentration (mol/L)", "0")?;
+    let min_distance = get_value_or_default_from_user::<f64>(
+        "Minimum distance to other components (nm)", "0.6")?;
+
+    let (commands, item_texts) = create_menu_items![
+        (IonPlacement::ReplaceSolvent, "Replace solvent residues"),
+        (IonPlacement::InsertIntoFreeSpace, "Insert into free space")
+    ];
+    eprintln!("Ion placement:");
+    let placement = select_command(item_texts, commands)?;
+
+    let params = IonParameters {
+        cation,
+        anion,
+        concentration,
+        min_distance,
+        placement,
+        seed: system.component_seed(),
+    };
+
+    let substrate = system.components
+        .iter()
+        .enumerate()
+        .filter(|&(i, _)| i != index)
+        .flat_map(|(_, component)| collect_atom_positions(component))
+        .collect::<Vec<_>>();
+
+    let net_charge = system.net_charge();
+    let mut solvent = system.components[index].clone();
+
+    let (cations, anions) = add_ions(&mut solvent, &substrate, net_charge, &params)
+        .map_err(|err| GrafenCliError::RunError(format!("Could not add ions: {}", err)))?;
+
+    let (num_cations, num_anions) = (cations.coords.len(), anions.coords.len());
+
+    let num_before = system.components.len();
+    let previous = mem::replace(&mut system.components[index], solvent);
+
+    for ions in vec![cations, anions] {
+        if !ions.coords.is_empty() {
+            system.components.push(ComponentEntry::from(ions));
+        }
+    }
+
+    history.push("Add ions", Change::Multiple(vec![
+        Change::Truncate(num_before),
+        Change::Replace(index, previous),
+    ]));
+
+    eprintln!("Added {} cations and {} anions", num_cations, num_anions);
+
+    let (commands, item_texts) = create_menu_items![
+        (YesOrNo::Yes, "Yes"),
+        (YesOrNo::No, "No (undo)")
+    ];
+    eprintln!("Keep the added ions?");
+
+    match select_command(item_texts, commands)? {
+        YesOrNo::Yes => Ok(None),
+        YesOrNo::No => {
+            history.undo(system);
+            Ok(Some("Removed the added ions".to_string()))
+        },
+    }
+}
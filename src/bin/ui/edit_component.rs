@@ -1,25 +1,48 @@
 //! Edit constructed `ComponentEntry` objects.
 
 use error::{GrafenCliError, Result};
-use ui::utils::{MenuResult, get_value_from_user, get_position_from_user, print_description,
+use ui::get_conf_volume_from_user;
+use ui::history::{Change, History, MAX_STEPS, MAX_STORED_ATOMS};
+use ui::utils::{MenuResult, YesOrNo, get_coord_from_user, get_value_from_user,
+                get_value_or_default_from_user, get_position_from_user, print_description,
                 select_command, select_item, select_item_index};
 
-use grafen::coord::Direction;
+use dialoguer::Checkboxes;
+
+use grafen::clash::remove_clashing_residues;
 use grafen::database::*;
+use grafen::describe::Describe;
 use grafen::system::*;
-use grafen::coord::{Coord, Translate};
-use grafen::volume::{Contains, Cuboid, Cylinder, prune_residues_from_volume};
+use grafen::coord::{BoxVectors, Coord, Periodic, Rotate, Rotation, Translate};
+use grafen::ions::collect_atom_positions;
+use grafen::random::next_seed;
+use grafen::read_conf::{ConfType, ReadConf, WrapReference};
+use grafen::surface::{ChainType, GraftedChains};
+use grafen::volume::{Composite, Cuboid, Cylinder, prune_residues_from_volume};
 
-use std::borrow::Borrow;
+use std::collections::VecDeque;
+use std::mem;
 
 /// Prompt the user to select a defined component and then edit it.
-pub fn user_menu(components: &mut Vec<ComponentEntry>) -> MenuResult {
+///
+/// The changes to the list of components are recorded in the history when finished.
+/// Seeds of new stochastic components are derived from the input system seed
+/// and number of seeds which have been derived from it.
+pub fn user_menu(components: &mut Vec<ComponentEntry>, residue_list: &[Residue],
+                 system_box: BoxVectors, system_seed: Option<u64>, num_seeds: &mut u64,
+                 history: &mut History) -> MenuResult {
     // The component should be a mutable reference to the object in the list,
     // since we want to edit it in-place.
     eprintln!("Select component to edit:");
     let mut index = select_item_index(components, 0)?;
     let mut component = components[index].clone();
 
+    let num_components_before = components.len();
+
+    // Edits which revert the component to a previous state, to undo edits before
+    // they are finished
+    let mut previous_edits: VecDeque<Edit> = VecDeque::new();
+
     create_menu![
         @pre: {
             eprint!("State: ");
@@ -32,23 +55,119 @@ pub fn user_menu(components: &mut Vec<ComponentEntry>) -> MenuResult {
             index = components.len() - 1;
             component = components[index].clone();
 
+            // The edits of the original do not apply to the copy
+            previous_edits.clear();
+
             Ok(None)
         },
 
         Translate, "Translate the component" => {
             let coord = get_position_from_user(None)?;
+            push_edit(&mut previous_edits, Edit::Translate(-coord));
             component.translate_in_place(coord);
 
             Ok(None)
         },
 
+        Rotate, "Rotate the component" => {
+            let (rotation, center) = get_rotation_from_user(&component)?;
+            push_edit(&mut previous_edits, Edit::Restore(component.clone()));
+            component.rotate_in_place(&rotation, center);
+
+            Ok(None)
+        },
+
+        Replicate, "Replicate the component along the box axes" => {
+            match get_replication_from_user(&component)? {
+                Some((nx, ny, nz)) => {
+                    push_edit(&mut previous_edits, Edit::Restore(component.clone()));
+                    component = component.pbc_multiply(nx, ny, nz);
+
+                    Ok(Some(format!("Replicated the component into {}x{}x{} images",
+                                    nx, ny, nz)))
+                },
+                None => Ok(None),
+            }
+        },
+
+        WrapResidues, "Wrap residues of a read configuration into a periodic box" => {
+            match component {
+                ComponentEntry::ConfigurationFile(_) => {
+                    let previous = component.clone();
+
+                    if let ComponentEntry::ConfigurationFile(ref mut conf) = component {
+                        wrap_configuration_residues(conf, system_box)?;
+                    }
+
+                    push_edit(&mut previous_edits, Edit::Restore(previous));
+
+                    Ok(Some("Wrapped residues into the box".to_string()))
+                },
+                _ => Err(GrafenCliError::RunError(
+                    "Only residues of read configurations can be wrapped".to_string()
+                )),
+            }
+        },
+
+        Reconstruct, "Resize or cut a read configuration into another volume" => {
+            match component {
+                ComponentEntry::ConfigurationFile(_) => {
+                    let previous = component.clone();
+
+                    if let ComponentEntry::ConfigurationFile(ref mut conf) = component {
+                        reconstruct_configuration(conf)?;
+                    }
+
+                    push_edit(&mut previous_edits, Edit::Restore(previous));
+
+                    Ok(Some("Reconstructed the configuration".to_string()))
+                },
+                _ => Err(GrafenCliError::RunError(
+                    "Only read configurations can be reconstructed".to_string()
+                )),
+            }
+        },
+
         PruneByVolume, "Remove residues which overlap another component" => {
-            let volume: Box<Contains> = get_volume_from_user(components)?;
+            let volume = get_volume_from_user(components)?;
             let num_before = component.num_atoms();
+            push_edit(&mut previous_edits, Edit::Restore(component.clone()));
+
+            match component {
+                // Read configurations record the pruning to be able to re-derive it
+                ComponentEntry::ConfigurationFile(ref mut conf) => conf.prune_residues(volume),
+                _ => {
+                    let remaining_residues = prune_residues_from_volume(&component, &volume);
+                    component.assign_residues(&remaining_residues);
+                },
+            }
+
+            let num_after = component.num_atoms();
 
-            let remaining_residues = prune_residues_from_volume::<_, Contains>(
-                &component, volume.borrow());
+            Ok(Some(format!("Removed {} atoms from the component", num_before - num_after)))
+        },
+
+        RemoveClashes, "Remove residues within a distance of other components" => {
+            let others = get_clashing_atoms_from_user(components, index)?;
+            let cutoff = get_value_or_default_from_user::<f64>(
+                "Minimum distance to other components (nm)", "0.3")?;
+
+            let (commands, item_texts) = create_menu_items![
+                (YesOrNo::Yes, "Yes"),
+                (YesOrNo::No, "No")
+            ];
+
+            eprintln!("Use periodic images in the system box?");
+            let box_vectors = match select_command(item_texts, commands)? {
+                YesOrNo::Yes => Some(system_box),
+                YesOrNo::No => None,
+            };
 
+            let num_before = component.num_atoms();
+            push_edit(&mut previous_edits, Edit::Restore(component.clone()));
+
+            let remaining_residues = remove_clashing_residues(
+                &component, &others, cutoff, box_vectors);
             component.assign_residues(&remaining_residues);
 
             let num_after = component.num_atoms();
@@ -56,24 +175,368 @@ pub fn user_menu(components: &mut Vec<ComponentEntry>) -> MenuResult {
             Ok(Some(format!("Removed {} atoms from the component", num_before - num_after)))
         },
 
+        Graft, "Graft chains onto the surface as a new component" => {
+            let seed = next_seed(system_seed, num_seeds);
+            let grafts = graft_chains_onto_component(&component, residue_list, seed)?;
+            let num_chains = grafts.num_chains();
+
+            components.push(ComponentEntry::from(grafts));
+
+            Ok(Some(format!("Grafted {} chains onto the component", num_chains)))
+        },
+
+        UndoEdit, "Undo the latest edit of the component" => {
+            match previous_edits.pop_back() {
+                Some(Edit::Translate(shift)) => {
+                    component.translate_in_place(shift);
+                    Ok(Some("Undid the latest edit".to_string()))
+                },
+                Some(Edit::Restore(previous)) => {
+                    component = previous;
+                    Ok(Some("Undid the latest edit".to_string()))
+                },
+                None => Ok(Some("Nothing to undo".to_string())),
+            }
+        },
+
         QuitAndSave, "Finish editing component" => {
-            components[index] = component;
+            let previous = mem::replace(&mut components[index], component);
+
+            // Edits of a new clone are reverted by removing it
+            let mut changes = vec![Change::Truncate(num_components_before)];
+            if index < num_components_before {
+                changes.push(Change::Replace(index, previous));
+            }
+
+            history.push("Edit a component", Change::Multiple(changes));
+
             return Ok(Some("Finished editing component".to_string()));
         },
 
         QuitWithoutSaving, "Abort editing and discard changes" => {
+            // Clones and grafts have already been added
+            if components.len() > num_components_before {
+                history.push("Edit a component", Change::Truncate(num_components_before));
+            }
+
             return Ok(Some("Discarding changes to component".to_string()));
         }
     ];
 }
 
+/// An edit which reverts the edited component to a previous state.
+enum Edit {
+    /// Translate the component by a shift.
+    Translate(Coord),
+    /// Replace the component with a stored copy.
+    Restore(ComponentEntry),
+}
+
+impl Edit {
+    fn num_atoms(&self) -> u64 {
+        match self {
+            &Edit::Translate(_) => 0,
+            &Edit::Restore(ref component) => component.num_atoms(),
+        }
+    }
+}
+
+/// Record an edit, dropping the oldest edits if too many or too large
+/// components are stored.
+fn push_edit(previous_edits: &mut VecDeque<Edit>, edit: Edit) {
+    previous_edits.push_back(edit);
+
+    while previous_edits.len() > MAX_STEPS
+            || (previous_edits.len() > 1 && num_stored_atoms(previous_edits) > MAX_STORED_ATOMS) {
+        previous_edits.pop_front();
+    }
+}
+
+fn num_stored_atoms(previous_edits: &VecDeque<Edit>) -> u64 {
+    previous_edits.iter().map(|edit| edit.num_atoms()).sum()
+}
+
+/// Ask the user for a rotation and the point to rotate the component around.
+fn get_rotation_from_user(component: &ComponentEntry) -> Result<(Rotation, Coord)> {
+    let (commands, item_texts) = create_menu_items![
+        (RotationSelect::AxisAngle, "Rotate by an angle around an axis"),
+        (RotationSelect::EulerAngles, "Rotate by Euler angles around the x, y and z axes")
+    ];
+
+    eprintln!("Rotation type:");
+    let rotation = match select_command(item_texts, commands)? {
+        RotationSelect::AxisAngle => {
+            let axis = get_coord_from_user("Rotation axis (x y z)", Some("0 0 1"))?;
+            let angle = get_value_from_user::<f64>("Angle (degrees)")?;
+
+            if axis == Coord::ORIGO {
+                return Err(GrafenCliError::RunError(
+                    "The rotation axis must be non-zero".to_string()
+                ));
+            }
+
+            Rotation::from_axis_angle(axis, angle.to_radians())
+        },
+        RotationSelect::EulerAngles => {
+            let angles = get_coord_from_user("Angles around x, y and z (degrees)", None)?;
+
+            Rotation::from_euler_angles(
+                angles.x.to_radians(), angles.y.to_radians(), angles.z.to_radians()
+            )
+        },
+    };
+
+    let (commands, item_texts) = create_menu_items![
+        (CenterSelect::Component, "Rotate around the center of the component"),
+        (CenterSelect::Position, "Rotate around a given position")
+    ];
+
+    eprintln!("Rotation center:");
+    let center = match select_command(item_texts, commands)? {
+        CenterSelect::Component => {
+            let origin = component.get_origin();
+            origin + (component.box_size() - origin) * 0.5
+        },
+        CenterSelect::Position => get_position_from_user(None)?,
+    };
+
+    Ok((rotation, center))
+}
+
+/// Ask the user for the number of images along every axis to replicate the component into.
+///
+/// The user is warned about axes along which the component is not periodic, in which case
+/// `None` is returned if they choose to not replicate it.
+fn get_replication_from_user(component: &ComponentEntry)
+        -> Result<Option<(usize, usize, usize)>> {
+    let nx = get_value_or_default_from_user::<usize>("Number of images along x", "1")?;
+    let ny = get_value_or_default_from_user::<usize>("Number of images along y", "1")?;
+    let nz = get_value_or_default_from_user::<usize>("Number of images along z", "1")?;
+
+    if nx == 0 || ny == 0 || nz == 0 {
+        return Err(GrafenCliError::RunError(
+            "The number of images must be positive along every axis".to_string()
+        ));
+    }
+
+    let non_periodic = ["x", "y", "z"]
+        .iter()
+        .zip([nx, ny, nz].iter().zip(component.periodic_axes().iter()))
+        .filter(|&(_, (&n, &is_periodic))| n > 1 && !is_periodic)
+        .map(|(axis, _)| *axis)
+        .collect::<Vec<_>>();
+
+    if !non_periodic.is_empty() {
+        eprintln!("warning: the component is not periodic along {}: its images will not join \
+                   seamlessly", non_periodic.join(", "));
+
+        let (commands, item_texts) = create_menu_items![
+            (YesOrNo::Yes, "Yes"),
+            (YesOrNo::No, "No")
+        ];
+
+        eprintln!("Replicate the component anyway?");
+        if let YesOrNo::No = select_command(item_texts, commands)? {
+            return Ok(None);
+        }
+    }
+
+    Ok(Some((nx, ny, nz)))
+}
+
+#[derive(Clone, Copy, Debug)]
+enum RotationSelect {
+    AxisAngle,
+    EulerAngles,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum CenterSelect {
+    Component,
+    Position,
+}
+
+/// Ask the user for the box and residue reference position, then wrap the residues.
+fn wrap_configuration_residues(conf: &mut ReadConf, system_box: BoxVectors) -> Result<()> {
+    let (commands, item_texts) = create_menu_items![
+        (WrapBoxSelect::Component, "Wrap into the box of the component volume"),
+        (WrapBoxSelect::System, "Wrap into the system box")
+    ];
+
+    eprintln!("Periodic box:");
+    let (box_origin, box_vectors) = match select_command(item_texts, commands)? {
+        WrapBoxSelect::Component => {
+            let origin = conf.get_origin();
+            (origin, BoxVectors::rectangular(conf.box_size() - origin))
+        },
+        WrapBoxSelect::System => (Coord::ORIGO, system_box),
+    };
+
+    let (commands, item_texts) = create_menu_items![
+        (WrapReference::CenterOfGeometry, "Wrap residues by their center of geometry"),
+        (WrapReference::FirstAtom, "Wrap residues by their first atom")
+    ];
+
+    eprintln!("Residue position:");
+    let reference = select_command(item_texts, commands)?;
+
+    conf.wrap_residues(box_origin, box_vectors, reference);
+
+    Ok(())
+}
+
+#[derive(Clone, Copy, Debug)]
+enum WrapBoxSelect {
+    Component,
+    System,
+}
+
+/// Ask the user for a new volume of a read configuration and reconstruct it.
+///
+/// The reconstruction is recorded, which replaces a previous one and re-derives
+/// the configuration from the original. The displayed origin is kept.
+fn reconstruct_configuration(conf: &mut ReadConf) -> Result<()> {
+    let origin = conf.get_displayed_origin();
+    let volume = get_conf_volume_from_user(&conf.volume_type)?;
+
+    conf.reconstruct(volume).map_err(|err| GrafenCliError::RunError(
+        format!("Could not reconstruct the configuration: {}", err)
+    ))?;
+
+    let displayed_origin = conf.get_displayed_origin();
+    conf.translate_in_place(origin - displayed_origin);
+
+    Ok(())
+}
+
+/// Ask the user to select other components and return the positions of all their atoms.
+fn get_clashing_atoms_from_user(components: &[ComponentEntry], index: usize)
+        -> Result<Vec<Coord>> {
+    let others = components
+        .iter()
+        .enumerate()
+        .filter(|&(i, _)| i != index)
+        .map(|(_, component)| component)
+        .collect::<Vec<_>>();
+
+    let item_texts = others
+        .iter()
+        .map(|component| component.describe_short())
+        .collect::<Vec<_>>();
+    let choices = item_texts.iter().map(|text| text.as_str()).collect::<Vec<_>>();
+
+    eprintln!("Select components to remove residues close to \
+               ([space] select, [enter] confirm):");
+    let selections = Checkboxes::new().items(&choices).interact()?;
+
+    if selections.is_empty() {
+        return Err(GrafenCliError::RunError("No components were selected".to_string()));
+    }
+
+    Ok(selections
+        .into_iter()
+        .flat_map(|i| collect_atom_positions(others[i]))
+        .collect())
+}
+
+/// Ask the user for chain parameters, then graft chains onto a surface component.
+fn graft_chains_onto_component(component: &ComponentEntry, residue_list: &[Residue],
+                               seed: Option<u64>) -> Result<GraftedChains> {
+    let (commands, item_texts) = create_menu_items![
+        (ChainSelect::Rigid, "Rigid molecules along the surface normal (monolayer)"),
+        (ChainSelect::RandomWalk, "Flexible chains grown by a self-avoiding random walk")
+    ];
+
+    eprintln!("Chain type:");
+    let chain = match select_command(item_texts, commands)? {
+        ChainSelect::Rigid => {
+            let residue = select_item(residue_list, Some("Select residue"))?
+                .clone();
+
+            if residue.atoms.is_empty() {
+                return Err(GrafenCliError::RunError(
+                    "The residue of rigid chains must have atoms".to_string()
+                ));
+            }
+
+            eprintln!("Anchor atom:");
+            let anchor = select_item_index(&residue.atoms, residue.atoms.len() - 1)?;
+            eprintln!("Head atom:");
+            let head = select_item_index(&residue.atoms, 0)?;
+
+            ChainType::Rigid { residue, anchor, head }
+        },
+        ChainSelect::RandomWalk => {
+            let monomer = select_item(residue_list, Some("Select monomer"))?
+                .clone();
+            let num_monomers = get_value_from_user::<usize>("Number of monomers per chain")?;
+            let bond_length = get_value_from_user::<f64>("Bond length (nm)")?;
+
+            ChainType::RandomWalk { monomer, num_monomers, bond_length }
+        },
+    };
+
+    let grafting_density = get_value_from_user::<f64>("Grafting density (1/nm^2)")?;
+    let min_distance = get_value_from_user::<f64>("Minimum distance to other atoms (nm)")?;
+
+    let grafts = GraftedChains {
+        name: Some("Grafted chains".to_string()),
+        chain,
+        grafting_density,
+        min_distance,
+        seed,
+        origin: Coord::ORIGO,
+        size: Coord::ORIGO,
+        residues: Vec::new(),
+    };
+
+    let result = match *component {
+        ComponentEntry::SurfaceSheet(ref surface) => grafts.construct(surface),
+        ComponentEntry::SurfaceCuboid(ref surface) => grafts.construct(surface),
+        ComponentEntry::SurfaceCylinder(ref surface) => grafts.construct(surface),
+        ComponentEntry::SurfacePore(ref surface) => grafts.construct(surface),
+        ComponentEntry::SurfaceSphere(ref surface) => grafts.construct(surface),
+        _ => {
+            return Err(GrafenCliError::RunError(
+                "Chains can only be grafted onto sheets, surface cuboids, cylinders, pores \
+                 and spheres".to_string()
+            ));
+        },
+    };
+
+    result.map_err(|err| GrafenCliError::RunError(format!("Could not graft chains: {}", err)))
+}
+
+#[derive(Clone, Copy, Debug)]
+enum ChainSelect {
+    Rigid,
+    RandomWalk,
+}
+
 /// Ask the user to select a volume object that has been constructed.
-fn get_volume_from_user(components: &[ComponentEntry]) -> Result<Box<Contains>> {
+fn get_volume_from_user(components: &[ComponentEntry]) -> Result<ConfType> {
     let volume_components = get_volume_objects(components);
     let component = select_item(&volume_components, Some("Select component to cut with"))?
         .clone();
 
-    let margin: f64 = get_value_from_user("Margin around volume to also exclude (nm)")?;
+    let is_rotated = match component {
+        ComponentEntry::VolumeCuboid(ref obj) => obj.frame.is_some(),
+        ComponentEntry::VolumeCylinder(ref obj) => obj.frame.is_some(),
+        ComponentEntry::VolumeComposite(ref obj) => obj.frame.is_some(),
+        _ => false,
+    };
+
+    if is_rotated {
+        return Err(GrafenCliError::RunError(
+            "Rotated volumes cannot be used to cut configurations".to_string()
+        ));
+    }
+
+    // Arbitrary shapes cannot be grown by a margin
+    let margin: f64 = match component {
+        ComponentEntry::VolumeComposite(_) => 0.0,
+        _ => get_value_from_user("Margin around volume to also exclude (nm)")?,
+    };
 
     match component {
         ComponentEntry::VolumeCuboid(mut obj) => {
@@ -81,20 +544,18 @@ fn get_volume_from_user(components: &[ComponentEntry]) -> Result<Box<Contains>>
             obj.origin -= coord_margins;
             obj.size += coord_margins * 2.0;
 
-            Ok(Box::new(obj))
+            Ok(ConfType::from(&obj))
         },
         ComponentEntry::VolumeCylinder(mut obj) => {
             obj.radius += margin;
             obj.height += 2.0 * margin;
 
-            match obj.alignment {
-                Direction::X => obj.translate_in_place(Coord::new(-margin, 0.0, 0.0)),
-                Direction::Y => obj.translate_in_place(Coord::new(0.0, -margin, 0.0)),
-                Direction::Z => obj.translate_in_place(Coord::new(0.0, 0.0, -margin)),
-            }
+            let shift = obj.alignment.to_unit_vector() * -margin;
+            obj.translate_in_place(shift);
 
-            Ok(Box::new(obj))
+            Ok(ConfType::from(&obj))
         },
+        ComponentEntry::VolumeComposite(obj) => Ok(ConfType::from(&obj)),
         _ => Err(GrafenCliError::RunError(String::from(
             "Tried to get a volume type that has not been implemented: this should be impossible")
         )),
@@ -115,7 +576,10 @@ fn get_volume_objects(components: &[ComponentEntry]) -> Vec<ComponentEntry> {
                               origin: obj.origin,
                               size: obj.size,
                               density: obj.density,
+                              seed: None,
                               coords: vec![],
+                              cell: None,
+                              frame: obj.frame,
                           };
 
                           Some(ComponentEntry::from(volume))
@@ -129,8 +593,27 @@ fn get_volume_objects(components: &[ComponentEntry]) -> Vec<ComponentEntry> {
                               radius: obj.radius,
                               height: obj.height,
                               density: obj.density,
+                              seed: None,
                               alignment: obj.alignment,
                               coords: vec![],
+                              cell: None,
+                              frame: obj.frame,
+                          };
+
+                          Some(ComponentEntry::from(volume))
+                      },
+
+                      &ComponentEntry::VolumeComposite(ref obj) => {
+                          let volume = Composite {
+                              name: obj.name.clone(),
+                              residue: obj.residue.clone(),
+                              shape: obj.shape.clone(),
+                              origin: obj.origin,
+                              density: obj.density,
+                              seed: None,
+                              coords: vec![],
+                              cell: None,
+                              frame: obj.frame,
                           };
 
                           Some(ComponentEntry::from(volume))
@@ -144,8 +627,11 @@ fn get_volume_objects(components: &[ComponentEntry]) -> Vec<ComponentEntry> {
                               radius: obj.radius,
                               height: obj.height,
                               density: None,
+                              seed: None,
                               alignment: obj.alignment,
                               coords: vec![],
+                              cell: None,
+                              frame: obj.frame,
                           };
 
                           Some(ComponentEntry::from(volume))
@@ -4,20 +4,22 @@
 
 use error::{GrafenCliError, UIResult, UIErrorKind};
 use ui::utils::{MenuResult, get_value_from_user, print_description, print_list_description_short,
-                remove_items, reorder_list, select_command, select_direction, select_item};
+                remove_items, reorder_list, select_command, select_direction, select_item,
+                select_item_index, select_shape};
 
 use grafen::coord::{Coord, Direction};
 use grafen::database::ComponentEntry;
 use grafen::database::ComponentEntry::*;
 use grafen::describe::Describe;
 use grafen::surface;
-use grafen::surface::{CylinderCap, LatticeType, Sides};
+use grafen::surface::{CapShape, CylinderCap, LatticeType, Lipid, LipidPacking, Roughness, Sides};
 use grafen::system::Residue;
 use grafen::volume;
 
 use dialoguer::Checkboxes;
 use std::error::Error;
 use std::fmt::Write;
+use std::path::PathBuf;
 use std::result;
 
 pub fn user_menu(mut component_list: &mut Vec<ComponentEntry>, residue_list: &[Residue])
@@ -67,6 +69,10 @@ enum ComponentSelect {
     Sheet,
     Cylinder,
     Cuboid,
+    Pore,
+    Sphere,
+    Bilayer,
+    Shape,
     Abort,
 }
 use self::ComponentSelect::*;
@@ -80,6 +86,10 @@ fn new_component(residue_list: &[Residue]) -> UIResult<ComponentEntry> {
             Sheet => create_sheet(&residue_list),
             Cylinder => create_cylinder(&residue_list),
             Cuboid => create_cuboid(&residue_list),
+            Pore => create_pore(&residue_list),
+            Sphere => create_sphere(&residue_list),
+            Bilayer => create_bilayer(&residue_list),
+            Shape => create_shape(&residue_list),
             Abort => return Err(UIErrorKind::Abort),
         };
 
@@ -104,6 +114,10 @@ fn select_component_type() -> UIResult<ComponentSelect> {
         (Sheet, "Sheet"),
         (Cylinder, "Cylinder"),
         (Cuboid, "Cuboid box"),
+        (Pore, "Sheet with a pore"),
+        (Sphere, "Sphere"),
+        (Bilayer, "Lipid bilayer"),
+        (Shape, "Volume shape (sphere, ellipsoid, cone, torus, ...)"),
         (Abort, "(Abort)")
     ];
 
@@ -143,6 +157,8 @@ struct SheetBuilder {
     residue: Residue,
     normal: Direction,
     std_z: Option<f64>,
+    roughness: Option<Roughness>,
+    orient_residues: bool,
 }
 
 impl SheetBuilder {
@@ -160,6 +176,8 @@ impl SheetBuilder {
             residue,
             normal,
             std_z: None,
+            roughness: None,
+            orient_residues: false,
         })
     }
 
@@ -172,11 +190,18 @@ impl SheetBuilder {
                 residue: Some(self.residue.clone()),
                 lattice: self.lattice.clone(),
                 std_z: self.std_z,
+                roughness: self.roughness.clone(),
+                orient_residues: self.orient_residues,
+                seed: None,
                 origin: Coord::default(),
                 normal: self.normal,
                 length: 0.0,
                 width: 0.0,
                 coords: vec![],
+                orientations: vec![],
+                normals: vec![],
+                cell: None,
+                frame: None,
             }))
         }
     }
@@ -193,6 +218,14 @@ impl Describe for SheetBuilder {
         writeln!(description, "Residue: {}", &self.residue.code).expect(ERR);
         writeln!(description, "Z-variance: {}", &self.std_z.unwrap_or(0.0)).expect(ERR);
 
+        match self.roughness {
+            Some(ref roughness) => writeln!(description, "Roughness: {}", roughness),
+            None => writeln!(description, "Roughness: None"),
+        }.expect(ERR);
+
+        writeln!(description, "Orient residues along the surface normal: {}",
+                 self.orient_residues).expect(ERR);
+
         description
     }
 
@@ -207,6 +240,8 @@ enum SheetMenu {
     SetNormal,
     SetResidue,
     SetVarianceZ,
+    SetRoughness,
+    ToggleOrientation,
     QuitAndSave,
     QuitWithoutSaving,
 }
@@ -221,6 +256,8 @@ fn create_sheet(residue_list: &[Residue]) -> result::Result<ComponentEntry, Chan
         (SetLattice, "Set lattice"),
         (SetNormal, "Set normal vector direction"),
         (SetVarianceZ, "Set variance of residue positions along z"),
+        (SetRoughness, "Set surface roughness"),
+        (ToggleOrientation, "Toggle orienting residues along the surface normal"),
         (QuitAndSave, "Finalize component definition and return"),
         (QuitWithoutSaving, "Abort")
     ];
@@ -266,6 +303,13 @@ fn create_sheet(residue_list: &[Residue]) -> result::Result<ComponentEntry, Chan
                 },
                 Err(_) => eprintln!("error: Could not read new variance"),
             },
+            SetRoughness => match select_roughness() {
+                Ok(new_roughness) => {
+                    builder.roughness = new_roughness;
+                },
+                Err(_) => eprintln!("error: Could not set new roughness"),
+            },
+            ToggleOrientation => builder.orient_residues = !builder.orient_residues,
             QuitAndSave => match builder.finalize() {
                 Ok(component) => return Ok(component),
                 Err(msg) => eprintln!("{}", msg),
@@ -285,6 +329,53 @@ fn get_variance() -> UIResult<Option<f64>> {
     }
 }
 
+#[derive(Clone, Copy, Debug)]
+enum RoughnessSelect {
+    Smooth,
+    SelfAffine,
+    HeightMap,
+}
+
+fn select_roughness() -> UIResult<Option<Roughness>> {
+    let (choices, item_texts) = create_menu_items![
+        (RoughnessSelect::Smooth, "Smooth: No roughness"),
+        (RoughnessSelect::SelfAffine, "Self-affine: A random fractal height profile"),
+        (RoughnessSelect::HeightMap, "Height map: A grayscale PGM image")
+    ];
+
+    eprintln!("Surface roughness:");
+    match select_command(item_texts, choices)? {
+        RoughnessSelect::Smooth => Ok(None),
+        RoughnessSelect::SelfAffine => {
+            let rms = get_value_from_user::<f64>("Root mean square height (nm)")?;
+            let correlation_length = get_value_from_user::<f64>("Correlation length (nm)")?;
+            let hurst = get_value_from_user::<f64>("Hurst exponent (0 < H <= 1)")?;
+
+            if rms < 0.0 || correlation_length <= 0.0 || hurst <= 0.0 || hurst > 1.0 {
+                return Err(UIErrorKind::BadValue(
+                    "the rms must be non-negative, the correlation length positive \
+                     and the Hurst exponent in (0, 1]".to_string()
+                ));
+            }
+
+            Ok(Some(Roughness::SelfAffine { rms, correlation_length, hurst }))
+        },
+        RoughnessSelect::HeightMap => {
+            let path = get_value_from_user::<String>(
+                "Path to image (relative to the database)")?;
+            let max_height = get_value_from_user::<f64>("Height of white pixels (nm)")?;
+
+            if max_height < 0.0 {
+                return Err(UIErrorKind::BadValue(
+                    "the height cannot be negative".to_string()
+                ));
+            }
+
+            Ok(Some(Roughness::HeightMap { path: PathBuf::from(path), max_height }))
+        },
+    }
+}
+
 /*************************
  * Cylinder construction *
  *************************/
@@ -296,6 +387,9 @@ struct CylinderBuilder {
     residue: Residue,
     density: Option<f64>,
     cap: Option<CylinderCap>,
+    bottom_cap_shape: CapShape,
+    top_cap_shape: CapShape,
+    orient_residues: bool,
     alignment: Direction,
 }
 
@@ -317,6 +411,9 @@ impl CylinderBuilder {
             residue,
             density: None,
             cap: None,
+            bottom_cap_shape: CapShape::Flat,
+            top_cap_shape: CapShape::Flat,
+            orient_residues: false,
             alignment: Direction::Z,
         })
     }
@@ -333,10 +430,18 @@ impl CylinderBuilder {
                         lattice: self.lattice.unwrap(),
                         alignment: self.alignment,
                         cap: self.cap,
+                        bottom_cap_shape: self.bottom_cap_shape,
+                        top_cap_shape: self.top_cap_shape,
+                        orient_residues: self.orient_residues,
+                        seed: None,
                         origin: Coord::default(),
                         radius: 0.0,
                         height: 0.0,
                         coords: vec![],
+                        orientations: vec![],
+                        cell: None,
+                        frame: None,
+                        images: None,
                     }))
                 },
 
@@ -349,7 +454,10 @@ impl CylinderBuilder {
                         radius: 0.0,
                         height: 0.0,
                         density: self.density,
+                        seed: None,
                         coords: vec![],
+                        cell: None,
+                        frame: None,
                     }))
                 },
             }
@@ -376,11 +484,17 @@ impl Describe for CylinderBuilder {
                 writeln!(description, "Lattice: {}", lattice_string).expect(ERR);
                 writeln!(description, "Residue: {}", self.residue.code).expect(ERR);
 
-                let cap_string = self.cap
-                    .map(|cap| format!("{}", cap))
-                    .unwrap_or("None".to_string());
+                let cap_string = match self.cap {
+                    Some(CylinderCap::Bottom) => format!("Bottom ({})", self.bottom_cap_shape),
+                    Some(CylinderCap::Top) => format!("Top ({})", self.top_cap_shape),
+                    Some(CylinderCap::Both) => format!("Both (bottom: {}, top: {})",
+                        self.bottom_cap_shape, self.top_cap_shape),
+                    None => "None".to_string(),
+                };
 
                 writeln!(description, "Cap: {}", cap_string).expect(ERR);
+                writeln!(description, "Orient residues along the surface normal: {}",
+                         self.orient_residues).expect(ERR);
             },
             Volume => {
                 writeln!(description, "Type: Cylinder Volume").expect(ERR);
@@ -408,6 +522,7 @@ enum CylinderSurfaceMenu {
     SetName,
     SetResidue,
     SetCap,
+    ToggleOrientation,
     SetAlignment,
     QuitAndSave,
     QuitWithoutSaving,
@@ -443,6 +558,7 @@ fn create_cylinder(residue_list: &[Residue]) -> result::Result<ComponentEntry, C
                     (SetName, "Set name"),
                     (SetResidue, "Set residue"),
                     (SetCap, "Cap either cylinder edge"),
+                    (ToggleOrientation, "Toggle orienting residues along the surface normal"),
                     (SetAlignment, "Set cylinder normal axis"),
                     (QuitAndSave, "Finalize component definition and return"),
                     (QuitWithoutSaving, "Abort")
@@ -481,11 +597,14 @@ fn create_cylinder(residue_list: &[Residue]) -> result::Result<ComponentEntry, C
                         Err(_) => eprintln!("error: Could not select new residue"),
                     },
                     SetCap => match select_cap() {
-                        Ok(new_cap) => {
+                        Ok((new_cap, bottom_shape, top_shape)) => {
                             builder.cap = new_cap;
+                            builder.bottom_cap_shape = bottom_shape;
+                            builder.top_cap_shape = top_shape;
                         },
                         Err(_) => eprintln!("error: Could not select new cap"),
                     },
+                    ToggleOrientation => builder.orient_residues = !builder.orient_residues,
                     SetAlignment => match select_direction(Some("Cylinder normal axis"), None) {
                         Ok(new_direction) => {
                             builder.alignment = new_direction;
@@ -581,7 +700,9 @@ fn select_cylinder_type() -> UIResult<ComponentType> {
     select_command(item_texts, classes)
 }
 
-fn select_cap() -> UIResult<Option<CylinderCap>> {
+/// Select which cylinder sides to cap and the shape of each cap. The shape of a side
+/// which is not capped is set to flat.
+fn select_cap() -> UIResult<(Option<CylinderCap>, CapShape, CapShape)> {
     let choices = &[
         "Bottom",
         "Top"
@@ -589,13 +710,29 @@ fn select_cap() -> UIResult<Option<CylinderCap>> {
 
     eprintln!("Set caps on cylinder sides ([space] select, [enter] confirm):");
     let selections = Checkboxes::new().items(choices).interact()?;
+    let (bottom, top) = (selections.contains(&0), selections.contains(&1));
 
-    match (selections.contains(&0), selections.contains(&1)) {
-        (true, true) => Ok(Some(CylinderCap::Both)),
-        (true, false) => Ok(Some(CylinderCap::Bottom)),
-        (false, true) => Ok(Some(CylinderCap::Top)),
-        _ => Ok(None),
-    }
+    let cap = match (bottom, top) {
+        (true, true) => Some(CylinderCap::Both),
+        (true, false) => Some(CylinderCap::Bottom),
+        (false, true) => Some(CylinderCap::Top),
+        _ => None,
+    };
+
+    let bottom_shape = if bottom { select_cap_shape("Bottom cap shape")? } else { CapShape::Flat };
+    let top_shape = if top { select_cap_shape("Top cap shape")? } else { CapShape::Flat };
+
+    Ok((cap, bottom_shape, top_shape))
+}
+
+fn select_cap_shape(description: &str) -> UIResult<CapShape> {
+    let (choices, item_texts) = create_menu_items![
+        (CapShape::Flat, "Flat: A circle cut from the cylinder lattice"),
+        (CapShape::Hemisphere, "Hemisphere: A closed, curved end like that of a nanotube")
+    ];
+
+    eprintln!("{}:", description);
+    select_command(item_texts, choices)
 }
 
 /***********************
@@ -657,10 +794,14 @@ impl CuboidBuilder {
                         residue: Some(self.residue.clone()),
                         lattice: self.lattice.unwrap().clone(),
                         std_z: None,
+                        seed: None,
                         origin: Coord::ORIGO,
                         size: Coord::ORIGO,
                         sides: self.sides.unwrap_or(Sides::all()),
                         coords: Vec::new(),
+                        cell: None,
+                        frame: None,
+                        images: None,
                     }))
                 }
             }
@@ -901,6 +1042,613 @@ fn select_sides() -> UIResult<Sides> {
     Ok(sides)
 }
 
+/*********************
+ * Pore construction *
+ *********************/
+
+struct PoreBuilder {
+    name: String,
+    lattice: LatticeType,
+    residue: Residue,
+    slit: bool,
+    orient_residues: bool,
+}
+
+impl PoreBuilder {
+    fn initialize(residue_list: &[Residue]) -> UIResult<PoreBuilder> {
+        let lattice = select_lattice()?;
+
+        eprintln!("Residue:");
+        let residue = select_residue(&residue_list)?;
+
+        Ok(PoreBuilder {
+            name: String::new(),
+            lattice,
+            residue,
+            slit: false,
+            orient_residues: false,
+        })
+    }
+
+    fn finalize(&self) -> result::Result<ComponentEntry, &str> {
+        if self.name.is_empty() {
+            Err("Cannot add component: No name is set")
+        } else {
+            Ok(SurfacePore(surface::Pore {
+                name: Some(self.name.clone()),
+                residue: Some(self.residue.clone()),
+                lattice: self.lattice.clone(),
+                slit: self.slit,
+                orient_residues: self.orient_residues,
+                seed: None,
+                origin: Coord::default(),
+                length: 0.0,
+                width: 0.0,
+                height: 0.0,
+                bottom_radius: 0.0,
+                top_radius: 0.0,
+                coords: vec![],
+                orientations: vec![],
+                cell: None,
+                frame: None,
+                images: None,
+            }))
+        }
+    }
+}
+
+impl Describe for PoreBuilder {
+    fn describe(&self) -> String {
+        let mut description = String::new();
+        const ERR: &'static str = "could not construct a string";
+
+        writeln!(description, "Name: {}", &self.name).expect(ERR);
+        writeln!(description, "Type: Pore").expect(ERR);
+        writeln!(description, "Lattice: {:?}", &self.lattice).expect(ERR);
+        writeln!(description, "Residue: {}", &self.residue.code).expect(ERR);
+        writeln!(description, "Slit (sheets at both ends): {}", self.slit).expect(ERR);
+        writeln!(description, "Orient residues along the surface normal: {}",
+                 self.orient_residues).expect(ERR);
+
+        description
+    }
+
+    fn describe_short(&self) -> String { self.describe() }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum PoreMenu {
+    ChangeComponent,
+    SetName,
+    SetResidue,
+    SetLattice,
+    ToggleSlit,
+    ToggleOrientation,
+    QuitAndSave,
+    QuitWithoutSaving,
+}
+
+fn create_pore(residue_list: &[Residue]) -> result::Result<ComponentEntry, ChangeOrError> {
+    use self::PoreMenu::*;
+
+    let (commands, item_texts) = create_menu_items![
+        (ChangeComponent, "Change component type"),
+        (SetName, "Set name"),
+        (SetResidue, "Set residue"),
+        (SetLattice, "Set lattice"),
+        (ToggleSlit, "Toggle a second sheet at the top of the pore (slit)"),
+        (ToggleOrientation, "Toggle orienting residues along the surface normal"),
+        (QuitAndSave, "Finalize component definition and return"),
+        (QuitWithoutSaving, "Abort")
+    ];
+
+    let mut builder = PoreBuilder::initialize(&residue_list)?;
+
+    loop {
+        print_description(&builder);
+
+        let command = select_command(item_texts, commands).map_err(|err| UIErrorKind::from(err))?;
+
+        match command {
+            ChangeComponent => return Err(ChangeOrError::ChangeComponent),
+            SetName => match get_value_from_user::<String>("Component name") {
+                Ok(new_name) => {
+                    builder.name = new_name;
+                },
+                Err(_) => {
+                    eprintln!("error: Could not read name");
+                },
+            },
+            SetResidue => match select_residue(&residue_list) {
+                Ok(new_residue) => {
+                    builder.residue = new_residue;
+                },
+                Err(_) => eprintln!("error: Could not select new residue"),
+            },
+            SetLattice => match select_lattice() {
+                Ok(new_lattice) => {
+                    builder.lattice = new_lattice;
+                },
+                Err(_) => eprintln!("error: Could not select new lattice"),
+            },
+            ToggleSlit => builder.slit = !builder.slit,
+            ToggleOrientation => builder.orient_residues = !builder.orient_residues,
+            QuitAndSave => match builder.finalize() {
+                Ok(component) => return Ok(component),
+                Err(msg) => eprintln!("{}", msg),
+            },
+            QuitWithoutSaving => return Err(ChangeOrError::Error(UIErrorKind::Abort)),
+        }
+
+        eprintln!("");
+    }
+}
+
+/***********************
+ * Sphere construction *
+ ***********************/
+
+struct SphereBuilder {
+    name: String,
+    residue: Residue,
+    density: f64,
+    orient_residues: bool,
+}
+
+impl SphereBuilder {
+    fn initialize(residue_list: &[Residue]) -> UIResult<SphereBuilder> {
+        eprintln!("Residue:");
+        let residue = select_residue(&residue_list)?;
+        let density = get_value_from_user::<f64>("Density (1/nm^2)")?;
+
+        Ok(SphereBuilder {
+            name: String::new(),
+            residue,
+            density,
+            orient_residues: false,
+        })
+    }
+
+    fn finalize(&self) -> result::Result<ComponentEntry, &str> {
+        if self.name.is_empty() {
+            Err("Cannot add component: No name is set")
+        } else {
+            Ok(SurfaceSphere(surface::Sphere {
+                name: Some(self.name.clone()),
+                residue: Some(self.residue.clone()),
+                density: self.density,
+                orient_residues: self.orient_residues,
+                origin: Coord::default(),
+                radius: 0.0,
+                coords: vec![],
+                orientations: vec![],
+                cell: None,
+                frame: None,
+                images: None,
+            }))
+        }
+    }
+}
+
+impl Describe for SphereBuilder {
+    fn describe(&self) -> String {
+        let mut description = String::new();
+        const ERR: &'static str = "could not construct a string";
+
+        writeln!(description, "Name: {}", &self.name).expect(ERR);
+        writeln!(description, "Type: Sphere").expect(ERR);
+        writeln!(description, "Residue: {}", &self.residue.code).expect(ERR);
+        writeln!(description, "Density: {} (1/nm^2)", self.density).expect(ERR);
+        writeln!(description, "Orient residues along the surface normal: {}",
+                 self.orient_residues).expect(ERR);
+
+        description
+    }
+
+    fn describe_short(&self) -> String { self.describe() }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum SphereMenu {
+    ChangeComponent,
+    SetName,
+    SetResidue,
+    SetDensity,
+    ToggleOrientation,
+    QuitAndSave,
+    QuitWithoutSaving,
+}
+
+fn create_sphere(residue_list: &[Residue]) -> result::Result<ComponentEntry, ChangeOrError> {
+    use self::SphereMenu::*;
+
+    let (commands, item_texts) = create_menu_items![
+        (ChangeComponent, "Change component type"),
+        (SetName, "Set name"),
+        (SetResidue, "Set residue"),
+        (SetDensity, "Set density"),
+        (ToggleOrientation, "Toggle orienting residues along the surface normal"),
+        (QuitAndSave, "Finalize component definition and return"),
+        (QuitWithoutSaving, "Abort")
+    ];
+
+    let mut builder = SphereBuilder::initialize(&residue_list)?;
+
+    loop {
+        print_description(&builder);
+
+        let command = select_command(item_texts, commands).map_err(|err| UIErrorKind::from(err))?;
+
+        match command {
+            ChangeComponent => return Err(ChangeOrError::ChangeComponent),
+            SetName => match get_value_from_user::<String>("Component name") {
+                Ok(new_name) => {
+                    builder.name = new_name;
+                },
+                Err(_) => {
+                    eprintln!("error: Could not read name");
+                },
+            },
+            SetResidue => match select_residue(&residue_list) {
+                Ok(new_residue) => {
+                    builder.residue = new_residue;
+                },
+                Err(_) => eprintln!("error: Could not select new residue"),
+            },
+            SetDensity => match get_value_from_user::<f64>("Density (1/nm^2)") {
+                Ok(new_density) => {
+                    builder.density = new_density;
+                },
+                Err(_) => eprintln!("error: Could not read density"),
+            },
+            ToggleOrientation => builder.orient_residues = !builder.orient_residues,
+            QuitAndSave => match builder.finalize() {
+                Ok(component) => return Ok(component),
+                Err(msg) => eprintln!("{}", msg),
+            },
+            QuitWithoutSaving => return Err(ChangeOrError::Error(UIErrorKind::Abort)),
+        }
+
+        eprintln!("");
+    }
+}
+
+/************************
+ * Bilayer construction *
+ ************************/
+
+struct BilayerBuilder {
+    name: String,
+    lipids: Vec<Lipid>,
+    area_per_lipid: f64,
+    packing: LipidPacking,
+    random_rotation: bool,
+}
+
+impl BilayerBuilder {
+    fn initialize(residue_list: &[Residue]) -> UIResult<BilayerBuilder> {
+        let lipid = create_lipid(&residue_list)?;
+        let area_per_lipid = get_value_from_user::<f64>("Area per lipid (nm^2)")?;
+        let packing = select_lipid_packing()?;
+
+        Ok(BilayerBuilder {
+            name: String::new(),
+            lipids: vec![lipid],
+            area_per_lipid,
+            packing,
+            random_rotation: true,
+        })
+    }
+
+    fn finalize(&self) -> result::Result<ComponentEntry, &str> {
+        if self.name.is_empty() {
+            Err("Cannot add component: No name is set")
+        } else if self.lipids.is_empty() {
+            Err("Cannot add component: No lipids are set")
+        } else if self.area_per_lipid <= 0.0 {
+            Err("Cannot add component: The area per lipid must be positive")
+        } else {
+            Ok(SurfaceBilayer(surface::Bilayer {
+                name: Some(self.name.clone()),
+                lipids: self.lipids.clone(),
+                area_per_lipid: self.area_per_lipid,
+                packing: self.packing,
+                random_rotation: self.random_rotation,
+                seed: None,
+                origin: Coord::ORIGO,
+                length: 0.0,
+                width: 0.0,
+                height: 0.0,
+                residues: Vec::new(),
+            }))
+        }
+    }
+}
+
+impl Describe for BilayerBuilder {
+    fn describe(&self) -> String {
+        let mut description = String::new();
+        const ERR: &'static str = "could not construct a string";
+
+        writeln!(description, "Name: {}", &self.name).expect(ERR);
+        writeln!(description, "Type: Lipid Bilayer").expect(ERR);
+        writeln!(description, "Area per lipid: {}", self.area_per_lipid).expect(ERR);
+        writeln!(description, "Packing: {:?}", self.packing).expect(ERR);
+        writeln!(description, "Random rotation: {}", self.random_rotation).expect(ERR);
+        writeln!(description, "Lipids (upper, lower leaflet amount):").expect(ERR);
+
+        for lipid in &self.lipids {
+            writeln!(description, "  {} ({}, {})",
+                lipid.residue.code, lipid.upper, lipid.lower).expect(ERR);
+        }
+
+        description
+    }
+
+    fn describe_short(&self) -> String { self.describe() }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum BilayerMenu {
+    ChangeComponent,
+    SetName,
+    AddLipid,
+    RemoveLipids,
+    SetAreaPerLipid,
+    SetPacking,
+    ToggleRotation,
+    QuitAndSave,
+    QuitWithoutSaving,
+}
+
+fn create_bilayer(residue_list: &[Residue]) -> result::Result<ComponentEntry, ChangeOrError> {
+    use self::BilayerMenu::*;
+
+    let (commands, item_texts) = create_menu_items![
+        (ChangeComponent, "Change component type"),
+        (SetName, "Set name"),
+        (AddLipid, "Add a lipid type"),
+        (RemoveLipids, "Remove lipid types"),
+        (SetAreaPerLipid, "Set area per lipid"),
+        (SetPacking, "Set lateral packing of lipids"),
+        (ToggleRotation, "Toggle random rotation of lipids around the normal"),
+        (QuitAndSave, "Finalize component definition and return"),
+        (QuitWithoutSaving, "Abort")
+    ];
+
+    let mut builder = BilayerBuilder::initialize(&residue_list)?;
+
+    loop {
+        print_description(&builder);
+
+        let command = select_command(item_texts, commands).map_err(|err| UIErrorKind::from(err))?;
+
+        match command {
+            ChangeComponent => return Err(ChangeOrError::ChangeComponent),
+            SetName => match get_value_from_user::<String>("Component name") {
+                Ok(new_name) => {
+                    builder.name = new_name;
+                },
+                Err(_) => {
+                    eprintln!("error: Could not read name");
+                },
+            },
+            AddLipid => match create_lipid(&residue_list) {
+                Ok(lipid) => builder.lipids.push(lipid),
+                Err(_) => eprintln!("error: Could not add lipid"),
+            },
+            RemoveLipids => {
+                let mut residues = builder.lipids
+                    .iter()
+                    .map(|lipid| lipid.residue.clone())
+                    .collect::<Vec<_>>();
+
+                while let Ok(index) = select_item_index(&residues, 0) {
+                    residues.remove(index);
+                    builder.lipids.remove(index);
+                }
+            },
+            SetAreaPerLipid => match get_value_from_user::<f64>("Area per lipid (nm^2)") {
+                Ok(area) => builder.area_per_lipid = area,
+                Err(_) => eprintln!("error: Could not read area per lipid"),
+            },
+            SetPacking => match select_lipid_packing() {
+                Ok(packing) => builder.packing = packing,
+                Err(_) => eprintln!("error: Could not select packing"),
+            },
+            ToggleRotation => builder.random_rotation = !builder.random_rotation,
+            QuitAndSave => match builder.finalize() {
+                Ok(component) => return Ok(component),
+                Err(msg) => eprintln!("{}", msg),
+            },
+            QuitWithoutSaving => return Err(ChangeOrError::Error(UIErrorKind::Abort)),
+        }
+
+        eprintln!("");
+    }
+}
+
+fn create_lipid(residue_list: &[Residue]) -> UIResult<Lipid> {
+    eprintln!("Lipid residue:");
+    let residue = select_residue(&residue_list)?;
+
+    if residue.atoms.is_empty() {
+        return Err(UIErrorKind::from("the lipid residue has no atoms"));
+    }
+
+    eprintln!("Head atom:");
+    let head = select_item_index(&residue.atoms, 0)?;
+    eprintln!("Tail atom:");
+    let tail = select_item_index(&residue.atoms, residue.atoms.len() - 1)?;
+
+    let upper = get_value_from_user::<f64>("Relative amount in upper leaflet")?;
+    let lower = get_value_from_user::<f64>("Relative amount in lower leaflet")?;
+
+    Ok(Lipid { residue, head, tail, upper, lower })
+}
+
+fn select_lipid_packing() -> UIResult<LipidPacking> {
+    let (choices, item_texts) = create_menu_items![
+        (LipidPacking::PoissonDisc, "Poisson disc: Randomly generated positions"),
+        (LipidPacking::Hexagonal, "Hexagonal: A close packed lattice")
+    ];
+
+    eprintln!("Lipid packing:");
+    select_command(item_texts, choices)
+}
+
+/*****************************
+ * Volume shape construction *
+ *****************************/
+
+struct ShapeBuilder {
+    name: String,
+    residue: Residue,
+    density: Option<f64>,
+    shape: volume::Shape,
+}
+
+impl ShapeBuilder {
+    fn initialize(residue_list: &[Residue]) -> UIResult<ShapeBuilder> {
+        let residue = select_residue(&residue_list)?;
+        let shape = select_shape()?;
+
+        Ok(ShapeBuilder {
+            name: String::new(),
+            residue,
+            density: None,
+            shape,
+        })
+    }
+
+    fn finalize(&self) -> result::Result<ComponentEntry, &str> {
+        if self.name.is_empty() {
+            Err("Cannot add component: No name is set")
+        } else if self.shape.bounds().is_none() {
+            Err("Cannot add component: The shape is unbounded, intersect it with a bounded shape")
+        } else {
+            Ok(VolumeComposite(volume::Composite {
+                name: Some(self.name.clone()),
+                residue: Some(self.residue.clone()),
+                shape: self.shape.clone(),
+                origin: Coord::ORIGO,
+                density: self.density,
+                seed: None,
+                coords: Vec::new(),
+                cell: None,
+                frame: None,
+            }))
+        }
+    }
+}
+
+impl Describe for ShapeBuilder {
+    fn describe(&self) -> String {
+        let mut description = String::new();
+        const ERR: &'static str = "could not construct a string";
+
+        writeln!(description, "Name: {}", &self.name).expect(ERR);
+        writeln!(description, "Type: Volume Shape").expect(ERR);
+        writeln!(description, "Shape: {:?}", self.shape).expect(ERR);
+        writeln!(description, "Residue: {}", self.residue.code).expect(ERR);
+
+        let density_string = self.density
+            .map(|dens| format!("{}", dens))
+            .unwrap_or("None".into());
+        writeln!(description, "Density: {}", density_string).expect(ERR);
+
+        description
+    }
+
+    fn describe_short(&self) -> String { self.describe() }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum ShapeMenu {
+    ChangeComponent,
+    SetName,
+    SetResidue,
+    SetShape,
+    AddShape,
+    IntersectShape,
+    SubtractShape,
+    SetDensity,
+    QuitAndSave,
+    QuitWithoutSaving,
+}
+
+fn create_shape(residue_list: &[Residue]) -> result::Result<ComponentEntry, ChangeOrError> {
+    use self::ShapeMenu::*;
+
+    let (commands, item_texts) = create_menu_items![
+        (ChangeComponent, "Change component type"),
+        (SetName, "Set name"),
+        (SetResidue, "Set residue"),
+        (SetShape, "Set shape"),
+        (AddShape, "Add another shape to the volume (union)"),
+        (IntersectShape, "Cut the volume by another shape (intersection)"),
+        (SubtractShape, "Remove another shape from the volume (difference)"),
+        (SetDensity, "Set default density"),
+        (QuitAndSave, "Finalize component definition and return"),
+        (QuitWithoutSaving, "Abort")
+    ];
+
+    let mut builder = ShapeBuilder::initialize(&residue_list)?;
+
+    loop {
+        print_description(&builder);
+
+        let command = select_command(item_texts, commands).map_err(|err| UIErrorKind::from(err))?;
+
+        match command {
+            ChangeComponent => return Err(ChangeOrError::ChangeComponent),
+            SetName => match get_value_from_user::<String>("Component name") {
+                Ok(new_name) => {
+                    builder.name = new_name;
+                },
+                Err(_) => {
+                    eprintln!("error: Could not read name");
+                },
+            },
+            SetResidue => match select_residue(&residue_list) {
+                Ok(new_residue) => {
+                    builder.residue = new_residue;
+                },
+                Err(_) => eprintln!("error: Could not select new residue"),
+            },
+            SetShape => match select_shape() {
+                Ok(shape) => builder.shape = shape,
+                Err(_) => eprintln!("error: Could not set shape"),
+            },
+            AddShape => match select_shape() {
+                Ok(shape) => builder.shape = builder.shape.clone().union(shape),
+                Err(_) => eprintln!("error: Could not set shape"),
+            },
+            IntersectShape => match select_shape() {
+                Ok(shape) => builder.shape = builder.shape.clone().intersection(shape),
+                Err(_) => eprintln!("error: Could not set shape"),
+            },
+            SubtractShape => match select_shape() {
+                Ok(shape) => builder.shape = builder.shape.clone().difference(shape),
+                Err(_) => eprintln!("error: Could not set shape"),
+            },
+            SetDensity => match get_density() {
+                Ok(density) => {
+                    builder.density = density;
+                },
+                Err(_) => eprintln!("error: Could not set density"),
+            },
+            QuitAndSave => match builder.finalize() {
+                Ok(component) => return Ok(component),
+                Err(msg) => eprintln!("{}", msg),
+            },
+            QuitWithoutSaving => return Err(ChangeOrError::Error(UIErrorKind::Abort)),
+        }
+
+        eprintln!("");
+    }
+}
+
 /************************************
  * Selection of lattice and residue *
  ************************************/
@@ -4,6 +4,7 @@ mod component;
 mod residue;
 
 use error::GrafenCliError;
+use ui::history::MAX_STEPS;
 use ui::utils::{MenuResult, print_description, select_command};
 
 use grafen::database::{write_database, DataBase};
@@ -14,13 +15,18 @@ pub fn user_menu(database: &mut DataBase) -> MenuResult {
     let residues_backup = database.residue_defs.clone();
     let components_backup = database.component_defs.clone();
 
+    // Previous states of the database, to undo edits before they are finished
+    let mut previous_states: Vec<DataBase> = Vec::new();
+
     create_menu![
         @pre: { print_description(database); };
 
         EditResidues, "Edit list of residues" => {
+            push_state(&mut previous_states, database.clone());
             residue::user_menu(&mut database.residue_defs).map(|msg| msg.into())
         },
         EditComponents, "Edit list of components" => {
+            push_state(&mut previous_states, database.clone());
             component::user_menu(&mut database.component_defs, &database.residue_defs)
                 .map(|msg| msg.into())
         },
@@ -31,6 +37,17 @@ pub fn user_menu(database: &mut DataBase) -> MenuResult {
                     format!("Could not write database: {}", err.description())
                 ))
         },
+        UndoEdit, "Undo the latest edit of residues or components" => {
+            match previous_states.pop() {
+                Some(previous) => {
+                    database.residue_defs = previous.residue_defs;
+                    database.component_defs = previous.component_defs;
+
+                    Ok(Some("Undid the latest edit".to_string()))
+                },
+                None => Ok(Some("Nothing to undo".to_string())),
+            }
+        },
         QuitAndSave, "Finish editing database" => {
             return Ok("Finished editing database".to_string().into());
         },
@@ -43,3 +60,12 @@ pub fn user_menu(database: &mut DataBase) -> MenuResult {
         }
     ];
 }
+
+/// Add a previous state of the database to the bounded list of states.
+fn push_state(previous_states: &mut Vec<DataBase>, state: DataBase) {
+    previous_states.push(state);
+
+    if previous_states.len() > MAX_STEPS {
+        previous_states.remove(0);
+    }
+}
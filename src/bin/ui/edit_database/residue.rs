@@ -2,9 +2,10 @@
 
 use error::{GrafenCliError, Result, UIErrorKind, UIResult};
 use ui::utils::{MenuResult, remove_items, reorder_list, select_command, get_position_from_user,
-                get_value_from_user, print_list_description};
+                get_optional_value_from_user, get_value_from_user, print_list_description};
 
 use grafen::describe::describe_list;
+use grafen::element::get_element;
 use grafen::system::{Atom, Residue};
 
 use std::error::Error;
@@ -141,9 +142,22 @@ fn create_atom() -> UIResult<Atom> {
     let name = get_value_from_user::<String>("Atom name")?;
     let position = get_position_from_user(None)?;
 
+    let element = get_optional_value_from_user::<String>("Element (optional)")?;
+    if let Some(ref symbol) = element {
+        if get_element(symbol).is_none() {
+            return Err(UIErrorKind::from("unknown element"));
+        }
+    }
+
+    let mass = get_optional_value_from_user::<f64>("Mass (g/mol, optional)")?;
+    let charge = get_optional_value_from_user::<f64>("Partial charge (e, optional)")?;
+
     Ok(Atom {
         code: name.to_uppercase().to_string(),
         position: position,
+        element,
+        mass,
+        charge,
     })
 }
 
@@ -164,7 +178,13 @@ mod tests {
         builder.name = "is_set".to_string();
         assert!(builder.finalize().is_err());
 
-        builder.atoms.push(Atom { code: "A".to_string(), position: Coord::ORIGO });
+        builder.atoms.push(Atom {
+            code: "A".to_string(),
+            position: Coord::ORIGO,
+            element: None,
+            mass: None,
+            charge: None,
+        });
         assert!(builder.finalize().is_ok());
     }
 }
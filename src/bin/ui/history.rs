@@ -0,0 +1,282 @@
+//! Undo and redo changes made to a `System` in the interactive builder.
+//!
+//! Changes are stored as the operations which revert them rather than as copies
+//! of the full system. Only components which are replaced or removed by a change
+//! are kept in memory, and the total number of atoms in the stored components is
+//! bounded by dropping the oldest changes.
+
+use grafen::coord::{Coord, Translate};
+use grafen::database::{ComponentEntry, DataBase};
+use grafen::system::{BoxSetting, Component, System};
+
+use std::collections::VecDeque;
+use std::mem;
+
+/// Maximum number of changes which can be undone.
+pub const MAX_STEPS: usize = 50;
+
+/// Maximum total number of atoms in components which are stored in the history.
+pub const MAX_STORED_ATOMS: u64 = 10_000_000;
+
+/// A change to a system. Applying it yields the change which reverts it.
+pub enum Change {
+    /// Replace the component at an index.
+    Replace(usize, ComponentEntry),
+    /// Remove all components after the first number of components.
+    Truncate(usize),
+    /// Append components to the end of the list.
+    Extend(Vec<ComponentEntry>),
+    /// Insert components at indices, in order.
+    Insert(Vec<(usize, ComponentEntry)>),
+    /// Remove components at indices, in order.
+    Remove(Vec<usize>),
+    /// Swap pairs of components, in order.
+    Swap(Vec<(usize, usize)>),
+    /// Translate all components.
+    TranslateAll(Coord),
+    /// Replace the box setting.
+    BoxSetting(BoxSetting),
+    /// Replace the database.
+    Database(DataBase),
+    /// Several changes which are applied in order.
+    Multiple(Vec<Change>),
+}
+
+impl Change {
+    /// Apply the change to a system and return the change which reverts it.
+    fn apply(self, system: &mut System) -> Change {
+        match self {
+            Change::Replace(i, component) => {
+                Change::Replace(i, mem::replace(&mut system.components[i], component))
+            },
+            Change::Truncate(num) => {
+                let num = num.min(system.components.len());
+                Change::Extend(system.components.split_off(num))
+            },
+            Change::Extend(mut components) => {
+                let num = system.components.len();
+                system.components.append(&mut components);
+
+                Change::Truncate(num)
+            },
+            Change::Insert(components) => {
+                let indices = components
+                    .into_iter()
+                    .map(|(i, component)| {
+                        system.components.insert(i, component);
+                        i
+                    })
+                    .collect::<Vec<_>>();
+
+                Change::Remove(indices.into_iter().rev().collect())
+            },
+            Change::Remove(indices) => {
+                let components = indices
+                    .into_iter()
+                    .map(|i| (i, system.components.remove(i)))
+                    .collect::<Vec<_>>();
+
+                Change::Insert(components.into_iter().rev().collect())
+            },
+            Change::Swap(pairs) => {
+                pairs.iter().for_each(|&(i, j)| system.components.swap(i, j));
+                Change::Swap(pairs.into_iter().rev().collect())
+            },
+            Change::TranslateAll(shift) => {
+                system.components
+                    .iter_mut()
+                    .for_each(|component| component.translate_in_place(shift));
+
+                Change::TranslateAll(-shift)
+            },
+            Change::BoxSetting(box_setting) => {
+                Change::BoxSetting(mem::replace(&mut system.box_setting, box_setting))
+            },
+            Change::Database(database) => {
+                Change::Database(mem::replace(&mut system.database, database))
+            },
+            Change::Multiple(changes) => {
+                let inverted = changes
+                    .into_iter()
+                    .map(|change| change.apply(system))
+                    .collect::<Vec<_>>();
+
+                Change::Multiple(inverted.into_iter().rev().collect())
+            },
+        }
+    }
+
+    /// Return the number of atoms in components which are stored by the change.
+    fn num_atoms(&self) -> u64 {
+        match self {
+            &Change::Replace(_, ref component) => component.num_stored_atoms(),
+            &Change::Extend(ref components) => {
+                components.iter().map(|component| component.num_stored_atoms()).sum()
+            },
+            &Change::Insert(ref components) => {
+                components.iter().map(|&(_, ref component)| component.num_stored_atoms()).sum()
+            },
+            &Change::Multiple(ref changes) => {
+                changes.iter().map(|change| change.num_atoms()).sum()
+            },
+            _ => 0,
+        }
+    }
+}
+
+/// Stacks of changes which can be undone or redone, with a description of each.
+pub struct History {
+    undo: VecDeque<(String, Change)>,
+    redo: Vec<(String, Change)>,
+}
+
+impl History {
+    pub fn new() -> History {
+        History {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+        }
+    }
+
+    /// Record the change which reverts the latest action made to the system.
+    ///
+    /// This clears all changes which could be redone.
+    pub fn push(&mut self, description: &str, change: Change) {
+        self.undo.push_back((description.to_string(), change));
+        self.redo.clear();
+
+        while self.undo.len() > MAX_STEPS
+                || (self.undo.len() > 1 && self.num_stored_atoms() > MAX_STORED_ATOMS) {
+            self.undo.pop_front();
+        }
+    }
+
+    /// Revert the latest change and return its description, or `None` if there
+    /// is nothing to undo.
+    pub fn undo(&mut self, system: &mut System) -> Option<String> {
+        self.undo.pop_back().map(|(description, change)| {
+            let inverse = change.apply(system);
+            self.redo.push((description.clone(), inverse));
+
+            description
+        })
+    }
+
+    /// Apply the latest reverted change again and return its description, or `None`
+    /// if there is nothing to redo.
+    pub fn redo(&mut self, system: &mut System) -> Option<String> {
+        self.redo.pop().map(|(description, change)| {
+            let inverse = change.apply(system);
+            self.undo.push_back((description.clone(), inverse));
+
+            description
+        })
+    }
+
+    fn num_stored_atoms(&self) -> u64 {
+        self.undo.iter().chain(self.redo.iter()).map(|&(_, ref change)| change.num_atoms()).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use grafen::volume::Cuboid;
+    use std::path::PathBuf;
+
+    fn setup_system(num_components: usize) -> System {
+        let components = (0..num_components)
+            .map(|i| ComponentEntry::from(Cuboid {
+                origin: Coord::new(i as f64, 0.0, 0.0),
+                .. Cuboid::default()
+            }))
+            .collect();
+
+        System {
+            title: String::new(),
+            output_path: PathBuf::new(),
+            database: DataBase::new(),
+            components,
+            box_setting: BoxSetting::default(),
+            seed: None,
+            num_seeds: 0,
+        }
+    }
+
+    fn get_origins(system: &System) -> Vec<f64> {
+        system.components.iter().map(|component| component.get_origin().x).collect()
+    }
+
+    #[test]
+    fn undo_and_redo_removal_and_reordering_of_components() {
+        let mut system = setup_system(4);
+        let mut history = History::new();
+
+        // Remove the components at index 1, then (the new) index 1
+        let removed = vec![
+            (1, system.components.remove(1)),
+            (1, system.components.remove(1)),
+        ];
+        history.push("Remove", Change::Insert(removed.into_iter().rev().collect()));
+        assert_eq!(get_origins(&system), vec![0.0, 3.0]);
+
+        system.components.swap(0, 1);
+        history.push("Reorder", Change::Swap(vec![(0, 1)]));
+        assert_eq!(get_origins(&system), vec![3.0, 0.0]);
+
+        assert_eq!(history.undo(&mut system), Some("Reorder".to_string()));
+        assert_eq!(get_origins(&system), vec![0.0, 3.0]);
+
+        assert_eq!(history.undo(&mut system), Some("Remove".to_string()));
+        assert_eq!(get_origins(&system), vec![0.0, 1.0, 2.0, 3.0]);
+        assert_eq!(history.undo(&mut system), None);
+
+        assert_eq!(history.redo(&mut system), Some("Remove".to_string()));
+        assert_eq!(get_origins(&system), vec![0.0, 3.0]);
+
+        assert_eq!(history.redo(&mut system), Some("Reorder".to_string()));
+        assert_eq!(get_origins(&system), vec![3.0, 0.0]);
+        assert_eq!(history.redo(&mut system), None);
+    }
+
+    #[test]
+    fn undo_a_combined_replacement_and_addition_of_components() {
+        let mut system = setup_system(2);
+        let mut history = History::new();
+
+        let replacement = system.components[0].clone().translate(Coord::new(5.0, 0.0, 0.0));
+        let previous = mem::replace(&mut system.components[0], replacement);
+        system.components.push(system.components[1].clone());
+
+        history.push("Edit", Change::Multiple(vec![
+            Change::Replace(0, previous),
+            Change::Truncate(2),
+        ]));
+        assert_eq!(get_origins(&system), vec![5.0, 1.0, 1.0]);
+
+        history.undo(&mut system);
+        assert_eq!(get_origins(&system), vec![0.0, 1.0]);
+
+        history.redo(&mut system);
+        assert_eq!(get_origins(&system), vec![5.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn new_changes_clear_the_redo_stack_and_the_history_is_bounded() {
+        let mut system = setup_system(1);
+        let mut history = History::new();
+
+        for _ in 0..(MAX_STEPS + 10) {
+            system.box_setting = BoxSetting::Fixed(Coord::new(1.0, 1.0, 1.0));
+            history.push("Set box", Change::BoxSetting(BoxSetting::default()));
+        }
+
+        assert_eq!(history.undo.len(), MAX_STEPS);
+
+        history.undo(&mut system);
+        assert_eq!(history.redo.len(), 1);
+
+        history.push("Set box", Change::BoxSetting(BoxSetting::default()));
+        assert!(history.redo.is_empty());
+    }
+}
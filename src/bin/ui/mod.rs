@@ -3,24 +3,30 @@
 //! systems.
 
 #[macro_use] mod utils;
+mod add_channel;
+mod add_ions;
 mod edit_component;
 mod edit_database;
+mod history;
 
 use super::Config;
 use error::{GrafenCliError, Result, UIErrorKind, UIResult};
 use output;
+use ui::history::{Change, History};
 use ui::utils::{MenuResult, YesOrNo,
     get_value_from_user, get_value_or_default_from_user, get_coord_from_user,
     get_position_from_user, remove_items, reorder_list, select_command,
-    select_direction, select_item};
+    select_composite_shape, select_direction, select_item};
 
 use grafen::coord::{Coord, Translate};
 use grafen::database::*;
 use grafen::read_conf::{ConfType, ReadConf};
-use grafen::surface::LatticeType;
+use grafen::session::write_session_file;
+use grafen::surface::{LatticeType, Roughness, Sheet};
 use grafen::system::*;
 use grafen::volume::{FillType, Volume};
 
+use serde_json;
 use std::path::Path;
 
 /// Loop over a menu in which the user can define the system which will be created, etc.
@@ -38,28 +44,129 @@ pub fn user_menu(config: Config) -> Result<()> {
         output_path: config.output_path,
         database: config.database,
         components: config.components,
+        box_setting: config.box_setting,
+        seed: config.seed,
+        num_seeds: config.num_seeds,
     };
 
+    if config.center {
+        system.center_components();
+    }
+
+    let mut history = History::new();
+    let mut session_path = config.session_path.unwrap_or(PathBuf::from("session.json"));
+
     create_menu![
         @pre: { system.print_state() };
 
         AddComponent, "Construct a component" => {
-            create_component(&mut system)
+            let num_before = system.components.len();
+            let result = create_component(&mut system);
+
+            if system.components.len() > num_before {
+                history.push("Construct a component", Change::Truncate(num_before));
+            }
+
+            result
         },
         EditComponent, "Edit or clone a component" => {
-            edit_component::user_menu(&mut system.components)
+            let box_vectors = system.box_vectors();
+            edit_component::user_menu(&mut system.components, &system.database.residue_defs,
+                                      box_vectors, system.seed, &mut system.num_seeds,
+                                      &mut history)
+        },
+        AddChannel, "Construct a channel of two parallel walls" => {
+            add_channel::user_menu(&mut system, &mut history)
+        },
+        AddIons, "Add ions to neutralize the system" => {
+            add_ions::user_menu(&mut system, &mut history)
         },
         RemoveItems, "Remove a component from the list" => {
-            remove_items(&mut system.components).map(|_| None)
+            remove_items(&mut system.components).map(|removed| {
+                if !removed.is_empty() {
+                    let change = Change::Insert(removed.into_iter().rev().collect());
+                    history.push("Remove components", change);
+                }
+
+                None
+            })
         },
         ReorderList, "Reorder list of components" => {
-            reorder_list(&mut system.components).map(|_| None)
+            reorder_list(&mut system.components).map(|swaps| {
+                if !swaps.is_empty() {
+                    let change = Change::Swap(swaps.into_iter().rev().collect());
+                    history.push("Reorder components", change);
+                }
+
+                None
+            })
+        },
+        SetBox, "Set the box size or vacuum padding of the system" => {
+            let box_before = system.box_setting;
+            let result = set_box_setting(&mut system);
+
+            if system.box_setting != box_before {
+                history.push("Set the box", Change::BoxSetting(box_before));
+            }
+
+            result
+        },
+        CenterComponents, "Center all components in the box" => {
+            let box_before = system.box_setting;
+            let shift = system.center_components();
+
+            history.push("Center the components", Change::Multiple(vec![
+                Change::TranslateAll(-shift),
+                Change::BoxSetting(box_before),
+            ]));
+
+            Ok(Some("Centered the components in the box".to_string()))
         },
         EditDatabase, "Edit the database of residue and object definitions" => {
-            edit_database::user_menu(&mut system.database)
+            let database_before = system.database.clone();
+            let result = edit_database::user_menu(&mut system.database);
+
+            if !is_same_database(&system.database, &database_before) {
+                history.push("Edit the database", Change::Database(database_before));
+            }
+
+            result
+        },
+        Undo, "Undo the latest change to the system" => {
+            match history.undo(&mut system) {
+                Some(description) => Ok(Some(format!("Undid '{}'", description))),
+                None => Ok(Some("Nothing to undo".to_string())),
+            }
+        },
+        Redo, "Redo the latest undone change to the system" => {
+            match history.redo(&mut system) {
+                Some(description) => Ok(Some(format!("Redid '{}'", description))),
+                None => Ok(Some("Nothing to redo".to_string())),
+            }
         },
         SaveSystem, "Save the constructed components to disk as a system" => {
-            output::write_gromos(&system).map(|_| "Saved system to disk".to_string().into())
+            let num_outside = system.num_atoms_outside_box();
+
+            output::write_system(&system).map(|_| {
+                let msg = if num_outside > 0 {
+                    format!("Saved system to disk ({} atoms lie outside of the box)", num_outside)
+                } else {
+                    "Saved system to disk".to_string()
+                };
+
+                Some(msg)
+            })
+        },
+        SaveSession, "Save the full session to continue it later" => {
+            let path = get_value_or_default_from_user::<String>(
+                "Session path", &format!("{}", session_path.display()))?;
+            session_path = PathBuf::from(path);
+
+            write_session_file(&system, &session_path)
+                .map(|_| Some(format!("Saved session to '{}'", session_path.display())))
+                .map_err(|err| GrafenCliError::RunError(
+                    format!("Could not save session: {}", err)
+                ))
         },
         Quit, "Quit the program" => {
             return Ok(());
@@ -67,12 +174,51 @@ pub fn user_menu(config: Config) -> Result<()> {
     ];
 }
 
+/// Ask the user for a fixed box size or the padding of an automatic box.
+fn set_box_setting(system: &mut System) -> MenuResult {
+    let (commands, item_texts) = create_menu_items![
+        (BoxSelect::Auto, "Enclose all components, with optional vacuum padding"),
+        (BoxSelect::Fixed, "Use a fixed box size")
+    ];
+
+    eprintln!("Box setting:");
+    system.box_setting = match select_command(item_texts, commands)? {
+        BoxSelect::Auto => {
+            let padding = get_coord_from_user("Padding (x y z nm)", Some("0 0 0"))?;
+            BoxSetting::Auto { padding }
+        },
+        BoxSelect::Fixed => {
+            let (x, y, z) = system.box_size().to_tuple();
+            let size = get_coord_from_user(
+                "Box size (x y z nm)", Some(&format!("{} {} {}", x, y, z)))?;
+
+            if size.x <= 0.0 || size.y <= 0.0 || size.z <= 0.0 {
+                return Err(GrafenCliError::RunError(
+                    "The box size must be positive along all axes".to_string()
+                ));
+            }
+
+            BoxSetting::Fixed(size)
+        },
+    };
+
+    Ok(Some("Updated the box setting".to_string()))
+}
+
+#[derive(Clone, Copy, Debug)]
+enum BoxSelect {
+    Auto,
+    Fixed,
+}
+
 /// Prompt the user to select a defined component from the `DataBase`, then create it.
 fn create_component(system: &mut System) -> MenuResult {
     let component = select_item(&system.database.component_defs, Some("Available components"))?
         .clone();
 
-    match fill_component(component, system.database.path.as_ref()) {
+    let seed = system.component_seed();
+
+    match fill_component(component, system.database.path.as_ref(), seed) {
         Ok(filled) => {
             system.components.push(filled);
             Ok(Some("Added component to system".to_string()))
@@ -84,16 +230,21 @@ fn create_component(system: &mut System) -> MenuResult {
 use std::path::PathBuf;
 
 /// Ask the user for information about the selected component, then return the constructed object.
-fn fill_component(component: ComponentEntry, database_path: Option<&PathBuf>)
-        -> Result<ComponentEntry> {
+///
+/// Stochastic components without a seed of their own are constructed with the input seed.
+fn fill_component(component: ComponentEntry, database_path: Option<&PathBuf>,
+                  seed: Option<u64>) -> Result<ComponentEntry> {
     match component {
         ComponentEntry::VolumeCuboid(mut conf) => {
+            conf.seed = conf.seed.or(seed);
             let position = get_position_from_user(Some("0 0 0"))?;
             let length = get_value_from_user::<f64>("Length ΔX (nm)")?;
             let width = get_value_from_user::<f64>("Width ΔY (nm)")?;
             let height = get_value_from_user::<f64>("Height ΔZ (nm)")?;
 
-            let fill_type = select_num_coords_or_density_with_default(conf.density)?;
+            let fill_type = select_num_coords_or_density_with_default(
+                conf.density, conf.residue.as_ref())?;
+            fill_type.check_residue(conf.residue.as_ref())?;
 
             conf.origin = position;
             conf.size = Coord::new(length, width, height);
@@ -102,16 +253,32 @@ fn fill_component(component: ComponentEntry, database_path: Option<&PathBuf>)
         },
 
         ComponentEntry::VolumeCylinder(mut conf) => {
+            conf.seed = conf.seed.or(seed);
             conf.origin = get_position_from_user(Some("0 0 0"))?;
             conf.radius = get_value_from_user::<f64>("Radius (nm)")?;
             conf.height = get_value_from_user::<f64>("Height (nm)")?;
 
-            let fill_type = select_num_coords_or_density_with_default(conf.density)?;
+            let fill_type = select_num_coords_or_density_with_default(
+                conf.density, conf.residue.as_ref())?;
+            fill_type.check_residue(conf.residue.as_ref())?;
+
+            Ok(ComponentEntry::from(conf.fill(fill_type)))
+        },
+
+        ComponentEntry::VolumeComposite(mut conf) => {
+            conf.seed = conf.seed.or(seed);
+            conf.origin = get_position_from_user(Some("0 0 0"))?;
+
+            let fill_type = select_num_coords_or_density_with_default(
+                conf.density, conf.residue.as_ref())?;
+            fill_type.check_residue(conf.residue.as_ref())?;
 
             Ok(ComponentEntry::from(conf.fill(fill_type)))
         },
 
         ComponentEntry::SurfaceSheet(mut conf) => {
+            conf.seed = conf.seed.or(seed);
+            resolve_height_map_path(&mut conf, database_path);
             conf.origin = get_position_from_user(Some("0 0 0"))?;
             conf.length = get_value_from_user::<f64>("Length ΔX (nm)")?;
             conf.width = get_value_from_user::<f64>("Width ΔY (nm)")?;
@@ -125,14 +292,15 @@ fn fill_component(component: ComponentEntry, database_path: Option<&PathBuf>)
 
             Ok(
                 ComponentEntry::from(
-                    conf.construct().map_err(|_| {
-                        UIErrorKind::from("Could not construct sheet")
+                    conf.construct().map_err(|err| {
+                        UIErrorKind::from(&format!("Could not construct sheet: {}", err))
                     })?
                 ).with_pbc()
             )
         },
 
         ComponentEntry::SurfaceCuboid(mut conf) => {
+            conf.seed = conf.seed.or(seed);
             conf.origin = get_position_from_user(Some("0 0 0"))?;
 
             let length = get_value_from_user::<f64>("Length ΔX (nm)")?;
@@ -147,6 +315,7 @@ fn fill_component(component: ComponentEntry, database_path: Option<&PathBuf>)
         },
 
         ComponentEntry::SurfaceCylinder(mut conf) => {
+            conf.seed = conf.seed.or(seed);
             conf.origin = get_position_from_user(Some("0 0 0"))?;
             conf.radius = get_value_from_user::<f64>("Radius (nm)")?;
             conf.height = get_value_from_user::<f64>("Height (nm)")?;
@@ -156,48 +325,56 @@ fn fill_component(component: ComponentEntry, database_path: Option<&PathBuf>)
             )?))
         },
 
-        ComponentEntry::ConfigurationFile(conf) => {
-            let default_volume = conf.volume_type.clone();
+        ComponentEntry::SurfacePore(mut conf) => {
+            conf.seed = conf.seed.or(seed);
+            conf.origin = get_position_from_user(Some("0 0 0"))?;
+            conf.length = get_value_from_user::<f64>("Length ΔX (nm)")?;
+            conf.width = get_value_from_user::<f64>("Width ΔY (nm)")?;
+            conf.height = get_value_from_user::<f64>("Height of pore wall ΔZ (nm)")?;
+            conf.bottom_radius = get_value_from_user::<f64>("Pore radius at bottom (nm)")?;
+            conf.top_radius = get_value_or_default_from_user::<f64>(
+                "Pore radius at top (nm)", &format!("{}", conf.bottom_radius))?;
+
+            Ok(ComponentEntry::from(conf.construct().map_err(|err| {
+                UIErrorKind::from(&format!("Could not construct pore: {}", err))
+            })?))
+        },
 
-            let origin = get_position_from_user(Some("0 0 0"))?;
+        ComponentEntry::SurfaceSphere(mut conf) => {
+            conf.origin = get_position_from_user(Some("0 0 0"))?;
+            conf.radius = get_value_from_user::<f64>("Radius (nm)")?;
 
-            let to_volume = match default_volume {
-                ConfType::Cuboid { origin: _, size: default_size } => {
-                    let (x, y, z) = default_size.to_tuple();
-                    let size = get_coord_from_user(
-                        "Size (x y z nm)", Some(&format!("{} {} {}", x, y, z)))?;
+            Ok(ComponentEntry::from(conf.construct().map_err(|err| {
+                UIErrorKind::from(&format!("Could not construct sphere: {}", err))
+            })?))
+        },
 
-                    ConfType::Cuboid { origin, size }
-                },
-                ConfType::Cylinder { origin: _, radius, height, normal } => {
-                    let radius = get_value_or_default_from_user::<f64>(
-                        "Radius (nm)", &format!("{}", radius))?;
-                    let height = get_value_or_default_from_user::<f64>(
-                        "Height (nm)", &format!("{}", height))?;
-                    let normal = select_direction(Some("Select normal"), Some(normal))?;
-
-                    ConfType::Cylinder { origin, radius, height, normal }
-                },
-            };
+        ComponentEntry::SurfaceBilayer(mut conf) => {
+            conf.seed = conf.seed.or(seed);
+            conf.origin = get_position_from_user(Some("0 0 0"))?;
+            conf.length = get_value_from_user::<f64>("Length ΔX (nm)")?;
+            conf.width = get_value_from_user::<f64>("Width ΔY (nm)")?;
 
-            // If the path is relative, it is relative to the database location.
-            // Construct the full path.
-            let path = if conf.path.is_absolute() {
-                conf.path
-            } else {
-                database_path
-                    .and_then(|db_path| db_path.parent())
-                    .map(|db_dir| PathBuf::from(db_dir))
-                    // If the database has no path, it has to be relative to
-                    // the current directory. Join with an empty path.
-                    .unwrap_or(PathBuf::new())
-                    .join(conf.path)
-            };
+            Ok(ComponentEntry::from(conf.construct().map_err(|err| {
+                UIErrorKind::from(&format!("Could not construct bilayer: {}", err))
+            })?))
+        },
 
+        ComponentEntry::SurfaceGrafts(_) => Err(GrafenCliError::ConstructError(
+            "Grafted chains are constructed by editing a surface component".into()
+        )),
+
+        ComponentEntry::ConfigurationFile(conf) => {
+            let origin = get_position_from_user(Some("0 0 0"))?;
+            let to_volume = get_conf_volume_from_user(&conf.volume_type)?;
+
+            let path = resolve_database_path(conf.path, database_path);
             let mut new_conf = read_configuration(&path)?;
 
             new_conf.description = conf.description;
-            new_conf.reconstruct(to_volume);
+            new_conf.reconstruct(to_volume).map_err(|err| GrafenCliError::RunError(
+                format!("Could not reconstruct the configuration: {}", err)
+            ))?;
 
             // Make sure that the origin is adjusted to that desired by the user.
             let displayed_origin = new_conf.get_displayed_origin();
@@ -208,6 +385,87 @@ fn fill_component(component: ComponentEntry, database_path: Option<&PathBuf>)
     }
 }
 
+/// Ask the user for the volume to cut a read configuration into. The user may select
+/// another shape, otherwise the dimensions of the current volume are used as defaults.
+///
+/// The origin of the returned volume is not set, since configurations are reconstructed
+/// at their current origin.
+fn get_conf_volume_from_user(current_volume: &ConfType) -> Result<ConfType> {
+    let (commands, item_texts) = create_menu_items![
+        (YesOrNo::No, "No"),
+        (YesOrNo::Yes, "Yes")
+    ];
+
+    eprintln!("Cut the configuration into another shape?");
+    let default_volume = match select_command(item_texts, commands)? {
+        YesOrNo::Yes => {
+            let shape = select_composite_shape()?;
+
+            if shape.bounds().is_none() {
+                return Err(GrafenCliError::from(UIErrorKind::from(
+                    "the shape is unbounded, intersect it with a bounded shape"
+                )));
+            }
+
+            ConfType::from(shape)
+        },
+        YesOrNo::No => current_volume.clone(),
+    };
+
+    let origin = Coord::ORIGO;
+
+    let volume = match default_volume {
+        ConfType::Cuboid { origin: _, size: default_size } => {
+            let (x, y, z) = default_size.to_tuple();
+            let size = get_coord_from_user(
+                "Size (x y z nm)", Some(&format!("{} {} {}", x, y, z)))?;
+
+            ConfType::Cuboid { origin, size }
+        },
+        ConfType::Cylinder { origin: _, radius, height, normal } => {
+            let radius = get_value_or_default_from_user::<f64>(
+                "Radius (nm)", &format!("{}", radius))?;
+            let height = get_value_or_default_from_user::<f64>(
+                "Height (nm)", &format!("{}", height))?;
+            let normal = select_direction(Some("Select normal"), Some(normal))?;
+
+            ConfType::Cylinder { origin, radius, height, normal }
+        },
+        ConfType::Shape { origin: _, shape } => ConfType::Shape { origin, shape },
+    };
+
+    Ok(volume)
+}
+
+/// Return whether two databases have the same path and definitions.
+fn is_same_database(database: &DataBase, other: &DataBase) -> bool {
+    database.path == other.path
+        && serde_json::to_string(database).ok() == serde_json::to_string(other).ok()
+}
+
+/// Return the full path of a file in a database. If the path is relative, it is relative
+/// to the database location.
+fn resolve_database_path(path: PathBuf, database_path: Option<&PathBuf>) -> PathBuf {
+    if path.is_absolute() {
+        path
+    } else {
+        database_path
+            .and_then(|db_path| db_path.parent())
+            .map(|db_dir| PathBuf::from(db_dir))
+            // If the database has no path, it has to be relative to
+            // the current directory. Join with an empty path.
+            .unwrap_or(PathBuf::new())
+            .join(path)
+    }
+}
+
+/// Set the full path to the height map image of a rough sheet from the database.
+fn resolve_height_map_path(sheet: &mut Sheet, database_path: Option<&PathBuf>) {
+    if let Some(Roughness::HeightMap { ref mut path, .. }) = sheet.roughness {
+        *path = resolve_database_path(path.clone(), database_path);
+    }
+}
+
 pub fn read_configuration(path: &Path) -> Result<ReadConf> {
     match path.to_str() {
         Some(p) => eprint!("Reading configuration at '{}' ... ", p),
@@ -222,7 +480,8 @@ pub fn read_configuration(path: &Path) -> Result<ReadConf> {
     Ok(conf)
 }
 
-fn select_num_coords_or_density_with_default(default_density: Option<f64>) -> UIResult<FillType> {
+fn select_num_coords_or_density_with_default(default_density: Option<f64>,
+                                              residue: Option<&Residue>) -> UIResult<FillType> {
     match default_density {
         Some(density) => {
             let (commands, item_texts) = create_menu_items![
@@ -235,16 +494,16 @@ fn select_num_coords_or_density_with_default(default_density: Option<f64>) -> UI
 
             match command {
                 YesOrNo::Yes => Ok(FillType::Density(density)),
-                YesOrNo::No => select_num_coords_or_density(),
+                YesOrNo::No => select_num_coords_or_density(residue),
             }
         },
         None => {
-            select_num_coords_or_density()
+            select_num_coords_or_density(residue)
         },
     }
 }
 
-fn select_num_coords_or_density() -> UIResult<FillType> {
+fn select_num_coords_or_density(residue: Option<&Residue>) -> UIResult<FillType> {
     create_menu![
         @pre: { };
 
@@ -257,6 +516,34 @@ fn select_num_coords_or_density() -> UIResult<FillType> {
                 Err(GrafenCliError::ConstructError("Invalid density: it must be positive".into()))
             }
         },
+        MassDensity, "Use mass density" => {
+            if residue.and_then(|res| res.mass()).is_none() {
+                Err(GrafenCliError::ConstructError(
+                    "Cannot use a mass density: the residue mass is not known".into()
+                ))
+            } else {
+                let density = get_value_from_user::<f64>("Mass density (g/cm^3)")?;
+
+                if density > 0.0 {
+                    return Ok(FillType::MassDensity(density));
+                } else {
+                    Err(GrafenCliError::ConstructError(
+                        "Invalid density: it must be positive".into()
+                    ))
+                }
+            }
+        },
+        Concentration, "Use molar concentration" => {
+            let concentration = get_value_from_user::<f64>("Concentration (mol/L)")?;
+
+            if concentration > 0.0 {
+                return Ok(FillType::Concentration(concentration));
+            } else {
+                Err(GrafenCliError::ConstructError(
+                    "Invalid concentration: it must be positive".into()
+                ))
+            }
+        },
         NumCoords, "Use a specific number of residues" => {
             let num_coords = get_value_from_user::<u64>("Number of residues")?;
 
@@ -4,6 +4,7 @@ use error::{GrafenCliError, Result, UIErrorKind, UIResult};
 
 use grafen::coord::{Coord, Direction};
 use grafen::describe::{describe_list_short, describe_list, Describe};
+use grafen::volume::Shape;
 
 use dialoguer::{Input, Select};
 use std::str::FromStr;
@@ -30,6 +31,23 @@ pub fn get_value_or_default_from_user<T: FromStr>(description: &str, default: &s
         .map_err(|_| UIErrorKind::from("could not parse a value"))
 }
 
+/// Get an optional value from the user. An empty input yields `None`.
+pub fn get_optional_value_from_user<T: FromStr>(description: &str) -> UIResult<Option<T>> {
+    let input = Input::new(description)
+        .default("")
+        .show_default(false)
+        .interact()?;
+
+    match input.trim() {
+        "" => Ok(None),
+        value => {
+            value.parse::<T>()
+                 .map(|v| Some(v))
+                 .map_err(|_| UIErrorKind::from("could not parse a value"))
+        },
+    }
+}
+
 /// Get a `Coord` either from the user or by a default.
 pub fn get_position_from_user(default: Option<&str>) -> UIResult<Coord> {
     get_coord_from_user("Position (x y z nm)", default)
@@ -158,13 +176,133 @@ pub fn select_direction(description: Option<&str>, default: Option<Direction>)
         eprint!("\n");
     }
 
+    let choices = [Some(Direction::X), Some(Direction::Y), Some(Direction::Z), None];
+    let item_texts = ["X", "Y", "Z", "Other (vector)"];
+
+    match select_command(&item_texts, &choices)? {
+        Some(direction) => Ok(direction),
+        None => {
+            let vector = get_coord_from_user("Vector (x y z)", None)?;
+
+            if vector == Coord::ORIGO {
+                Err(UIErrorKind::from("the vector must be non-zero"))
+            } else {
+                Ok(Direction::Vector(vector))
+            }
+        },
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+/// Available primitive shapes.
+enum ShapeSelect {
+    Cuboid,
+    Cylinder,
+    Sphere,
+    Ellipsoid,
+    Frustum,
+    Torus,
+    HollowCylinder,
+    Slab,
+}
+
+/// Select a primitive shape and its geometry. Positions are relative to the component origin.
+pub fn select_shape() -> UIResult<Shape> {
     let (choices, item_texts) = create_menu_items![
-        (Direction::X, "X"),
-        (Direction::Y, "Y"),
-        (Direction::Z, "Z")
+        (ShapeSelect::Cuboid, "Cuboid"),
+        (ShapeSelect::Cylinder, "Cylinder"),
+        (ShapeSelect::Sphere, "Sphere"),
+        (ShapeSelect::Ellipsoid, "Ellipsoid"),
+        (ShapeSelect::Frustum, "Cone or cone frustum"),
+        (ShapeSelect::Torus, "Torus"),
+        (ShapeSelect::HollowCylinder, "Hollow cylinder"),
+        (ShapeSelect::Slab, "Slab or half-space (has to be intersected with another shape)")
     ];
 
-    select_command(item_texts, choices).map_err(|err| UIErrorKind::from(err))
+    eprintln!("Shape:");
+    let shape = match select_command(item_texts, choices)? {
+        ShapeSelect::Cuboid => Shape::Cuboid {
+            origin: get_shape_position("Lower corner")?,
+            size: get_coord_from_user("Size (x y z nm)", None)?,
+        },
+        ShapeSelect::Cylinder => Shape::Cylinder {
+            origin: get_shape_position("Bottom center")?,
+            radius: get_value_from_user::<f64>("Radius (nm)")?,
+            height: get_value_from_user::<f64>("Height (nm)")?,
+            alignment: select_direction(Some("Alignment"), None)?,
+        },
+        ShapeSelect::Sphere => Shape::Sphere {
+            center: get_shape_position("Center")?,
+            radius: get_value_from_user::<f64>("Radius (nm)")?,
+        },
+        ShapeSelect::Ellipsoid => Shape::Ellipsoid {
+            center: get_shape_position("Center")?,
+            semi_axes: get_coord_from_user("Semi-axes (x y z nm)", None)?,
+        },
+        ShapeSelect::Frustum => Shape::Frustum {
+            origin: get_shape_position("Bottom center")?,
+            bottom_radius: get_value_from_user::<f64>("Bottom radius (nm)")?,
+            top_radius: get_value_from_user::<f64>("Top radius (nm, 0 for a cone)")?,
+            height: get_value_from_user::<f64>("Height (nm)")?,
+            alignment: select_direction(Some("Alignment"), None)?,
+        },
+        ShapeSelect::Torus => Shape::Torus {
+            center: get_shape_position("Center")?,
+            major_radius: get_value_from_user::<f64>("Major radius (nm, center to tube)")?,
+            minor_radius: get_value_from_user::<f64>("Minor radius (nm, of tube)")?,
+            alignment: select_direction(Some("Symmetry axis"), None)?,
+        },
+        ShapeSelect::HollowCylinder => Shape::HollowCylinder {
+            origin: get_shape_position("Bottom center")?,
+            inner_radius: get_value_from_user::<f64>("Inner radius (nm)")?,
+            outer_radius: get_value_from_user::<f64>("Outer radius (nm)")?,
+            height: get_value_from_user::<f64>("Height (nm)")?,
+            alignment: select_direction(Some("Alignment"), None)?,
+        },
+        ShapeSelect::Slab => Shape::Slab {
+            origin: get_shape_position("Point on plane")?,
+            normal: select_direction(Some("Normal"), None)?,
+            thickness: get_optional_value_from_user::<f64>(
+                "Thickness along normal (nm, empty: half-space)")?,
+        },
+    };
+
+    Ok(shape)
+}
+
+#[derive(Clone, Copy, Debug)]
+enum CombineShape {
+    Union,
+    Intersection,
+    Difference,
+    Done,
+}
+
+/// Select a shape which may be combined with other shapes by unions, intersections
+/// and differences. Positions are relative to the component origin.
+pub fn select_composite_shape() -> UIResult<Shape> {
+    let mut shape = select_shape()?;
+
+    let (choices, item_texts) = create_menu_items![
+        (CombineShape::Done, "Use the shape"),
+        (CombineShape::Union, "Add another shape to the volume (union)"),
+        (CombineShape::Intersection, "Cut the volume by another shape (intersection)"),
+        (CombineShape::Difference, "Remove another shape from the volume (difference)")
+    ];
+
+    loop {
+        shape = match select_command(item_texts, choices)? {
+            CombineShape::Union => shape.union(select_shape()?),
+            CombineShape::Intersection => shape.intersection(select_shape()?),
+            CombineShape::Difference => shape.difference(select_shape()?),
+            CombineShape::Done => return Ok(shape),
+        };
+    }
+}
+
+fn get_shape_position(description: &str) -> UIResult<Coord> {
+    get_coord_from_user(&format!("{} relative to component origin (x y z nm)", description),
+                        Some("0 0 0"))
 }
 
 /// Promp the user to select an item from an input list. Return as a reference
@@ -212,17 +350,20 @@ fn select_string(items: &[String], default: usize) -> UIResult<usize> {
 }
 
 /// Prompt the user to remove items from a list.
-pub fn remove_items<T: Describe>(item_list: &mut Vec<T>) -> Result<()> {
+///
+/// Returns the removed items with their indices, in the order that they were removed.
+pub fn remove_items<T: Describe>(item_list: &mut Vec<T>) -> Result<Vec<(usize, T)>> {
     let mut last_index = 0;
+    let mut removed = Vec::new();
 
     loop {
         match select_item_index(&item_list, last_index) {
             Ok(index) => {
-                item_list.remove(index);
+                removed.push((index, item_list.remove(index)));
                 last_index = index;
             },
             Err(UIErrorKind::Abort) => {
-                return Ok(());
+                return Ok(removed);
             },
             Err(err) => {
                 return Err(GrafenCliError::from(err));
@@ -232,8 +373,11 @@ pub fn remove_items<T: Describe>(item_list: &mut Vec<T>) -> Result<()> {
 }
 
 /// Prompt the user to reorder a list in-place.
-pub fn reorder_list<T: Describe>(item_list: &mut Vec<T>) -> Result<()> {
+///
+/// Returns the swapped pairs of indices, in the order that they were swapped.
+pub fn reorder_list<T: Describe>(item_list: &mut Vec<T>) -> Result<Vec<(usize, usize)>> {
     let mut last_index = 0;
+    let mut swaps = Vec::new();
 
     loop {
         let mut item_texts: Vec<_> = item_list.iter().map(|item| item.describe_short()).collect();
@@ -245,6 +389,7 @@ pub fn reorder_list<T: Describe>(item_list: &mut Vec<T>) -> Result<()> {
                 match select_string(&item_texts, i) {
                     Ok(j) => {
                         item_list.swap(i, j);
+                        swaps.push((i, j));
                         last_index = j;
                     },
                     Err(UIErrorKind::Abort) => {
@@ -256,7 +401,7 @@ pub fn reorder_list<T: Describe>(item_list: &mut Vec<T>) -> Result<()> {
                 }
             },
             Err(UIErrorKind::Abort) => {
-                return Ok(());
+                return Ok(swaps);
             },
             Err(err) => {
                 return Err(GrafenCliError::from(err));
@@ -0,0 +1,280 @@
+//! Construct slit pores and channels from two parallel walls.
+//!
+//! A channel consists of two identical sheets in the x-y plane which are separated
+//! by the channel height along z. The space between them can be filled with a fluid,
+//! either as residues at a density or as a configuration which is read from disk.
+//! The in-plane size of the system box matches that of the walls, which makes them
+//! periodic along x and y.
+
+use coord::{Coord, Direction, Translate};
+use database::ComponentEntry;
+use error::{GrafenError, Result};
+use random::{derive_seed, seed_or_random};
+use read_conf::{ConfType, ReadConf};
+use surface::Sheet;
+use system::{Component, Residue};
+use volume::{Cuboid, FillType, Volume};
+
+#[derive(Clone, Debug)]
+/// The fluid which fills the space between the walls of a channel.
+pub enum ChannelFluid {
+    /// Fill the space with residues.
+    Residue {
+        /// Residue to fill with.
+        residue: Residue,
+        /// How the space is filled.
+        fill_type: FillType,
+    },
+    /// Cut a read configuration into the space. It is replicated to fill it if necessary.
+    Configuration(ReadConf),
+}
+
+#[derive(Clone, Debug)]
+/// Parameters for constructing a channel.
+pub struct ChannelParameters {
+    /// Definition of the walls. Their normal is set to the z axis.
+    pub wall: Sheet,
+    /// Length of the walls along x (nm).
+    pub length: f64,
+    /// Width of the walls along y (nm).
+    pub width: f64,
+    /// Distance between the planes of the two walls (nm).
+    pub height: f64,
+    /// Distance between the walls and the fluid (nm).
+    pub wall_distance: f64,
+    /// Empty space between the top wall and the box edge along z (nm).
+    pub vacuum: f64,
+    /// Optional fluid between the walls.
+    pub fluid: Option<ChannelFluid>,
+    /// Seed for the random number generator, or `None` to use a random seed.
+    pub seed: Option<u64>,
+}
+
+#[derive(Clone, Debug)]
+/// A constructed channel.
+pub struct Channel {
+    /// The bottom wall, in the plane z = 0.
+    pub bottom: Sheet,
+    /// The top wall, in the plane z = height.
+    pub top: Sheet,
+    /// The fluid between the walls, if any.
+    pub fluid: Option<ComponentEntry>,
+    /// Size of the system box which makes the walls periodic in-plane.
+    pub box_size: Coord,
+}
+
+/// Construct a channel with its lower corner at the origin.
+///
+/// The wall lattice may adjust the length and width to fit its periodicity, in which case
+/// the fluid and box use the adjusted size. The fluid fills the space between the walls
+/// minus the wall distance on either side. The box height is that of the channel plus
+/// the vacuum above the top wall, which separates it from the periodic image of the
+/// bottom wall.
+///
+/// # Errors
+/// Returns an error if the walls could not be constructed, if their lattice does not
+/// have a rectangular periodic cell or if no space is left for the fluid.
+pub fn construct_channel(params: &ChannelParameters) -> Result<Channel> {
+    if params.vacuum < 0.0 {
+        return Err(GrafenError::RunError("the vacuum cannot be negative".to_string()));
+    }
+
+    if params.height <= 0.0 {
+        return Err(GrafenError::RunError("the channel height must be positive".to_string()));
+    }
+
+    let fluid_height = params.height - 2.0 * params.wall_distance;
+
+    if params.fluid.is_some() && fluid_height <= 0.0 {
+        return Err(GrafenError::RunError(
+            "the channel is too narrow to fit a fluid between the walls".to_string()
+        ));
+    }
+
+    let is_stochastic = params.wall.is_stochastic();
+    let seed = if is_stochastic {
+        Some(seed_or_random(params.wall.seed.or(params.seed)))
+    } else {
+        params.wall.seed
+    };
+
+    // The walls are constructed with their own seeds to not be identical copies
+    let wall_seed = |i| if is_stochastic { seed.map(|seed| derive_seed(seed, i)) } else { seed };
+
+    let construct_wall = |i, z| -> Result<Sheet> {
+        let wall = Sheet {
+            seed: wall_seed(i),
+            origin: Coord::new(0.0, 0.0, z),
+            normal: Direction::Z,
+            length: params.length,
+            width: params.width,
+            coords: vec![],
+            cell: None,
+            .. params.wall.clone()
+        }.construct()?.with_pbc();
+
+        if !wall.box_vectors().is_rectangular() {
+            return Err(GrafenError::RunError(
+                "the wall lattice must have a rectangular periodic cell".to_string()
+            ));
+        }
+
+        Ok(wall)
+    };
+
+    let bottom = construct_wall(0, 0.0)?;
+    let top = construct_wall(1, params.height)?;
+
+    let (length, width) = (bottom.length, bottom.width);
+    let fluid_origin = Coord::new(0.0, 0.0, params.wall_distance);
+    let fluid_size = Coord::new(length, width, fluid_height);
+
+    let fluid = match params.fluid {
+        Some(ChannelFluid::Residue { ref residue, fill_type }) => {
+            fill_type.check_residue(Some(residue))?;
+
+            let cuboid = Cuboid {
+                residue: Some(residue.clone()),
+                origin: fluid_origin,
+                size: fluid_size,
+                seed: params.seed.map(|seed| derive_seed(seed, 2)),
+                .. Cuboid::default()
+            }.fill(fill_type);
+
+            Some(ComponentEntry::from(cuboid))
+        },
+        Some(ChannelFluid::Configuration(ref conf)) => {
+            let mut conf = conf.clone();
+            conf.reconstruct(ConfType::Cuboid { origin: Coord::ORIGO, size: fluid_size })
+                .map_err(GrafenError::RunError)?;
+
+            let shift = fluid_origin - conf.get_displayed_origin();
+            conf.translate_in_place(shift);
+
+            Some(ComponentEntry::from(conf))
+        },
+        None => None,
+    };
+
+    Ok(Channel {
+        bottom,
+        top,
+        fluid,
+        box_size: Coord::new(length, width, params.height + params.vacuum),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use surface::LatticeType;
+    use system::Atom;
+
+    fn setup_parameters(fluid: Option<ChannelFluid>) -> ChannelParameters {
+        ChannelParameters {
+            wall: Sheet {
+                name: None,
+                residue: Some(resbase!["WALL", ("C", 0.0, 0.0, 0.0)]),
+                lattice: LatticeType::Hexagonal { a: 0.142 },
+                std_z: None,
+                roughness: None,
+                orient_residues: false,
+                seed: None,
+                origin: Coord::ORIGO,
+                normal: Direction::X,
+                length: 0.0,
+                width: 0.0,
+                coords: vec![],
+                orientations: vec![],
+                normals: vec![],
+                cell: None,
+                frame: None,
+            },
+            length: 3.0,
+            width: 2.0,
+            height: 4.0,
+            wall_distance: 0.5,
+            vacuum: 2.0,
+            fluid,
+            seed: Some(7),
+        }
+    }
+
+    #[test]
+    fn channel_walls_are_parallel_and_separated_by_the_height() {
+        let channel = construct_channel(&setup_parameters(None)).unwrap();
+
+        assert_eq!(channel.bottom.normal, Direction::Z);
+        assert_eq!(channel.bottom.get_origin(), Coord::ORIGO);
+        assert_eq!(channel.top.get_origin(), Coord::new(0.0, 0.0, 4.0));
+        assert_eq!(channel.bottom.coords, channel.top.coords);
+        assert!(channel.fluid.is_none());
+
+        // The box matches the adjusted size of the walls in-plane
+        let expected = Coord::new(channel.bottom.length, channel.bottom.width, 6.0);
+        assert_eq!(channel.box_size, expected);
+    }
+
+    #[test]
+    fn channel_fluid_fills_the_space_between_the_walls() {
+        let fluid = ChannelFluid::Residue {
+            residue: resbase!["SOL", ("OW", 0.0, 0.0, 0.0)],
+            fill_type: FillType::NumCoords(100),
+        };
+
+        let channel = construct_channel(&setup_parameters(Some(fluid))).unwrap();
+        let fluid = channel.fluid.unwrap();
+
+        assert_eq!(fluid.num_atoms(), 100);
+        assert_eq!(fluid.get_origin(), Coord::new(0.0, 0.0, 0.5));
+
+        let size = fluid.box_size() - fluid.get_origin();
+        assert_eq!(size.z, 3.0);
+        assert_eq!(size.x, channel.bottom.length);
+        assert_eq!(size.y, channel.bottom.width);
+    }
+
+    #[test]
+    fn channel_without_space_for_the_fluid_is_an_error() {
+        let fluid = ChannelFluid::Residue {
+            residue: resbase!["SOL", ("OW", 0.0, 0.0, 0.0)],
+            fill_type: FillType::NumCoords(100),
+        };
+
+        let params = ChannelParameters {
+            height: 1.0,
+            .. setup_parameters(Some(fluid))
+        };
+
+        assert!(construct_channel(&params).is_err());
+        assert!(construct_channel(&ChannelParameters { fluid: None, .. params }).is_ok());
+    }
+
+    #[test]
+    fn channel_fluid_with_a_mass_density_and_unknown_mass_is_an_error() {
+        let fluid = ChannelFluid::Residue {
+            residue: resbase!["XXX", ("XX", 0.0, 0.0, 0.0)],
+            fill_type: FillType::MassDensity(1.0),
+        };
+
+        assert!(construct_channel(&setup_parameters(Some(fluid))).is_err());
+    }
+
+    #[test]
+    fn stochastic_channel_walls_are_not_identical_but_reproducible() {
+        let params = ChannelParameters {
+            wall: Sheet {
+                lattice: LatticeType::PoissonDisc { density: 10.0 },
+                .. setup_parameters(None).wall
+            },
+            .. setup_parameters(None)
+        };
+
+        let channel = construct_channel(&params).unwrap();
+        assert!(channel.bottom.coords != channel.top.coords);
+
+        let again = construct_channel(&params).unwrap();
+        assert_eq!(channel.bottom.coords, again.bottom.coords);
+        assert_eq!(channel.top.coords, again.top.coords);
+    }
+}
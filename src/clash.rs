@@ -0,0 +1,156 @@
+//! Remove residues which clash with atoms of other components.
+//!
+//! Atoms of the other components are indexed in a `CellList`, which makes the search
+//! scale linearly with the number of atoms.
+
+use coord::{BoxVectors, Coord};
+use iterator::ResidueIterOut;
+use spatial::CellList;
+use system::Component;
+
+/// Return the residues of a component which have no atoms within a cutoff distance
+/// of any of the input atoms (absolute positions).
+///
+/// If box vectors are given distances are calculated between the closest periodic
+/// images in the box, which has its lower corner at the origin.
+///
+/// The residues are returned like from `iter_residues()` and can be assigned back
+/// to the component with `assign_residues`.
+pub fn remove_clashing_residues<'a, T>(component: &T, others: &[Coord], cutoff: f64,
+                                       box_vectors: Option<BoxVectors>) -> Vec<ResidueIterOut>
+        where T: Component<'a> {
+    let origin = component.get_origin();
+    let cell_list = CellList::new(others, cutoff, box_vectors);
+
+    component
+        .iter_residues()
+        .filter(|residue| {
+            residue.get_atoms()
+                .iter()
+                .map(|&(_, position)| position + origin)
+                .all(|position| !cell_list.has_neighbour_within(position, cutoff))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use system::{Atom, Residue};
+    use volume::Cuboid;
+
+    fn setup_cuboid(coords: Vec<Coord>) -> Cuboid {
+        Cuboid {
+            residue: Some(resbase!["RES", ("A", 0.0, 0.0, 0.0), ("B", 0.5, 0.0, 0.0)]),
+            size: Coord::new(10.0, 10.0, 10.0),
+            coords,
+            .. Cuboid::default()
+        }
+    }
+
+    fn get_residue_positions(residues: &[ResidueIterOut]) -> Vec<Coord> {
+        residues.iter().map(|residue| residue.get_atoms()[0].1).collect()
+    }
+
+    #[test]
+    fn residues_with_any_atom_within_the_cutoff_are_removed() {
+        let cuboid = Cuboid {
+            origin: Coord::new(1.0, 0.0, 0.0),
+            .. setup_cuboid(vec![
+                Coord::new(1.0, 1.0, 1.0), // atom B at (2.5, 1, 1) is close to the other atom
+                Coord::new(1.0, 3.0, 1.0),
+                Coord::new(5.0, 5.0, 5.0),
+            ])
+        };
+
+        let others = vec![Coord::new(2.8, 1.0, 1.0), Coord::new(8.0, 8.0, 8.0)];
+
+        let remaining = remove_clashing_residues(&cuboid, &others, 0.5, None);
+        assert_eq!(
+            get_residue_positions(&remaining),
+            vec![Coord::new(1.0, 3.0, 1.0), Coord::new(5.0, 5.0, 5.0)]
+        );
+
+        let remaining = remove_clashing_residues(&cuboid, &others, 0.1, None);
+        assert_eq!(remaining.len(), 3);
+
+        let remaining = remove_clashing_residues(&cuboid, &[], 0.5, None);
+        assert_eq!(remaining.len(), 3);
+    }
+
+    #[test]
+    fn clashes_are_found_across_periodic_boundaries() {
+        let cuboid = setup_cuboid(vec![Coord::new(0.1, 5.0, 5.0), Coord::new(4.0, 5.0, 5.0)]);
+        let others = vec![Coord::new(9.9, 5.0, 5.0)];
+
+        let remaining = remove_clashing_residues(&cuboid, &others, 0.5, None);
+        assert_eq!(remaining.len(), 2);
+
+        let box_vectors = BoxVectors::rectangular(Coord::new(10.0, 10.0, 10.0));
+        let remaining = remove_clashing_residues(&cuboid, &others, 0.5, Some(box_vectors));
+        assert_eq!(get_residue_positions(&remaining), vec![Coord::new(4.0, 5.0, 5.0)]);
+    }
+
+    #[test]
+    fn clashes_are_found_across_triclinic_periodic_boundaries() {
+        let box_vectors = BoxVectors {
+            a: Coord::new(10.0, 0.0, 0.0),
+            b: Coord::new(5.0, 10.0, 0.0),
+            c: Coord::new(0.0, 0.0, 10.0),
+        };
+
+        // Atom A of the residue at the top of the box is close to the image of
+        // the other atom shifted by b, but not to its image shifted by (0, 10, 0)
+        let cuboid = setup_cuboid(vec![Coord::new(7.0, 9.9, 5.0)]);
+        let others = vec![Coord::new(2.0, 0.1, 5.0)];
+
+        let remaining = remove_clashing_residues(&cuboid, &others, 0.5, Some(box_vectors));
+        assert!(remaining.is_empty());
+
+        let others = vec![Coord::new(7.0, 0.1, 5.0)];
+        let remaining = remove_clashing_residues(&cuboid, &others, 0.5, Some(box_vectors));
+        assert_eq!(remaining.len(), 1);
+    }
+
+    #[test]
+    fn cell_list_search_matches_brute_force_search() {
+        // Pseudo-random positions from a simple linear congruential generator
+        let mut state = 12345u64;
+        let mut next = || {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (state >> 11) as f64 / (1u64 << 53) as f64 * 10.0
+        };
+
+        let others = (0..500).map(|_| Coord::new(next(), next(), next())).collect::<Vec<_>>();
+        let coords = (0..200).map(|_| Coord::new(next(), next(), next())).collect::<Vec<_>>();
+        let cutoff = 0.8;
+
+        let cuboid = Cuboid {
+            residue: Some(resbase!["RES", ("A", 0.0, 0.0, 0.0)]),
+            coords: coords.clone(),
+            .. Cuboid::default()
+        };
+
+        let box_vectors = BoxVectors::rectangular(Coord::new(10.0, 10.0, 10.0));
+
+        for &periodic in &[None, Some(box_vectors)] {
+            let expected = coords
+                .iter()
+                .filter(|&&coord| {
+                    others.iter().all(|&other| {
+                        let delta = match periodic {
+                            Some(vectors) => vectors.minimum_image(other - coord),
+                            None => other - coord,
+                        };
+
+                        delta.norm() > cutoff
+                    })
+                })
+                .cloned()
+                .collect::<Vec<_>>();
+
+            let remaining = remove_clashing_residues(&cuboid, &others, cutoff, periodic);
+            assert_eq!(get_residue_positions(&remaining), expected);
+        }
+    }
+}
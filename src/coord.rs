@@ -1,8 +1,11 @@
 //! Implement elementary coordinate operations.
 
 use mdio::RVec;
+use serde::{Deserialize, Deserializer};
+use serde::de;
 
 use std::error::Error;
+use std::f64::consts::FRAC_PI_2;
 use std::fmt;
 use std::fmt::{Display, Formatter};
 use std::ops::{Add, AddAssign, Sub, SubAssign, Neg, Mul, MulAssign};
@@ -69,6 +72,76 @@ impl Coord {
         (dx.x * dx.x + dx.y * dx.y + dx.z * dx.z).sqrt()
     }
 
+    /// Calculate the dot product of two coordinates.
+    ///
+    /// # Examples
+    /// ```
+    /// # use grafen::coord::Coord;
+    /// let coord1 = Coord::new(1.0, 2.0, 3.0);
+    /// let coord2 = Coord::new(4.0, -5.0, 6.0);
+    /// assert_eq!(coord1.dot(coord2), 12.0);
+    /// ```
+    pub fn dot(self, other: Coord) -> f64 {
+        self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    /// Calculate the cross product of two coordinates.
+    ///
+    /// # Examples
+    /// ```
+    /// # use grafen::coord::Coord;
+    /// let x = Coord::new(1.0, 0.0, 0.0);
+    /// let y = Coord::new(0.0, 1.0, 0.0);
+    /// assert_eq!(x.cross(y), Coord::new(0.0, 0.0, 1.0));
+    /// ```
+    pub fn cross(self, other: Coord) -> Coord {
+        Coord::new(
+            self.y * other.z - self.z * other.y,
+            self.z * other.x - self.x * other.z,
+            self.x * other.y - self.y * other.x
+        )
+    }
+
+    /// Calculate the length of the coordinate vector.
+    ///
+    /// # Examples
+    /// ```
+    /// # use grafen::coord::Coord;
+    /// assert_eq!(Coord::new(3.0, 0.0, 4.0).norm(), 5.0);
+    /// ```
+    pub fn norm(self) -> f64 {
+        self.dot(self).sqrt()
+    }
+
+    /// Rotate the coordinate by an angle (in radians) around an axis vector
+    /// which passes through (0, 0, 0). The rotation is counter-clockwise
+    /// when the axis points towards the viewer.
+    ///
+    /// The axis does not have to be normalized. If it has zero length the coordinate
+    /// is returned unchanged.
+    ///
+    /// # Examples
+    /// ```
+    /// # use grafen::coord::Coord;
+    /// # use std::f64::consts::PI;
+    /// let coord = Coord::new(1.0, 0.0, 0.0);
+    /// let axis = Coord::new(0.0, 0.0, 2.0);
+    /// assert_eq!(coord.rotate_around(axis, PI / 2.0), Coord::new(0.0, 1.0, 0.0));
+    /// ```
+    pub fn rotate_around(self, axis: Coord, angle: f64) -> Coord {
+        let length = axis.norm();
+
+        if length == 0.0 {
+            return self;
+        }
+
+        // Rodrigues' rotation formula
+        let k = axis * (1.0 / length);
+        let (sin, cos) = angle.sin_cos();
+
+        self * cos + k.cross(self) * sin + k * (k.dot(self) * (1.0 - cos))
+    }
+
     /// Calculate the cylindrical distance between two coordinates along an input `Direction`.
     /// Returns the 2-tuple (radius, height).
     ///
@@ -89,6 +162,13 @@ impl Coord {
             X => (self.y - other.y, self.z - other.z, other.x - self.x),
             Y => (self.x - other.x, self.z - other.z, other.y - self.y),
             Z => (self.x - other.x, self.y - other.y, other.z - self.z),
+            Vector(_) => {
+                let axis = dir.to_unit_vector();
+                let diff = other - self;
+                let dh = diff.dot(axis);
+
+                ((diff - axis * dh).norm(), 0.0, dh)
+            },
         };
 
         let dr = (a * a + b * b).sqrt();
@@ -113,6 +193,7 @@ impl Coord {
             Direction::X => Coord { x: self.x, y: -self.z, z: self.y },
             Direction::Y => Coord { x: self.z, y: self.y, z: -self.x },
             Direction::Z => Coord { x: -self.y, y: self.x, z: self.z },
+            Direction::Vector(vector) => self.rotate_around(vector, FRAC_PI_2),
         }
     }
 
@@ -292,10 +373,80 @@ impl PartialEq for Coord {
             && (self.z - other.z).abs() < atol
     }
 }
-#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
 /// Component direction axis. Eg. for `Cylinder`s this is the cylinder axis.
 /// For a `Sheet` the normal.
-pub enum Direction { X, Y, Z }
+pub enum Direction {
+    X,
+    Y,
+    Z,
+    /// An arbitrary axis along a vector, which does not have to be normalized
+    /// but has to be non-zero.
+    Vector(Coord),
+}
+
+impl<'de> Deserialize<'de> for Direction {
+    /// A `Vector` direction of zero length is an error, since it has no direction.
+    fn deserialize<D>(deserializer: D) -> Result<Direction, D::Error>
+            where D: Deserializer<'de> {
+        #[derive(Deserialize)]
+        #[serde(rename = "Direction")]
+        enum DirectionDef {
+            X,
+            Y,
+            Z,
+            Vector(Coord),
+        }
+
+        match DirectionDef::deserialize(deserializer)? {
+            DirectionDef::X => Ok(Direction::X),
+            DirectionDef::Y => Ok(Direction::Y),
+            DirectionDef::Z => Ok(Direction::Z),
+            DirectionDef::Vector(vector) if vector == Coord::ORIGO => {
+                Err(de::Error::custom("a direction vector must be non-zero"))
+            },
+            DirectionDef::Vector(vector) => Ok(Direction::Vector(vector)),
+        }
+    }
+}
+
+impl Direction {
+    /// Return the direction as a vector of unit length. A `Vector` direction
+    /// of zero length is returned as is.
+    ///
+    /// # Examples
+    /// ```
+    /// # use grafen::coord::{Coord, Direction};
+    /// assert_eq!(Direction::Y.to_unit_vector(), Coord::new(0.0, 1.0, 0.0));
+    ///
+    /// let direction = Direction::Vector(Coord::new(3.0, 0.0, 4.0));
+    /// assert_eq!(direction.to_unit_vector(), Coord::new(0.6, 0.0, 0.8));
+    /// ```
+    pub fn to_unit_vector(self) -> Coord {
+        match self {
+            Direction::X => Coord::new(1.0, 0.0, 0.0),
+            Direction::Y => Coord::new(0.0, 1.0, 0.0),
+            Direction::Z => Coord::new(0.0, 0.0, 1.0),
+            Direction::Vector(vector) => {
+                let length = vector.norm();
+
+                if length > 0.0 { vector * (1.0 / length) } else { vector }
+            },
+        }
+    }
+
+    /// Return the index of the axis (0, 1 or 2 for x, y or z) which the direction is
+    /// along, or `None` for an arbitrary `Vector`.
+    pub fn axis_index(self) -> Option<usize> {
+        match self {
+            Direction::X => Some(0),
+            Direction::Y => Some(1),
+            Direction::Z => Some(2),
+            Direction::Vector(_) => None,
+        }
+    }
+}
 
 impl Display for Direction {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
@@ -303,10 +454,39 @@ impl Display for Direction {
             Direction::X => write!(f, "X"),
             Direction::Y => write!(f, "Y"),
             Direction::Z => write!(f, "Z"),
+            Direction::Vector(vector) => write!(f, "{}", vector),
         }
     }
 }
 
+/// Calculate the lower and upper corners of the box which encloses a cylinder
+/// with its bottom center at (0, 0, 0) and its axis along the input `Direction`.
+///
+/// # Examples
+/// ```
+/// # use grafen::coord::{Coord, Direction, calc_cylinder_extent};
+/// let (min, max) = calc_cylinder_extent(1.0, 3.0, Direction::Y);
+///
+/// assert_eq!(min, Coord::new(-1.0, 0.0, -1.0));
+/// assert_eq!(max, Coord::new(1.0, 3.0, 1.0));
+/// ```
+pub fn calc_cylinder_extent(radius: f64, height: f64, alignment: Direction) -> (Coord, Coord) {
+    let axis = alignment.to_unit_vector();
+
+    // The extent of the circular faces along an axis is given by the sine of the angle
+    // between the axis and the cylinder axis.
+    let extent = |n: f64| {
+        let radial = radius * (1.0 - n * n).max(0.0).sqrt();
+        ((height * n).min(0.0) - radial, (height * n).max(0.0) + radial)
+    };
+
+    let (x0, x1) = extent(axis.x);
+    let (y0, y1) = extent(axis.y);
+    let (z0, z1) = extent(axis.z);
+
+    (Coord::new(x0, y0, z0), Coord::new(x1, y1, z1))
+}
+
 /// Rotate a set of coordinates around an axis.
 pub fn rotate_coords(coords: &[Coord], axis: Direction) -> Vec<Coord> {
     coords.iter()
@@ -345,10 +525,328 @@ pub fn rotate_planar_coords_to_alignment(coords: &[Coord], from: Direction, to:
         (Z, Y) => {
             rotate_coords(coords, X)
         },
+        (Vector(_), _) | (_, Vector(_)) => {
+            align_coords(coords, from.to_unit_vector(), to.to_unit_vector())
+        },
         _ => coords.into(),
     }
 }
 
+/// Rotate a set of coordinates around (0, 0, 0) such that the vector `from` is aligned
+/// with the vector `to`.
+///
+/// # Examples
+/// ```
+/// # use grafen::coord::{Coord, align_coords};
+/// let coords = vec![Coord::new(1.0, 0.0, 0.0), Coord::new(2.0, 0.0, 0.0)];
+/// let aligned = align_coords(&coords, Coord::new(1.0, 0.0, 0.0), Coord::new(0.0, 0.0, 1.0));
+///
+/// assert_eq!(aligned, vec![Coord::new(0.0, 0.0, 1.0), Coord::new(0.0, 0.0, 2.0)]);
+/// ```
+pub fn align_coords(coords: &[Coord], from: Coord, to: Coord) -> Vec<Coord> {
+    match calc_alignment(from, to) {
+        Some((axis, angle)) => coords.iter().map(|&c| c.rotate_around(axis, angle)).collect(),
+        None => coords.to_vec(),
+    }
+}
+
+/// Calculate the axis and angle of the rotation which aligns the vector `from` with `to`,
+/// or `None` if either vector has zero length.
+fn calc_alignment(from: Coord, to: Coord) -> Option<(Coord, f64)> {
+    let (len_from, len_to) = (from.norm(), to.norm());
+
+    if len_from == 0.0 || len_to == 0.0 {
+        return None;
+    }
+
+    let cos = (from.dot(to) / (len_from * len_to)).max(-1.0).min(1.0);
+    let axis = from.cross(to);
+
+    // Antiparallel vectors have no unique rotation axis: use any perpendicular axis.
+    let axis = if axis.norm() < 1e-9 * len_from * len_to {
+        let trial = if from.x.abs() < from.y.abs() {
+            Coord::new(1.0, 0.0, 0.0)
+        } else {
+            Coord::new(0.0, 1.0, 0.0)
+        };
+
+        from.cross(trial)
+    } else {
+        axis
+    };
+
+    Some((axis, cos.acos()))
+}
+
+/// Return the minimum and maximum corners of the box which encloses all input coordinates,
+/// or `None` if the list is empty.
+///
+/// # Examples
+/// ```
+/// # use grafen::coord::{Coord, calc_extent};
+/// let coords = vec![Coord::new(1.0, -1.0, 2.0), Coord::new(-1.0, 3.0, 0.0)];
+/// let (min, max) = calc_extent(&coords).unwrap();
+///
+/// assert_eq!(min, Coord::new(-1.0, -1.0, 0.0));
+/// assert_eq!(max, Coord::new(1.0, 3.0, 2.0));
+/// ```
+pub fn calc_extent(coords: &[Coord]) -> Option<(Coord, Coord)> {
+    coords.split_first().map(|(&first, rest)| {
+        rest.iter().fold((first, first), |(min, max), &c| {
+            (
+                Coord::new(min.x.min(c.x), min.y.min(c.y), min.z.min(c.z)),
+                Coord::new(max.x.max(c.x), max.y.max(c.y), max.z.max(c.z))
+            )
+        })
+    })
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+/// A general rotation in three dimensions, stored as a rotation matrix.
+pub struct Rotation {
+    matrix: [[f64; 3]; 3],
+}
+
+impl Rotation {
+    /// The rotation which leaves all coordinates unchanged.
+    pub const IDENTITY: Rotation = Rotation {
+        matrix: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+    };
+
+    /// Construct a rotation by an angle (in radians) around an axis vector. The rotation
+    /// is counter-clockwise when the axis points towards the viewer.
+    ///
+    /// The axis does not have to be normalized. If it has zero length the identity
+    /// rotation is returned.
+    ///
+    /// # Examples
+    /// ```
+    /// # use grafen::coord::{Coord, Rotation};
+    /// # use std::f64::consts::PI;
+    /// let rotation = Rotation::from_axis_angle(Coord::new(0.0, 0.0, 1.0), PI / 2.0);
+    /// assert_eq!(rotation.apply(Coord::new(1.0, 0.0, 0.0)), Coord::new(0.0, 1.0, 0.0));
+    /// ```
+    pub fn from_axis_angle(axis: Coord, angle: f64) -> Rotation {
+        // The matrix columns are the rotated base vectors
+        let (ex, ey, ez) = (
+            Coord::new(1.0, 0.0, 0.0).rotate_around(axis, angle),
+            Coord::new(0.0, 1.0, 0.0).rotate_around(axis, angle),
+            Coord::new(0.0, 0.0, 1.0).rotate_around(axis, angle)
+        );
+
+        Rotation {
+            matrix: [
+                [ex.x, ey.x, ez.x],
+                [ex.y, ey.y, ez.y],
+                [ex.z, ey.z, ez.z],
+            ],
+        }
+    }
+
+    /// Construct a rotation from Euler angles (in radians). The object is first rotated
+    /// by `alpha` around the x axis, then by `beta` around the y axis and finally
+    /// by `gamma` around the z axis. All axes are fixed in space.
+    ///
+    /// # Examples
+    /// ```
+    /// # use grafen::coord::{Coord, Rotation};
+    /// # use std::f64::consts::PI;
+    /// let rotation = Rotation::from_euler_angles(PI / 2.0, 0.0, PI / 2.0);
+    /// assert_eq!(rotation.apply(Coord::new(0.0, 1.0, 0.0)), Coord::new(0.0, 0.0, 1.0));
+    /// assert_eq!(rotation.apply(Coord::new(1.0, 0.0, 0.0)), Coord::new(0.0, 1.0, 0.0));
+    /// ```
+    pub fn from_euler_angles(alpha: f64, beta: f64, gamma: f64) -> Rotation {
+        let rot_x = Rotation::from_axis_angle(Coord::new(1.0, 0.0, 0.0), alpha);
+        let rot_y = Rotation::from_axis_angle(Coord::new(0.0, 1.0, 0.0), beta);
+        let rot_z = Rotation::from_axis_angle(Coord::new(0.0, 0.0, 1.0), gamma);
+
+        rot_x.then(&rot_y).then(&rot_z)
+    }
+
+    /// Construct the rotation which aligns the vector `from` with `to`, around the axis
+    /// which is perpendicular to both. The vectors do not have to be normalized.
+    /// If either has zero length the identity rotation is returned.
+    ///
+    /// # Examples
+    /// ```
+    /// # use grafen::coord::{Coord, Rotation};
+    /// let rotation = Rotation::align(Coord::new(0.0, 0.0, 1.0), Coord::new(0.0, 2.0, 0.0));
+    /// let rotated = rotation.apply(Coord::new(0.0, 0.0, 1.0));
+    ///
+    /// assert!(rotated.distance(Coord::new(0.0, 1.0, 0.0)) < 1e-9);
+    /// ```
+    pub fn align(from: Coord, to: Coord) -> Rotation {
+        calc_alignment(from, to)
+            .map(|(axis, angle)| Rotation::from_axis_angle(axis, angle))
+            .unwrap_or(Rotation::IDENTITY)
+    }
+
+    /// Construct the rotation which maps a rigid set of `reference` positions onto
+    /// rotated (and translated) `positions`, by the vectors from their first positions.
+    ///
+    /// Positions which are all on a line only determine the rotation which aligns
+    /// the line, and a single position yields the identity rotation.
+    ///
+    /// # Examples
+    /// ```
+    /// # use grafen::coord::{Coord, Rotation};
+    /// # use std::f64::consts::PI;
+    /// let rotation = Rotation::from_axis_angle(Coord::new(1.0, 2.0, 3.0), PI / 3.0);
+    ///
+    /// let reference = vec![Coord::ORIGO, Coord::new(1.0, 0.0, 0.0), Coord::new(0.0, 1.0, 0.0)];
+    /// let positions = reference.iter()
+    ///     .map(|&coord| rotation.apply(coord) + Coord::new(5.0, 0.0, 0.0))
+    ///     .collect::<Vec<_>>();
+    ///
+    /// let found = Rotation::from_positions(&reference, &positions);
+    /// let coord = Coord::new(1.0, 1.0, 1.0);
+    ///
+    /// assert!(found.apply(coord).distance(rotation.apply(coord)) < 1e-9);
+    /// ```
+    pub fn from_positions(reference: &[Coord], positions: &[Coord]) -> Rotation {
+        const TOLERANCE: f64 = 1e-9;
+
+        let vectors = reference.iter()
+            .zip(positions.iter())
+            .map(|(&from, &to)| (from - reference[0], to - positions[0]))
+            .collect::<Vec<_>>();
+
+        let first = vectors.iter().find(|&&(from, _)| from.norm() > TOLERANCE);
+
+        let (u1, v1) = match first {
+            Some(&vectors) => vectors,
+            None => return Rotation::IDENTITY,
+        };
+
+        let second = vectors.iter().find(|&&(from, _)| from.cross(u1).norm() > TOLERANCE);
+
+        let (u2, v2) = match second {
+            Some(&vectors) => vectors,
+            None => return Rotation::align(u1, v1),
+        };
+
+        // Orthonormal bases from the two vectors, the rotation maps one onto the other
+        let basis = |a: Coord, b: Coord| {
+            let e1 = a * (1.0 / a.norm());
+            let b = b - e1 * b.dot(e1);
+            let e2 = b * (1.0 / b.norm());
+
+            [e1, e2, e1.cross(e2)]
+        };
+
+        let as_array = |coord: Coord| [coord.x, coord.y, coord.z];
+        let from = basis(u1, u2).iter().map(|&e| as_array(e)).collect::<Vec<_>>();
+        let to = basis(v1, v2).iter().map(|&e| as_array(e)).collect::<Vec<_>>();
+
+        let mut matrix = [[0.0; 3]; 3];
+
+        for i in 0..3 {
+            for j in 0..3 {
+                matrix[i][j] = (0..3).map(|k| to[k][i] * from[k][j]).sum();
+            }
+        }
+
+        Rotation { matrix }
+    }
+
+    /// Return the inverse rotation.
+    pub fn inverse(&self) -> Rotation {
+        let mut matrix = [[0.0; 3]; 3];
+
+        for i in 0..3 {
+            for j in 0..3 {
+                matrix[i][j] = self.matrix[j][i];
+            }
+        }
+
+        Rotation { matrix }
+    }
+
+    /// Return the rotation which corresponds to first applying this rotation
+    /// and then the `other`.
+    pub fn then(&self, other: &Rotation) -> Rotation {
+        let mut matrix = [[0.0; 3]; 3];
+
+        for i in 0..3 {
+            for j in 0..3 {
+                matrix[i][j] = (0..3).map(|k| other.matrix[i][k] * self.matrix[k][j]).sum();
+            }
+        }
+
+        Rotation { matrix }
+    }
+
+    /// Rotate a coordinate around (0, 0, 0).
+    pub fn apply(&self, coord: Coord) -> Coord {
+        let m = &self.matrix;
+
+        Coord::new(
+            m[0][0] * coord.x + m[0][1] * coord.y + m[0][2] * coord.z,
+            m[1][0] * coord.x + m[1][1] * coord.y + m[1][2] * coord.z,
+            m[2][0] * coord.x + m[2][1] * coord.y + m[2][2] * coord.z
+        )
+    }
+
+    /// Rotate a coordinate around an input `center`.
+    pub fn apply_around(&self, coord: Coord, center: Coord) -> Coord {
+        self.apply(coord - center) + center
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+/// The frame of a rotated object.
+///
+/// Rotating an object leaves its geometry (eg. the size of a cuboid or the axis
+/// of a cylinder) as it was. Positions are mapped to that geometry by undoing
+/// the rotation around the point which was the origin before it was rotated.
+pub struct Frame {
+    /// Total rotation of the object.
+    pub rotation: Rotation,
+    /// Position of the unrotated origin, relative to the object origin.
+    pub pivot: Coord,
+}
+
+impl Frame {
+    /// Return the frame after a further `rotation` around `center`, for an object
+    /// which is at `origin` before and at `new_origin` after the rotation.
+    ///
+    /// An object without a frame is given one.
+    pub fn rotate(frame: Option<Frame>, rotation: &Rotation, center: Coord,
+                  origin: Coord, new_origin: Coord) -> Frame {
+        let Frame { rotation: current, pivot } = frame.unwrap_or(Frame {
+            rotation: Rotation::IDENTITY,
+            pivot: Coord::ORIGO,
+        });
+
+        Frame {
+            rotation: current.then(rotation),
+            pivot: rotation.apply_around(origin + pivot, center) - new_origin,
+        }
+    }
+
+    /// Map a position relative to the object origin to its position in the
+    /// unrotated geometry.
+    ///
+    /// # Examples
+    /// ```
+    /// # use grafen::coord::{Coord, Frame, Rotation};
+    /// # use std::f64::consts::PI;
+    /// let rotation = Rotation::from_axis_angle(Coord::new(0.0, 0.0, 1.0), PI / 2.0);
+    /// let frame = Frame { rotation, pivot: Coord::new(1.0, 0.0, 0.0) };
+    ///
+    /// let unrotated = frame.to_unrotated(Coord::new(1.0, 2.0, 0.0));
+    /// assert!(unrotated.distance(Coord::new(2.0, 0.0, 0.0)) < 1e-9);
+    /// ```
+    pub fn to_unrotated(&self, position: Coord) -> Coord {
+        self.rotation.inverse().apply(position - self.pivot)
+    }
+
+    /// Map a direction in the unrotated geometry to its rotated direction.
+    pub fn to_rotated(&self, direction: Coord) -> Coord {
+        self.rotation.apply(direction)
+    }
+}
+
 /// Translate an object by a `Coord`.
 pub trait Translate {
     fn translate(self, coord: Coord) -> Self;
@@ -380,11 +878,287 @@ macro_rules! impl_translate {
 pub trait Periodic {
     /// Extend an object by some integer amounts.
     fn pbc_multiply(&self, nx: usize, ny: usize, nz: usize) -> Self;
+
+    /// Return whether the object is periodic along the x, y and z axes of its box,
+    /// ie. whether the images which are created by `pbc_multiply` join seamlessly.
+    ///
+    /// The default implementation is not periodic along any axis.
+    fn periodic_axes(&self) -> [bool; 3] {
+        [false; 3]
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+/// Vectors which span a (possibly triclinic) periodic box.
+///
+/// As in GROMACS the first vector `a` is directed along the x axis and the second vector `b`
+/// lies in the x-y plane. The third vector `c` may point in any direction with a positive
+/// z component.
+pub struct BoxVectors {
+    pub a: Coord,
+    pub b: Coord,
+    pub c: Coord,
+}
+
+impl BoxVectors {
+    /// Construct a rectangular box of input size.
+    pub fn rectangular(size: Coord) -> BoxVectors {
+        BoxVectors {
+            a: Coord::new(size.x, 0.0, 0.0),
+            b: Coord::new(0.0, size.y, 0.0),
+            c: Coord::new(0.0, 0.0, size.z),
+        }
+    }
+
+    /// Construct a box from the lengths of its vectors and the angles (in degrees)
+    /// between them: `alpha` between `b` and `c`, `beta` between `a` and `c` and
+    /// `gamma` between `a` and `b`.
+    ///
+    /// # Examples
+    /// ```
+    /// # use grafen::coord::{BoxVectors, Coord};
+    /// let lengths = Coord::new(1.0, 2.0, 3.0);
+    /// let vectors = BoxVectors::from_lengths_and_angles(lengths, 90.0, 90.0, 60.0);
+    ///
+    /// assert_eq!(vectors.b, Coord::new(1.0, 3.0f64.sqrt(), 0.0));
+    ///
+    /// let (_, _, gamma) = vectors.angles();
+    /// assert!((gamma - 60.0).abs() < 1e-9);
+    /// ```
+    pub fn from_lengths_and_angles(lengths: Coord, alpha: f64, beta: f64, gamma: f64)
+            -> BoxVectors {
+        let (cos_alpha, cos_beta) = (alpha.to_radians().cos(), beta.to_radians().cos());
+        let (sin_gamma, cos_gamma) = gamma.to_radians().sin_cos();
+
+        let cx = lengths.z * cos_beta;
+        let cy = lengths.z * (cos_alpha - cos_beta * cos_gamma) / sin_gamma;
+        let cz = (lengths.z.powi(2) - cx.powi(2) - cy.powi(2)).max(0.0).sqrt();
+
+        BoxVectors {
+            a: Coord::new(lengths.x, 0.0, 0.0),
+            b: Coord::new(lengths.y * cos_gamma, lengths.y * sin_gamma, 0.0),
+            c: Coord::new(cx, cy, cz),
+        }
+    }
+
+    /// Return whether all box vectors are along their respective axis.
+    pub fn is_rectangular(&self) -> bool {
+        self.a.y == 0.0 && self.a.z == 0.0
+            && self.b.x == 0.0 && self.b.z == 0.0
+            && self.c.x == 0.0 && self.c.y == 0.0
+    }
+
+    /// Return the lengths of the box vectors.
+    pub fn lengths(&self) -> Coord {
+        Coord::new(self.a.norm(), self.b.norm(), self.c.norm())
+    }
+
+    /// Return the angles (alpha, beta, gamma) in degrees between the box vectors. Angles
+    /// which are undefined since a vector has zero length are set to 90 degrees.
+    pub fn angles(&self) -> (f64, f64, f64) {
+        let angle = |v1: Coord, v2: Coord| {
+            let norms = v1.norm() * v2.norm();
+
+            if norms > 0.0 {
+                (v1.dot(v2) / norms).max(-1.0).min(1.0).acos().to_degrees()
+            } else {
+                90.0
+            }
+        };
+
+        (angle(self.b, self.c), angle(self.a, self.c), angle(self.a, self.b))
+    }
+
+    /// Return the upper corner of the rectangular box which encloses the box.
+    pub fn bounding_size(&self) -> Coord {
+        let positive = |v: f64| v.max(0.0);
+
+        Coord::new(
+            self.a.x + positive(self.b.x) + positive(self.c.x),
+            self.b.y + positive(self.c.y),
+            self.c.z
+        )
+    }
+
+    /// Return the coordinate with its position adjusted to lie within the box.
+    ///
+    /// As for `Coord::with_pbc`, the coordinate is not adjusted along box vectors
+    /// which have a non-positive length along their axis.
+    ///
+    /// # Examples
+    /// ```
+    /// # use grafen::coord::{BoxVectors, Coord};
+    /// let vectors = BoxVectors {
+    ///     a: Coord::new(2.0, 0.0, 0.0),
+    ///     b: Coord::new(1.0, 2.0, 0.0),
+    ///     c: Coord::new(0.0, 0.0, 2.0),
+    /// };
+    ///
+    /// assert_eq!(vectors.wrap(Coord::new(0.5, 2.5, -0.5)), Coord::new(1.5, 0.5, 1.5));
+    /// ```
+    pub fn wrap(&self, coord: Coord) -> Coord {
+        let mut wrapped = coord;
+
+        if self.c.z > 0.0 {
+            wrapped -= self.c * (wrapped.z / self.c.z).floor();
+        }
+
+        if self.b.y > 0.0 {
+            wrapped -= self.b * (wrapped.y / self.b.y).floor();
+        }
+
+        if self.a.x > 0.0 {
+            wrapped -= self.a * (wrapped.x / self.a.x).floor();
+        }
+
+        wrapped
+    }
+
+    /// Return the shortest periodic image of a distance vector between two positions.
+    ///
+    /// As in GROMACS this is exact for rectangular boxes. For triclinic boxes it is exact
+    /// for distances shorter than half of the shortest box height.
+    ///
+    /// # Examples
+    /// ```
+    /// # use grafen::coord::{BoxVectors, Coord};
+    /// let vectors = BoxVectors::rectangular(Coord::new(2.0, 2.0, 2.0));
+    /// let delta = vectors.minimum_image(Coord::new(1.5, -0.5, 0.0));
+    ///
+    /// assert_eq!(delta, Coord::new(-0.5, -0.5, 0.0));
+    /// ```
+    pub fn minimum_image(&self, delta: Coord) -> Coord {
+        let mut image = delta;
+
+        if self.c.z > 0.0 {
+            image -= self.c * (image.z / self.c.z).round();
+        }
+
+        if self.b.y > 0.0 {
+            image -= self.b * (image.y / self.b.y).round();
+        }
+
+        if self.a.x > 0.0 {
+            image -= self.a * (image.x / self.a.x).round();
+        }
+
+        image
+    }
+
+    /// Return the shifts from the box to all of its periodic images in a block of
+    /// `nx` by `ny` by `nz` boxes. The shifts are ordered with the images along z
+    /// varying the fastest, starting from no shift.
+    ///
+    /// # Examples
+    /// ```
+    /// # use grafen::coord::{BoxVectors, Coord};
+    /// let vectors = BoxVectors::rectangular(Coord::new(1.0, 2.0, 3.0));
+    /// let shifts = vectors.image_shifts(2, 1, 2);
+    ///
+    /// assert_eq!(shifts, vec![
+    ///     Coord::new(0.0, 0.0, 0.0),
+    ///     Coord::new(0.0, 0.0, 3.0),
+    ///     Coord::new(1.0, 0.0, 0.0),
+    ///     Coord::new(1.0, 0.0, 3.0)
+    /// ]);
+    /// ```
+    pub fn image_shifts(&self, nx: usize, ny: usize, nz: usize) -> Vec<Coord> {
+        let mut shifts = Vec::with_capacity(nx * ny * nz);
+
+        for i in 0..nx {
+            for j in 0..ny {
+                for k in 0..nz {
+                    shifts.push(self.a * i as f64 + self.b * j as f64 + self.c * k as f64);
+                }
+            }
+        }
+
+        shifts
+    }
+
+    /// Return the perpendicular heights of the box, ie. the distances between the
+    /// opposite faces spanned by (b, c), (a, c) and (a, b) respectively.
+    pub fn heights(&self) -> Coord {
+        let volume = self.a.dot(self.b.cross(self.c)).abs();
+        let height = |v1: Coord, v2: Coord| {
+            let area = v1.cross(v2).norm();
+            if area > 0.0 { volume / area } else { 0.0 }
+        };
+
+        Coord::new(height(self.b, self.c), height(self.a, self.c), height(self.a, self.b))
+    }
+
+    /// Return the box with its perpendicular heights increased by the `padding` along
+    /// each axis. The angles between the box vectors are kept. Vectors along which
+    /// the box has no height are extended along their axis.
+    ///
+    /// # Examples
+    /// ```
+    /// # use grafen::coord::{BoxVectors, Coord};
+    /// let vectors = BoxVectors {
+    ///     a: Coord::new(2.0, 0.0, 0.0),
+    ///     b: Coord::new(1.0, 2.0, 0.0),
+    ///     c: Coord::new(0.0, 0.0, 2.0),
+    /// };
+    ///
+    /// let padded = vectors.padded(Coord::new(0.0, 2.0, 1.0));
+    /// assert_eq!(padded.b, Coord::new(2.0, 4.0, 0.0));
+    /// assert_eq!(padded.c, Coord::new(0.0, 0.0, 3.0));
+    /// ```
+    pub fn padded(&self, padding: Coord) -> BoxVectors {
+        let heights = self.heights();
+        let pad = |vector: Coord, height: f64, amount: f64, axis: Coord| {
+            if height > 0.0 {
+                vector * ((height + amount) / height)
+            } else {
+                vector + axis * amount
+            }
+        };
+
+        BoxVectors {
+            a: pad(self.a, heights.x, padding.x, Coord::new(1.0, 0.0, 0.0)),
+            b: pad(self.b, heights.y, padding.y, Coord::new(0.0, 1.0, 0.0)),
+            c: pad(self.c, heights.z, padding.z, Coord::new(0.0, 0.0, 1.0)),
+        }
+    }
+}
+
+impl Periodic for BoxVectors {
+    /// Extend the box by integer amounts of each vector.
+    fn pbc_multiply(&self, nx: usize, ny: usize, nz: usize) -> BoxVectors {
+        BoxVectors {
+            a: self.a * nx as f64,
+            b: self.b * ny as f64,
+            c: self.c * nz as f64,
+        }
+    }
+
+    /// A box is periodic along all of its axes.
+    fn periodic_axes(&self) -> [bool; 3] {
+        [true; 3]
+    }
+}
+
+/// Rotate an object around a point.
+///
+/// After a rotation the object is (if necessary) re-positioned such that its origin
+/// is the lower corner of the box which encloses all of its atoms, and its box size
+/// is updated to that enclosing box.
+pub trait Rotate {
+    /// Rotate the object in-place around the absolute position `center`.
+    fn rotate_in_place(&mut self, rotation: &Rotation, center: Coord);
+
+    /// Rotate the object around the absolute position `center` and return it.
+    fn rotate(mut self, rotation: &Rotation, center: Coord) -> Self where Self: Sized {
+        self.rotate_in_place(rotation, center);
+        self
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::f64::consts::PI;
     use std::str::FromStr;
 
     #[test]
@@ -407,6 +1181,145 @@ mod tests {
         assert_eq!(coord, Coord::new(1e-9, 2e-9, 3e-9));
     }
 
+    #[test]
+    fn align_coords_handles_parallel_and_antiparallel_vectors() {
+        let coords = vec![Coord::new(1.0, 2.0, 3.0)];
+        let dir = Coord::new(0.0, 0.0, 1.0);
+
+        assert_eq!(align_coords(&coords, dir, dir * 2.0), coords);
+
+        let flipped = align_coords(&coords, dir, -dir);
+        assert!((flipped[0].z + 3.0).abs() < 1e-9);
+        assert!((flipped[0].norm() - coords[0].norm()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn rotation_around_an_axis_keeps_the_projection_on_the_axis() {
+        let coord = Coord::new(1.0, 2.0, 3.0);
+        let axis = Coord::new(1.0, 1.0, 0.0);
+        let rotated = coord.rotate_around(axis, 1.2);
+
+        assert!((rotated.dot(axis) - coord.dot(axis)).abs() < 1e-9);
+        assert!((rotated.norm() - coord.norm()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn rotation_from_axis_angle_matches_coord_rotation() {
+        let coord = Coord::new(1.0, -2.0, 0.5);
+        let axis = Coord::new(1.0, 2.0, 3.0);
+        let rotation = Rotation::from_axis_angle(axis, 0.7);
+
+        assert_eq!(rotation.apply(coord), coord.rotate_around(axis, 0.7));
+        assert_eq!(Rotation::from_axis_angle(Coord::ORIGO, 0.7), Rotation::IDENTITY);
+    }
+
+    #[test]
+    fn rotation_around_a_center_keeps_the_center_fixed() {
+        let center = Coord::new(1.0, 1.0, 0.0);
+        let rotation = Rotation::from_axis_angle(Coord::new(0.0, 0.0, 1.0), PI);
+
+        assert_eq!(rotation.apply_around(center, center), center);
+        assert_eq!(rotation.apply_around(Coord::new(2.0, 1.0, 0.0), center),
+                   Coord::new(0.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn aligning_rotations_match_aligned_coordinates_and_are_inverted() {
+        let coord = Coord::new(1.0, -2.0, 0.5);
+        let (from, to) = (Coord::new(0.0, 0.0, 1.0), Coord::new(1.0, 1.0, 0.0));
+
+        let rotation = Rotation::align(from, to);
+        let aligned = align_coords(&[coord], from, to)[0];
+        assert!(rotation.apply(coord).distance(aligned) < 1e-9);
+
+        let restored = rotation.then(&rotation.inverse()).apply(coord);
+        assert!(restored.distance(coord) < 1e-9);
+
+        let flipped = Rotation::align(from, -from).apply(from);
+        assert!(flipped.distance(-from) < 1e-9);
+
+        assert_eq!(Rotation::align(Coord::ORIGO, to), Rotation::IDENTITY);
+    }
+
+    #[test]
+    fn directions_are_read_from_axis_names_or_vectors() {
+        use serde_json;
+
+        let direction: Direction = serde_json::from_str("\"Y\"").unwrap();
+        assert_eq!(direction, Direction::Y);
+
+        let vector = Direction::Vector(Coord::new(1.0, 2.0, 3.0));
+        let serialized = serde_json::to_string(&vector).unwrap();
+        assert_eq!(serde_json::from_str::<Direction>(&serialized).unwrap(), vector);
+
+        // Zero vectors have no direction
+        let zero = r#"{ "Vector": { "x": 0.0, "y": 0.0, "z": 0.0 } }"#;
+        assert!(serde_json::from_str::<Direction>(zero).is_err());
+    }
+
+    #[test]
+    fn cylindrical_distance_along_a_vector_matches_the_axis_directions() {
+        let coord1 = Coord::new(1.0, 2.0, 3.0);
+        let coord2 = Coord::new(-1.0, 4.0, 2.0);
+
+        for &(axis, vector) in &[(Direction::X, Coord::new(2.0, 0.0, 0.0)),
+                                 (Direction::Y, Coord::new(0.0, 1.0, 0.0)),
+                                 (Direction::Z, Coord::new(0.0, 0.0, 3.0))] {
+            let (dr, dh) = coord1.distance_cylindrical(coord2, axis);
+            let (vr, vh) = coord1.distance_cylindrical(coord2, Direction::Vector(vector));
+
+            assert!((dr - vr).abs() < 1e-9);
+            assert!((dh - vh).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn cylinder_extent_along_a_tilted_axis() {
+        let axis = Coord::new(1.0, 0.0, 1.0);
+        let (min, max) = calc_cylinder_extent(1.0, 2.0, Direction::Vector(axis));
+
+        let sin = 1.0 / 2.0f64.sqrt();
+        assert_eq!(min, Coord::new(-sin, -1.0, -sin));
+        assert_eq!(max, Coord::new(2.0 * sin + sin, 1.0, 2.0 * sin + sin));
+    }
+
+    #[test]
+    fn rectangular_box_vectors_wrap_like_coordinates() {
+        let size = Coord::new(2.0, 4.0, 6.0);
+        let vectors = BoxVectors::rectangular(size);
+
+        assert!(vectors.is_rectangular());
+        assert_eq!(vectors.bounding_size(), size);
+
+        let (alpha, beta, gamma) = vectors.angles();
+        assert!((alpha - 90.0).abs() < 1e-9);
+        assert!((beta - 90.0).abs() < 1e-9);
+        assert!((gamma - 90.0).abs() < 1e-9);
+
+        for &coord in &[Coord::new(3.0, -1.0, 13.0), Coord::new(-5.5, 4.5, 0.5)] {
+            assert_eq!(vectors.wrap(coord), coord.with_pbc(size));
+        }
+    }
+
+    #[test]
+    fn box_vectors_from_lengths_and_angles_match_their_lengths_and_angles() {
+        let lengths = Coord::new(2.0, 3.0, 4.0);
+        let vectors = BoxVectors::from_lengths_and_angles(lengths, 70.0, 80.0, 110.0);
+
+        assert!(!vectors.is_rectangular());
+        assert_eq!(vectors.lengths(), lengths);
+
+        let (alpha, beta, gamma) = vectors.angles();
+        assert!((alpha - 70.0).abs() < 1e-9);
+        assert!((beta - 80.0).abs() < 1e-9);
+        assert!((gamma - 110.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn calc_extent_of_empty_list_is_none() {
+        assert!(calc_extent(&[]).is_none());
+    }
+
     #[test]
     fn coord_distance_calc() {
         let coord1 = Coord::new(1.0, 1.0, 1.0);
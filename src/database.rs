@@ -1,7 +1,7 @@
 //! Collect definitions for `Residue` and `SheetConf` objects
 //! into a `DataBase` which can be read from or saved to disk.
 
-use coord::{Coord, Translate};
+use coord::{BoxVectors, Coord, Periodic, Rotate, Rotation, Translate};
 use describe::{describe_list_short, describe_list, Describe};
 use iterator::{ResidueIter, ResidueIterOut};
 use read_conf;
@@ -30,7 +30,7 @@ pub enum DataBaseError {
 /// The enum is used to hold created objects of different types in one container,
 /// sharing one interface.
 ///
-/// Implements `Describe`, `Component` and `Translate` for the enum.
+/// Implements `Describe`, `Component`, `Periodic`, `Rotate` and `Translate` for the enum.
 ///
 /// # Requires
 /// Wrapped objects have to implement the above traits and `Clone`, `Debug`,
@@ -43,7 +43,7 @@ pub enum DataBaseError {
 /// # #[macro_use] extern crate grafen;
 /// # extern crate serde_json;
 /// # #[macro_use] extern crate serde_derive;
-/// # use grafen::coord::{Coord, Translate};
+/// # use grafen::coord::{BoxVectors, Coord, Frame, Periodic, Rotate, Rotation, Translate};
 /// # use grafen::describe::Describe;
 /// # use grafen::iterator::{ResidueIter, ResidueIterOut};
 /// # use grafen::system::{Component, Residue};
@@ -52,19 +52,31 @@ pub enum DataBaseError {
 /// pub struct StructOne {
 ///     origin: Coord,
 ///     residue: Option<Residue>,
-///     coords: Vec<Coord>
+///     coords: Vec<Coord>,
+///     cell: Option<BoxVectors>,
+///     frame: Option<Frame>
 /// }
 ///
 /// #[derive(Clone, Debug, Deserialize, Serialize)]
 /// pub struct StructTwo {
 ///     origin: Coord,
 ///     residue: Option<Residue>,
-///     coords: Vec<Coord>
+///     coords: Vec<Coord>,
+///     cell: Option<BoxVectors>,
+///     frame: Option<Frame>
 /// }
 ///
 /// // Not shown: implement required traits
-/// # impl StructOne { fn calc_box_size(&self) -> Coord { Coord::default() } }
-/// # impl StructTwo { fn calc_box_size(&self) -> Coord { Coord::default() } }
+/// # impl StructOne {
+/// #     fn calc_box_size(&self) -> Coord { Coord::default() }
+/// #     fn calc_periodic_axes(&self) -> [bool; 3] { [false; 3] }
+/// #     fn replicate_geometry(&mut self, _: usize, _: usize, _: usize) {}
+/// # }
+/// # impl StructTwo {
+/// #     fn calc_box_size(&self) -> Coord { Coord::default() }
+/// #     fn calc_periodic_axes(&self) -> [bool; 3] { [false; 3] }
+/// #     fn replicate_geometry(&mut self, _: usize, _: usize, _: usize) {}
+/// # }
 /// # impl Describe for StructOne {
 /// #     fn describe(&self) -> String { "StructOne".to_string() }
 /// #     fn describe_short(&self) -> String { self.describe() }
@@ -75,6 +87,8 @@ pub enum DataBaseError {
 /// # }
 /// # impl_component![StructOne, StructTwo];
 /// # impl_translate![StructOne, StructTwo];
+/// # impl_rotate![StructOne, StructTwo];
+/// # impl_periodic![StructOne, StructTwo];
 ///
 /// // Construct the wrapping enum container
 /// create_entry_wrapper![
@@ -89,12 +103,16 @@ pub enum DataBaseError {
 ///     Wrapper::One(StructOne {
 ///         origin: Coord::default(),
 ///         residue: None,
-///         coords: vec![]
+///         coords: vec![],
+///         cell: None,
+///         frame: None
 ///     }),
 ///     Wrapper::Two(StructTwo {
 ///         origin: Coord::default(),
 ///         residue: None,
-///         coords: vec![]
+///         coords: vec![],
+///         cell: None,
+///         frame: None
 ///     })
 /// ];
 ///
@@ -153,6 +171,14 @@ macro_rules! create_entry_wrapper {
                 }
             }
 
+            fn box_vectors(&self) -> BoxVectors {
+                match *self {
+                    $(
+                        $name::$entry(ref object) => object.box_vectors(),
+                    )*
+                }
+            }
+
             fn get_origin(&self) -> Coord {
                 match *self {
                     $(
@@ -178,6 +204,30 @@ macro_rules! create_entry_wrapper {
                 }
             }
 
+            fn num_stored_atoms(&self) -> u64 {
+                match *self {
+                    $(
+                        $name::$entry(ref object) => object.num_stored_atoms(),
+                    )*
+                }
+            }
+
+            fn total_mass(&self) -> Option<f64> {
+                match *self {
+                    $(
+                        $name::$entry(ref object) => object.total_mass(),
+                    )*
+                }
+            }
+
+            fn net_charge(&self) -> f64 {
+                match *self {
+                    $(
+                        $name::$entry(ref object) => object.net_charge(),
+                    )*
+                }
+            }
+
             fn with_pbc(self) -> Self {
                 match self {
                     $(
@@ -187,6 +237,36 @@ macro_rules! create_entry_wrapper {
             }
         }
 
+        impl Periodic for $name {
+            fn pbc_multiply(&self, nx: usize, ny: usize, nz: usize) -> Self {
+                match *self {
+                    $(
+                        $name::$entry(ref object)
+                            => $name::$entry(object.pbc_multiply(nx, ny, nz)),
+                    )*
+                }
+            }
+
+            fn periodic_axes(&self) -> [bool; 3] {
+                match *self {
+                    $(
+                        $name::$entry(ref object) => object.periodic_axes(),
+                    )*
+                }
+            }
+        }
+
+        impl Rotate for $name {
+            fn rotate_in_place(&mut self, rotation: &Rotation, center: Coord) {
+                match *self {
+                    $(
+                        $name::$entry(ref mut object)
+                            => { object.rotate_in_place(rotation, center); }
+                    )*
+                }
+            }
+        }
+
         impl Translate for $name {
             fn translate(self, coord: Coord) -> Self {
                 match self {
@@ -221,9 +301,14 @@ create_entry_wrapper![
     ComponentEntry,
     (volume::Cuboid => VolumeCuboid),
     (volume::Cylinder => VolumeCylinder),
+    (volume::Composite => VolumeComposite),
     (surface::Sheet => SurfaceSheet),
     (surface::Cuboid => SurfaceCuboid),
     (surface::Cylinder => SurfaceCylinder),
+    (surface::Pore => SurfacePore),
+    (surface::Sphere => SurfaceSphere),
+    (surface::Bilayer => SurfaceBilayer),
+    (surface::GraftedChains => SurfaceGrafts),
     (read_conf::ReadConf => ConfigurationFile)
 ];
 
@@ -349,8 +434,20 @@ mod tests {
         let base = Residue {
             code: "RES".to_string(),
             atoms: vec![
-                Atom { code: "A1".to_string(), position: Coord::new(0.0, 1.0, 2.0) },
-                Atom { code: "A2".to_string(), position: Coord::new(3.0, 4.0, 5.0) },
+                Atom {
+                    code: "A1".to_string(),
+                    position: Coord::new(0.0, 1.0, 2.0),
+                    element: None,
+                    mass: None,
+                    charge: None,
+                },
+                Atom {
+                    code: "A2".to_string(),
+                    position: Coord::new(3.0, 4.0, 5.0),
+                    element: None,
+                    mass: None,
+                    charge: None,
+                },
             ]
         };
 
@@ -372,8 +469,20 @@ mod tests {
         let base = Residue {
             code: "RES".to_string(),
             atoms: vec![
-                Atom { code: "A1".to_string(), position: Coord::new(0.0, 1.0, 2.0) },
-                Atom { code: "A2".to_string(), position: Coord::new(3.0, 4.0, 5.0) },
+                Atom {
+                    code: "A1".to_string(),
+                    position: Coord::new(0.0, 1.0, 2.0),
+                    element: None,
+                    mass: None,
+                    charge: None,
+                },
+                Atom {
+                    code: "A2".to_string(),
+                    position: Coord::new(3.0, 4.0, 5.0),
+                    element: None,
+                    mass: None,
+                    charge: None,
+                },
             ]
         };
 
@@ -440,6 +549,9 @@ mod tests {
             residue: None,
             lattice: LatticeType::Hexagonal { a: 0.1 },
             std_z: None,
+            roughness: None,
+            orient_residues: false,
+            seed: None,
             origin: Coord::ORIGO,
             normal: Direction::Z,
             length: 2.0,
@@ -450,6 +562,10 @@ mod tests {
                 Coord::new(2.5, 0.0, 0.0), // outside box by 0.5 along x
                 Coord::new(0.0, 1.5, 0.0) // outside box by 0.5 along y
             ],
+            orientations: vec![],
+            normals: vec![],
+            cell: None,
+            frame: None,
         };
 
         let component = ComponentEntry::from(sheet);
@@ -0,0 +1,133 @@
+//! Look up chemical element data for atoms.
+
+/// A chemical element with its standard atomic mass.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Element {
+    /// Element symbol, eg. "C" or "Na".
+    pub symbol: &'static str,
+    /// Standard atomic mass (g/mol).
+    pub mass: f64,
+}
+
+/// Elements which are commonly found in molecular simulation systems.
+const ELEMENTS: &[Element] = &[
+    Element { symbol: "H", mass: 1.008 },
+    Element { symbol: "He", mass: 4.0026 },
+    Element { symbol: "Li", mass: 6.94 },
+    Element { symbol: "B", mass: 10.81 },
+    Element { symbol: "C", mass: 12.011 },
+    Element { symbol: "N", mass: 14.007 },
+    Element { symbol: "O", mass: 15.999 },
+    Element { symbol: "F", mass: 18.998 },
+    Element { symbol: "Ne", mass: 20.180 },
+    Element { symbol: "Na", mass: 22.990 },
+    Element { symbol: "Mg", mass: 24.305 },
+    Element { symbol: "Al", mass: 26.982 },
+    Element { symbol: "Si", mass: 28.085 },
+    Element { symbol: "P", mass: 30.974 },
+    Element { symbol: "S", mass: 32.06 },
+    Element { symbol: "Cl", mass: 35.45 },
+    Element { symbol: "Ar", mass: 39.948 },
+    Element { symbol: "K", mass: 39.098 },
+    Element { symbol: "Ca", mass: 40.078 },
+    Element { symbol: "Ti", mass: 47.867 },
+    Element { symbol: "Fe", mass: 55.845 },
+    Element { symbol: "Cu", mass: 63.546 },
+    Element { symbol: "Zn", mass: 65.38 },
+    Element { symbol: "Br", mass: 79.904 },
+    Element { symbol: "Ag", mass: 107.87 },
+    Element { symbol: "I", mass: 126.90 },
+    Element { symbol: "Cs", mass: 132.91 },
+    Element { symbol: "Pt", mass: 195.08 },
+    Element { symbol: "Au", mass: 196.97 },
+];
+
+/// Atom names in proteins which match an element symbol but are read by their first letter.
+const PROTEIN_ATOM_NAMES: &[&str] = &["HE", "NE"];
+
+/// Return the element with an input symbol. The comparison is case insensitive.
+///
+/// # Examples
+/// ```
+/// # use grafen::element::get_element;
+/// assert_eq!(get_element("NA").unwrap().symbol, "Na");
+/// assert!(get_element("Xx").is_none());
+/// ```
+pub fn get_element(symbol: &str) -> Option<&'static Element> {
+    ELEMENTS.iter().find(|element| element.symbol.eq_ignore_ascii_case(symbol))
+}
+
+/// Guess the element of an atom from its code.
+///
+/// Leading digits and other non-letter characters are removed from the code. If the rest
+/// is exactly an element symbol that element is returned, otherwise the element matching
+/// the first letter. Only if the first letter is not an element are the first two
+/// letters read as a symbol. This means that eg. "OW", "HE1" and "C1A" are read
+/// as oxygen, hydrogen and carbon, while "CL" and "NA" are read as chlorine and sodium.
+/// The protein atom names "HE" and "NE" are read as hydrogen and nitrogen. Note that
+/// "CA" is read as calcium, not as an alpha carbon: set the element explicitly
+/// for atoms where the guess is wrong.
+///
+/// # Examples
+/// ```
+/// # use grafen::element::guess_element;
+/// assert_eq!(guess_element("OW").unwrap().symbol, "O");
+/// assert_eq!(guess_element("HW1").unwrap().symbol, "H");
+/// assert_eq!(guess_element("SI").unwrap().symbol, "Si");
+/// assert_eq!(guess_element("ZN2").unwrap().symbol, "Zn");
+/// ```
+pub fn guess_element(code: &str) -> Option<&'static Element> {
+    let name = code.trim().trim_start_matches(|c: char| !c.is_alphabetic());
+
+    let is_protein_atom = PROTEIN_ATOM_NAMES.iter().any(|atom| atom.eq_ignore_ascii_case(name));
+    let exact = if is_protein_atom { None } else { get_element(name) };
+
+    exact
+        .or_else(|| name.get(..1).and_then(get_element))
+        .or_else(|| {
+            name.get(..2)
+                .filter(|symbol| symbol.chars().all(|c| c.is_alphabetic()))
+                .and_then(get_element)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn elements_are_found_by_their_symbol_regardless_of_case() {
+        assert_eq!(get_element("C").unwrap().mass, 12.011);
+        assert_eq!(get_element("c").unwrap().mass, 12.011);
+        assert_eq!(get_element("cl").unwrap().symbol, "Cl");
+        assert!(get_element("").is_none());
+    }
+
+    #[test]
+    fn guessing_elements_from_atom_codes_strips_digits_and_falls_back_to_first_letter() {
+        assert_eq!(guess_element("C1").unwrap().symbol, "C");
+        assert_eq!(guess_element("O2").unwrap().symbol, "O");
+        assert_eq!(guess_element("HW2").unwrap().symbol, "H");
+        assert_eq!(guess_element("CL").unwrap().symbol, "Cl");
+        assert_eq!(guess_element("1NA").unwrap().symbol, "Na");
+        assert!(guess_element("X").is_none());
+        assert!(guess_element("123").is_none());
+    }
+
+    #[test]
+    fn guessing_elements_of_protein_and_lipid_atoms_uses_the_first_letter() {
+        assert_eq!(guess_element("HE1").unwrap().symbol, "H");
+        assert_eq!(guess_element("HE").unwrap().symbol, "H");
+        assert_eq!(guess_element("NE").unwrap().symbol, "N");
+        assert_eq!(guess_element("C1A").unwrap().symbol, "C");
+        assert_eq!(guess_element("HG1").unwrap().symbol, "H");
+        assert_eq!(guess_element("NE2").unwrap().symbol, "N");
+    }
+
+    #[test]
+    fn guessing_elements_reads_two_letters_if_the_first_is_not_an_element() {
+        assert_eq!(guess_element("ZN1").unwrap().symbol, "Zn");
+        assert_eq!(guess_element("MG").unwrap().symbol, "Mg");
+        assert!(guess_element("X1").is_none());
+    }
+}
@@ -0,0 +1,416 @@
+//! Place ions in a system to neutralize it and reach a salt concentration.
+//!
+//! This works like `gmx genion`: a number of cations and anions is calculated
+//! from the target concentration and the net charge of the system, after which
+//! the ions are placed either by replacing randomly selected solvent residues
+//! or by inserting them into free space in the solvent component's box.
+
+use coord::Coord;
+use error::{GrafenError, Result};
+use iterator::ResidueIterOut;
+use random::{rng_from_seed, seed_or_random};
+use spatial::CellList;
+use system::{Component, Residue};
+use volume::{Cuboid, FillType};
+
+use rand::distributions::{IndependentSample, Range};
+use rand::Rng;
+use std::collections::HashSet;
+
+/// Maximum number of trial positions per ion when inserting into free space.
+const MAX_INSERTION_ATTEMPTS: usize = 1000;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// How ions are placed in the solvent component.
+pub enum IonPlacement {
+    /// Replace randomly selected solvent residues by ions.
+    ReplaceSolvent,
+    /// Insert ions into free space in the box of the solvent component.
+    InsertIntoFreeSpace,
+}
+
+#[derive(Clone, Debug)]
+/// Parameters for adding ions to a system.
+pub struct IonParameters {
+    /// Residue of positive ions. It must have a positive net charge.
+    pub cation: Residue,
+    /// Residue of negative ions. It must have a negative net charge.
+    pub anion: Residue,
+    /// Target salt concentration (mol/L), counted as cations.
+    pub concentration: f64,
+    /// Minimum distance between ions and atoms of substrate components (nm).
+    pub min_distance: f64,
+    /// How ions are placed.
+    pub placement: IonPlacement,
+    /// Seed for the random number generator, or `None` to use a random seed.
+    pub seed: Option<u64>,
+}
+
+/// Calculate the number of cations and anions to add to a system.
+///
+/// The number of salt cations is set by the concentration in the input volume (nm^3).
+/// Counter ions are then added to neutralize the system with the input net charge.
+/// Charges of the ions must be integer multiples of each other.
+///
+/// # Examples
+/// ```
+/// # use grafen::ions::calc_num_ions;
+/// // A 0.1 M NaCl solution in 100 nm^3 with a system charge of -2
+/// assert_eq!(calc_num_ions(1.0, -1.0, -2.0, 100.0, 0.1).unwrap(), (8, 6));
+/// ```
+///
+/// # Errors
+/// Returns an error if the cation charge is not positive, the anion charge not negative
+/// or if the system cannot be neutralized with the input ion charges.
+pub fn calc_num_ions(cation_charge: f64, anion_charge: f64, net_charge: f64,
+                     volume: f64, concentration: f64) -> Result<(u64, u64)> {
+    if cation_charge <= 0.0 || anion_charge >= 0.0 {
+        return Err(GrafenError::RunError(
+            "the cation must have a positive and the anion a negative charge".to_string()
+        ));
+    }
+
+    let number_density = FillType::Concentration(concentration)
+        .to_number_density(None)
+        .unwrap_or(0.0);
+    let num_salt = (number_density * volume).round().max(0.0);
+
+    // At least this many cations are required to neutralize a negative system
+    let num_neutralizing = (-net_charge / cation_charge - 1e-6).ceil().max(0.0);
+    let num_cations_min = (num_salt + num_neutralizing) as u64;
+
+    // Increase the number of cations until a whole number of anions neutralizes the system
+    let max_tries = anion_charge.abs().ceil() as u64 + 1;
+
+    (num_cations_min..(num_cations_min + max_tries))
+        .filter_map(|num_cations| {
+            let num_anions = (net_charge + num_cations as f64 * cation_charge) / -anion_charge;
+
+            if (num_anions - num_anions.round()).abs() < 1e-3 && num_anions.round() >= 0.0 {
+                Some((num_cations, num_anions.round() as u64))
+            } else {
+                None
+            }
+        })
+        .next()
+        .ok_or(GrafenError::RunError(format!(
+            "could not neutralize a system of charge {} using ions of charge {} and {}",
+            net_charge, cation_charge, anion_charge
+        )))
+}
+
+/// Return the absolute positions of all atoms in a component.
+pub fn collect_atom_positions<'a, T: Component<'a>>(component: &T) -> Vec<Coord> {
+    let origin = component.get_origin();
+
+    component
+        .iter_residues()
+        .flat_map(|residue| residue.get_atoms())
+        .map(|(_, position)| position + origin)
+        .collect()
+}
+
+/// Add ions to a solvent component to neutralize a system and reach a salt concentration.
+///
+/// The concentration is calculated using the box volume of the solvent component
+/// and the input net charge is that of the full system. Ions are not placed closer
+/// than the minimum distance to any input substrate atom (absolute positions).
+///
+/// If solvent residues are replaced they are removed from the component and
+/// the ions are placed at their center of geometry. When inserting into free space
+/// ions are additionally kept away from all solvent atoms and other ions.
+///
+/// Returns the cations and anions as separate components with the same origin
+/// and size as the solvent component.
+///
+/// # Errors
+/// Returns an error if the numbers of ions could not be calculated (see `calc_num_ions`),
+/// if there are not enough solvent residues to replace or if free space could not be found.
+pub fn add_ions<'a, T>(solvent: &mut T, substrate: &[Coord], net_charge: f64,
+                       params: &IonParameters) -> Result<(Cuboid, Cuboid)>
+        where T: Component<'a> {
+    let origin = solvent.get_origin();
+    let size = solvent.box_size() - origin;
+    let volume = size.x * size.y * size.z;
+
+    let (num_cations, num_anions) = calc_num_ions(
+        params.cation.charge(), params.anion.charge(), net_charge, volume, params.concentration
+    )?;
+    let num_ions = (num_cations + num_anions) as usize;
+
+    let mut rng = rng_from_seed(seed_or_random(params.seed));
+
+    // Positions relative to the solvent origin
+    let positions = match params.placement {
+        IonPlacement::ReplaceSolvent => {
+            replace_solvent(solvent, substrate, num_ions, params.min_distance, &mut rng)?
+        },
+        IonPlacement::InsertIntoFreeSpace => {
+            insert_into_free_space(solvent, substrate, num_ions, params.min_distance, &mut rng)?
+        },
+    };
+
+    let (cation_coords, anion_coords) = positions.split_at(num_cations as usize);
+
+    Ok((
+        create_ion_component(&params.cation, origin, size, cation_coords),
+        create_ion_component(&params.anion, origin, size, anion_coords)
+    ))
+}
+
+/// Create a component of ions at the input positions.
+fn create_ion_component(residue: &Residue, origin: Coord, size: Coord, positions: &[Coord])
+        -> Cuboid {
+    Cuboid {
+        name: Some(residue.code.clone()),
+        residue: Some(residue.clone()),
+        origin,
+        size,
+        density: None,
+        seed: None,
+        coords: positions.to_vec(),
+        cell: None,
+        frame: None,
+    }
+}
+
+/// Return whether a position is at least the minimum distance from all indexed coordinates.
+fn is_free(position: Coord, coords: &CellList, min_distance: f64) -> bool {
+    coords.within(position, min_distance)
+        .into_iter()
+        .all(|i| coords.coords()[i].distance(position) >= min_distance)
+}
+
+/// Return the center of geometry of a residue.
+fn center_of_residue(residue: &ResidueIterOut) -> Coord {
+    let atoms = residue.get_atoms();
+    let sum = atoms.iter().fold(Coord::ORIGO, |acc, &(_, position)| acc + position);
+
+    sum * (1.0 / atoms.len() as f64)
+}
+
+/// Remove randomly selected solvent residues from the component and return their centers.
+fn replace_solvent<'a, T, R>(solvent: &mut T, substrate: &[Coord], num_ions: usize,
+                             min_distance: f64, rng: &mut R) -> Result<Vec<Coord>>
+        where T: Component<'a>, R: Rng {
+    let origin = solvent.get_origin();
+    let residues = solvent.iter_residues().collect::<Vec<_>>();
+    let substrate = CellList::new(substrate, min_distance, None);
+
+    let mut candidates = residues
+        .iter()
+        .enumerate()
+        .filter(|&(_, res)| {
+            res.get_atoms()
+                .iter()
+                .all(|&(_, position)| is_free(position + origin, &substrate, min_distance))
+        })
+        .map(|(i, _)| i)
+        .collect::<Vec<_>>();
+
+    if candidates.len() < num_ions {
+        return Err(GrafenError::RunError(format!(
+            "cannot place {} ions: only {} solvent residues are available for replacement",
+            num_ions, candidates.len()
+        )));
+    }
+
+    rng.shuffle(&mut candidates);
+    candidates.truncate(num_ions);
+
+    let positions = candidates
+        .iter()
+        .map(|&i| center_of_residue(&residues[i]))
+        .collect::<Vec<_>>();
+
+    let replaced = candidates.iter().cloned().collect::<HashSet<_>>();
+
+    let remaining = residues
+        .iter()
+        .enumerate()
+        .filter(|&(i, _)| !replaced.contains(&i))
+        .map(|(_, res)| res.clone())
+        .collect::<Vec<_>>();
+
+    solvent.assign_residues(&remaining);
+
+    Ok(positions)
+}
+
+/// Find random positions in the solvent box which are free from all other atoms.
+fn insert_into_free_space<'a, T, R>(solvent: &T, substrate: &[Coord], num_ions: usize,
+                                    min_distance: f64, rng: &mut R) -> Result<Vec<Coord>>
+        where T: Component<'a>, R: Rng {
+    let origin = solvent.get_origin();
+    let size = solvent.box_size() - origin;
+
+    let mut atoms = collect_atom_positions(solvent);
+    atoms.extend_from_slice(substrate);
+    let mut occupied = CellList::new(&atoms, min_distance, None);
+
+    let range_x = Range::new(0.0, size.x);
+    let range_y = Range::new(0.0, size.y);
+    let range_z = Range::new(0.0, size.z);
+
+    let mut positions = Vec::with_capacity(num_ions);
+
+    for _ in 0..num_ions {
+        let position = (0..MAX_INSERTION_ATTEMPTS)
+            .map(|_| Coord::new(
+                range_x.ind_sample(rng),
+                range_y.ind_sample(rng),
+                range_z.ind_sample(rng)
+            ))
+            .find(|&position| is_free(position + origin, &occupied, min_distance))
+            .ok_or(GrafenError::RunError(
+                "could not find free space to insert an ion into".to_string()
+            ))?;
+
+        occupied.insert(position + origin);
+        positions.push(position);
+    }
+
+    Ok(positions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use system::Atom;
+
+    fn ion(code: &str, charge: f64) -> Residue {
+        let mut residue = resbase![code, (code, 0.0, 0.0, 0.0)];
+        residue.atoms[0].charge = Some(charge);
+        residue
+    }
+
+    fn setup_solvent() -> Cuboid {
+        // 27 residues with a spacing of 1 nm in a 3 nm box
+        let coords = (0..27)
+            .map(|i| Coord::new(
+                0.5 + (i % 3) as f64, 0.5 + ((i / 3) % 3) as f64, 0.5 + (i / 9) as f64
+            ))
+            .collect::<Vec<_>>();
+
+        Cuboid {
+            residue: Some(resbase!["SOL", ("OW", 0.0, 0.0, 0.0)]),
+            origin: Coord::new(1.0, 1.0, 1.0),
+            size: Coord::new(3.0, 3.0, 3.0),
+            coords,
+            .. Cuboid::default()
+        }
+    }
+
+    #[test]
+    fn number_of_ions_neutralizes_the_system() {
+        assert_eq!(calc_num_ions(1.0, -1.0, 0.0, 100.0, 0.0).unwrap(), (0, 0));
+        assert_eq!(calc_num_ions(1.0, -1.0, 3.0, 100.0, 0.0).unwrap(), (0, 3));
+        assert_eq!(calc_num_ions(1.0, -1.0, -3.0, 100.0, 0.0).unwrap(), (3, 0));
+
+        // 1 M in 10 nm^3 gives 6 cations
+        assert_eq!(calc_num_ions(1.0, -1.0, 0.0, 10.0, 1.0).unwrap(), (6, 6));
+        assert_eq!(calc_num_ions(1.0, -1.0, 2.0, 10.0, 1.0).unwrap(), (6, 8));
+
+        // Divalent cations (eg. CaCl2)
+        assert_eq!(calc_num_ions(2.0, -1.0, 0.0, 10.0, 1.0).unwrap(), (6, 12));
+        assert_eq!(calc_num_ions(2.0, -1.0, -3.0, 0.0, 0.0).unwrap(), (2, 1));
+    }
+
+    #[test]
+    fn number_of_ions_requires_correct_signs_and_compatible_charges() {
+        assert!(calc_num_ions(-1.0, -1.0, 0.0, 10.0, 1.0).is_err());
+        assert!(calc_num_ions(1.0, 1.0, 0.0, 10.0, 1.0).is_err());
+        assert!(calc_num_ions(1.0, -2.0, 0.5, 10.0, 0.0).is_err());
+    }
+
+    #[test]
+    fn replacing_solvent_with_ions_removes_residues() {
+        let mut solvent = setup_solvent();
+
+        let params = IonParameters {
+            cation: ion("NA", 1.0),
+            anion: ion("CL", -1.0),
+            concentration: 0.0,
+            min_distance: 0.0,
+            placement: IonPlacement::ReplaceSolvent,
+            seed: None,
+        };
+
+        let (cations, anions) = add_ions(&mut solvent, &[], -2.0, &params).unwrap();
+
+        assert_eq!(cations.coords.len(), 2);
+        assert_eq!(anions.coords.len(), 0);
+        assert_eq!(solvent.coords.len(), 25);
+
+        assert_eq!(cations.origin, solvent.origin);
+        assert_eq!(cations.size, solvent.size);
+        assert_eq!(cations.net_charge() + anions.net_charge(), 2.0);
+
+        // The ions are placed at previous solvent positions
+        let original = setup_solvent();
+        for coord in &cations.coords {
+            assert!(original.coords.contains(coord));
+            assert!(!solvent.coords.contains(coord));
+        }
+    }
+
+    #[test]
+    fn replaced_solvent_is_kept_away_from_the_substrate() {
+        let mut solvent = setup_solvent();
+
+        // The substrate is at the solvent residue in the absolute position (1.5, 1.5, 1.5)
+        // and the distance to the closest neighbours is 1 nm
+        let substrate = vec![Coord::new(1.5, 1.5, 1.5)];
+
+        let params = IonParameters {
+            cation: ion("NA", 1.0),
+            anion: ion("CL", -1.0),
+            concentration: 0.0,
+            min_distance: 0.9,
+            placement: IonPlacement::ReplaceSolvent,
+            seed: None,
+        };
+
+        // 26 residues can be replaced but not 27
+        assert!(add_ions(&mut solvent.clone(), &substrate, -26.0, &params).is_ok());
+        assert!(add_ions(&mut solvent, &substrate, -27.0, &params).is_err());
+
+        let (cations, _) = add_ions(&mut setup_solvent(), &substrate, -26.0, &params).unwrap();
+        assert!(!cations.coords.contains(&Coord::new(0.5, 0.5, 0.5)));
+    }
+
+    #[test]
+    fn inserting_ions_into_free_space_keeps_the_solvent_and_minimum_distance() {
+        let mut solvent = Cuboid {
+            residue: Some(resbase!["SOL", ("OW", 0.0, 0.0, 0.0)]),
+            origin: Coord::new(1.0, 1.0, 1.0),
+            size: Coord::new(3.0, 3.0, 3.0),
+            coords: vec![Coord::new(1.5, 1.5, 1.5)],
+            .. Cuboid::default()
+        };
+
+        let params = IonParameters {
+            cation: ion("NA", 1.0),
+            anion: ion("CL", -1.0),
+            concentration: 0.0,
+            min_distance: 0.3,
+            placement: IonPlacement::InsertIntoFreeSpace,
+            seed: None,
+        };
+
+        let (cations, anions) = add_ions(&mut solvent, &[], 3.0, &params).unwrap();
+
+        assert_eq!(solvent.coords.len(), 1);
+        assert_eq!(cations.coords.len(), 0);
+        assert_eq!(anions.coords.len(), 3);
+
+        let mut all_coords = anions.coords.clone();
+        all_coords.push(Coord::new(1.5, 1.5, 1.5));
+
+        for (i, &c1) in all_coords.iter().enumerate() {
+            for &c2 in all_coords.iter().skip(i + 1) {
+                assert!(c1.distance(c2) >= 0.3);
+            }
+        }
+    }
+}
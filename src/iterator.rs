@@ -1,12 +1,13 @@
 //! Iterate over atoms in components.
 
-use coord::Coord;
-use system::Residue;
+use coord::{Coord, Rotation};
+use system::{PlacedResidue, Residue};
 
 use mdio;
 
 use std::cell::RefCell;
 use std::rc::Rc;
+use std::iter::Enumerate;
 use std::slice::Iter;
 
 /// Iteration object which owns the iterator. Used to ensure that it always exists.
@@ -28,7 +29,11 @@ impl<'a> ConfIter<'a> {
 
 pub enum ResidueIter<'a> {
     Conf(ConfIter<'a>),
-    Component(&'a Residue, Iter<'a, Coord>),
+    /// Copies of a residue at positions, with optional rotations of the residue
+    /// at every position. Positions without a rotation keep the residue orientation.
+    Component(&'a Residue, Enumerate<Iter<'a, Coord>>, &'a [Rotation]),
+    /// Residues with explicitly placed atoms, with their residue types.
+    Placed(Vec<&'a Residue>, Iter<'a, PlacedResidue>),
     None,
 }
 
@@ -81,18 +86,42 @@ impl<'a> Iterator for ResidueIter<'a> {
                         ResidueIterOut::FromConf(res.iter().map(|&atom| Rc::new(RefCell::new(atom.clone()))).collect())
                     })
             },
-            &mut ResidueIter::Component(ref res, ref mut iter) => {
+            &mut ResidueIter::Component(ref res, ref mut iter, orientations) => {
                 iter.next()
-                    .map(|&coord| ResidueIterOut::FromComp(
-                        Rc::new(RefCell::new(res.code.clone())),
-                        res.atoms
-                            .iter()
-                            .map(|atom| (
-                                Rc::new(RefCell::new(atom.code.clone())),
-                                atom.position + coord
-                            ))
-                            .collect::<Vec<_>>()
-                    ))
+                    .map(|(i, &coord)| {
+                        let orientation = orientations.get(i);
+
+                        ResidueIterOut::FromComp(
+                            Rc::new(RefCell::new(res.code.clone())),
+                            res.atoms
+                                .iter()
+                                .map(|atom| (
+                                    Rc::new(RefCell::new(atom.code.clone())),
+                                    orientation
+                                        .map(|rotation| rotation.apply(atom.position))
+                                        .unwrap_or(atom.position) + coord
+                                ))
+                                .collect::<Vec<_>>()
+                        )
+                    })
+            },
+            &mut ResidueIter::Placed(ref residues, ref mut iter) => {
+                iter.next()
+                    .map(|placed| {
+                        let res = residues[placed.residue];
+
+                        ResidueIterOut::FromComp(
+                            Rc::new(RefCell::new(res.code.clone())),
+                            res.atoms
+                                .iter()
+                                .zip(placed.atoms.iter())
+                                .map(|(atom, &position)| (
+                                    Rc::new(RefCell::new(atom.code.clone())),
+                                    position
+                                ))
+                                .collect::<Vec<_>>()
+                        )
+                    })
             },
         }
     }
@@ -9,16 +9,25 @@
 extern crate colored;
 extern crate mdio;
 extern crate rand;
+extern crate serde;
 extern crate serde_json;
 #[macro_use] extern crate serde_derive;
 
 #[macro_use] pub mod coord;
 #[macro_use] pub mod system;
 
+pub mod channel;
+pub mod clash;
 pub mod describe;
 pub mod database;
+pub mod element;
 pub mod error;
+pub mod ions;
 pub mod iterator;
+pub mod random;
 pub mod read_conf;
+pub mod recipe;
+pub mod session;
+pub mod spatial;
 pub mod surface;
 pub mod volume;
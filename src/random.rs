@@ -0,0 +1,90 @@
+//! Seeded random number generation for reproducible systems.
+//!
+//! Components which are constructed stochastically store the seed of the random
+//! number generator that was used to construct them. Constructing them again with
+//! the same seed and parameters yields the same coordinates. Components without
+//! a seed are given a random seed when they are constructed.
+
+use rand;
+use rand::{ChaChaRng, Rng, SeedableRng};
+
+/// Create a random number generator from a seed.
+///
+/// The generator is seeded with fixed-width words, so that a seed yields the same
+/// numbers on all platforms.
+/// # Examples
+/// ```
+/// # extern crate rand;
+/// # extern crate grafen;
+/// # use rand::Rng;
+/// # use grafen::random::rng_from_seed;
+/// # fn main() {
+/// let first = rng_from_seed(42).gen::<u64>();
+/// let second = rng_from_seed(42).gen::<u64>();
+///
+/// assert_eq!(first, second);
+/// # }
+/// ```
+pub fn rng_from_seed(seed: u64) -> ChaChaRng {
+    ChaChaRng::from_seed(&[seed as u32, (seed >> 32) as u32][..])
+}
+
+/// Return the input seed if it is set, or else a random seed.
+pub fn seed_or_random(seed: Option<u64>) -> u64 {
+    seed.unwrap_or_else(|| rand::thread_rng().gen())
+}
+
+/// Derive a seed for an item of an input index from a base seed.
+///
+/// Derived seeds of different indices are uncorrelated, which is used to give
+/// every component of a system or every part of a component its own seed.
+///
+/// # Examples
+/// ```
+/// # use grafen::random::derive_seed;
+/// assert_eq!(derive_seed(42, 0), derive_seed(42, 0));
+/// assert_ne!(derive_seed(42, 0), derive_seed(42, 1));
+/// assert_ne!(derive_seed(42, 0), derive_seed(43, 0));
+/// ```
+pub fn derive_seed(seed: u64, index: u64) -> u64 {
+    // The SplitMix64 mixing function
+    let mut z = seed.wrapping_add(index.wrapping_add(1).wrapping_mul(0x9e3779b97f4a7c15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+
+    z ^ (z >> 31)
+}
+
+/// Derive the seed of the next item from a base seed and the number of items which
+/// have been given seeds before, which is incremented. Returns `None` if no base
+/// seed is set.
+///
+/// # Examples
+/// ```
+/// # use grafen::random::{derive_seed, next_seed};
+/// let mut num_seeds = 0;
+///
+/// assert_eq!(next_seed(Some(42), &mut num_seeds), Some(derive_seed(42, 0)));
+/// assert_eq!(next_seed(Some(42), &mut num_seeds), Some(derive_seed(42, 1)));
+/// assert_eq!(next_seed(None, &mut num_seeds), None);
+/// assert_eq!(num_seeds, 3);
+/// ```
+pub fn next_seed(seed: Option<u64>, num_seeds: &mut u64) -> Option<u64> {
+    let index = *num_seeds;
+    *num_seeds += 1;
+
+    seed.map(|seed| derive_seed(seed, index))
+}
+
+/// Describe the seed of a component to append to its description, or return
+/// an empty string if no seed is set.
+///
+/// # Examples
+/// ```
+/// # use grafen::random::describe_seed;
+/// assert_eq!(describe_seed(Some(42)), " with seed 42");
+/// assert_eq!(describe_seed(None), "");
+/// ```
+pub fn describe_seed(seed: Option<u64>) -> String {
+    seed.map(|seed| format!(" with seed {}", seed)).unwrap_or_default()
+}
@@ -1,8 +1,10 @@
-use coord::{Coord, Direction, Translate};
+use coord::{calc_cylinder_extent, calc_extent, BoxVectors, Coord, Direction, Periodic, Rotate,
+            Rotation, Translate};
 use describe::Describe;
 use iterator::{ConfIter, ResidueIter, ResidueIterOut};
 use system::Component;
-use volume::{Contains, keep_residues_within_volume};
+use volume::{Composite, Contains, Cuboid, Cylinder, Shape, keep_residues_within_volume,
+             prune_residues_from_volume};
 
 use mdio;
 use std::path::{Path, PathBuf};
@@ -11,21 +13,31 @@ use std::rc::Rc;
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 /// The read configuration can be morphed into different types of elements.
+///
+/// The origins are absolute positions, since volumes of recorded operations are kept
+/// where they were applied. They default to the origin when omitted.
 pub enum ConfType {
     /// The cuboid uses a specific size.
     Cuboid {
-        #[serde(skip_deserializing)]
+        #[serde(default)]
         origin: Coord,
         size: Coord,
     },
     /// The cylinder requires some data about its construction.
     Cylinder {
-        #[serde(skip_deserializing)]
+        #[serde(default)]
         origin: Coord,
         radius: f64,
         height: f64,
         normal: Direction,
     },
+    /// Any volume shape, eg. a sphere or a composite. The shape positions are relative
+    /// to the origin, which is the lower corner of the box that encloses the shape.
+    Shape {
+        #[serde(default)]
+        origin: Coord,
+        shape: Shape,
+    },
 }
 
 impl ConfType {
@@ -33,12 +45,50 @@ impl ConfType {
         match self {
             &ConfType::Cuboid { origin: _, size } => size,
             &ConfType::Cylinder { origin: _, radius, height, normal } => {
-                match normal {
-                    Direction::X => Coord::new(height, 2.0 * radius, 2.0 * radius),
-                    Direction::Y => Coord::new(2.0 * radius, height, 2.0 * radius),
-                    Direction::Z => Coord::new(2.0 * radius, 2.0 * radius, height),
-                }
-            }
+                let (min, max) = calc_cylinder_extent(radius, height, normal);
+                max - min
+            },
+            &ConfType::Shape { origin: _, ref shape } => {
+                shape.bounds().map(|(min, max)| max - min).unwrap_or(Coord::ORIGO)
+            },
+        }
+    }
+}
+
+impl From<Shape> for ConfType {
+    /// The shape is moved such that the lower corner of the box which encloses it
+    /// is at the `ConfType` origin. Unbounded shapes are kept as they are.
+    fn from(shape: Shape) -> ConfType {
+        match shape.bounds() {
+            Some((min, _)) => ConfType::Shape { origin: min, shape: shape.translate(-min) },
+            None => ConfType::Shape { origin: Coord::ORIGO, shape },
+        }
+    }
+}
+
+impl<'a> From<&'a Composite> for ConfType {
+    fn from(composite: &'a Composite) -> ConfType {
+        ConfType::from(composite.shape.clone().translate(composite.origin))
+    }
+}
+
+impl<'a> From<&'a Cuboid> for ConfType {
+    fn from(cuboid: &'a Cuboid) -> ConfType {
+        ConfType::Cuboid { origin: cuboid.origin, size: cuboid.size }
+    }
+}
+
+impl<'a> From<&'a Cylinder> for ConfType {
+    /// The origin of a cylinder volume is its bottom center, while that of the `ConfType`
+    /// is the lower corner of the box which encloses it.
+    fn from(cylinder: &'a Cylinder) -> ConfType {
+        let (min, _) = calc_cylinder_extent(cylinder.radius, cylinder.height, cylinder.alignment);
+
+        ConfType::Cylinder {
+            origin: cylinder.origin + min,
+            radius: cylinder.radius,
+            height: cylinder.height,
+            normal: cylinder.alignment,
         }
     }
 }
@@ -53,20 +103,47 @@ impl Contains for ConfType {
             },
             &ConfType::Cylinder { origin, radius, height, normal } => {
                 // Check distance from the "bottom center" of the cylinder
-                let center = origin + match normal {
-                    Direction::X => Coord::new(0.0, radius, radius),
-                    Direction::Y => Coord::new(radius, 0.0, radius),
-                    Direction::Z => Coord::new(radius, radius, 0.0),
-                };
+                let (min, _) = calc_cylinder_extent(radius, height, normal);
+                let center = origin - min;
 
                 let (dr, dh) = center.distance_cylindrical(coord, normal);
 
                 dr <= radius && dh >= 0.0 && dh <= height
             },
+            &ConfType::Shape { origin, ref shape } => shape.contains(coord - origin),
         }
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+/// The position of a residue which is used to wrap it into a periodic box.
+pub enum WrapReference {
+    /// The position of the first atom of the residue.
+    FirstAtom,
+    /// The center of geometry of all atoms in the residue.
+    CenterOfGeometry,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+/// An operation which has been applied to a read configuration.
+///
+/// All operations are recorded such that the configuration can be re-derived
+/// from the original that was read from disk.
+pub enum ConfOperation {
+    /// Reconstruct the configuration into a volume at its current origin.
+    Reconstruct(ConfType),
+    /// Translate the configuration.
+    Translate(Coord),
+    /// Rotate the configuration around an absolute position.
+    Rotate(Rotation, Coord),
+    /// Remove residues with any atom inside of an absolute volume.
+    Prune(ConfType),
+    /// Wrap residues into a periodic box with its lower corner at an absolute position.
+    Wrap(Coord, BoxVectors, WrapReference),
+    /// Replicate the configuration into periodic images along x, y and z.
+    Replicate(usize, usize, usize),
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 /// Wrap a configuration that is read from disk into an object we can handle.
 pub struct ReadConf {
@@ -74,11 +151,14 @@ pub struct ReadConf {
     /// A configuration that may have been read by the system.
     pub conf: Option<mdio::Conf>,
     #[serde(skip)]
-    /// A backup configuration before any modifications were done.
+    /// The original configuration before any modifications were done.
     ///
-    /// In the case of several modifications to the system being done, this should
-    /// be used as the base element since it was the original. TODO: Implement!
+    /// The recorded `operations` are re-applied to this configuration when an earlier
+    /// operation is replaced, so that no atoms are lost from previous cuts.
     pub backup_conf: Option<mdio::Conf>,
+    #[serde(skip)]
+    /// Operations which have been applied to the original configuration, in order.
+    pub operations: Vec<ConfOperation>,
     /// The path to the configuration on disk.
     pub path: PathBuf,
     /// A short description of the configuration.
@@ -98,14 +178,31 @@ impl ReadConf {
         let size = Coord::from(conf.size);
 
         Ok(ReadConf {
+            backup_conf: Some(conf.clone()),
             conf: Some(conf),
-            backup_conf: None,
+            operations: Vec::new(),
             path: PathBuf::from(path),
             description,
             volume_type: ConfType::Cuboid { origin, size },
         })
     }
 
+    /// Read the original configuration again from the path and re-apply all
+    /// recorded operations to it.
+    ///
+    /// # Errors
+    /// Returns an error if the configuration could not be read.
+    pub fn reload(&mut self) -> Result<(), String> {
+        let conf = mdio::Conf::from_gromos87(&self.path).map_err(|err| {
+            format!("could not read configuration at '{}' ({})", self.path.display(), err)
+        })?;
+
+        self.backup_conf = Some(conf);
+        self.rederive();
+
+        Ok(())
+    }
+
     /// Calculate the size of the component using the volume type.
     ///
     /// Does not use the values of the read `mdio::Conf` object, but those set in the
@@ -119,49 +216,316 @@ impl ReadConf {
     pub fn get_displayed_origin(&self) -> Coord {
         match self.volume_type {
             ConfType::Cuboid { origin: _, size: _ } => self.get_origin(),
-            ConfType::Cylinder { origin: _, radius, height: _, normal } => {
-                self.get_origin() + match normal {
-                    Direction::X => Coord::new(0.0, radius, radius),
-                    Direction::Y => Coord::new(radius, 0.0, radius),
-                    Direction::Z => Coord::new(radius, radius, 0.0),
+            ConfType::Cylinder { origin: _, radius, height, normal } => {
+                let (min, _) = calc_cylinder_extent(radius, height, normal);
+                self.get_origin() - min
+            },
+            ConfType::Shape { .. } => self.get_origin(),
+        }
+    }
+
+    /// Reconstruct the configuration into a new volume at its current origin.
+    ///
+    /// A reconstruction replaces that of a previous reconstruction if the configuration
+    /// has not been rotated or wrapped since, in which case the configuration is
+    /// re-derived from the original. Eg. cutting a cuboid configuration into a cylinder
+    /// and then into a larger cuboid thus yields the full larger cuboid.
+    ///
+    /// The configuration is replicated to cover the box which encloses the new volume,
+    /// after which all residues with any atom inside of the volume are kept. Unbounded
+    /// shapes are cut from the current configuration without replicating it.
+    ///
+    /// # Examples
+    /// Cut a spherical droplet of radius 3 nm from a configuration:
+    ///
+    /// ```no_run
+    /// # use grafen::coord::Coord;
+    /// # use grafen::read_conf::{ConfType, ReadConf};
+    /// # use grafen::volume::Shape;
+    /// # use std::path::Path;
+    /// let mut conf = ReadConf::from_gromos87(Path::new("water_block.gro")).unwrap();
+    ///
+    /// let droplet = Shape::Sphere { center: Coord::ORIGO, radius: 3.0 };
+    /// conf.reconstruct(ConfType::from(droplet)).unwrap();
+    /// ```
+    ///
+    /// # Errors
+    /// Returns an error if the volume is an unbounded shape, since it has no size
+    /// to reconstruct the configuration into. Also returns an error if the volume
+    /// is larger than the configuration after it has been rotated or wrapped, since
+    /// it is then no longer a periodic block which can be replicated.
+    pub fn reconstruct(&mut self, new_conf_type: ConfType) -> Result<(), String> {
+        if let ConfType::Shape { origin: _, ref shape } = new_conf_type {
+            if shape.bounds().is_none() {
+                return Err("cannot reconstruct a configuration into an unbounded shape"
+                    .to_string());
+            }
+        }
+
+        let is_periodic_block = self.operations
+            .iter()
+            .all(|op| match op {
+                &ConfOperation::Rotate(..) | &ConfOperation::Wrap(..) => false,
+                _ => true,
+            });
+
+        if !is_periodic_block && self.calc_image_multiples(&new_conf_type) != (1, 1, 1) {
+            return Err("cannot reconstruct a rotated or wrapped configuration into a larger \
+                volume, since it is no longer a periodic block: reconstruct it first".to_string());
+        }
+
+        self.push_operation(ConfOperation::Reconstruct(new_conf_type));
+
+        Ok(())
+    }
+
+    /// Remove all residues with any atom inside of a volume.
+    pub fn prune_residues(&mut self, volume: ConfType) {
+        self.push_operation(ConfOperation::Prune(volume));
+    }
+
+    /// Wrap all residues into a periodic box with its lower corner at the absolute
+    /// position `box_origin`.
+    ///
+    /// Residues are moved as whole molecules: the reference position of each residue
+    /// is wrapped into the box and all of its atoms are translated by the same amount.
+    /// Molecules which straddle a box edge are thus kept whole.
+    pub fn wrap_residues(&mut self, box_origin: Coord, box_vectors: BoxVectors,
+                         reference: WrapReference) {
+        self.push_operation(ConfOperation::Wrap(box_origin, box_vectors, reference));
+    }
+
+    /// Replicate the configuration into a block of `nx` by `ny` by `nz` periodic images
+    /// of the box which encloses its volume. The replicated configuration is a cuboid
+    /// of the extended box.
+    pub fn replicate(&mut self, nx: usize, ny: usize, nz: usize) {
+        self.push_operation(ConfOperation::Replicate(nx, ny, nz));
+    }
+
+    /// Re-derive the configuration by applying all recorded operations to the original.
+    ///
+    /// Does nothing if the original configuration is not available.
+    pub fn rederive(&mut self) {
+        if let Some(original) = self.backup_conf.clone() {
+            self.volume_type = ConfType::Cuboid {
+                origin: Coord::ORIGO,
+                size: Coord::from(original.size),
+            };
+            self.conf = Some(original);
+
+            for operation in self.operations.clone() {
+                self.apply_operation(&operation);
+            }
+        }
+    }
+
+    /// Record an operation and apply it to the configuration.
+    fn push_operation(&mut self, operation: ConfOperation) {
+        if self.record_operation(operation.clone()) && self.backup_conf.is_some() {
+            self.rederive();
+        } else {
+            self.apply_operation(&operation);
+        }
+    }
+
+    /// Add an operation to the list of recorded operations. Returns whether a previously
+    /// recorded operation was replaced, in which case the configuration has to be re-derived.
+    fn record_operation(&mut self, operation: ConfOperation) -> bool {
+        match operation {
+            ConfOperation::Translate(shift) => {
+                if let Some(&mut ConfOperation::Translate(ref mut previous)) =
+                        self.operations.last_mut() {
+                    *previous += shift;
+                    return false;
                 }
             },
+            ConfOperation::Reconstruct(_) => {
+                // Translations and prunes can be kept after a replaced reconstruction,
+                // but rotations or wrapping change which atoms it cut
+                let replaceable = self.operations
+                    .iter()
+                    .rposition(|op| match op {
+                        &ConfOperation::Translate(_) | &ConfOperation::Prune(_) => false,
+                        _ => true,
+                    })
+                    .and_then(|i| match self.operations[i] {
+                        ConfOperation::Reconstruct(_) => Some(i),
+                        _ => None,
+                    });
+
+                if let Some(i) = replaceable {
+                    self.operations[i] = operation;
+                    return true;
+                }
+            },
+            _ => (),
         }
+
+        self.operations.push(operation);
+        false
     }
 
-    pub fn reconstruct(&mut self, new_conf_type: ConfType) {
+    /// Apply an operation to the current configuration.
+    fn apply_operation(&mut self, operation: &ConfOperation) {
+        match operation {
+            &ConfOperation::Reconstruct(ref conf_type) => self.apply_reconstruct(conf_type),
+            &ConfOperation::Translate(shift) => self.apply_translate(shift),
+            &ConfOperation::Rotate(ref rotation, center) => self.apply_rotate(rotation, center),
+            &ConfOperation::Prune(ref volume) => {
+                let remaining_residues = prune_residues_from_volume(self, volume);
+                self.assign_residues(&remaining_residues);
+            },
+            &ConfOperation::Wrap(box_origin, box_vectors, reference) => {
+                self.apply_wrap(box_origin, box_vectors, reference);
+            },
+            &ConfOperation::Replicate(nx, ny, nz) => self.apply_replicate(nx, ny, nz),
+        }
+    }
+
+    fn apply_reconstruct(&mut self, new_conf_type: &ConfType) {
         // Ensure that the volume we want to create has our origin.
         let new_conf_type = match new_conf_type {
-            ConfType::Cuboid { origin: _, size } => {
+            &ConfType::Cuboid { origin: _, size } => {
                 ConfType::Cuboid { origin: self.get_origin(), size }
             },
-            ConfType::Cylinder { origin: _, radius, height, normal } => {
+            &ConfType::Cylinder { origin: _, radius, height, normal } => {
                 ConfType::Cylinder { origin: self.get_origin(), radius, height, normal }
             },
+            &ConfType::Shape { origin: _, ref shape } => {
+                ConfType::Shape { origin: self.get_origin(), shape: shape.clone() }
+            },
         };
 
+        let (nx, ny, nz) = self.calc_image_multiples(&new_conf_type);
+
         if let Some(conf) = self.conf.as_ref() {
-            let mut current_size = self.calc_size();
+            self.conf = Some(conf.pbc_multiply(nx, ny, nz));
+        }
+
+        let contained_residues = keep_residues_within_volume(self, &new_conf_type);
+        self.assign_residues(&contained_residues);
+
+        self.volume_type = new_conf_type;
+    }
 
-            if current_size == Coord::ORIGO {
+    /// Calculate the number of periodic images of the current configuration along
+    /// each axis which cover the box that encloses a volume.
+    fn calc_image_multiples(&self, conf_type: &ConfType) -> (usize, usize, usize) {
+        let mut current_size = self.calc_size();
+
+        if current_size == Coord::ORIGO {
+            if let Some(conf) = self.conf.as_ref() {
                 current_size = Coord::from(conf.size);
             }
+        }
 
-            let new_size = new_conf_type.calc_size();
+        let new_size = conf_type.calc_size();
 
-            let (nx, ny, nz) = (
-                ((new_size.x / current_size.x).ceil() as usize).max(1),
-                ((new_size.y / current_size.y).ceil() as usize).max(1),
-                ((new_size.z / current_size.z).ceil() as usize).max(1),
-            );
+        (
+            ((new_size.x / current_size.x).ceil() as usize).max(1),
+            ((new_size.y / current_size.y).ceil() as usize).max(1),
+            ((new_size.z / current_size.z).ceil() as usize).max(1),
+        )
+    }
 
-            self.conf = Some(conf.pbc_multiply(nx, ny, nz));
+    fn apply_translate(&mut self, shift: Coord) {
+        self.volume_type = match self.volume_type {
+            ConfType::Cuboid { origin, size } => {
+                ConfType::Cuboid { origin: origin + shift, size }
+            },
+            ConfType::Cylinder { origin, radius, height, normal } => {
+                ConfType::Cylinder { origin: origin + shift, radius, height, normal }
+            },
+            ConfType::Shape { origin, ref shape } => {
+                ConfType::Shape { origin: origin + shift, shape: shape.clone() }
+            },
+        };
+    }
+
+    fn apply_wrap(&mut self, box_origin: Coord, box_vectors: BoxVectors,
+                  reference: WrapReference) {
+        let origin = self.get_origin();
+
+        let residues = self.iter_residues()
+            .map(|residue| {
+                let atoms = residue.get_atoms();
+
+                let position = match reference {
+                    WrapReference::FirstAtom => {
+                        atoms.first().map(|&(_, position)| position).unwrap_or(Coord::ORIGO)
+                    },
+                    WrapReference::CenterOfGeometry => {
+                        let sum = atoms.iter()
+                            .fold(Coord::ORIGO, |acc, &(_, position)| acc + position);
+
+                        sum * (1.0 / (atoms.len().max(1) as f64))
+                    },
+                };
+
+                let relative = origin + position - box_origin;
+                let shift = box_vectors.wrap(relative) - relative;
+
+                let shifted_atoms = atoms
+                    .into_iter()
+                    .map(|(name, position)| (name, position + shift))
+                    .collect();
+
+                ResidueIterOut::FromComp(residue.get_residue(), shifted_atoms)
+            })
+            .collect::<Vec<_>>();
+
+        self.assign_residues(&residues);
+    }
+
+    fn apply_replicate(&mut self, nx: usize, ny: usize, nz: usize) {
+        let size = self.calc_size();
+        let shifts = BoxVectors::rectangular(size).image_shifts(nx, ny, nz);
+
+        let residues = shifts
+            .iter()
+            .flat_map(|&shift| self.iter_residues().map(move |residue| {
+                let atoms = residue.get_atoms()
+                    .into_iter()
+                    .map(|(name, position)| (name, position + shift))
+                    .collect();
+
+                ResidueIterOut::FromComp(residue.get_residue(), atoms)
+            }))
+            .collect::<Vec<_>>();
+
+        self.assign_residues(&residues);
+
+        let new_size = size.pbc_multiply(nx, ny, nz);
+
+        if let Some(conf) = self.conf.as_mut() {
+            let (x, y, z) = new_size.to_tuple();
+            conf.size = mdio::RVec { x, y, z };
         }
 
-        let contained_residues = keep_residues_within_volume(self, &new_conf_type);
-        self.assign_residues(&contained_residues);
+        self.volume_type = ConfType::Cuboid { origin: self.get_origin(), size: new_size };
+    }
 
-        self.volume_type = new_conf_type;
+    fn apply_rotate(&mut self, rotation: &Rotation, center: Coord) {
+        let origin = self.get_origin();
+
+        if let Some(conf) = self.conf.as_mut() {
+            let positions = conf.atoms
+                .iter()
+                .map(|atom| rotation.apply_around(origin + Coord::from(atom.position), center))
+                .collect::<Vec<_>>();
+
+            if let Some((min, max)) = calc_extent(&positions) {
+                for (atom, &position) in conf.atoms.iter_mut().zip(positions.iter()) {
+                    let (x, y, z) = (position - min).to_tuple();
+                    atom.position = mdio::RVec { x, y, z };
+                }
+
+                let (x, y, z) = (max - min).to_tuple();
+                conf.size = mdio::RVec { x, y, z };
+
+                self.volume_type = ConfType::Cuboid { origin: min, size: max - min };
+            }
+        }
     }
 }
 
@@ -206,6 +570,7 @@ impl<'a> Component<'a> for ReadConf {
         match self.volume_type {
             ConfType::Cuboid { origin, size: _ } => origin,
             ConfType::Cylinder { origin, radius: _, height: _, normal: _ } => origin,
+            ConfType::Shape { origin, shape: _ } => origin,
         }
     }
 
@@ -221,9 +586,18 @@ impl<'a> Component<'a> for ReadConf {
         self.conf.as_ref().map(|c| c.atoms.len()).unwrap_or(0) as u64
     }
 
-    /// Currently does nothing at all.
-    fn with_pbc(self) -> Self {
-        eprintln!("Warning: PBC adjustment has not been added to read configurations.");
+    /// Returns the number of atoms in the read configuration and its original backup.
+    fn num_stored_atoms(&self) -> u64 {
+        let backup_atoms = self.backup_conf.as_ref().map(|c| c.atoms.len()).unwrap_or(0);
+        self.num_atoms() + backup_atoms as u64
+    }
+
+    /// Wrap residues by their center of geometry into the box enclosing the volume.
+    fn with_pbc(mut self) -> Self {
+        let box_origin = self.get_origin();
+        let box_vectors = BoxVectors::rectangular(self.calc_size());
+
+        self.wrap_residues(box_origin, box_vectors, WrapReference::CenterOfGeometry);
         self
     }
 }
@@ -250,6 +624,12 @@ impl Describe for ReadConf {
                         self.num_atoms(), radius, height, self.get_displayed_origin()
                     ));
                 },
+                ConfType::Shape { .. } => {
+                    description.push_str(&format!(
+                        " (Shape of {} atoms of size {} at {})",
+                        self.num_atoms(), self.calc_size(), self.get_displayed_origin()
+                    ));
+                },
             }
         }
 
@@ -274,14 +654,50 @@ impl Translate for ReadConf {
     }
 
     fn translate_in_place(&mut self, coord: Coord) {
-        self.volume_type = match self.volume_type {
-            ConfType::Cuboid { origin, size } => {
-                ConfType::Cuboid { origin: origin + coord, size }
-            },
-            ConfType::Cylinder { origin, radius, height, normal } => {
-                ConfType::Cylinder { origin: origin + coord, radius, height, normal }
+        self.push_operation(ConfOperation::Translate(coord));
+    }
+}
+
+impl Rotate for ReadConf {
+    /// Rotate all atoms of the configuration. Since the rotated configuration in general
+    /// does not fit its previous volume it is converted into a cuboid which encloses
+    /// all of its atoms.
+    fn rotate_in_place(&mut self, rotation: &Rotation, center: Coord) {
+        self.push_operation(ConfOperation::Rotate(*rotation, center));
+    }
+}
+
+impl Periodic for ReadConf {
+    /// Replicate the configuration into PBC multiples. The replication is recorded
+    /// as an operation of the returned configuration.
+    fn pbc_multiply(&self, nx: usize, ny: usize, nz: usize) -> ReadConf {
+        let mut conf = self.clone();
+        conf.replicate(nx, ny, nz);
+        conf
+    }
+
+    /// A cuboid configuration is periodic along the axes where its size is a multiple
+    /// of the box of the original configuration, unless it has been rotated or pruned.
+    fn periodic_axes(&self) -> [bool; 3] {
+        let is_modified = self.operations.iter().any(|op| match op {
+            &ConfOperation::Rotate(..) | &ConfOperation::Prune(_) => true,
+            _ => false,
+        });
+
+        match (&self.volume_type, self.backup_conf.as_ref()) {
+            (&ConfType::Cuboid { origin: _, size }, Some(original)) if !is_modified => {
+                let (x, y, z) = size.to_tuple();
+                let (x0, y0, z0) = Coord::from(original.size).to_tuple();
+
+                let is_multiple = |value: f64, base: f64| {
+                    let ratio = value / base;
+                    ratio.round() >= 1.0 && (ratio - ratio.round()).abs() < 1e-3
+                };
+
+                [is_multiple(x, x0), is_multiple(y, y0), is_multiple(z, z0)]
             },
-        };
+            _ => [false; 3],
+        }
     }
 }
 
@@ -290,6 +706,7 @@ pub mod tests {
     use super::*;
     use mdio::RVec;
     use std::cell::RefCell;
+    use std::f64::consts::PI;
     use std::rc::Rc;
 
     #[test]
@@ -331,6 +748,7 @@ pub mod tests {
         let read_conf = ReadConf {
             conf: Some(conf),
             backup_conf: None,
+            operations: Vec::new(),
             path: PathBuf::from(""),
             description: String::new(),
             volume_type: ConfType::Cuboid { origin: Coord::ORIGO, size: Coord::ORIGO },
@@ -398,6 +816,7 @@ pub mod tests {
         let read_conf = ReadConf {
             conf: Some(conf),
             backup_conf: None,
+            operations: Vec::new(),
             path: PathBuf::from(""),
             description: String::new(),
             volume_type: ConfType::Cuboid { origin: Coord::ORIGO, size: Coord::ORIGO },
@@ -421,6 +840,7 @@ pub mod tests {
         let unread_conf = ReadConf {
             conf: None,
             backup_conf: None,
+            operations: Vec::new(),
             path: PathBuf::from(""),
             description: String::new(),
             volume_type: ConfType::Cuboid { origin: Coord::ORIGO, size: Coord::ORIGO },
@@ -467,6 +887,7 @@ pub mod tests {
         let mut read_conf = ReadConf {
             conf: Some(conf),
             backup_conf: None,
+            operations: Vec::new(),
             path: PathBuf::from(""),
             description: String::new(),
             volume_type: ConfType::Cuboid { origin: Coord::ORIGO, size: Coord::ORIGO },
@@ -511,6 +932,7 @@ pub mod tests {
         let comp = ReadConf {
             conf: Some(conf),
             backup_conf: None,
+            operations: Vec::new(),
             path: PathBuf::from(""),
             description: "".to_string(),
             volume_type: ConfType::Cuboid { origin, size: Coord::ORIGO },
@@ -540,6 +962,7 @@ pub mod tests {
         let comp = ReadConf {
             conf: Some(conf),
             backup_conf: None,
+            operations: Vec::new(),
             path: PathBuf::from(""),
             description: "".to_string(),
             volume_type: ConfType::Cylinder {
@@ -576,6 +999,7 @@ pub mod tests {
         let cuboid = ReadConf {
             conf: Some(conf.clone()),
             backup_conf: None,
+            operations: Vec::new(),
             path: PathBuf::from(""),
             description: "".to_string(),
             volume_type: ConfType::Cuboid { origin, size: Coord::ORIGO },
@@ -588,6 +1012,7 @@ pub mod tests {
         let cylinder_x = ReadConf {
             conf: Some(conf.clone()),
             backup_conf: None,
+            operations: Vec::new(),
             path: PathBuf::from(""),
             description: "".to_string(),
             volume_type: ConfType::Cylinder {
@@ -604,6 +1029,7 @@ pub mod tests {
         let cylinder_y = ReadConf {
             conf: Some(conf.clone()),
             backup_conf: None,
+            operations: Vec::new(),
             path: PathBuf::from(""),
             description: "".to_string(),
             volume_type: ConfType::Cylinder {
@@ -620,6 +1046,7 @@ pub mod tests {
         let cylinder_z = ReadConf {
             conf: Some(conf.clone()),
             backup_conf: None,
+            operations: Vec::new(),
             path: PathBuf::from(""),
             description: "".to_string(),
             volume_type: ConfType::Cylinder {
@@ -679,6 +1106,7 @@ pub mod tests {
         let mut cuboid = ReadConf {
             conf: Some(conf),
             backup_conf: None,
+            operations: Vec::new(),
             path: PathBuf::from(""),
             description: String::new(),
             volume_type: ConfType::Cuboid { origin, size: Coord::ORIGO },
@@ -690,7 +1118,7 @@ pub mod tests {
         let new_size = Coord::new(dx * 1.5, dy * 2.0, dz * 2.0);
         let new_volume = ConfType::Cuboid { origin: Coord::ORIGO, size: new_size };
 
-        cuboid.reconstruct(new_volume);
+        cuboid.reconstruct(new_volume).unwrap();
 
         eprintln!("{:?}", cuboid.conf.as_ref().unwrap().atoms);
         assert_eq!(cuboid.calc_size(), new_size);
@@ -718,6 +1146,7 @@ pub mod tests {
         let cuboid = ReadConf {
             conf: Some(conf.clone()),
             backup_conf: None,
+            operations: Vec::new(),
             path: PathBuf::from(""),
             description: "".to_string(),
             volume_type: ConfType::Cuboid { origin, size },
@@ -732,6 +1161,7 @@ pub mod tests {
         let cylinder = ReadConf {
             conf: Some(conf.clone()),
             backup_conf: None,
+            operations: Vec::new(),
             path: PathBuf::from(""),
             description: "".to_string(),
             volume_type: ConfType::Cylinder { origin, radius, height, normal },
@@ -760,6 +1190,7 @@ pub mod tests {
         let cuboid = ReadConf {
             conf: Some(conf.clone()),
             backup_conf: None,
+            operations: Vec::new(),
             path: PathBuf::from(""),
             description: "".to_string(),
             volume_type: ConfType::Cuboid { origin, size },
@@ -787,6 +1218,7 @@ pub mod tests {
         let cuboid = ReadConf {
             conf: Some(conf.clone()),
             backup_conf: None,
+            operations: Vec::new(),
             path: PathBuf::from(""),
             description: "".to_string(),
             volume_type: ConfType::Cuboid { origin: Coord::ORIGO, size },
@@ -801,6 +1233,7 @@ pub mod tests {
         let cylinder_x = ReadConf {
             conf: Some(conf.clone()),
             backup_conf: None,
+            operations: Vec::new(),
             path: PathBuf::from(""),
             description: "".to_string(),
             volume_type: ConfType::Cylinder { origin: Coord::ORIGO, radius, height, normal },
@@ -813,6 +1246,7 @@ pub mod tests {
         let cylinder_y = ReadConf {
             conf: Some(conf.clone()),
             backup_conf: None,
+            operations: Vec::new(),
             path: PathBuf::from(""),
             description: "".to_string(),
             volume_type: ConfType::Cylinder { origin: Coord::ORIGO, radius, height, normal },
@@ -825,6 +1259,7 @@ pub mod tests {
         let cylinder_z = ReadConf {
             conf: Some(conf.clone()),
             backup_conf: None,
+            operations: Vec::new(),
             path: PathBuf::from(""),
             description: "".to_string(),
             volume_type: ConfType::Cylinder { origin: Coord::ORIGO, radius, height, normal },
@@ -854,6 +1289,7 @@ pub mod tests {
         let cuboid = ReadConf {
             conf: Some(conf.clone()),
             backup_conf: None,
+            operations: Vec::new(),
             path: PathBuf::from(""),
             description: "".to_string(),
             volume_type: ConfType::Cuboid { origin, size: Coord::ORIGO },
@@ -868,6 +1304,7 @@ pub mod tests {
         let cylinder = ReadConf {
             conf: Some(conf.clone()),
             backup_conf: None,
+            operations: Vec::new(),
             path: PathBuf::from(""),
             description: "".to_string(),
             volume_type: ConfType::Cylinder { origin, radius, height, normal },
@@ -893,6 +1330,7 @@ pub mod tests {
         let mut cuboid = ReadConf {
             conf: Some(conf.clone()),
             backup_conf: None,
+            operations: Vec::new(),
             path: PathBuf::from(""),
             description: "".to_string(),
             volume_type: ConfType::Cuboid { origin, size: Coord::ORIGO },
@@ -904,4 +1342,308 @@ pub mod tests {
         cuboid.translate_in_place(-origin);
         assert_eq!(cuboid.get_origin(), target);
     }
+
+    fn setup_conf_with_two_residues() -> ReadConf {
+        let residues = vec![
+            Rc::new(RefCell::new(mdio::Residue {
+                name: Rc::new(RefCell::new("RES".to_string())),
+                atoms: vec![
+                    Rc::new(RefCell::new("A1".to_string())),
+                    Rc::new(RefCell::new("A2".to_string())),
+                ],
+            })),
+            Rc::new(RefCell::new(mdio::Residue {
+                name: Rc::new(RefCell::new("SOL".to_string())),
+                atoms: vec![Rc::new(RefCell::new("OW".to_string()))],
+            })),
+        ];
+
+        let atom = |residue: usize, atom: usize, x: f64| mdio::Atom {
+            name: Rc::clone(&residues[residue].borrow().atoms[atom]),
+            residue: Rc::clone(&residues[residue]),
+            position: RVec { x, y: 0.5, z: 0.5 },
+            velocity: None,
+        };
+
+        let conf = mdio::Conf {
+            title: "A title".to_string(),
+            origin: RVec { x: 0.0, y: 0.0, z: 0.0 },
+            size: RVec { x: 1.0, y: 1.0, z: 1.0 },
+            residues: residues.clone(),
+            atoms: vec![atom(0, 0, 0.9), atom(0, 1, 1.3), atom(1, 0, -0.5)],
+        };
+
+        ReadConf {
+            conf: Some(conf),
+            backup_conf: None,
+            operations: Vec::new(),
+            path: PathBuf::from(""),
+            description: String::new(),
+            volume_type: ConfType::Cuboid {
+                origin: Coord::new(5.0, 0.0, 0.0),
+                size: Coord::new(1.0, 1.0, 1.0),
+            },
+        }
+    }
+
+    fn get_x_positions(conf: &ReadConf) -> Vec<f64> {
+        conf.iter_residues()
+            .flat_map(|residue| residue.get_atoms())
+            .map(|(_, position)| position.x)
+            .collect()
+    }
+
+    fn assert_positions_eq(result: &[f64], expected: &[f64]) {
+        assert_eq!(result.len(), expected.len());
+
+        for (x0, x1) in result.iter().zip(expected.iter()) {
+            assert!((x0 - x1).abs() < 1e-9, "{:?} != {:?}", result, expected);
+        }
+    }
+
+    #[test]
+    fn with_pbc_wraps_whole_residues_by_their_center_into_the_volume() {
+        let conf = setup_conf_with_two_residues().with_pbc();
+
+        // The first residue has its center at x = 1.1 and is moved as a whole
+        assert_positions_eq(&get_x_positions(&conf), &[-0.1, 0.3, 0.5]);
+        assert_eq!(conf.get_origin(), Coord::new(5.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn wrap_residues_by_first_atom_into_a_system_box() {
+        let mut conf = setup_conf_with_two_residues();
+
+        // Absolute positions are (5.9, 6.3, 4.5) along x
+        let box_vectors = BoxVectors::rectangular(Coord::new(2.0, 2.0, 2.0));
+        conf.wrap_residues(Coord::ORIGO, box_vectors, WrapReference::FirstAtom);
+
+        assert_positions_eq(&get_x_positions(&conf), &[-3.1, -2.7, -4.5]);
+        assert_eq!(conf.get_origin(), Coord::new(5.0, 0.0, 0.0));
+    }
+
+    fn setup_conf_with_original(xs: &[f64]) -> ReadConf {
+        let residue = Rc::new(RefCell::new(mdio::Residue {
+            name: Rc::new(RefCell::new("SOL".to_string())),
+            atoms: vec![Rc::new(RefCell::new("OW".to_string()))],
+        }));
+
+        let conf = mdio::Conf {
+            title: "A title".to_string(),
+            origin: RVec { x: 0.0, y: 0.0, z: 0.0 },
+            size: RVec { x: 1.0, y: 1.0, z: 1.0 },
+            residues: vec![Rc::clone(&residue)],
+            atoms: xs.iter()
+                .map(|&x| mdio::Atom {
+                    name: Rc::clone(&residue.borrow().atoms[0]),
+                    residue: Rc::clone(&residue),
+                    position: RVec { x, y: 0.5, z: 0.5 },
+                    velocity: None,
+                })
+                .collect(),
+        };
+
+        ReadConf {
+            conf: Some(conf.clone()),
+            backup_conf: Some(conf),
+            operations: Vec::new(),
+            path: PathBuf::from(""),
+            description: String::new(),
+            volume_type: ConfType::Cuboid {
+                origin: Coord::ORIGO,
+                size: Coord::new(1.0, 1.0, 1.0),
+            },
+        }
+    }
+
+    #[test]
+    fn repeated_reconstructions_are_derived_from_the_original_configuration() {
+        let mut conf = setup_conf_with_original(&[0.1, 0.3, 0.6, 0.9]);
+
+        let small = ConfType::Cuboid { origin: Coord::ORIGO, size: Coord::new(0.5, 1.0, 1.0) };
+        let full = ConfType::Cuboid { origin: Coord::ORIGO, size: Coord::new(1.0, 1.0, 1.0) };
+
+        conf.reconstruct(small).unwrap();
+        assert_eq!(conf.num_atoms(), 2);
+
+        let translate = Coord::new(3.0, 0.0, 0.0);
+        conf.translate_in_place(translate);
+
+        conf.reconstruct(full).unwrap();
+        assert_eq!(conf.num_atoms(), 4);
+        assert_eq!(conf.get_origin(), translate);
+        assert_eq!(conf.calc_size(), Coord::new(1.0, 1.0, 1.0));
+
+        // The first reconstruction was replaced by the second
+        assert_eq!(conf.operations.len(), 2);
+    }
+
+    #[test]
+    fn stored_atoms_of_a_configuration_include_the_original() {
+        let mut conf = setup_conf_with_original(&[0.1, 0.3, 0.6, 0.9]);
+        assert_eq!(conf.num_stored_atoms(), 8);
+
+        conf.reconstruct(ConfType::Cuboid {
+            origin: Coord::ORIGO,
+            size: Coord::new(0.5, 1.0, 1.0),
+        }).unwrap();
+
+        assert_eq!(conf.num_atoms(), 2);
+        assert_eq!(conf.num_stored_atoms(), 6);
+    }
+
+    #[test]
+    fn pruning_is_kept_when_a_configuration_is_rederived() {
+        let mut conf = setup_conf_with_original(&[0.1, 0.3, 0.6, 0.9]);
+
+        let small = ConfType::Cuboid { origin: Coord::ORIGO, size: Coord::new(0.5, 1.0, 1.0) };
+        let full = ConfType::Cuboid { origin: Coord::ORIGO, size: Coord::new(1.0, 1.0, 1.0) };
+
+        conf.reconstruct(small).unwrap();
+        conf.prune_residues(ConfType::Cuboid {
+            origin: Coord::new(0.2, 0.0, 0.0),
+            size: Coord::new(0.2, 1.0, 1.0),
+        });
+        assert_eq!(conf.num_atoms(), 1);
+
+        conf.reconstruct(full).unwrap();
+        assert_eq!(conf.num_atoms(), 3);
+    }
+
+    #[test]
+    fn reconstructions_after_wrapping_are_not_replaced() {
+        let mut conf = setup_conf_with_original(&[0.1, 0.3, 0.6, 0.9]);
+
+        let small = ConfType::Cuboid { origin: Coord::ORIGO, size: Coord::new(0.5, 1.0, 1.0) };
+        let smaller = ConfType::Cuboid { origin: Coord::ORIGO, size: Coord::new(0.2, 1.0, 1.0) };
+        let box_vectors = BoxVectors::rectangular(Coord::new(1.0, 1.0, 1.0));
+
+        conf.reconstruct(small).unwrap();
+        conf.wrap_residues(Coord::ORIGO, box_vectors, WrapReference::FirstAtom);
+        conf.reconstruct(smaller).unwrap();
+
+        assert_eq!(conf.operations.len(), 3);
+        assert_eq!(conf.num_atoms(), 1);
+    }
+
+    #[test]
+    fn rotated_or_wrapped_configurations_cannot_be_reconstructed_into_larger_volumes() {
+        let mut conf = setup_conf_with_original(&[0.1, 0.3, 0.6, 0.9]);
+
+        let cuboid = ConfType::Cuboid { origin: Coord::ORIGO, size: Coord::new(1.0, 1.0, 1.0) };
+        let larger = ConfType::Cuboid { origin: Coord::ORIGO, size: Coord::new(2.0, 1.0, 1.0) };
+
+        conf.reconstruct(cuboid).unwrap();
+
+        let rotation = Rotation::from_axis_angle(Coord::new(0.0, 0.0, 1.0), 0.25 * PI);
+        conf.rotate_in_place(&rotation, Coord::new(0.5, 0.5, 0.5));
+
+        let num_atoms = conf.num_atoms();
+        let num_operations = conf.operations.len();
+
+        assert!(conf.reconstruct(larger.clone()).is_err());
+        assert_eq!(conf.num_atoms(), num_atoms);
+        assert_eq!(conf.operations.len(), num_operations);
+
+        // Wrapped configurations are not periodic blocks either
+        let mut conf = setup_conf_with_original(&[0.1, 0.3, 0.6, 0.9]);
+        let box_vectors = BoxVectors::rectangular(Coord::new(1.0, 1.0, 1.0));
+
+        conf.wrap_residues(Coord::ORIGO, box_vectors, WrapReference::FirstAtom);
+        assert!(conf.reconstruct(larger.clone()).is_err());
+
+        // Without them the configuration is replicated
+        let mut conf = setup_conf_with_original(&[0.1, 0.3, 0.6, 0.9]);
+        conf.reconstruct(larger).unwrap();
+        assert_eq!(conf.num_atoms(), 8);
+    }
+
+    #[test]
+    fn reconstruct_into_a_shape_replicates_and_keeps_residues_inside_it() {
+        // Reconstruct a single atom at the center of a unit box into a sphere,
+        // which replicates the box 4 times along every direction
+        let mut conf = setup_conf_with_original(&[0.5]);
+
+        let sphere = Shape::Sphere { center: Coord::new(5.0, 5.0, 5.0), radius: 2.0 };
+        conf.reconstruct(ConfType::from(sphere.clone())).unwrap();
+
+        // Atoms are offset by 0.5 or 1.5 from the sphere center along every axis:
+        // those with at most one offset of 1.5 are inside of it
+        assert_eq!(conf.num_atoms(), 32);
+        assert_eq!(conf.calc_size(), Coord::new(4.0, 4.0, 4.0));
+        assert_eq!(conf.get_origin(), Coord::ORIGO);
+
+        // Cut the sphere into its upper hemisphere
+        let upper = Shape::Slab {
+            origin: Coord::new(0.0, 0.0, 5.0),
+            normal: Direction::Z,
+            thickness: None,
+        };
+        conf.reconstruct(ConfType::from(sphere.intersection(upper))).unwrap();
+
+        assert_eq!(conf.num_atoms(), 16);
+        assert_eq!(conf.operations.len(), 1);
+    }
+
+    #[test]
+    fn reconstruct_into_an_unbounded_shape_is_an_error() {
+        let mut conf = setup_conf_with_original(&[0.1, 0.3, 0.6, 0.9]);
+
+        let slab = Shape::Slab {
+            origin: Coord::new(0.0, 0.0, 0.5),
+            normal: Direction::Z,
+            thickness: None,
+        };
+
+        assert!(conf.reconstruct(ConfType::from(slab)).is_err());
+        assert!(conf.operations.is_empty());
+        assert_eq!(conf.num_atoms(), 4);
+    }
+
+    #[test]
+    fn composite_volumes_can_prune_read_configurations() {
+        let mut conf = setup_conf_with_original(&[0.1, 0.3, 0.6, 0.9]);
+
+        let composite = Composite {
+            name: None,
+            residue: None,
+            shape: Shape::Sphere { center: Coord::new(0.0, 0.5, 0.5), radius: 0.25 },
+            origin: Coord::new(0.3, 0.0, 0.0),
+            density: None,
+            seed: None,
+            coords: vec![],
+            cell: None,
+            frame: None,
+        };
+
+        conf.prune_residues(ConfType::from(&composite));
+        assert_positions_eq(&get_x_positions(&conf), &[0.6, 0.9]);
+    }
+
+    #[test]
+    fn replicated_configurations_are_periodic_multiples_of_the_original() {
+        let mut conf = setup_conf_with_original(&[0.1, 0.6]);
+        conf.translate_in_place(Coord::new(2.0, 0.0, 0.0));
+
+        let replicated = conf.pbc_multiply(3, 1, 2);
+
+        assert_eq!(replicated.num_atoms(), 12);
+        assert_eq!(replicated.get_origin(), Coord::new(2.0, 0.0, 0.0));
+        assert_eq!(replicated.calc_size(), Coord::new(3.0, 1.0, 2.0));
+        assert_positions_eq(&get_x_positions(&replicated),
+                            &[0.1, 0.6, 0.1, 0.6, 1.1, 1.6, 1.1, 1.6, 2.1, 2.6, 2.1, 2.6]);
+        assert_eq!(replicated.periodic_axes(), [true; 3]);
+
+        // The replication is kept when the configuration is re-derived
+        let mut rederived = replicated.clone();
+        rederived.rederive();
+        assert_eq!(rederived.num_atoms(), 12);
+
+        // Configurations cut to other sizes are not periodic along those axes
+        conf.reconstruct(ConfType::Cuboid {
+            origin: Coord::ORIGO,
+            size: Coord::new(0.5, 2.0, 1.0),
+        }).unwrap();
+        assert_eq!(conf.periodic_axes(), [false, true, true]);
+    }
 }
@@ -0,0 +1,467 @@
+//! Build a `System` from a recipe, without user interaction.
+//!
+//! A recipe is a JSON formatted file with the settings of a system and a list of steps
+//! which construct components from the definitions of a `DataBase` and edit them.
+//! Components are referred to by their index in the system, in the order in which
+//! they were constructed. A water slab above a sheet, centered in a box with
+//! vacuum padding along z:
+//!
+//! ```json
+//! {
+//!     "title": "Water on graphene",
+//!     "output_path": "water_on_graphene.gro",
+//!     "box_setting": { "Auto": { "padding": { "x": 0.0, "y": 0.0, "z": 4.0 } } },
+//!     "center": true,
+//!     "seed": 42,
+//!     "steps": [
+//!         { "Construct": { "name": "Graphene", "size": { "x": 5.0, "y": 5.0, "z": 0.0 } } },
+//!         { "Construct": {
+//!             "name": "Water",
+//!             "size": { "x": 5.0, "y": 5.0, "z": 3.0 },
+//!             "fill": { "Density": 33.4 }
+//!         } },
+//!         { "Translate": { "component": 1, "shift": { "x": 0.0, "y": 0.0, "z": 0.5 } } }
+//!     ]
+//! }
+//! ```
+
+use coord::{Coord, Periodic, Translate};
+use database::ComponentEntry;
+use error::{GrafenError, Result};
+use read_conf::{ConfType, ReadConf};
+use surface::LatticeType;
+use system::{BoxSetting, Component, System};
+use volume::{FillType, Volume};
+
+use serde_json;
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Debug, Deserialize)]
+/// Settings and steps to build a `System` with.
+pub struct Recipe {
+    /// Title of the system.
+    #[serde(default)]
+    pub title: Option<String>,
+    /// Path to the output configuration.
+    #[serde(default)]
+    pub output_path: Option<PathBuf>,
+    /// Path to the database of residue and component definitions.
+    #[serde(default)]
+    pub database: Option<PathBuf>,
+    /// Setting for the box size of the system.
+    #[serde(default)]
+    pub box_setting: Option<BoxSetting>,
+    /// Center the components in the box after all steps have been applied.
+    #[serde(default)]
+    pub center: bool,
+    /// Seed from which the seeds of stochastically constructed components are derived.
+    #[serde(default)]
+    pub seed: Option<u64>,
+    /// Steps which construct and edit the components of the system.
+    #[serde(default)]
+    pub steps: Vec<Step>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+/// A step of a recipe.
+pub enum Step {
+    /// Construct a component from its definition in the database.
+    Construct(Construct),
+    /// Translate a component.
+    Translate {
+        /// Index of the component in the system.
+        component: usize,
+        /// Translation of the component (nm).
+        shift: Coord,
+    },
+    /// Replicate a component into periodic images along the axes of its box.
+    Replicate {
+        /// Index of the component in the system.
+        component: usize,
+        /// Number of images along x.
+        nx: usize,
+        /// Number of images along y.
+        ny: usize,
+        /// Number of images along z.
+        nz: usize,
+    },
+}
+
+#[derive(Clone, Debug, Deserialize)]
+/// Parameters for constructing a component from its definition in the database.
+///
+/// Parameters which do not apply to the type of the component are ignored.
+pub struct Construct {
+    /// Name of the component definition, or the description of a configuration.
+    pub name: String,
+    /// Position of the component origin (nm).
+    #[serde(default)]
+    pub position: Coord,
+    /// Size of cuboids, the length and width of sheets, pores and bilayers, and
+    /// the wall height of pores (nm). A configuration is cut to a non-zero size.
+    #[serde(default)]
+    pub size: Coord,
+    /// Radius of cylinders and spheres, and the bottom radius of pores (nm).
+    #[serde(default)]
+    pub radius: f64,
+    /// Height of cylinders (nm).
+    #[serde(default)]
+    pub height: f64,
+    /// Radius at the top of pores (nm). Defaults to the bottom radius.
+    #[serde(default)]
+    pub top_radius: Option<f64>,
+    /// Number of residues of sheets with a blue noise lattice.
+    #[serde(default)]
+    pub number: Option<u64>,
+    /// How volumes are filled. Defaults to the density of the definition.
+    #[serde(default)]
+    pub fill: Option<FillType>,
+    /// Seed of a stochastically constructed component. Defaults to the seed of the
+    /// definition, or else one derived from the seed of the system.
+    #[serde(default)]
+    pub seed: Option<u64>,
+}
+
+impl Recipe {
+    /// Apply all steps of the recipe to a `System`, using the component definitions
+    /// of its database. The box setting and seed of the recipe replace those of the system.
+    ///
+    /// # Errors
+    /// Returns an error if a component definition is not found, could not be constructed
+    /// or if a step refers to a component which does not exist.
+    pub fn apply(&self, system: &mut System) -> Result<()> {
+        if let Some(box_setting) = self.box_setting {
+            system.box_setting = box_setting;
+        }
+
+        if self.seed.is_some() {
+            system.seed = self.seed;
+        }
+
+        for step in &self.steps {
+            match *step {
+                Step::Construct(ref construct) => {
+                    let component = construct.build(system)?;
+                    system.components.push(component);
+                },
+                Step::Translate { component, shift } => {
+                    get_component(system, component)?.translate_in_place(shift);
+                },
+                Step::Replicate { component, nx, ny, nz } => {
+                    if nx == 0 || ny == 0 || nz == 0 {
+                        return Err(GrafenError::RunError(
+                            "the number of images must be positive along every axis"
+                                .to_string()
+                        ));
+                    }
+
+                    let object = get_component(system, component)?;
+                    *object = object.pbc_multiply(nx, ny, nz);
+                },
+            }
+        }
+
+        if self.center {
+            system.center_components();
+        }
+
+        Ok(())
+    }
+}
+
+impl Construct {
+    /// Construct the component from its definition in the database of a `System`.
+    fn build(&self, system: &mut System) -> Result<ComponentEntry> {
+        let definition = system.database.component_defs
+            .iter()
+            .find(|entry| get_name(entry) == Some(&self.name))
+            .cloned()
+            .ok_or_else(|| GrafenError::RunError(
+                format!("no component definition named '{}' in the database", self.name)
+            ))?;
+
+        let derived_seed = system.component_seed();
+        let seed = |definition_seed: Option<u64>| self.seed.or(definition_seed).or(derived_seed);
+
+        let error = |err: GrafenError| {
+            GrafenError::RunError(format!("could not construct '{}': {}", self.name, err))
+        };
+
+        match definition {
+            ComponentEntry::VolumeCuboid(mut conf) => {
+                conf.seed = seed(conf.seed);
+                conf.origin = self.position;
+                conf.size = self.size;
+
+                let fill_type = self.get_fill_type(conf.density)?;
+                fill_type.check_residue(conf.residue.as_ref()).map_err(&error)?;
+
+                Ok(ComponentEntry::from(conf.fill(fill_type)))
+            },
+            ComponentEntry::VolumeCylinder(mut conf) => {
+                conf.seed = seed(conf.seed);
+                conf.origin = self.position;
+                conf.radius = self.radius;
+                conf.height = self.height;
+
+                let fill_type = self.get_fill_type(conf.density)?;
+                fill_type.check_residue(conf.residue.as_ref()).map_err(&error)?;
+
+                Ok(ComponentEntry::from(conf.fill(fill_type)))
+            },
+            ComponentEntry::VolumeComposite(mut conf) => {
+                conf.seed = seed(conf.seed);
+                conf.origin = self.position;
+
+                let fill_type = self.get_fill_type(conf.density)?;
+                fill_type.check_residue(conf.residue.as_ref()).map_err(&error)?;
+
+                Ok(ComponentEntry::from(conf.fill(fill_type)))
+            },
+            ComponentEntry::SurfaceSheet(mut conf) => {
+                conf.seed = seed(conf.seed);
+                conf.origin = self.position;
+                conf.length = self.size.x;
+                conf.width = self.size.y;
+
+                if let LatticeType::BlueNoise { ref mut number } = conf.lattice {
+                    *number = self.number.unwrap_or(*number);
+                }
+
+                Ok(ComponentEntry::from(conf.construct().map_err(&error)?).with_pbc())
+            },
+            ComponentEntry::SurfaceCuboid(mut conf) => {
+                conf.seed = seed(conf.seed);
+                conf.origin = self.position;
+                conf.size = self.size;
+
+                Ok(ComponentEntry::from(conf.construct().map_err(&error)?))
+            },
+            ComponentEntry::SurfaceCylinder(mut conf) => {
+                conf.seed = seed(conf.seed);
+                conf.origin = self.position;
+                conf.radius = self.radius;
+                conf.height = self.height;
+
+                Ok(ComponentEntry::from(conf.construct().map_err(&error)?))
+            },
+            ComponentEntry::SurfacePore(mut conf) => {
+                conf.seed = seed(conf.seed);
+                conf.origin = self.position;
+                conf.length = self.size.x;
+                conf.width = self.size.y;
+                conf.height = self.size.z;
+                conf.bottom_radius = self.radius;
+                conf.top_radius = self.top_radius.unwrap_or(self.radius);
+
+                Ok(ComponentEntry::from(conf.construct().map_err(&error)?))
+            },
+            ComponentEntry::SurfaceSphere(mut conf) => {
+                conf.origin = self.position;
+                conf.radius = self.radius;
+
+                Ok(ComponentEntry::from(conf.construct().map_err(&error)?))
+            },
+            ComponentEntry::SurfaceBilayer(mut conf) => {
+                conf.seed = seed(conf.seed);
+                conf.origin = self.position;
+                conf.length = self.size.x;
+                conf.width = self.size.y;
+
+                Ok(ComponentEntry::from(conf.construct().map_err(&error)?))
+            },
+            ComponentEntry::SurfaceGrafts(_) => Err(GrafenError::RunError(
+                "grafted chains cannot be constructed from a recipe".to_string()
+            )),
+            ComponentEntry::ConfigurationFile(conf) => {
+                let path = resolve_path(&conf.path, system.database.path.as_ref());
+                let mut new_conf = ReadConf::from_gromos87(&path).map_err(|err| {
+                    GrafenError::RunError(
+                        format!("could not read configuration at '{}' ({})", path.display(), err)
+                    )
+                })?;
+
+                new_conf.description = conf.description;
+
+                if self.size != Coord::ORIGO {
+                    let size = self.size;
+                    new_conf.reconstruct(ConfType::Cuboid { origin: Coord::ORIGO, size })
+                        .map_err(GrafenError::RunError)?;
+                }
+
+                let displayed_origin = new_conf.get_displayed_origin();
+                new_conf.translate_in_place(self.position - displayed_origin);
+
+                Ok(ComponentEntry::from(new_conf))
+            },
+        }
+    }
+
+    /// Return the fill type of a volume, or its default density if none is set.
+    fn get_fill_type(&self, density: Option<f64>) -> Result<FillType> {
+        self.fill
+            .or(density.map(FillType::Density))
+            .ok_or_else(|| GrafenError::RunError(
+                format!("no fill or default density is set for '{}'", self.name)
+            ))
+    }
+}
+
+/// Return the name of a component definition, or the description of a configuration.
+fn get_name(entry: &ComponentEntry) -> Option<&String> {
+    match *entry {
+        ComponentEntry::VolumeCuboid(ref conf) => conf.name.as_ref(),
+        ComponentEntry::VolumeCylinder(ref conf) => conf.name.as_ref(),
+        ComponentEntry::VolumeComposite(ref conf) => conf.name.as_ref(),
+        ComponentEntry::SurfaceSheet(ref conf) => conf.name.as_ref(),
+        ComponentEntry::SurfaceCuboid(ref conf) => conf.name.as_ref(),
+        ComponentEntry::SurfaceCylinder(ref conf) => conf.name.as_ref(),
+        ComponentEntry::SurfacePore(ref conf) => conf.name.as_ref(),
+        ComponentEntry::SurfaceSphere(ref conf) => conf.name.as_ref(),
+        ComponentEntry::SurfaceBilayer(ref conf) => conf.name.as_ref(),
+        ComponentEntry::SurfaceGrafts(ref conf) => conf.name.as_ref(),
+        ComponentEntry::ConfigurationFile(ref conf) => Some(&conf.description),
+    }
+}
+
+/// Return a component of the system by its index.
+fn get_component(system: &mut System, index: usize) -> Result<&mut ComponentEntry> {
+    let num_components = system.components.len();
+
+    system.components.get_mut(index).ok_or_else(|| GrafenError::RunError(format!(
+        "no component with index {} in a system of {} components", index, num_components
+    )))
+}
+
+/// Return the full path of a file in a database. A relative path is relative
+/// to the database location.
+fn resolve_path(path: &Path, database_path: Option<&PathBuf>) -> PathBuf {
+    if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        database_path
+            .and_then(|db_path| db_path.parent())
+            .map(|db_dir| db_dir.join(path))
+            .unwrap_or(path.to_path_buf())
+    }
+}
+
+/// Read a recipe from a JSON formatted reader.
+pub fn read_recipe<R: io::Read>(reader: R) -> Result<Recipe> {
+    serde_json::from_reader(reader)
+        .map_err(|err| GrafenError::RunError(format!("could not parse recipe ({})", err)))
+}
+
+/// Read a recipe from a file. A relative database path in it is relative
+/// to the location of the recipe.
+pub fn read_recipe_file(path: &Path) -> Result<Recipe> {
+    let file = File::open(path).map_err(|err| {
+        GrafenError::RunError(format!("could not open recipe '{}' ({})", path.display(), err))
+    })?;
+
+    let mut recipe = read_recipe(file)?;
+
+    if let Some(ref mut database) = recipe.database {
+        if let Some(dir) = path.parent() {
+            *database = dir.join(&database);
+        }
+    }
+
+    Ok(recipe)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use database::DataBase;
+    use system::{Atom, Residue};
+    use volume::Cuboid;
+
+    fn setup_system() -> System {
+        let mut database = DataBase::new();
+        database.component_defs.push(ComponentEntry::from(Cuboid {
+            name: Some("Water".to_string()),
+            residue: Some(resbase!["SOL", ("OW", 0.0, 0.0, 0.0)]),
+            density: Some(10.0),
+            .. Cuboid::default()
+        }));
+
+        System {
+            title: String::new(),
+            output_path: PathBuf::new(),
+            database,
+            components: vec![],
+            box_setting: BoxSetting::default(),
+            seed: None,
+            num_seeds: 0,
+        }
+    }
+
+    #[test]
+    fn recipe_constructs_and_edits_components_and_sets_the_box() {
+        let input = r#"{
+            "box_setting": { "Auto": { "padding": { "x": 0.0, "y": 0.0, "z": 2.0 } } },
+            "center": true,
+            "steps": [
+                { "Construct": { "name": "Water", "size": { "x": 2.0, "y": 2.0, "z": 1.0 } } },
+                { "Construct": {
+                    "name": "Water",
+                    "size": { "x": 2.0, "y": 2.0, "z": 1.0 },
+                    "fill": { "NumCoords": 5 }
+                } },
+                { "Translate": { "component": 1, "shift": { "x": 0.0, "y": 0.0, "z": 1.0 } } }
+            ]
+        }"#;
+
+        let recipe = read_recipe(input.as_bytes()).unwrap();
+        let mut system = setup_system();
+        recipe.apply(&mut system).unwrap();
+
+        assert_eq!(system.components.len(), 2);
+        assert_eq!(system.components[0].num_atoms(), 40);
+        assert_eq!(system.components[1].num_atoms(), 5);
+
+        // The combined extent of height 2 is centered in the padded box of height 4
+        assert_eq!(system.box_setting, BoxSetting::Fixed(Coord::new(2.0, 2.0, 4.0)));
+        assert_eq!(system.components[0].get_origin(), Coord::new(0.0, 0.0, 1.0));
+        assert_eq!(system.components[1].get_origin(), Coord::new(0.0, 0.0, 2.0));
+    }
+
+    #[test]
+    fn recipe_steps_with_unknown_definitions_or_components_are_errors() {
+        let mut system = setup_system();
+
+        let input = r#"{ "steps": [ { "Construct": { "name": "Ice" } } ] }"#;
+        assert!(read_recipe(input.as_bytes()).unwrap().apply(&mut system).is_err());
+
+        let input = r#"{ "steps": [
+            { "Translate": { "component": 0, "shift": { "x": 1.0, "y": 0.0, "z": 0.0 } } }
+        ] }"#;
+        assert!(read_recipe(input.as_bytes()).unwrap().apply(&mut system).is_err());
+    }
+
+    #[test]
+    fn recipe_replicates_components_into_images_along_the_box_axes() {
+        let input = r#"{ "steps": [
+            { "Construct": { "name": "Water", "size": { "x": 2.0, "y": 2.0, "z": 1.0 } } },
+            { "Replicate": { "component": 0, "nx": 2, "ny": 1, "nz": 3 } }
+        ] }"#;
+
+        let mut system = setup_system();
+        read_recipe(input.as_bytes()).unwrap().apply(&mut system).unwrap();
+
+        assert_eq!(system.components[0].num_atoms(), 6 * 40);
+        assert_eq!(system.components[0].box_size(), Coord::new(4.0, 2.0, 3.0));
+
+        // Every axis needs at least one image
+        let input = r#"{ "steps": [
+            { "Construct": { "name": "Water", "size": { "x": 2.0, "y": 2.0, "z": 1.0 } } },
+            { "Replicate": { "component": 0, "nx": 0, "ny": 1, "nz": 1 } }
+        ] }"#;
+
+        let mut system = setup_system();
+        assert!(read_recipe(input.as_bytes()).unwrap().apply(&mut system).is_err());
+    }
+}
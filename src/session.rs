@@ -0,0 +1,364 @@
+//! Save and restore the full state of a `System`.
+//!
+//! Component definitions in a `DataBase` only contain the parameters used to construct
+//! them. Their runtime state (origin, size, constructed coordinates, recorded edits etc.)
+//! is never part of a definition. A session stores it separately for every component,
+//! next to the definition, and sets it again when the session is restored.
+//!
+//! Read configurations are not stored in the session. They are read again from their
+//! path when the session is restored and their recorded operations are re-applied.
+//! The path is stored as an absolute path, so that the session can be restored
+//! from any directory.
+
+use database::{ComponentEntry, DataBase};
+use read_conf::ReadConf;
+use surface;
+use system::{BoxSetting, System};
+use volume;
+
+use serde_json;
+use serde_json::{Map, Value};
+use std::env;
+use std::fs::File;
+use std::io;
+use std::mem;
+use std::path::{Path, PathBuf};
+
+/// Get and set the runtime state of a component.
+trait ComponentState {
+    /// Return the runtime state fields as a JSON object.
+    fn get_state(&self) -> Result<Value, serde_json::Error>;
+
+    /// Set the runtime state fields from a JSON object. Missing fields are left as is.
+    fn set_state(&mut self, state: Value) -> Result<(), serde_json::Error>;
+}
+
+/// Implement `ComponentState` for components with their list of runtime state fields,
+/// and for the `ComponentEntry` variants which wrap them.
+macro_rules! impl_component_state {
+    ( $( ($class:path => $entry:ident) [ $( $field:ident ),+ ] ),+ ) => {
+        $(
+            impl ComponentState for $class {
+                fn get_state(&self) -> Result<Value, serde_json::Error> {
+                    let mut state = Map::new();
+
+                    $(
+                        state.insert(stringify!($field).to_string(),
+                                     serde_json::to_value(&self.$field)?);
+                    )+
+
+                    Ok(Value::Object(state))
+                }
+
+                fn set_state(&mut self, mut state: Value) -> Result<(), serde_json::Error> {
+                    $(
+                        if let Some(value) = state.get_mut(stringify!($field)) {
+                            self.$field = serde_json::from_value(mem::replace(value, Value::Null))?;
+                        }
+                    )+
+
+                    Ok(())
+                }
+            }
+        )+
+
+        impl ComponentState for ComponentEntry {
+            fn get_state(&self) -> Result<Value, serde_json::Error> {
+                match self {
+                    $( &ComponentEntry::$entry(ref object) => object.get_state(), )+
+                }
+            }
+
+            fn set_state(&mut self, state: Value) -> Result<(), serde_json::Error> {
+                match self {
+                    $( &mut ComponentEntry::$entry(ref mut object) => object.set_state(state), )+
+                }
+            }
+        }
+    }
+}
+impl_component_state![
+    (volume::Cuboid => VolumeCuboid) [origin, size, coords, cell, frame],
+    (volume::Cylinder => VolumeCylinder) [origin, radius, height, coords, cell, frame],
+    (volume::Composite => VolumeComposite) [origin, coords, cell, frame],
+    (surface::Sheet => SurfaceSheet)
+        [origin, length, width, coords, orientations, normals, cell, frame],
+    (surface::Cuboid => SurfaceCuboid) [origin, size, coords, cell, frame, images],
+    (surface::Cylinder => SurfaceCylinder)
+        [origin, radius, height, coords, orientations, cell, frame, images],
+    (surface::Pore => SurfacePore) [origin, length, width, height, bottom_radius, top_radius,
+                                    coords, orientations, cell, frame, images],
+    (surface::Sphere => SurfaceSphere)
+        [origin, radius, coords, orientations, cell, frame, images],
+    (surface::Bilayer => SurfaceBilayer) [origin, length, width, height, residues],
+    (surface::GraftedChains => SurfaceGrafts) [origin, size, residues],
+    (ReadConf => ConfigurationFile) [path, operations]
+];
+
+#[derive(Serialize)]
+struct ComponentRef<'a> {
+    definition: &'a ComponentEntry,
+    state: Value,
+}
+
+#[derive(Deserialize)]
+struct SessionComponent {
+    definition: ComponentEntry,
+    #[serde(default)]
+    state: Value,
+}
+
+#[derive(Serialize)]
+struct SessionRef<'a> {
+    title: &'a str,
+    output_path: &'a Path,
+    database_path: Option<&'a PathBuf>,
+    database: &'a DataBase,
+    box_setting: BoxSetting,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<u64>,
+    num_seeds: u64,
+    components: Vec<ComponentRef<'a>>,
+}
+
+#[derive(Deserialize)]
+struct Session {
+    title: String,
+    output_path: PathBuf,
+    database_path: Option<PathBuf>,
+    database: DataBase,
+    #[serde(default)]
+    box_setting: BoxSetting,
+    #[serde(default)]
+    seed: Option<u64>,
+    #[serde(default)]
+    num_seeds: u64,
+    components: Vec<SessionComponent>,
+}
+
+/// Write a full `System` as a JSON formatted session to an input writer.
+pub fn write_session<W: io::Write>(system: &System, writer: &mut W) -> Result<(), io::Error> {
+    let mut components = Vec::with_capacity(system.components.len());
+
+    for component in &system.components {
+        let mut state = component.get_state()?;
+
+        if let ComponentEntry::ConfigurationFile(ref conf) = *component {
+            let path = env::current_dir()?.join(&conf.path);
+            state["path"] = serde_json::to_value(&path)?;
+        }
+
+        components.push(ComponentRef { definition: component, state });
+    }
+
+    let session = SessionRef {
+        title: &system.title,
+        output_path: &system.output_path,
+        database_path: system.database.path.as_ref(),
+        database: &system.database,
+        box_setting: system.box_setting,
+        seed: system.seed,
+        num_seeds: system.num_seeds,
+        components,
+    };
+
+    serde_json::to_writer(writer, &session).map_err(io::Error::from)
+}
+
+/// Read a `System` from a JSON formatted session.
+///
+/// Read configurations are read from their paths and their recorded edits are re-applied.
+///
+/// # Errors
+/// Returns an error if the session could not be parsed or if a configuration
+/// file is missing or could not be read.
+pub fn read_session<R: io::Read>(reader: R) -> Result<System, io::Error> {
+    let session: Session = serde_json::from_reader(reader).map_err(io::Error::from)?;
+
+    let mut database = session.database;
+    database.path = session.database_path;
+
+    let mut components = Vec::with_capacity(session.components.len());
+
+    for SessionComponent { definition: mut component, state } in session.components {
+        component.set_state(state)?;
+
+        if let ComponentEntry::ConfigurationFile(ref mut conf) = component {
+            if !conf.path.is_file() {
+                return Err(io::Error::new(io::ErrorKind::NotFound, format!(
+                    "the configuration file '{}' of a component was not found",
+                    conf.path.display()
+                )));
+            }
+
+            conf.reload().map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+        }
+
+        components.push(component);
+    }
+
+    Ok(System {
+        title: session.title,
+        output_path: session.output_path,
+        database,
+        components,
+        box_setting: session.box_setting,
+        seed: session.seed,
+        num_seeds: session.num_seeds,
+    })
+}
+
+/// Write a session file of a `System` to a path.
+pub fn write_session_file(system: &System, path: &Path) -> Result<(), io::Error> {
+    let mut file = File::create(path)?;
+    write_session(system, &mut file)
+}
+
+/// Read a `System` from a session file.
+pub fn read_session_file(path: &Path) -> Result<System, io::Error> {
+    let file = File::open(path)?;
+    read_session(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use coord::{Coord, Direction};
+    use read_conf::ConfType;
+    use surface::{LatticeType, Sheet};
+    use system::{Atom, Component, Residue};
+    use volume::Cuboid;
+
+    #[test]
+    fn component_state_is_only_serialized_in_sessions() {
+        let cuboid = Cuboid {
+            name: Some("Cuboid".to_string()),
+            origin: Coord::new(1.0, 2.0, 3.0),
+            size: Coord::new(4.0, 5.0, 6.0),
+            coords: vec![Coord::new(0.5, 0.5, 0.5)],
+            .. Cuboid::default()
+        };
+
+        let definition = serde_json::to_string(&cuboid).unwrap();
+        assert!(!definition.contains("origin"));
+        assert!(!definition.contains("coords"));
+
+        let system = System {
+            title: "A title".to_string(),
+            output_path: PathBuf::from("conf.gro"),
+            database: DataBase::new(),
+            components: vec![ComponentEntry::from(cuboid)],
+            box_setting: BoxSetting::Fixed(Coord::new(10.0, 10.0, 10.0)),
+            seed: Some(42),
+            num_seeds: 0,
+        };
+
+        let mut buffer = Vec::new();
+        write_session(&system, &mut buffer).unwrap();
+
+        // The state is kept out of the definitions in the session
+        let session: Value = serde_json::from_slice(&buffer).unwrap();
+        let stored = &session["components"][0];
+        assert!(stored["definition"]["VolumeCuboid"].get("origin").is_none());
+        assert!(stored["state"].get("origin").is_some());
+
+        let restored = read_session(buffer.as_slice()).unwrap();
+
+        assert_eq!(restored.title, system.title);
+        assert_eq!(restored.output_path, system.output_path);
+        assert_eq!(restored.box_setting, system.box_setting);
+        assert_eq!(restored.seed, Some(42));
+        assert_eq!(restored.components.len(), 1);
+
+        match restored.components[0] {
+            ComponentEntry::VolumeCuboid(ref restored_cuboid) => {
+                assert_eq!(restored_cuboid.origin, Coord::new(1.0, 2.0, 3.0));
+                assert_eq!(restored_cuboid.size, Coord::new(4.0, 5.0, 6.0));
+                assert_eq!(restored_cuboid.coords, vec![Coord::new(0.5, 0.5, 0.5)]);
+            },
+            _ => panic!("the restored component is not a cuboid"),
+        }
+    }
+
+    #[test]
+    fn constructed_sheets_are_restored_from_sessions() {
+        let sheet = Sheet {
+            name: None,
+            residue: Some(resbase!["RES", ("A", 0.0, 0.0, 0.0)]),
+            lattice: LatticeType::Triclinic { a: 1.0, b: 1.0, gamma: 60.0 },
+            std_z: None,
+            roughness: None,
+            orient_residues: false,
+            seed: None,
+            origin: Coord::new(0.0, 0.0, 5.0),
+            normal: Direction::Z,
+            length: 4.0,
+            width: 3.0,
+            coords: vec![],
+            orientations: vec![],
+            normals: vec![],
+            cell: None,
+            frame: None,
+        }.construct().unwrap();
+
+        let mut database = DataBase::new();
+        database.path = Some(PathBuf::from("database.json"));
+
+        let system = System {
+            title: String::new(),
+            output_path: PathBuf::new(),
+            database,
+            components: vec![ComponentEntry::from(sheet.clone())],
+            box_setting: BoxSetting::default(),
+            seed: None,
+            num_seeds: 0,
+        };
+
+        let mut buffer = Vec::new();
+        write_session(&system, &mut buffer).unwrap();
+        let restored = read_session(buffer.as_slice()).unwrap();
+
+        assert_eq!(restored.database.path, system.database.path);
+        assert_eq!(restored.num_atoms(), system.num_atoms());
+        assert_eq!(restored.box_vectors(), system.box_vectors());
+        assert_eq!(restored.components[0].get_origin(), sheet.origin);
+    }
+
+    #[test]
+    fn configuration_paths_are_stored_as_absolute_paths_and_must_exist() {
+        let path = PathBuf::from("not_a_configuration_in_this_directory.gro");
+
+        let conf = ReadConf {
+            conf: None,
+            backup_conf: None,
+            operations: vec![],
+            path: path.clone(),
+            description: String::new(),
+            volume_type: ConfType::Cuboid { origin: Coord::ORIGO, size: Coord::ORIGO },
+        };
+
+        let system = System {
+            title: String::new(),
+            output_path: PathBuf::new(),
+            database: DataBase::new(),
+            components: vec![ComponentEntry::from(conf)],
+            box_setting: BoxSetting::default(),
+            seed: None,
+            num_seeds: 0,
+        };
+
+        let mut buffer = Vec::new();
+        write_session(&system, &mut buffer).unwrap();
+
+        let session: Value = serde_json::from_slice(&buffer).unwrap();
+        let stored_path: PathBuf =
+            serde_json::from_value(session["components"][0]["state"]["path"].clone()).unwrap();
+
+        assert!(stored_path.is_absolute());
+        assert!(stored_path.ends_with(&path));
+
+        let err = read_session(buffer.as_slice()).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert!(err.to_string().contains(&stored_path.display().to_string()));
+    }
+}
@@ -0,0 +1,509 @@
+//! Index coordinates in space for fast neighbour searches.
+//!
+//! Coordinates are sorted into the cells of a grid. A search around a position
+//! then only has to compare it to the coordinates in the cells close to it,
+//! which makes constructing and querying the index scale linearly with the
+//! number of coordinates.
+
+use coord::{calc_extent, BoxVectors, Coord};
+
+/// Maximum number of cells per indexed coordinate, to bound the memory use for sparse sets.
+const MAX_CELLS_PER_COORD: usize = 2;
+
+/// Maximum number of cells in an index which is created without coordinates.
+const MAX_EMPTY_CELLS: usize = 1 << 20;
+
+/// A spatial index of coordinates sorted into cells.
+///
+/// The grid either spans a bounded region or a periodic box. In a periodic box distances
+/// are measured between the closest periodic images (see `BoxVectors::minimum_image`),
+/// and box vectors which are zero are not periodic, which is used for two-dimensional
+/// boxes. In a bounded region coordinates may still be added outside of the region,
+/// but searches around them are slower.
+///
+/// # Examples
+/// ```
+/// # use grafen::coord::{BoxVectors, Coord};
+/// # use grafen::spatial::CellList;
+/// let coords = vec![Coord::new(0.5, 0.5, 0.5), Coord::new(9.8, 0.5, 0.5)];
+/// let box_vectors = BoxVectors::rectangular(Coord::new(10.0, 10.0, 10.0));
+/// let cell_list = CellList::new(&coords, 1.0, Some(box_vectors));
+///
+/// assert_eq!(cell_list.within(Coord::new(0.1, 0.5, 0.5), 0.5), vec![0, 1]);
+///
+/// let (index, distance) = cell_list.nearest(Coord::new(9.0, 0.5, 0.5)).unwrap();
+/// assert_eq!(index, 1);
+/// assert!((distance - 0.8).abs() < 1e-9);
+/// ```
+#[derive(Clone, Debug)]
+pub struct CellList {
+    /// Indices of coordinates in each cell.
+    cells: Vec<Vec<usize>>,
+    coords: Vec<Coord>,
+    shape: (usize, usize, usize),
+    /// Widths of the cells along each direction (zero for a direction with a single cell).
+    widths: Coord,
+    grid: Grid,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Grid {
+    /// Cells span a region with the input lower corner.
+    Bounded { origin: Coord, cell_size: Coord },
+    /// Cells span the fractional coordinates of a periodic box.
+    Periodic(BoxVectors),
+}
+
+impl CellList {
+    /// Create an index of input coordinates with cells of at least the input size,
+    /// which should be set to the typical search radius.
+    ///
+    /// If box vectors are given the grid spans the periodic box, which has its lower
+    /// corner at the origin. Otherwise it spans the box which encloses all coordinates.
+    pub fn new(coords: &[Coord], cutoff: f64, box_vectors: Option<BoxVectors>) -> CellList {
+        let max_cells = MAX_CELLS_PER_COORD * coords.len().max(1);
+
+        let mut cell_list = match box_vectors {
+            Some(vectors) => CellList::new_periodic(vectors, cutoff, max_cells),
+            None => {
+                let (min, max) = calc_extent(coords).unwrap_or((Coord::ORIGO, Coord::ORIGO));
+                CellList::new_bounded(min, max, cutoff, max_cells)
+            },
+        };
+
+        cell_list.coords.reserve(coords.len());
+        coords.iter().for_each(|&coord| { cell_list.insert(coord); });
+
+        cell_list
+    }
+
+    /// Create an empty index for coordinates inside the region between the lower
+    /// and upper corners, with cells of at least the input size.
+    pub fn with_bounds(lower: Coord, upper: Coord, cutoff: f64) -> CellList {
+        CellList::new_bounded(lower, upper, cutoff, MAX_EMPTY_CELLS)
+    }
+
+    /// Create an empty index for coordinates in a periodic box, with cells of at least
+    /// the input size.
+    pub fn periodic(box_vectors: BoxVectors, cutoff: f64) -> CellList {
+        CellList::new_periodic(box_vectors, cutoff, MAX_EMPTY_CELLS)
+    }
+
+    /// Add a coordinate to the index and return its index.
+    ///
+    /// In a periodic box the coordinate is wrapped into the box.
+    pub fn insert(&mut self, coord: Coord) -> usize {
+        let coord = match self.grid {
+            Grid::Periodic(vectors) => vectors.wrap(coord),
+            Grid::Bounded { .. } => coord,
+        };
+
+        let (ix, iy, iz) = self.cell_index(coord);
+        let cell = self.flat_index(ix, iy, iz);
+        let index = self.coords.len();
+
+        self.cells[cell].push(index);
+        self.coords.push(coord);
+
+        index
+    }
+
+    /// Return the indexed coordinates in the order they were added.
+    ///
+    /// In a periodic box these have been wrapped into the box.
+    pub fn coords(&self) -> &[Coord] {
+        &self.coords
+    }
+
+    /// Return the number of indexed coordinates.
+    pub fn len(&self) -> usize {
+        self.coords.len()
+    }
+
+    /// Return whether the index is empty.
+    pub fn is_empty(&self) -> bool {
+        self.coords.is_empty()
+    }
+
+    /// Return the indices of all coordinates within a radius of a position, in increasing order.
+    pub fn within(&self, coord: Coord, radius: f64) -> Vec<usize> {
+        let coord = self.prepare_query(coord);
+        let radius_sq = radius * radius;
+
+        let mut indices = self.candidates(coord, radius)
+            .filter(|&i| self.distance_sq(coord, self.coords[i]) <= radius_sq)
+            .collect::<Vec<_>>();
+
+        indices.sort();
+        indices
+    }
+
+    /// Return whether any coordinate lies within a radius of a position.
+    pub fn has_neighbour_within(&self, coord: Coord, radius: f64) -> bool {
+        let coord = self.prepare_query(coord);
+        let radius_sq = radius * radius;
+
+        self.candidates(coord, radius)
+            .any(|i| self.distance_sq(coord, self.coords[i]) <= radius_sq)
+    }
+
+    /// Return the index of and distance to the coordinate closest to a position,
+    /// or `None` if the index is empty.
+    pub fn nearest(&self, coord: Coord) -> Option<(usize, f64)> {
+        if self.coords.is_empty() {
+            return None;
+        }
+
+        let coord = self.prepare_query(coord);
+        let min_width = [self.widths.x, self.widths.y, self.widths.z]
+            .iter()
+            .cloned()
+            .filter(|&w| w > 0.0)
+            .fold(::std::f64::MAX, f64::min);
+
+        // Search in increasingly large spheres until a coordinate is found
+        let mut radius = min_width;
+
+        while min_width < ::std::f64::MAX && !self.covers_all_cells(radius) {
+            let radius_sq = radius * radius;
+
+            let closest = self.candidates(coord, radius)
+                .map(|i| (i, self.distance_sq(coord, self.coords[i])))
+                .filter(|&(_, dist_sq)| dist_sq <= radius_sq)
+                .fold(None, min_by_distance);
+
+            if let Some((i, dist_sq)) = closest {
+                return Some((i, dist_sq.sqrt()));
+            }
+
+            radius *= 2.0;
+        }
+
+        (0..self.coords.len())
+            .map(|i| (i, self.distance_sq(coord, self.coords[i])))
+            .fold(None, min_by_distance)
+            .map(|(i, dist_sq)| (i, dist_sq.sqrt()))
+    }
+
+    fn new_bounded(lower: Coord, upper: Coord, cutoff: f64, max_cells: usize) -> CellList {
+        let lengths = Coord::new(
+            (upper.x - lower.x).max(0.0), (upper.y - lower.y).max(0.0), (upper.z - lower.z).max(0.0)
+        );
+
+        let shape = calc_shape(lengths, cutoff, max_cells);
+        let cell_size = Coord::new(
+            lengths.x / shape.0 as f64,
+            lengths.y / shape.1 as f64,
+            lengths.z / shape.2 as f64
+        );
+
+        CellList::empty(Grid::Bounded { origin: lower, cell_size }, shape, cell_size)
+    }
+
+    fn new_periodic(box_vectors: BoxVectors, cutoff: f64, max_cells: usize) -> CellList {
+        let heights = calc_periodic_heights(&box_vectors);
+        let shape = calc_shape(heights, cutoff, max_cells);
+
+        let widths = Coord::new(
+            heights.x / shape.0 as f64,
+            heights.y / shape.1 as f64,
+            heights.z / shape.2 as f64
+        );
+
+        CellList::empty(Grid::Periodic(box_vectors), shape, widths)
+    }
+
+    fn empty(grid: Grid, shape: (usize, usize, usize), widths: Coord) -> CellList {
+        CellList {
+            cells: vec![Vec::new(); shape.0 * shape.1 * shape.2],
+            coords: Vec::new(),
+            shape,
+            widths,
+            grid,
+        }
+    }
+
+    /// Wrap a query position into a periodic box.
+    fn prepare_query(&self, coord: Coord) -> Coord {
+        match self.grid {
+            Grid::Periodic(vectors) => vectors.wrap(coord),
+            Grid::Bounded { .. } => coord,
+        }
+    }
+
+    fn distance_sq(&self, coord: Coord, other: Coord) -> f64 {
+        let delta = match self.grid {
+            Grid::Periodic(vectors) => vectors.minimum_image(other - coord),
+            Grid::Bounded { .. } => other - coord,
+        };
+
+        delta.dot(delta)
+    }
+
+    /// Return the indices of all coordinates in the cells which may hold coordinates
+    /// within the radius of a (prepared) position.
+    fn candidates<'a>(&'a self, coord: Coord, radius: f64) -> Box<Iterator<Item=usize> + 'a> {
+        let (ix, iy, iz) = self.cell_index(coord);
+        let (nx, ny, nz) = self.shape;
+
+        let xs = self.cell_range(ix, nx, self.num_cells_within(radius, self.widths.x));
+        let ys = self.cell_range(iy, ny, self.num_cells_within(radius, self.widths.y));
+        let zs = self.cell_range(iz, nz, self.num_cells_within(radius, self.widths.z));
+
+        let mut cells = Vec::with_capacity(xs.len() * ys.len() * zs.len());
+
+        for &z in &zs {
+            for &y in &ys {
+                for &x in &xs {
+                    cells.push(self.flat_index(x, y, z));
+                }
+            }
+        }
+
+        Box::new(cells.into_iter().flat_map(move |cell| self.cells[cell].iter().cloned()))
+    }
+
+    /// Return whether a search of the input radius includes all cells.
+    fn covers_all_cells(&self, radius: f64) -> bool {
+        let (nx, ny, nz) = self.shape;
+
+        [(nx, self.widths.x), (ny, self.widths.y), (nz, self.widths.z)]
+            .iter()
+            .all(|&(n, width)| 2 * self.num_cells_within(radius, width) + 1 >= n)
+    }
+
+    /// Return the number of cells along a direction which may hold coordinates
+    /// within the radius.
+    fn num_cells_within(&self, radius: f64, width: f64) -> usize {
+        if width > 0.0 {
+            // Bounded to not overflow for large radii
+            (radius / width).ceil().min(self.cells.len() as f64) as usize
+        } else {
+            0
+        }
+    }
+
+    /// Return the indices of cells within a number of cells from an index along a direction.
+    fn cell_range(&self, i: usize, n: usize, num: usize) -> Vec<usize> {
+        match self.grid {
+            Grid::Periodic(_) if 2 * num + 1 >= n => (0..n).collect(),
+            Grid::Periodic(_) => (0..(2 * num + 1)).map(|j| (i + n + j - num) % n).collect(),
+            Grid::Bounded { .. } => {
+                (i.saturating_sub(num)..(i + num + 1).min(n)).collect()
+            },
+        }
+    }
+
+    /// Return the cell index along each direction of a position.
+    /// Positions outside of the grid are put in the closest cell.
+    fn cell_index(&self, coord: Coord) -> (usize, usize, usize) {
+        let (nx, ny, nz) = self.shape;
+        let frac = |v: f64, size: f64| if size > 0.0 { v / size } else { 0.0 };
+
+        let (fx, fy, fz) = match self.grid {
+            Grid::Bounded { origin, cell_size } => {
+                let r = coord - origin;
+                (frac(r.x, cell_size.x), frac(r.y, cell_size.y), frac(r.z, cell_size.z))
+            },
+            Grid::Periodic(vectors) => {
+                // Box vectors are lower triangular, so fractional coordinates
+                // are found by back substitution. Positions wrapped into the (rectangular)
+                // box can still lie outside of the unit cell, so fractions are wrapped too.
+                let sz = frac(coord.z, vectors.c.z);
+                let sy = frac(coord.y - sz * vectors.c.y, vectors.b.y);
+                let sx = frac(coord.x - sy * vectors.b.x - sz * vectors.c.x, vectors.a.x);
+                let unit = |s: f64| s - s.floor();
+
+                (unit(sx) * nx as f64, unit(sy) * ny as f64, unit(sz) * nz as f64)
+            },
+        };
+
+        let clamp = |f: f64, n: usize| {
+            if f > 0.0 { (f.floor().min((n - 1) as f64)) as usize } else { 0 }
+        };
+
+        (clamp(fx, nx), clamp(fy, ny), clamp(fz, nz))
+    }
+
+    fn flat_index(&self, ix: usize, iy: usize, iz: usize) -> usize {
+        let (nx, ny, _) = self.shape;
+        ix + nx * (iy + ny * iz)
+    }
+}
+
+fn min_by_distance(acc: Option<(usize, f64)>, (i, dist_sq): (usize, f64)) -> Option<(usize, f64)> {
+    match acc {
+        Some((_, min_sq)) if min_sq <= dist_sq => acc,
+        _ => Some((i, dist_sq)),
+    }
+}
+
+/// Return the perpendicular heights of a periodic box, with zero heights along
+/// directions which are not periodic.
+fn calc_periodic_heights(box_vectors: &BoxVectors) -> Coord {
+    let (a, b, c) = (box_vectors.a, box_vectors.b, box_vectors.c);
+
+    // Replace vectors which are not periodic by unit vectors, which for lower triangular
+    // boxes are perpendicular to the others and do not change their heights
+    let vectors = BoxVectors {
+        a: if a.x > 0.0 { a } else { Coord::new(1.0, 0.0, 0.0) },
+        b: if b.y > 0.0 { b } else { Coord::new(0.0, 1.0, 0.0) },
+        c: if c.z > 0.0 { c } else { Coord::new(0.0, 0.0, 1.0) },
+    };
+
+    let heights = vectors.heights();
+
+    Coord::new(
+        if a.x > 0.0 { heights.x } else { 0.0 },
+        if b.y > 0.0 { heights.y } else { 0.0 },
+        if c.z > 0.0 { heights.z } else { 0.0 }
+    )
+}
+
+/// Calculate the number of cells along each direction such that no cell is smaller than
+/// the cutoff, with the total number of cells not exceeding the input maximum.
+fn calc_shape(lengths: Coord, cutoff: f64, max_cells: usize) -> (usize, usize, usize) {
+    let cutoff = cutoff.max(::std::f64::EPSILON);
+    let num_cells = |length: f64| {
+        (length / cutoff).floor().min(max_cells as f64).max(1.0) as usize
+    };
+
+    let mut shape = [num_cells(lengths.x), num_cells(lengths.y), num_cells(lengths.z)];
+
+    while shape[0].saturating_mul(shape[1]).saturating_mul(shape[2]) > max_cells.max(1) {
+        let (i, _) = shape.iter().enumerate().max_by_key(|&(_, &n)| n).unwrap();
+        shape[i] = (shape[i] / 2).max(1);
+    }
+
+    (shape[0], shape[1], shape[2])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Return pseudo-random coordinates from a linear congruential generator.
+    fn gen_coords(num: usize, size: Coord, seed: u64) -> Vec<Coord> {
+        let mut state = seed;
+        let mut next = || {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (state >> 11) as f64 / (1u64 << 53) as f64
+        };
+
+        (0..num)
+            .map(|_| Coord::new(next() * size.x, next() * size.y, next() * size.z))
+            .collect()
+    }
+
+    fn brute_force_within(coords: &[Coord], coord: Coord, radius: f64,
+                          box_vectors: Option<BoxVectors>) -> Vec<usize> {
+        coords.iter()
+            .enumerate()
+            .filter(|&(_, &other)| {
+                let delta = match box_vectors {
+                    Some(vectors) => vectors.minimum_image(other - coord),
+                    None => other - coord,
+                };
+
+                delta.norm() <= radius
+            })
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    #[test]
+    fn radius_searches_match_brute_force_searches() {
+        let size = Coord::new(10.0, 8.0, 6.0);
+        let coords = gen_coords(400, size, 1);
+        let queries = gen_coords(100, size, 2);
+
+        let triclinic = BoxVectors {
+            a: Coord::new(10.0, 0.0, 0.0),
+            b: Coord::new(3.0, 8.0, 0.0),
+            c: Coord::new(-2.0, 1.0, 6.0),
+        };
+
+        for &box_vectors in &[None, Some(BoxVectors::rectangular(size)), Some(triclinic)] {
+            let cell_list = CellList::new(&coords, 0.7, box_vectors);
+            let wrapped = cell_list.coords().to_vec();
+
+            // Also search with a radius larger than the cells
+            for &radius in &[0.7, 1.9] {
+                for &query in &queries {
+                    assert_eq!(
+                        cell_list.within(query, radius),
+                        brute_force_within(&wrapped, query, radius, box_vectors)
+                    );
+                    assert_eq!(
+                        cell_list.has_neighbour_within(query, radius),
+                        !brute_force_within(&wrapped, query, radius, box_vectors).is_empty()
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn nearest_neighbour_searches_match_brute_force_searches() {
+        let size = Coord::new(10.0, 8.0, 6.0);
+        let coords = gen_coords(50, size, 3);
+
+        // Include queries far outside of the region
+        let mut queries = gen_coords(50, size, 4);
+        queries.push(Coord::new(-50.0, 30.0, 100.0));
+
+        for &box_vectors in &[None, Some(BoxVectors::rectangular(size))] {
+            let cell_list = CellList::new(&coords, 0.5, box_vectors);
+
+            for &query in &queries {
+                let (index, distance) = cell_list.nearest(query).unwrap();
+
+                let expected = cell_list.coords()
+                    .iter()
+                    .map(|&c| {
+                        let delta = match box_vectors {
+                            Some(vectors) => vectors.minimum_image(c - vectors.wrap(query)),
+                            None => c - query,
+                        };
+
+                        delta.norm()
+                    })
+                    .fold(::std::f64::MAX, f64::min);
+
+                assert!((distance - expected).abs() < 1e-9);
+                assert!(cell_list.within(query, distance + 1e-9).contains(&index));
+            }
+        }
+
+        assert!(CellList::new(&[], 0.5, None).nearest(Coord::ORIGO).is_none());
+    }
+
+    #[test]
+    fn coordinates_inserted_outside_of_the_bounds_are_found() {
+        let mut cell_list = CellList::with_bounds(Coord::ORIGO, Coord::new(5.0, 5.0, 5.0), 1.0);
+        assert!(cell_list.is_empty());
+
+        assert_eq!(cell_list.insert(Coord::new(1.0, 1.0, 1.0)), 0);
+        assert_eq!(cell_list.insert(Coord::new(-3.0, 1.0, 1.0)), 1);
+        assert_eq!(cell_list.insert(Coord::new(12.0, 1.0, 1.0)), 2);
+        assert_eq!(cell_list.len(), 3);
+
+        assert_eq!(cell_list.within(Coord::new(-2.5, 1.0, 1.0), 1.0), vec![1]);
+        assert_eq!(cell_list.within(Coord::new(11.5, 1.0, 1.0), 1.0), vec![2]);
+        assert!(!cell_list.has_neighbour_within(Coord::new(7.0, 1.0, 1.0), 1.0));
+        assert_eq!(cell_list.within(Coord::new(1.0, 1.0, 1.0), 20.0), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn two_dimensional_periodic_boxes_are_only_periodic_in_the_plane() {
+        let box_vectors = BoxVectors::rectangular(Coord::new(4.0, 4.0, 0.0));
+        let mut cell_list = CellList::periodic(box_vectors, 0.5);
+
+        cell_list.insert(Coord::new(0.1, 2.0, 0.0));
+        cell_list.insert(Coord::new(2.0, 2.0, 3.0));
+
+        assert_eq!(cell_list.within(Coord::new(3.9, 2.0, 0.0), 0.3), vec![0]);
+        assert_eq!(cell_list.within(Coord::new(2.0, 2.0, -0.5), 1.0), Vec::<usize>::new());
+        assert_eq!(cell_list.within(Coord::new(2.0, 2.0, 2.5), 1.0), vec![1]);
+    }
+}
@@ -0,0 +1,567 @@
+//! Construct lipid bilayers.
+
+use surface::distribution::Distribution;
+use surface::lattice::Lattice;
+use surface::points::Points;
+
+use coord::{Coord, Periodic, Rotate, Rotation, Translate, align_coords};
+use describe::{unwrap_name, Describe};
+use error::{GrafenError, Result};
+use iterator::{ResidueIter, ResidueIterOut};
+use random::{describe_seed, rng_from_seed, seed_or_random};
+use system::*;
+
+use rand::Rng;
+use rand::distributions::{IndependentSample, Range};
+use std::f64::consts::PI;
+
+/// Spacing between the tail atoms of the two leaflets (nm).
+const LEAFLET_SPACING: f64 = 0.2;
+
+impl_translate![Bilayer];
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+/// A lipid type of a bilayer with its composition in the two leaflets.
+pub struct Lipid {
+    /// Lipid residue.
+    pub residue: Residue,
+    /// Index of the head atom in the residue.
+    pub head: usize,
+    /// Index of the tail atom in the residue. The lipid axis points from the tail to the head.
+    pub tail: usize,
+    /// Relative amount of the lipid in the upper leaflet.
+    pub upper: f64,
+    /// Relative amount of the lipid in the lower leaflet.
+    pub lower: f64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+/// How lipids are distributed in the leaflets.
+pub enum LipidPacking {
+    /// A Poisson disc distribution at the density of the area per lipid.
+    PoissonDisc,
+    /// A hexagonal close packed lattice with the area per lipid.
+    Hexagonal,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+/// A bilayer of lipids with its normal along z.
+///
+/// The lipids are aligned with their axis along the normal. Lipids in the upper leaflet
+/// point their heads up and lipids in the lower leaflet are flipped to point their heads
+/// down. The tails of the two leaflets meet at the bilayer center.
+pub struct Bilayer {
+    /// Name of component.
+    pub name: Option<String>,
+    /// Lipid types and leaflet compositions.
+    pub lipids: Vec<Lipid>,
+    /// Area per lipid (nm^2).
+    pub area_per_lipid: f64,
+    /// Lateral distribution of lipids.
+    pub packing: LipidPacking,
+    /// Rotate every lipid by a random angle around the normal.
+    pub random_rotation: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// Seed for the random number generator. Set to the used seed when
+    /// `construct` is called.
+    pub seed: Option<u64>,
+    #[serde(skip)]
+    /// Origin of the bilayer. Located in the lower-left position of it.
+    pub origin: Coord,
+    #[serde(skip)]
+    /// Length of the bilayer along the x axis.
+    pub length: f64,
+    #[serde(skip)]
+    /// Length of the bilayer along the y axis.
+    pub width: f64,
+    #[serde(skip)]
+    /// Thickness of the bilayer along the z axis. Set when the bilayer is constructed.
+    pub height: f64,
+    #[serde(skip)]
+    /// Placed lipids of the bilayer. Relative to the `origin`.
+    pub residues: Vec<PlacedResidue>,
+}
+
+impl Bilayer {
+    /// Construct the bilayer and return the object.
+    ///
+    /// # Errors
+    /// Returns an error if the size or area per lipid is non-positive, if no lipids
+    /// are set, if any head or tail index is out of bounds, if the head and tail
+    /// atoms of a lipid are at the same position or if the composition of a leaflet
+    /// is not positive.
+    pub fn construct(self) -> Result<Bilayer> {
+        if self.length <= 0.0 || self.width <= 0.0 {
+            return Err(
+                GrafenError::RunError("cannot create a bilayer of non-positive size".to_string())
+            );
+        }
+
+        if self.area_per_lipid <= 0.0 {
+            return Err(
+                GrafenError::RunError("the area per lipid must be positive".to_string())
+            );
+        }
+
+        if self.lipids.is_empty() {
+            return Err(GrafenError::RunError("no lipids are set for the bilayer".to_string()));
+        }
+
+        let upper_weights = self.lipids.iter().map(|lipid| lipid.upper).collect::<Vec<_>>();
+        let lower_weights = self.lipids.iter().map(|lipid| lipid.lower).collect::<Vec<_>>();
+
+        for weights in &[&upper_weights, &lower_weights] {
+            if weights.iter().any(|&w| w < 0.0) || weights.iter().sum::<f64>() <= 0.0 {
+                return Err(GrafenError::RunError(
+                    "the lipid composition of both leaflets must be positive".to_string()
+                ));
+            }
+        }
+
+        // Lipid atoms aligned along +z with the tail atom at origo
+        let aligned = self.lipids
+            .iter()
+            .map(align_lipid)
+            .collect::<Result<Vec<_>>>()?;
+
+        let max_extent = aligned
+            .iter()
+            .flat_map(|atoms| atoms.iter().map(|c| c.z))
+            .fold(0.0, f64::max);
+
+        let center = LEAFLET_SPACING / 2.0 + max_extent;
+        let height = 2.0 * center;
+
+        let seed = seed_or_random(self.seed);
+        let mut rng = rng_from_seed(seed);
+
+        let (upper_points, lower_points) = self.distribute_sites(&mut rng);
+        let (length, width, _) = upper_points.box_size.to_tuple();
+
+        let mut residues = Vec::new();
+
+        let upper_types = assign_lipid_types(upper_points.coords.len(), &upper_weights, &mut rng);
+        for (&coord, &index) in upper_points.coords.iter().zip(upper_types.iter()) {
+            let tail_position = Coord::new(coord.x, coord.y, center + LEAFLET_SPACING / 2.0);
+            residues.push(
+                self.place_lipid(index, &aligned[index], tail_position, false, &mut rng)
+            );
+        }
+
+        let lower_types = assign_lipid_types(lower_points.coords.len(), &lower_weights, &mut rng);
+        for (&coord, &index) in lower_points.coords.iter().zip(lower_types.iter()) {
+            let tail_position = Coord::new(coord.x, coord.y, center - LEAFLET_SPACING / 2.0);
+            residues.push(
+                self.place_lipid(index, &aligned[index], tail_position, true, &mut rng)
+            );
+        }
+
+        Ok(Bilayer {
+            seed: Some(seed),
+            length,
+            width,
+            height,
+            residues,
+            .. self
+        })
+    }
+
+    /// Calculate the box size.
+    fn calc_box_size(&self) -> Coord {
+        Coord::new(self.length, self.width, self.height)
+    }
+
+    /// Return references to the residues of all lipid types.
+    fn residue_types(&self) -> Vec<&Residue> {
+        self.lipids.iter().map(|lipid| &lipid.residue).collect()
+    }
+
+    /// Generate the lateral lipid positions of the upper and lower leaflets.
+    fn distribute_sites<R: Rng>(&self, rng: &mut R) -> (Points, Points) {
+        match self.packing {
+            LipidPacking::PoissonDisc => {
+                // See `Sheet::construct` for this relation between the density
+                // and minimum distance.
+                let rmin = (2.0 * self.area_per_lipid / PI).sqrt();
+
+                (
+                    Distribution::poisson(rmin, self.length, self.width, rng),
+                    Distribution::poisson(rmin, self.length, self.width, rng)
+                )
+            },
+            LipidPacking::Hexagonal => {
+                // The area of a unit cell with side a and angle 60 degrees is a^2 sin(60)
+                let a = (self.area_per_lipid / (PI / 3.0).sin()).sqrt();
+
+                let build = || {
+                    Lattice::triclinic(a, a, PI / 3.0)
+                        .with_size(self.length, self.width)
+                        .finalize()
+                };
+
+                (build(), build())
+            },
+        }
+    }
+
+    /// Place a lipid with its tail atom at a position, optionally flipped
+    /// to point its head down.
+    fn place_lipid<R: Rng>(&self, index: usize, aligned: &[Coord], tail_position: Coord,
+                           flip: bool, rng: &mut R) -> PlacedResidue {
+        let angle = if self.random_rotation {
+            Range::new(0.0, 2.0 * PI).ind_sample(rng)
+        } else {
+            0.0
+        };
+
+        let normal = Coord::new(0.0, 0.0, 1.0);
+        let flip_axis = Coord::new(1.0, 0.0, 0.0);
+
+        let atoms = aligned
+            .iter()
+            .map(|&c| {
+                let rotated = c.rotate_around(normal, angle);
+
+                if flip {
+                    rotated.rotate_around(flip_axis, PI)
+                } else {
+                    rotated
+                }
+            })
+            .map(|c| c + tail_position)
+            .collect();
+
+        PlacedResidue { residue: index, atoms }
+    }
+}
+
+/// Return the atom positions of a lipid aligned with its tail-to-head axis along +z
+/// and its tail atom at origo.
+fn align_lipid(lipid: &Lipid) -> Result<Vec<Coord>> {
+    let atoms = &lipid.residue.atoms;
+
+    if lipid.head >= atoms.len() || lipid.tail >= atoms.len() {
+        return Err(GrafenError::RunError(format!(
+            "the head or tail atom index of lipid '{}' is out of bounds", lipid.residue.code
+        )));
+    }
+
+    let tail = atoms[lipid.tail].position;
+    let axis = atoms[lipid.head].position - tail;
+
+    if axis.norm() == 0.0 {
+        return Err(GrafenError::RunError(format!(
+            "the head and tail atoms of lipid '{}' are at the same position", lipid.residue.code
+        )));
+    }
+
+    let relative = atoms.iter().map(|atom| atom.position - tail).collect::<Vec<_>>();
+
+    Ok(align_coords(&relative, axis, Coord::new(0.0, 0.0, 1.0)))
+}
+
+/// Assign lipid types to a number of sites according to relative weights.
+///
+/// The number of every type is rounded using the largest remainder method
+/// and the list of types is shuffled.
+fn assign_lipid_types<R: Rng>(num_sites: usize, weights: &[f64], rng: &mut R) -> Vec<usize> {
+    let total: f64 = weights.iter().sum();
+    let exact = weights.iter().map(|w| w / total * num_sites as f64).collect::<Vec<_>>();
+
+    let mut counts = exact.iter().map(|n| n.floor() as usize).collect::<Vec<_>>();
+    let num_remaining = num_sites - counts.iter().sum::<usize>();
+
+    let mut remainders = exact
+        .iter()
+        .enumerate()
+        .map(|(i, n)| (i, n - n.floor()))
+        .collect::<Vec<_>>();
+    remainders.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+
+    for &(i, _) in remainders.iter().take(num_remaining) {
+        counts[i] += 1;
+    }
+
+    let mut types = counts
+        .iter()
+        .enumerate()
+        .flat_map(|(i, &n)| vec![i; n])
+        .collect::<Vec<_>>();
+
+    rng.shuffle(&mut types);
+
+    types
+}
+
+impl<'a> Component<'a> for Bilayer {
+    fn assign_residues(&mut self, residues: &[ResidueIterOut]) {
+        self.residues = placed_residues_from_iter(&self.residue_types(), residues);
+    }
+
+    fn box_size(&self) -> Coord {
+        self.calc_box_size() + self.origin
+    }
+
+    fn get_origin(&self) -> Coord {
+        self.origin
+    }
+
+    fn iter_residues(&self) -> ResidueIter {
+        ResidueIter::Placed(self.residue_types(), self.residues.iter())
+    }
+
+    fn num_atoms(&self) -> u64 {
+        self.residues.iter().map(|res| res.atoms.len() as u64).sum()
+    }
+
+    fn total_mass(&self) -> Option<f64> {
+        self.residues
+            .iter()
+            .fold(Some(0.0), |acc, res| {
+                acc.and_then(|sum| self.lipids[res.residue].residue.mass().map(|m| sum + m))
+            })
+    }
+
+    fn net_charge(&self) -> f64 {
+        self.residues.iter().map(|res| self.lipids[res.residue].residue.charge()).sum()
+    }
+
+    /// Wrap whole lipids into the box using their tail atom position.
+    fn with_pbc(mut self) -> Self {
+        let box_size = Coord::new(self.length, self.width, 0.0);
+
+        for res in self.residues.iter_mut() {
+            let tail = self.lipids[res.residue].tail;
+            let shift = res.atoms[tail].with_pbc(box_size) - res.atoms[tail];
+
+            res.atoms.iter_mut().for_each(|c| *c += shift);
+        }
+
+        self
+    }
+}
+
+impl Rotate for Bilayer {
+    /// Rotate all lipids and set the bilayer size to the box which encloses them.
+    fn rotate_in_place(&mut self, rotation: &Rotation, center: Coord) {
+        let extent = rotate_placed_residues(&mut self.residues, self.origin, rotation, center);
+
+        if let Some((origin, size)) = extent {
+            self.origin = origin;
+            self.length = size.x;
+            self.width = size.y;
+            self.height = size.z;
+        }
+    }
+}
+
+impl Describe for Bilayer {
+    fn describe(&self) -> String {
+        format!("{} (Bilayer of size ({:.2}, {:.2}, {:.2}) at {}{})",
+            unwrap_name(&self.name), self.length, self.width, self.height, self.origin,
+            describe_seed(self.seed))
+    }
+
+    fn describe_short(&self) -> String {
+        format!("{} (Bilayer)", unwrap_name(&self.name))
+    }
+}
+
+impl Periodic for Bilayer {
+    /// Clone the lipids into PBC multiples, which are stacked along z by the height
+    /// of the bilayer.
+    fn pbc_multiply(&self, nx: usize, ny: usize, nz: usize) -> Bilayer {
+        let mut residues = Vec::with_capacity(nx * ny * nz * self.residues.len());
+
+        for i in 0..nx {
+            for j in 0..ny {
+                for k in 0..nz {
+                    let shift = Coord::new(
+                        i as f64 * self.length, j as f64 * self.width, k as f64 * self.height
+                    );
+
+                    residues.extend(self.residues.iter().map(|res| PlacedResidue {
+                        residue: res.residue,
+                        atoms: res.atoms.iter().map(|&c| c + shift).collect(),
+                    }));
+                }
+            }
+        }
+
+        Bilayer {
+            length: nx as f64 * self.length,
+            width: ny as f64 * self.width,
+            height: nz as f64 * self.height,
+            residues,
+            .. self.clone()
+        }
+    }
+
+    /// A bilayer is periodic within its plane.
+    fn periodic_axes(&self) -> [bool; 3] {
+        [true, true, false]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup_lipid(code: &str, upper: f64, lower: f64) -> Lipid {
+        // A lipid lying along x with its head at the first atom
+        let residue = resbase![
+            code,
+            ("HEAD", 2.0, 0.0, 0.0),
+            ("MID", 1.0, 0.0, 0.0),
+            ("TAIL", 0.0, 0.0, 0.0)
+        ];
+
+        Lipid { residue, head: 0, tail: 2, upper, lower }
+    }
+
+    fn setup_bilayer(lipids: Vec<Lipid>, packing: LipidPacking) -> Bilayer {
+        Bilayer {
+            name: None,
+            lipids,
+            area_per_lipid: 0.64,
+            packing,
+            random_rotation: false,
+            seed: None,
+            origin: Coord::ORIGO,
+            length: 8.0,
+            width: 8.0,
+            height: 0.0,
+            residues: vec![],
+        }
+    }
+
+    #[test]
+    fn bilayer_leaflets_point_their_heads_away_from_the_center() {
+        let bilayer = setup_bilayer(vec![setup_lipid("LIP", 1.0, 1.0)], LipidPacking::Hexagonal)
+            .construct()
+            .unwrap();
+
+        let center = LEAFLET_SPACING / 2.0 + 2.0;
+        assert_eq!(bilayer.height, 2.0 * center);
+        assert!(!bilayer.residues.is_empty());
+
+        for res in &bilayer.residues {
+            let (head, tail) = (res.atoms[0], res.atoms[2]);
+
+            // Aligned along the normal
+            assert!((head.x - tail.x).abs() < 1e-9 && (head.y - tail.y).abs() < 1e-9);
+
+            if tail.z > center {
+                assert!((head.z - (bilayer.height)).abs() < 1e-9);
+            } else {
+                assert!(head.z.abs() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn bilayer_has_the_area_per_lipid_in_both_leaflets() {
+        let bilayer = setup_bilayer(vec![setup_lipid("LIP", 1.0, 1.0)], LipidPacking::Hexagonal)
+            .construct()
+            .unwrap();
+
+        let center = bilayer.height / 2.0;
+        let num_upper = bilayer.residues.iter().filter(|res| res.atoms[2].z > center).count();
+        let num_lower = bilayer.residues.len() - num_upper;
+
+        let area = bilayer.length * bilayer.width;
+        assert!((area / num_upper as f64 - 0.64).abs() / 0.64 < 0.05);
+        assert_eq!(num_upper, num_lower);
+
+        // The density of Poisson disc packings is approximate: use a seed to not fail at random
+        let bilayer = Bilayer {
+            seed: Some(7),
+            .. setup_bilayer(vec![setup_lipid("LIP", 1.0, 1.0)], LipidPacking::PoissonDisc)
+        }.construct().unwrap();
+
+        let expected = 2.0 * area / 0.64;
+        assert!((bilayer.residues.len() as f64 - expected).abs() / expected < 0.1);
+    }
+
+    #[test]
+    fn bilayer_leaflet_compositions_are_set_per_lipid() {
+        let lipids = vec![setup_lipid("POPC", 1.0, 0.0), setup_lipid("POPE", 1.0, 1.0)];
+        let bilayer = setup_bilayer(lipids, LipidPacking::Hexagonal).construct().unwrap();
+
+        let center = bilayer.height / 2.0;
+
+        let lower = bilayer.residues.iter().filter(|res| res.atoms[2].z < center);
+        assert!(lower.clone().count() > 0);
+        assert!(lower.clone().all(|res| res.residue == 1));
+
+        let upper = bilayer.residues.iter().filter(|res| res.atoms[2].z > center);
+        let num_upper = upper.clone().count();
+        let num_popc = upper.filter(|res| res.residue == 0).count();
+        assert!((2 * num_popc as i64 - num_upper as i64).abs() <= 1);
+    }
+
+    #[test]
+    fn bilayer_with_bad_input_returns_error() {
+        assert!(setup_bilayer(vec![], LipidPacking::Hexagonal).construct().is_err());
+
+        let mut lipid = setup_lipid("LIP", 1.0, 1.0);
+        lipid.head = 3;
+        assert!(setup_bilayer(vec![lipid], LipidPacking::Hexagonal).construct().is_err());
+
+        let lipid = setup_lipid("LIP", 1.0, 0.0);
+        assert!(setup_bilayer(vec![lipid], LipidPacking::Hexagonal).construct().is_err());
+
+        let mut lipid = setup_lipid("LIP", 1.0, 1.0);
+        lipid.head = 2;
+        assert!(setup_bilayer(vec![lipid], LipidPacking::Hexagonal).construct().is_err());
+    }
+
+    #[test]
+    fn iterating_and_assigning_bilayer_residues_keeps_lipid_types() {
+        let lipids = vec![setup_lipid("POPC", 1.0, 1.0), setup_lipid("POPE", 1.0, 1.0)];
+        let mut bilayer = setup_bilayer(lipids, LipidPacking::Hexagonal).construct().unwrap();
+
+        let original = bilayer.residues.clone();
+        assert_eq!(bilayer.num_atoms(), 3 * original.len() as u64);
+
+        let residues = bilayer.iter_residues().collect::<Vec<_>>();
+        assert_eq!(residues.len(), original.len());
+
+        bilayer.assign_residues(&residues[1..]);
+        assert_eq!(&bilayer.residues[..], &original[1..]);
+    }
+
+    #[test]
+    fn bilayer_constructed_with_the_same_seed_is_reproduced() {
+        let bilayer = Bilayer {
+            random_rotation: true,
+            seed: Some(7),
+            .. setup_bilayer(
+                vec![setup_lipid("LIP1", 1.0, 1.0), setup_lipid("LIP2", 1.0, 2.0)],
+                LipidPacking::PoissonDisc
+            )
+        };
+
+        let first = bilayer.clone().construct().unwrap();
+        let second = bilayer.construct().unwrap();
+
+        assert_eq!(first.seed, Some(7));
+        assert_eq!(first.residues, second.residues);
+    }
+
+    #[test]
+    fn assigning_lipid_types_rounds_to_the_number_of_sites() {
+        let mut rng = rng_from_seed(0);
+
+        let types = assign_lipid_types(10, &[1.0, 1.0, 1.0], &mut rng);
+        assert_eq!(types.len(), 10);
+
+        for i in 0..3 {
+            let count = types.iter().filter(|&&t| t == i).count();
+            assert!(count == 3 || count == 4);
+        }
+
+        let types = assign_lipid_types(10, &[3.0, 1.0], &mut rng);
+        assert_eq!(types.iter().filter(|&&t| t == 0).count(), 8);
+    }
+}
@@ -1,12 +1,12 @@
 //! Construct planar sheets.
 
-use surface::LatticeType;
-use surface::Sheet;
+use surface::{LatticeType, Sheet, SurfaceNormal, multiply_images};
 
-use coord::{Coord, Direction, Translate};
+use coord::{BoxVectors, Coord, Direction, Frame, Rotate, Rotation, Translate};
 use describe::{unwrap_name, Describe};
 use error::Result;
 use iterator::{ResidueIter, ResidueIterOut};
+use random::{derive_seed, describe_seed, seed_or_random};
 use system::*;
 
 use std::fmt;
@@ -14,6 +14,8 @@ use std::fmt::{Display, Formatter};
 
 impl_component![Cuboid];
 impl_translate![Cuboid];
+impl_rotate![Cuboid];
+impl_periodic![Cuboid];
 
 bitflags! {
     #[derive(Deserialize, Serialize)]
@@ -66,6 +68,10 @@ pub struct Cuboid {
     /// Standard deviation along z of coordinates. Added to the coordinates when `construct`
     /// is called.
     pub std_z: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// Seed for the random number generator of stochastic lattices and the deviation
+    /// along z. Set to the used seed when `construct` is called.
+    pub seed: Option<u64>,
     #[serde(skip)]
     /// Origin of the sheet. Located in the lower-left position of it.
     pub origin: Coord,
@@ -77,6 +83,17 @@ pub struct Cuboid {
     #[serde(skip)]
     /// List of coordinates belonging to the sheet. Relative to the `origin`.
     pub coords: Vec<Coord>,
+    #[serde(skip)]
+    /// Periodic cell of the object if it differs from the box calculated from its geometry,
+    /// eg. the box enclosing all atoms after the object has been rotated.
+    pub cell: Option<BoxVectors>,
+    #[serde(skip)]
+    /// Rotation of the object relative to its geometry, if it has been rotated.
+    pub frame: Option<Frame>,
+    #[serde(skip)]
+    /// Number of periodic images along each axis, if the cuboid has been replicated
+    /// into several cuboids.
+    pub images: Option<[usize; 3]>,
 }
 
 fn translate_coordinate_list(coords: &[Coord], translate: Coord) -> Vec<Coord> {
@@ -89,22 +106,39 @@ impl Cuboid {
     /// # Errors
     /// Returns an error if either the length or width is non-positive.
     pub fn construct(self) -> Result<Cuboid> {
+        let seed = if self.lattice.is_stochastic() || self.std_z.is_some() {
+            Some(seed_or_random(self.seed))
+        } else {
+            self.seed
+        };
+
+        // Every side is constructed with its own seed
+        let side_seed = |i| seed.map(|seed| derive_seed(seed, i));
+
         let sheet_base = Sheet {
             name: None,
             residue: None,
             lattice: self.lattice.clone(),
             std_z: self.std_z,
+            roughness: None,
+            orient_residues: false,
+            seed: None,
             origin: Coord::ORIGO,
             normal: Direction::X,
             length: 0.0,
             width: 0.0,
             coords: Vec::new(),
+            orientations: vec![],
+            normals: vec![],
+            cell: None,
+            frame: None,
         };
 
         let mut coords: Vec<Coord> = Vec::new();
         let (dx_target, dy_target, dz_target) = self.size.to_tuple();
 
         let sheet_yz = Sheet {
+            seed: side_seed(0),
             normal: Direction::X,
             length: dz_target,
             width: dy_target,
@@ -112,6 +146,7 @@ impl Cuboid {
         }.construct()?.with_pbc();
 
         let sheet_xz = Sheet {
+            seed: side_seed(1),
             normal: Direction::Y,
             length: dx_target,
             width: dz_target,
@@ -119,6 +154,7 @@ impl Cuboid {
         }.construct()?.with_pbc();
 
         let sheet_xy = Sheet {
+            seed: side_seed(2),
             normal: Direction::Z,
             length: dx_target,
             width: dy_target,
@@ -155,6 +191,7 @@ impl Cuboid {
         }
 
         Ok(Cuboid {
+            seed,
             coords,
             size: Coord::new(dx, dy, dz),
             .. self
@@ -165,12 +202,76 @@ impl Cuboid {
     fn calc_box_size(&self) -> Coord {
         self.size
     }
+
+    /// The sides of a cuboid surface enclose it, so it is not periodic along any axis.
+    fn calc_periodic_axes(&self) -> [bool; 3] {
+        [false; 3]
+    }
+
+    /// Update the geometry after the cuboid has been replicated. The images are
+    /// several cuboids.
+    fn replicate_geometry(&mut self, nx: usize, ny: usize, nz: usize) {
+        self.images = multiply_images(self.images, nx, ny, nz);
+    }
+}
+
+impl SurfaceNormal for Cuboid {
+    fn surface_coords(&self) -> &[Coord] {
+        &self.coords
+    }
+
+    fn frame(&self) -> Option<Frame> {
+        self.frame
+    }
+
+    /// The normal is that of the closest constructed side, pointing out of the box.
+    fn unrotated_normal_at(&self, coord: Coord) -> Coord {
+        let (x, y, z) = coord.to_tuple();
+        let (dx, dy, dz) = self.size.to_tuple();
+
+        let sides = [
+            (Sides::X0, x, Coord::new(-1.0, 0.0, 0.0)),
+            (Sides::X1, dx - x, Coord::new(1.0, 0.0, 0.0)),
+            (Sides::Y0, y, Coord::new(0.0, -1.0, 0.0)),
+            (Sides::Y1, dy - y, Coord::new(0.0, 1.0, 0.0)),
+            (Sides::Z0, z, Coord::new(0.0, 0.0, -1.0)),
+            (Sides::Z1, dz - z, Coord::new(0.0, 0.0, 1.0)),
+        ];
+
+        let default = (::std::f64::MAX, Coord::new(0.0, 0.0, 1.0));
+
+        sides.iter()
+            .filter(|&&(side, _, _)| self.sides.contains(side))
+            .fold(default, |(min_dist, normal), &(_, dist, n)| {
+                if dist.abs() < min_dist { (dist.abs(), n) } else { (min_dist, normal) }
+            })
+            .1
+    }
+
+    fn surface_area(&self) -> f64 {
+        let (dx, dy, dz) = self.size.to_tuple();
+
+        let sides = [
+            (Sides::X0, dy * dz), (Sides::X1, dy * dz),
+            (Sides::Y0, dx * dz), (Sides::Y1, dx * dz),
+            (Sides::Z0, dx * dy), (Sides::Z1, dx * dy),
+        ];
+
+        sides.iter()
+            .filter(|&&(side, _)| self.sides.contains(side))
+            .map(|&(_, area)| area)
+            .sum()
+    }
+
+    fn is_replicated(&self) -> bool {
+        self.images.is_some()
+    }
 }
 
 impl Describe for Cuboid {
     fn describe(&self) -> String {
-        format!("{} (Surface box of size {} at {})",
-            unwrap_name(&self.name), self.size, self.origin)
+        format!("{} (Surface box of size {} at {}{})",
+            unwrap_name(&self.name), self.size, self.origin, describe_seed(self.seed))
     }
 
     fn describe_short(&self) -> String {
@@ -191,12 +292,19 @@ mod tests {
             name: None,
             residue: None,
             std_z: None,
+            roughness: None,
+            orient_residues: false,
+            seed: None,
             origin: Coord::ORIGO,
             lattice: lattice.clone(),
             normal: Direction::X,
             length: 0.0,
             width: 0.0,
             coords: Vec::new(),
+            orientations: vec![],
+            normals: vec![],
+            cell: None,
+            frame: None,
         };
 
         let sheet_xy = Sheet {
@@ -230,10 +338,14 @@ mod tests {
             residue: None,
             lattice: lattice.clone(),
             std_z: None,
+            seed: None,
             origin: Coord::ORIGO,
             size: size,
             sides: Sides::empty(),
             coords: Vec::new(),
+            cell: None,
+            frame: None,
+            images: None,
         };
 
         (sheet_xy, sheet_xz, sheet_yz, cuboid_base)
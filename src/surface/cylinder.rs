@@ -1,12 +1,13 @@
 //! Construct cylinders that are curved sheets, not volumes.
 
-use surface::{Sheet, LatticeType};
+use surface::{Sheet, LatticeType, SurfaceNormal, calc_normal_orientations, multiply_images};
 
-use coord::{Coord, Direction, Translate,
-    rotate_coords, rotate_planar_coords_to_alignment};
+use coord::{BoxVectors, Coord, Direction, Frame, Rotate, Rotation, Translate,
+    align_coords, calc_cylinder_extent, rotate_coords, rotate_planar_coords_to_alignment};
 use describe::{unwrap_name, Describe};
 use error::Result;
 use iterator::{ResidueIter, ResidueIterOut};
+use random::{describe_seed, seed_or_random};
 use system::*;
 
 use std::f64::consts::PI;
@@ -14,8 +15,10 @@ use std::fmt;
 use std::fmt::{Display, Formatter};
 
 
-impl_component![Cylinder];
+impl_component![@oriented Cylinder];
 impl_translate![Cylinder];
+impl_rotate![Cylinder];
+impl_periodic![Cylinder];
 
 #[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
 /// Cylinders can be capped in either or both ends.
@@ -35,6 +38,32 @@ impl Display for CylinderCap {
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+/// Shape of a cylinder cap.
+pub enum CapShape {
+    /// A flat circle cut from the sheet of the cylinder.
+    Flat,
+    /// A hemispherical shell which closes the cylinder, like the end of a carbon nanotube.
+    /// Its points are evenly spaced at the density of the cylinder lattice. A bottom
+    /// hemisphere is placed at the origin, with the wall above it.
+    Hemisphere,
+}
+
+impl Default for CapShape {
+    fn default() -> CapShape {
+        CapShape::Flat
+    }
+}
+
+impl Display for CapShape {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            CapShape::Flat => write!(f, "Flat"),
+            CapShape::Hemisphere => write!(f, "Hemisphere"),
+        }
+    }
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 /// A 2D cylindrical surface.
 pub struct Cylinder {
@@ -49,6 +78,19 @@ pub struct Cylinder {
     pub alignment: Direction,
     /// Cylinders can be capped at its ends.
     pub cap: Option<CylinderCap>,
+    #[serde(default)]
+    /// Shape of the bottom cap, if it is set.
+    pub bottom_cap_shape: CapShape,
+    #[serde(default)]
+    /// Shape of the top cap, if it is set.
+    pub top_cap_shape: CapShape,
+    #[serde(default)]
+    /// Rotate residues such that their z axis follows the surface normal at their position.
+    pub orient_residues: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// Seed for the random number generator of stochastic lattices. Set to the used
+    /// seed when `construct` is called.
+    pub seed: Option<u64>,
     #[serde(skip)]
     /// Origin of the cylinder. Located in the center of the bottom.
     pub origin: Coord,
@@ -61,6 +103,21 @@ pub struct Cylinder {
     #[serde(skip)]
     /// List of coordinates belonging to the cylinder. Relative to the `origin.
     pub coords: Vec<Coord>,
+    #[serde(skip)]
+    /// Rotation of the residue at every coordinate, if residues are oriented along
+    /// the surface normal. Empty if they are not.
+    pub orientations: Vec<Rotation>,
+    #[serde(skip)]
+    /// Periodic cell of the object if it differs from the box calculated from its geometry,
+    /// eg. the box enclosing all atoms after the object has been rotated.
+    pub cell: Option<BoxVectors>,
+    #[serde(skip)]
+    /// Rotation of the object relative to its geometry, if it has been rotated.
+    pub frame: Option<Frame>,
+    #[serde(skip)]
+    /// Number of periodic images along each axis, if the cylinder has been replicated
+    /// into several cylinders.
+    pub images: Option<[usize; 3]>,
 }
 
 impl Cylinder {
@@ -73,16 +130,29 @@ impl Cylinder {
         let length = 2.0 * PI * self.radius;
         let width = self.height;
 
+        let seed = if self.lattice.is_stochastic() {
+            Some(seed_or_random(self.seed))
+        } else {
+            self.seed
+        };
+
         let sheet = Sheet {
             name: None,
             residue: None,
             lattice: self.lattice.clone(),
             std_z: None,
+            roughness: None,
+            orient_residues: false,
+            seed,
             origin: Coord::default(),
             normal: Direction::Z,
             length,
             width,
             coords: vec![],
+            orientations: vec![],
+            normals: vec![],
+            cell: None,
+            frame: None,
         }.construct()?;
 
         let final_radius = sheet.length / (2.0 * PI);
@@ -104,55 +174,232 @@ impl Cylinder {
             .collect();
 
         if let Some(cap) = self.cap {
-            // The cylinder is aligned along the y axis. Construct a cap from
-            // the same sheet and rotate it to match.
-
-            let mut bottom = sheet.to_circle(final_radius); //.rotate(Direction::X);
-            bottom.coords = rotate_planar_coords_to_alignment(&bottom.coords,
-                Direction::Z, Direction::Y);
-
-            // Get the top cap coordinates by shifting the bottom ones, not just the origin.
-            let top_coords: Vec<_> = bottom.coords
-                .iter()
-                .map(|&coord| coord + Coord::new(0.0, final_height, 0.0))
-                .collect();
+            // The cylinder is aligned along the y axis. Construct the caps at the bottom
+            // and mirror the top caps to the other end.
+            let mirror_to_top = |coords: Vec<Coord>| -> Vec<Coord> {
+                coords.into_iter()
+                    .map(|coord| Coord::new(coord.x, final_height - coord.y, coord.z))
+                    .collect()
+            };
 
             match cap {
-                CylinderCap::Bottom => coords.extend_from_slice(&bottom.coords),
-                CylinderCap::Top => coords.extend_from_slice(&top_coords),
+                CylinderCap::Bottom => {
+                    coords.extend(construct_cap(&sheet, final_radius, self.bottom_cap_shape));
+                },
+                CylinderCap::Top => {
+                    let top = construct_cap(&sheet, final_radius, self.top_cap_shape);
+                    coords.extend(mirror_to_top(top));
+                },
                 CylinderCap::Both => {
-                    coords.extend_from_slice(&bottom.coords);
-                    coords.extend_from_slice(&top_coords);
+                    let top = construct_cap(&sheet, final_radius, self.top_cap_shape);
+                    coords.extend(construct_cap(&sheet, final_radius, self.bottom_cap_shape));
+                    coords.extend(mirror_to_top(top));
                 }
             }
         }
 
-        // Rotate the cylinder once along the x-axis to align them to the z-axis.
-        Ok(Cylinder {
-            alignment: Direction::Z,
+        // Place a hemispherical bottom cap at the origin, with the wall above it
+        let depth = self.calc_bottom_cap_depth(final_radius);
+        coords.iter_mut().for_each(|coord| coord.y += depth);
+
+        // Rotate the cylinder once along the x-axis to align them to the z-axis,
+        // then to the final alignment.
+        let coords = rotate_coords(&coords, Direction::X);
+
+        let coords = match self.alignment {
+            Direction::Z => coords,
+            alignment => {
+                align_coords(&coords, Coord::new(0.0, 0.0, 1.0), alignment.to_unit_vector())
+            },
+        };
+
+        let mut cylinder = Cylinder {
+            seed,
             radius: final_radius,
             height: final_height,
-            coords: rotate_coords(&coords, Direction::X),
+            coords,
+            orientations: vec![],
             .. self
-        })
+        };
+
+        if cylinder.orient_residues {
+            cylinder.orientations = calc_normal_orientations(&cylinder);
+        }
+
+        Ok(cylinder)
     }
 
-    /// Calculate the box size.
+    /// Calculate the box size. Hemispherical caps extend the box along the cylinder axis.
     fn calc_box_size(&self) -> Coord {
-        let diameter = 2.0 * self.radius;
+        let (bottom, top) = self.get_cap_shapes();
+        let num_hemispheres = [bottom, top].iter()
+            .filter(|&&shape| shape == Some(CapShape::Hemisphere))
+            .count();
+
+        let length = self.height + num_hemispheres as f64 * self.radius;
+        let (min, max) = calc_cylinder_extent(self.radius, length, self.alignment);
+
+        max - min
+    }
 
-        match self.alignment {
-            Direction::X => Coord::new(self.height, diameter, diameter),
-            Direction::Y => Coord::new(diameter, self.height, diameter),
-            Direction::Z => Coord::new(diameter, diameter, self.height),
+    /// Calculate along which axes the cylinder is periodic. Its lattice is matched to
+    /// the height, so a cylinder without caps along one of the axes is periodic along it,
+    /// unless it has been rotated.
+    fn calc_periodic_axes(&self) -> [bool; 3] {
+        let mut periodic = [false; 3];
+
+        if let (None, None, Some(axis)) = (self.cap, self.frame, self.alignment.axis_index()) {
+            periodic[axis] = true;
+        }
+
+        periodic
+    }
+
+    /// Update the geometry after the cylinder has been replicated. Images along the axis
+    /// of a single cylinder without caps extend its height, others are several cylinders.
+    fn replicate_geometry(&mut self, nx: usize, ny: usize, nz: usize) {
+        let multiples = [nx, ny, nz];
+
+        match (self.images, self.frame, self.cap, self.alignment.axis_index()) {
+            (None, None, None, Some(axis))
+                    if (0..3).all(|i| i == axis || multiples[i] == 1) => {
+                self.height *= multiples[axis] as f64;
+                self.cell = None;
+            },
+            _ => self.images = multiply_images(self.images, nx, ny, nz),
         }
     }
+
+    /// Return the distance from the origin to the bottom of the wall along the axis,
+    /// which is the radius for a hemispherical bottom cap and zero otherwise.
+    fn calc_bottom_cap_depth(&self, radius: f64) -> f64 {
+        match self.get_cap_shapes() {
+            (Some(CapShape::Hemisphere), _) => radius,
+            _ => 0.0,
+        }
+    }
+
+    /// Return the shapes of the bottom and top caps, or `None` for ends which are not capped.
+    fn get_cap_shapes(&self) -> (Option<CapShape>, Option<CapShape>) {
+        match self.cap {
+            None => (None, None),
+            Some(CylinderCap::Bottom) => (Some(self.bottom_cap_shape), None),
+            Some(CylinderCap::Top) => (None, Some(self.top_cap_shape)),
+            Some(CylinderCap::Both) => (Some(self.bottom_cap_shape), Some(self.top_cap_shape)),
+        }
+    }
+}
+
+/// Construct a cap at the bottom of a cylinder which is aligned along the y axis.
+fn construct_cap(sheet: &Sheet, radius: f64, shape: CapShape) -> Vec<Coord> {
+    match shape {
+        CapShape::Flat => {
+            // Cut the cap from the same sheet and rotate it to match the cylinder.
+            let circle = sheet.to_circle(radius);
+            rotate_planar_coords_to_alignment(&circle.coords, Direction::Z, Direction::Y)
+        },
+        CapShape::Hemisphere => {
+            let density = sheet.coords.len() as f64 / (sheet.length * sheet.width);
+
+            // Leave out the rim by half a lattice spacing, since it is on the end
+            // of the wall
+            let spacing = 1.0 / density.sqrt();
+            let start_depth = (0.5 * spacing / radius).min(1.0);
+
+            let area = 2.0 * PI * radius.powi(2) * (1.0 - start_depth);
+            let num_coords = (area * density).round() as usize;
+
+            hemisphere_coords(radius, num_coords, start_depth)
+        },
+    }
+}
+
+/// Distribute coordinates evenly on a hemisphere which bulges toward negative y,
+/// by placing them along a Fibonacci spiral from the rim to the pole. The spiral
+/// starts at a depth below the rim, relative to the radius. A start depth of -1
+/// covers the full sphere.
+///
+/// The spacing along y is constant since it yields equal areas on a sphere.
+pub fn hemisphere_coords(radius: f64, num_coords: usize, start_depth: f64) -> Vec<Coord> {
+    let golden_angle = PI * (3.0 - 5.0f64.sqrt());
+
+    (0..num_coords)
+        .map(|i| {
+            let depth = start_depth
+                + (1.0 - start_depth) * (i as f64 + 0.5) / (num_coords as f64);
+            let in_plane = (1.0 - depth.powi(2)).sqrt();
+            let angle = golden_angle * (i as f64);
+
+            Coord::new(
+                radius * in_plane * angle.cos(),
+                -radius * depth,
+                radius * in_plane * angle.sin()
+            )
+        })
+        .collect()
+}
+
+impl SurfaceNormal for Cylinder {
+    fn surface_coords(&self) -> &[Coord] {
+        &self.coords
+    }
+
+    fn frame(&self) -> Option<Frame> {
+        self.frame
+    }
+
+    /// The normal points radially out from the cylinder axis, or along the axis
+    /// for positions on flat caps. On hemispherical caps it points out from
+    /// the center of the hemisphere.
+    fn unrotated_normal_at(&self, coord: Coord) -> Coord {
+        // Positions which are inside of the wall are on the caps
+        let tolerance = 1e-3 * self.radius.max(1.0);
+        let axis = self.alignment.to_unit_vector();
+
+        // Positions are measured from the bottom of the wall
+        let coord = coord - axis * self.calc_bottom_cap_depth(self.radius);
+        let (dr, dh) = Coord::ORIGO.distance_cylindrical(coord, self.alignment);
+
+        // Positions beyond the ends are on hemispherical caps
+        if dh < -tolerance || dh > self.height + tolerance {
+            let center = if dh < 0.0 { Coord::ORIGO } else { axis * self.height };
+            let radial = coord - center;
+
+            return radial * (1.0 / radial.norm());
+        }
+
+        if self.cap.is_some() && dr < self.radius - tolerance {
+            if dh < 0.5 * self.height { -axis } else { axis }
+        } else {
+            let radial = coord - axis * dh;
+            radial * (1.0 / radial.norm())
+        }
+    }
+
+    fn surface_area(&self) -> f64 {
+        let (bottom, top) = self.get_cap_shapes();
+
+        let cap_area = [bottom, top].iter()
+            .map(|shape| match *shape {
+                None => 0.0,
+                Some(CapShape::Flat) => PI * self.radius.powi(2),
+                Some(CapShape::Hemisphere) => 2.0 * PI * self.radius.powi(2),
+            })
+            .sum::<f64>();
+
+        2.0 * PI * self.radius * self.height + cap_area
+    }
+
+    fn is_replicated(&self) -> bool {
+        self.images.is_some()
+    }
 }
 
 impl Describe for Cylinder {
     fn describe(&self) -> String {
-        format!("{} (Cylinder surface of radius {:.2} and height {:.2} at {})",
-            unwrap_name(&self.name), self.radius, self.height, self.origin)
+        format!("{} (Cylinder surface of radius {:.2} and height {:.2} at {}{})",
+            unwrap_name(&self.name), self.radius, self.height, self.origin,
+            describe_seed(self.seed))
     }
 
     fn describe_short(&self) -> String {
@@ -163,6 +410,7 @@ impl Describe for Cylinder {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use coord::Periodic;
     use surface::LatticeType::*;
 
     fn setup_cylinder(radius: f64, height: f64, lattice: &LatticeType) -> Cylinder {
@@ -172,10 +420,18 @@ mod tests {
             lattice: lattice.clone(),
             alignment: Direction::Z,
             cap: None,
+            bottom_cap_shape: CapShape::Flat,
+            top_cap_shape: CapShape::Flat,
+            orient_residues: false,
+            seed: None,
             origin: Coord::default(),
             radius,
             height,
             coords: vec![],
+            orientations: vec![],
+            cell: None,
+            frame: None,
+            images: None,
         }
     }
 
@@ -196,7 +452,7 @@ mod tests {
         let sum_z = cylinder.coords.iter().map(|&Coord { x: _, y: _, z }| z.abs()).sum::<f64>();
         assert!(sum_z > 0.0);
 
-        // Currently the alignment should be along Z
+        // The alignment is kept
         assert_eq!(Direction::Z, cylinder.alignment);
 
         // Rigorous test of coordinate structure
@@ -286,6 +542,158 @@ mod tests {
         assert_eq!(top, top_from_both);
     }
 
+    #[test]
+    fn hemispherical_caps_close_the_cylinder_with_evenly_spaced_shells() {
+        let radius = 2.0;
+        let height = 5.0;
+        let lattice = Hexagonal { a: 0.1 };
+
+        let base = setup_cylinder(radius, height, &lattice);
+        let num_wall = base.clone().construct().unwrap().coords.len();
+
+        let cylinder = Cylinder {
+            cap: Some(CylinderCap::Both),
+            bottom_cap_shape: CapShape::Hemisphere,
+            top_cap_shape: CapShape::Hemisphere,
+            .. base.clone()
+        }.construct().unwrap();
+
+        let (wall, caps) = cylinder.coords.split_at(num_wall);
+        let (bottom, top) = caps.split_at(caps.len() / 2);
+
+        // The bottom cap is placed at the origin and the wall above it
+        let (radius, height) = (cylinder.radius, cylinder.height);
+        let bottom_center = Coord::new(0.0, 0.0, radius);
+        let top_center = Coord::new(0.0, 0.0, radius + height);
+
+        assert!(wall.iter().all(|c| c.z >= radius - 1e-9 && c.z <= radius + height + 1e-9));
+
+        let wall_density = num_wall as f64 / (2.0 * PI * radius * height);
+        let spacing = (1.0 / wall_density).sqrt();
+
+        // The caps leave out the rims at the ends of the wall
+        for &coord in bottom {
+            assert!((coord.distance(bottom_center) - radius).abs() < 1e-9);
+            assert!(coord.z >= 0.0 && coord.z < radius - 0.4 * spacing);
+        }
+
+        for &coord in top {
+            assert!((coord.distance(top_center) - radius).abs() < 1e-9);
+            assert!(coord.z > radius + height + 0.4 * spacing);
+        }
+
+        // The shells have the density of the cylinder wall
+        let start_depth = 0.5 * spacing / radius;
+        let expected = 2.0 * PI * radius.powi(2) * (1.0 - start_depth) * wall_density;
+        assert!((bottom.len() as f64 - expected).abs() / expected < 0.05);
+
+        // and are roughly evenly spaced
+        for (i, &coord1) in bottom.iter().enumerate() {
+            for &coord2 in &bottom[i + 1..] {
+                assert!(coord1.distance(coord2) > 0.5 * spacing);
+            }
+        }
+
+        // The box encloses the caps along the axis
+        let size = cylinder.calc_box_size();
+        assert!((size.z - (cylinder.height + 2.0 * cylinder.radius)).abs() < 1e-9);
+
+        let normal = cylinder.normal_at(Coord::ORIGO);
+        assert_eq!(normal, Coord::new(0.0, 0.0, -1.0));
+
+        let normal = cylinder.normal_at(Coord::new(radius, 0.0, radius + 0.5 * height));
+        assert_eq!(normal, Coord::new(1.0, 0.0, 0.0));
+
+        let area = 2.0 * PI * cylinder.radius * (cylinder.height + 2.0 * cylinder.radius);
+        assert!((cylinder.surface_area() - area).abs() < 1e-9);
+    }
+
+    #[test]
+    fn cap_shapes_are_set_for_each_end() {
+        let radius = 2.0;
+        let height = 5.0;
+        let lattice = Hexagonal { a: 0.1 };
+
+        let cylinder = Cylinder {
+            cap: Some(CylinderCap::Both),
+            bottom_cap_shape: CapShape::Flat,
+            top_cap_shape: CapShape::Hemisphere,
+            .. setup_cylinder(radius, height, &lattice)
+        }.construct().unwrap();
+
+        assert!(cylinder.coords.iter().any(|c| c.z > cylinder.height + 1e-9));
+        assert!(cylinder.coords.iter().all(|c| c.z >= 0.0));
+
+        // The shape of an end which is not capped is not used
+        let cylinder = Cylinder {
+            cap: Some(CylinderCap::Bottom),
+            bottom_cap_shape: CapShape::Flat,
+            top_cap_shape: CapShape::Hemisphere,
+            .. setup_cylinder(radius, height, &lattice)
+        }.construct().unwrap();
+
+        assert!(cylinder.coords.iter().all(|c| c.z >= 0.0 && c.z <= cylinder.height + 1e-9));
+    }
+
+    #[test]
+    fn cylinder_can_be_constructed_along_an_arbitrary_axis() {
+        let radius = 2.0;
+        let height = 5.0;
+        let axis = Coord::new(1.0, 1.0, 0.0);
+        let lattice = PoissonDisc { density: 10.0 };
+
+        let cylinder = Cylinder {
+            alignment: Direction::Vector(axis),
+            .. setup_cylinder(radius, height, &lattice)
+        }.construct().unwrap();
+
+        for &coord in &cylinder.coords {
+            let (r, h) = Coord::ORIGO.distance_cylindrical(coord, cylinder.alignment);
+            assert!((r - cylinder.radius).abs() < 1e-6);
+            assert!(h >= -1e-9 && h <= cylinder.height + 1e-9);
+        }
+
+        let normal = cylinder.normal_at(cylinder.coords[0]);
+        assert!(normal.dot(axis).abs() < 1e-6);
+        assert!((normal.norm() - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn oriented_residues_point_along_the_normal_of_the_cylinder() {
+        let residue = resbase!["SIO", ("SI", 0.0, 0.0, 0.0), ("O", 0.0, 0.0, 0.2)];
+
+        let cylinder = Cylinder {
+            residue: Some(residue),
+            cap: Some(CylinderCap::Bottom),
+            orient_residues: true,
+            .. setup_cylinder(2.0, 3.0, &Hexagonal { a: 0.5 })
+        }.construct().unwrap();
+
+        assert_eq!(cylinder.orientations.len(), cylinder.coords.len());
+
+        for (&coord, residue) in cylinder.coords.iter().zip(cylinder.iter_residues()) {
+            let atoms = residue.get_atoms();
+            let direction = (atoms[1].1 - atoms[0].1) * (1.0 / 0.2);
+
+            assert!(direction.distance(cylinder.normal_at(coord)) < 1e-9);
+        }
+
+        // Rotating the cylinder rotates the residues along with it
+        let rotation = Rotation::from_axis_angle(Coord::new(1.0, 0.0, 0.0), 0.5);
+        let rotated = cylinder.clone().rotate(&rotation, Coord::ORIGO);
+
+        let sites = cylinder.coords.iter().zip(rotated.coords.iter());
+
+        for ((&coord, &rotated_coord), residue) in sites.zip(rotated.iter_residues()) {
+            let atoms = residue.get_atoms();
+            let direction = (atoms[1].1 - atoms[0].1) * (1.0 / 0.2);
+            let expected = rotation.apply(cylinder.normal_at(coord));
+
+            assert!(direction.distance(expected) < 1e-9);
+            assert!(rotated.normal_at(rotated_coord).distance(expected) < 1e-9);
+        }
+    }
+
     #[test]
     fn calc_box_size_of_cylinder() {
         let radius = 2.0;
@@ -308,4 +716,26 @@ mod tests {
         cylinder.alignment = Direction::Z;
         assert_eq!(Coord::new(diameter, diameter, height), cylinder.calc_box_size());
     }
+
+    #[test]
+    fn replicating_cylinder_along_its_axis_extends_it_and_otherwise_makes_images() {
+        let lattice = PoissonDisc { density: 10.0 };
+        let cylinder = setup_cylinder(1.0, 2.0, &lattice).construct().unwrap();
+        let height = cylinder.height;
+
+        let extended = cylinder.pbc_multiply(1, 1, 3);
+        assert_eq!(extended.coords.len(), 3 * cylinder.coords.len());
+        assert_eq!(extended.height, 3.0 * height);
+        assert_eq!(extended.images, None);
+        assert_eq!(extended.periodic_axes(), [false, false, true]);
+        assert!(!extended.is_replicated());
+
+        let images = cylinder.pbc_multiply(2, 1, 1);
+        assert_eq!(images.height, height);
+        assert_eq!(images.images, Some([2, 1, 1]));
+        assert!(images.is_replicated());
+
+        // Further replication multiplies the number of images
+        assert_eq!(images.pbc_multiply(1, 3, 2).images, Some([2, 3, 2]));
+    }
 }
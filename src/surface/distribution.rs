@@ -1,9 +1,10 @@
 //! Implement a Poisson Disc distribution algorithm.
 
-use rand;
-use std::cmp;
+use rand::Rng;
+use rand::distributions::{IndependentSample, Range};
 
-use coord::Coord;
+use coord::{BoxVectors, Coord};
+use spatial::CellList;
 use surface::points::Points;
 
 /// Container for constructing different randomly sampled distributions.
@@ -12,46 +13,49 @@ pub struct Distribution;
 impl Distribution {
     /// Return a set of points that have been generated using a Poisson disk sampling
     /// algorithm. They will be separated from each other at minimum by an input distance.
-    pub fn poisson(rmin: f64, size_x: f64, size_y: f64) -> Points {
-        self::density::PoissonDistribution::new(rmin, size_x, size_y)
+    pub fn poisson<R: Rng>(rmin: f64, size_x: f64, size_y: f64, rng: &mut R) -> Points {
+        self::density::PoissonDistribution::new(rmin, size_x, size_y, rng)
     }
 
     /// Return a set of an input number of points that have been generated using
     /// a blue noise sampling algorithm.
-    pub fn blue_noise(num_points: u64, size_x: f64, size_y: f64) -> Points {
-        self::number::BlueNoiseDistribution::new(num_points, size_x, size_y)
+    pub fn blue_noise<R: Rng>(num_points: u64, size_x: f64, size_y: f64, rng: &mut R)
+            -> Points {
+        self::number::BlueNoiseDistribution::new(num_points, size_x, size_y, rng)
     }
 }
 
 mod number {
-    use rand::distributions::IndependentSample;
     use super::*;
 
     pub struct BlueNoiseDistribution;
 
     impl BlueNoiseDistribution {
-        // This algorithm is quite expensive since every candidate has to be checked
-        // against every coordinate that has been constructed, and the number of candidates
-        // which we create increases equally. Thus it scales very poorly with the number
+        // Every candidate is compared to the closest of the constructed coordinates,
+        // which are kept in a periodic cell list. The number of candidates increases
+        // with every point, so the algorithm still scales quadratically with the number
         // of points that are created.
         //
-        // Could be sped up by implementing a grid for the final coordinates and compare
-        // every candidate to those inside a neighbouring area only. Also by making it
-        // parallel, but more power cannot substitute bad algorithms.
-        //
         // **Note that before any performance improvements are made, a benchmark test should
         // be created!**
-        pub fn new(num_points: u64, size_x: f64, size_y: f64) -> Points {
-            let mut coords = vec![gen_coord(size_x, size_y)];
+        pub fn new<R: Rng>(num_points: u64, size_x: f64, size_y: f64, rng: &mut R) -> Points {
+            let box_size = Coord::new(size_x, size_y, 0.0);
+            let box_vectors = BoxVectors::rectangular(box_size);
+
+            // Cells are sized after the mean distance between the final points
+            let spacing = (size_x * size_y / num_points.max(1) as f64).sqrt();
+            let mut samples = CellList::periodic(box_vectors, spacing);
+            samples.insert(gen_coord(size_x, size_y, rng));
+
             const NUM_CANDIDATES_MULTIPLIER: u64 = 1;
 
             for i in 1..num_points {
-                let mut current_best = gen_coord(size_x, size_y);
-                let mut max_dist = calc_min_dist(current_best, &coords, size_x, size_y);
+                let mut current_best = gen_coord(size_x, size_y, rng);
+                let mut max_dist = calc_min_dist(current_best, &samples);
 
                 for _ in 0..(NUM_CANDIDATES_MULTIPLIER * i) {
-                    let candidate = gen_coord(size_x, size_y);
-                    let dist = calc_min_dist(candidate, &coords, size_x, size_y);
+                    let candidate = gen_coord(size_x, size_y, rng);
+                    let dist = calc_min_dist(candidate, &samples);
 
                     if dist > max_dist {
                         max_dist = dist;
@@ -59,171 +63,73 @@ mod number {
                     }
                 };
 
-                coords.push(current_best);
+                samples.insert(current_best);
             }
 
             Points {
-                box_size: Coord::new(size_x, size_y, 0.0),
-                coords,
+                box_size,
+                box_vectors,
+                coords: samples.coords().to_vec(),
             }
         }
     }
 
-    pub fn calc_min_dist(coord: Coord, samples: &[Coord], size_x: f64, size_y: f64) -> f64 {
-        use std::f64::MAX;
-
-        samples.iter()
-               .fold(MAX, |dist, &other| {
-                    dist.min(calc_toroidal_distance(coord, other, size_x, size_y))
-               })
-    }
-
-    pub fn calc_toroidal_distance(coord: Coord, other: Coord, size_x: f64, size_y: f64) -> f64 {
-        let mut dx = (coord.x - other.x).abs();
-        let mut dy = (coord.y - other.y).abs();
-
-        if dx > size_x / 2.0 {
-           dx = size_x - dx;
-        }
-
-        if dy > size_y / 2.0 {
-            dy = size_y - dy;
-        }
-
-        dx.powi(2) + dy.powi(2)
+    /// Return the distance from a coordinate to the closest sample, wrapped across
+    /// the periodic boundaries.
+    pub fn calc_min_dist(coord: Coord, samples: &CellList) -> f64 {
+        samples.nearest(coord).map(|(_, dist)| dist).unwrap_or(::std::f64::MAX)
     }
 
-    fn gen_coord(dx: f64, dy: f64) -> Coord {
-        let mut rng = rand::thread_rng();
-        let range_x = rand::distributions::Range::new(0.0, dx);
-        let range_y = rand::distributions::Range::new(0.0, dy);
+    fn gen_coord<R: Rng>(dx: f64, dy: f64, rng: &mut R) -> Coord {
+        let range_x = Range::new(0.0, dx);
+        let range_y = Range::new(0.0, dy);
 
-        Coord::new(range_x.ind_sample(&mut rng), range_y.ind_sample(&mut rng), 0.0)
+        Coord::new(range_x.ind_sample(rng), range_y.ind_sample(rng), 0.0)
     }
 }
 
 mod density {
-    use rand::distributions::IndependentSample;
     use super::*;
 
     pub struct PoissonDistribution;
 
     impl PoissonDistribution {
-        pub fn new(rmin: f64, size_x: f64, size_y: f64) -> Points {
-            let mut grid = PoissonGrid::new(rmin, size_x, size_y);
+        pub fn new<R: Rng>(rmin: f64, size_x: f64, size_y: f64, rng: &mut R) -> Points {
+            let box_size = Coord::new(size_x, size_y, 0.0);
+            let mut grid = CellList::with_bounds(Coord::ORIGO, box_size, rmin);
 
-            let init_coord = gen_grid_coord(size_x, size_y);
+            let init_coord = gen_grid_coord(size_x, size_y, rng);
             let mut active: Vec<Coord> = vec![init_coord];
-            grid.set_coord(init_coord).expect("There was an error when creating the Poisson disc distribution");
+            grid.insert(init_coord);
 
             while !active.is_empty() {
-                let index = select_coordinate(&active);
+                let index = select_coordinate(&active, rng);
+                let found = find_candidate(&active[index], &grid, rmin, box_size, rng);
 
-                if let Some(candidate) = find_candidate(&active[index], &grid) {
-                    if grid.set_coord(candidate).is_ok() {
-                        active.push(candidate);
-                    }
+                if let Some(candidate) = found {
+                    grid.insert(candidate);
+                    active.push(candidate);
                 } else {
                     active.remove(index);
                 };
             }
 
             Points {
-                box_size: Coord::new(size_x, size_y, 0.0),
-                coords: grid.into_coords(),
-            }
-        }
-    }
-
-    struct PoissonGrid {
-        spacing: f64,
-        rmin: f64,
-        size: (f64, f64),
-        shape: (usize, usize),
-        cells: Vec<Option<Coord>>,
-    }
-
-    impl PoissonGrid {
-        fn new(rmin: f64, size_x: f64, size_y: f64) -> PoissonGrid {
-            let a = rmin / 2.0f64.sqrt();
-            let nx = (size_x / a).ceil() as usize;
-            let ny = (size_y / a).ceil() as usize;
-
-            PoissonGrid {
-                spacing: a,
-                rmin: rmin,
-                size: (size_x, size_y),
-                shape: (nx, ny),
-                cells: vec![None; nx * ny],
-            }
-        }
-
-        fn cell_at_position(&self, col: usize, row: usize) -> usize {
-            let (nx, _) = self.shape;
-            row * nx + col
-        }
-
-        fn cell_at_coord(&self, coord: &Coord) -> usize {
-            let col = (coord.x / self.spacing).floor() as usize;
-            let row = (coord.y / self.spacing).floor() as usize;
-            self.cell_at_position(col, row)
-        }
-
-        fn collision(&self, coord: &Coord) -> bool {
-            let index = self.cell_at_coord(&coord);
-            self.get_neighbours(index)
-                .iter()
-                .filter_map(|opt| opt.map(|c| c.distance(*coord)))
-                .any(|r| r < self.rmin)
-        }
-
-        fn get_neighbours(&self, index: usize) -> Vec<Option<Coord>> {
-            let (nx, ny) = self.shape;
-            let i = (index % nx) as isize;
-            let j = (index / nx) as isize;
-
-            let mut neighbours = Vec::new();
-
-            // Since rmin = sqrt(2) * spacing we need to check cells
-            // that are up to two positions away
-            let (imin, imax) = (cmp::max(0, i - 2), cmp::min(nx as isize, i + 3));
-            let (jmin, jmax) = (cmp::max(0, j - 2), cmp::min(ny as isize, j + 3));
-
-            for col in imin..imax {
-                for row in jmin..jmax {
-                    let neighbour_index = self.cell_at_position(col as usize, row as usize);
-                    neighbours.push(self.cells[neighbour_index]);
-                }
+                box_size,
+                box_vectors: BoxVectors::rectangular(box_size),
+                coords: grid.coords().to_vec(),
             }
-
-            neighbours
-        }
-
-        fn into_coords(self) -> Vec<Coord> {
-            self.cells.iter().filter_map(|&c| c).collect()
-        }
-
-        fn set_coord(&mut self, coord: Coord) -> Result<(), &'static str> {
-            let index = self.cell_at_coord(&coord);
-
-            // Consistency check for the algorithm: this should never be reached
-            // but we prefer to not panic if it happens
-            if self.cells[index].is_some() {
-                return Err("Cannot add a coordinate to a cell which is already occupied");
-            }
-
-            self.cells[index] = Some(coord);
-            Ok(())
         }
     }
 
-    fn find_candidate(coord: &Coord, grid: &PoissonGrid) -> Option<Coord> {
+    fn find_candidate<R: Rng>(coord: &Coord, grid: &CellList, rmin: f64, box_size: Coord,
+                              rng: &mut R) -> Option<Coord> {
         const NUM_CANDIDATES: usize = 30;
 
         for _ in 0..NUM_CANDIDATES {
-            let candidate = gen_coord_around(&coord, &grid);
+            let candidate = gen_coord_around(coord, rmin, box_size, rng);
 
-            if !grid.collision(&candidate) {
+            if !grid.has_neighbour_within(candidate, rmin) {
                 return Some(candidate);
             }
         }
@@ -231,51 +137,49 @@ mod density {
         None
     }
 
-    fn gen_coord_around(coord: &Coord, grid: &PoissonGrid) -> Coord {
+    fn gen_coord_around<R: Rng>(coord: &Coord, rmin: f64, box_size: Coord, rng: &mut R)
+            -> Coord {
         use std::f64::consts::PI;
-        let mut rng = rand::thread_rng();
-        let range_dr = rand::distributions::Range::new(grid.rmin, 2.0 * grid.rmin);
-        let range_angle = rand::distributions::Range::new(0.0, 2.0 * PI);
-
-        let (max_x, max_y) = grid.size;
+        let range_dr = Range::new(rmin, 2.0 * rmin);
+        let range_angle = Range::new(0.0, 2.0 * PI);
 
         loop {
-            let dr = range_dr.ind_sample(&mut rng);
-            let angle = range_angle.ind_sample(&mut rng);
+            let dr = range_dr.ind_sample(rng);
+            let angle = range_angle.ind_sample(rng);
             let x = coord.x + dr * angle.cos();
             let y = coord.y + dr * angle.sin();
 
-            if x >= 0.0 && x < max_x && y >= 0.0 && y < max_y {
+            if x >= 0.0 && x < box_size.x && y >= 0.0 && y < box_size.y {
                 return Coord::new(x, y, 0.0);
             }
         }
     }
 
-    fn gen_grid_coord(x: f64, y: f64) -> Coord {
-        let mut rng = rand::thread_rng();
-        let range_x = rand::distributions::Range::new(0.0, x);
-        let range_y = rand::distributions::Range::new(0.0, y);
+    fn gen_grid_coord<R: Rng>(x: f64, y: f64, rng: &mut R) -> Coord {
+        let range_x = Range::new(0.0, x);
+        let range_y = Range::new(0.0, y);
 
-        Coord::new(range_x.ind_sample(&mut rng), range_y.ind_sample(&mut rng), 0.0)
+        Coord::new(range_x.ind_sample(rng), range_y.ind_sample(rng), 0.0)
     }
 
-    fn select_coordinate(coords: &Vec<Coord>) -> usize {
-        let mut rng = rand::thread_rng();
-        let range = rand::distributions::Range::new(0, coords.len());
+    fn select_coordinate<R: Rng>(coords: &Vec<Coord>, rng: &mut R) -> usize {
+        let range = Range::new(0, coords.len());
 
-        range.ind_sample(&mut rng)
+        range.ind_sample(rng)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use random::rng_from_seed;
 
     #[test]
     fn create_poisson_distribution() {
         let rmin = 1.0;
         let (size_x, size_y) = (5.0, 10.0);
-        let distribution = density::PoissonDistribution::new(rmin, size_x, size_y);
+        let distribution = density::PoissonDistribution::new(
+            rmin, size_x, size_y, &mut rng_from_seed(1));
 
         // We can only assert that no coordinates are within the minimum
         // distance of each other, or outside the box.
@@ -295,7 +199,8 @@ mod tests {
     fn create_blue_noise_distribution() {
         let num_points = 152;
         let (size_x, size_y) = (5.0, 10.0);
-        let distribution = number::BlueNoiseDistribution::new(num_points, size_x, size_y);
+        let distribution = number::BlueNoiseDistribution::new(
+            num_points, size_x, size_y, &mut rng_from_seed(1));
 
         // We can only (easily) assert that we have the input number of points
         // and that none are outside of the box.
@@ -308,32 +213,47 @@ mod tests {
     }
 
     #[test]
-    fn calculate_toroidal_distance_is_squared_and_wraps_coordinates_to_closest() {
-        let coord = Coord::new(0.0, 0.0, 10.0); // skip the z value
-        let other = Coord::new(1.5, 2.5, -10.0);
+    fn distributions_with_the_same_seed_are_reproduced() {
+        let first = Distribution::poisson(0.5, 5.0, 5.0, &mut rng_from_seed(7));
+        let second = Distribution::poisson(0.5, 5.0, 5.0, &mut rng_from_seed(7));
+        assert_eq!(first.coords, second.coords);
+
+        let first = Distribution::blue_noise(20, 5.0, 5.0, &mut rng_from_seed(7));
+        let second = Distribution::blue_noise(20, 5.0, 5.0, &mut rng_from_seed(7));
+        assert_eq!(first.coords, second.coords);
+    }
+
+    #[test]
+    fn calculate_min_distance_wraps_coordinates_to_closest() {
+        let coord = Coord::new(0.0, 0.0, 0.0);
+        let other = Coord::new(1.5, 2.5, 0.0);
 
         let (size_x, size_y) = (2.0_f64, 3.0_f64);
+        let box_vectors = BoxVectors::rectangular(Coord::new(size_x, size_y, 0.0));
+        let samples = CellList::new(&[other], 0.5, Some(box_vectors));
 
-        // Both coordinates are closer by wrapping around! Also, ignore the z coordinate!
-        let dist = (size_x - 1.5).powi(2) + (size_y - 2.5).powi(2);
+        // Both coordinates are closer by wrapping around!
+        let dist = ((size_x - 1.5).powi(2) + (size_y - 2.5).powi(2)).sqrt();
 
-        assert_eq!(number::calc_toroidal_distance(coord, other, size_x, size_y), dist);
+        assert!((number::calc_min_dist(coord, &samples) - dist).abs() < 1e-9);
     }
 
     #[test]
-    fn calculate_min_distance_squared_between_a_coordinate_and_a_set() {
-        let coord = Coord::new(2.0, 2.0, 2.0);
+    fn calculate_min_distance_between_a_coordinate_and_a_set() {
+        let coord = Coord::new(2.0, 2.0, 0.0);
 
         let candidates = vec![
             Coord::new(0.0, 0.0, 0.0), // distance squared: 2^2 + 2^2 =  8
-            Coord::new(3.0, 1.0, 3.0), //                   1^2 + 1^2 =  2, minimum!
-            Coord::new(5.0, 5.0, 5.0)  //                   3^2 + 3^2 = 18
+            Coord::new(3.0, 1.0, 0.0), //                   1^2 + 1^2 =  2, minimum!
+            Coord::new(5.0, 5.0, 0.0)  //                   3^2 + 3^2 = 18
         ];
 
-        let dist = 2.0 * 1.0_f64.powi(2);
+        let dist = 2.0_f64.sqrt();
 
-        let (size_x, size_y) = (10.0, 10.0);
+        let box_vectors = BoxVectors::rectangular(Coord::new(10.0, 10.0, 0.0));
+        let samples = CellList::new(&candidates, 1.0, Some(box_vectors));
 
-        assert_eq!(number::calc_min_dist(coord, &candidates, size_x, size_y), dist);
+        assert!((number::calc_min_dist(coord, &samples) - dist).abs() < 1e-9);
+        assert_eq!(number::calc_min_dist(coord, &CellList::new(&[], 1.0, None)), ::std::f64::MAX);
     }
 }
@@ -0,0 +1,563 @@
+//! Graft chains onto surfaces: either rigid molecules which form self-assembled
+//! monolayers or flexible polymer brushes.
+
+use surface::SurfaceNormal;
+
+use coord::{BoxVectors, Coord, Periodic, Rotate, Rotation, Translate, align_coords,
+            calc_extent};
+use describe::{unwrap_name, Describe};
+use error::{GrafenError, Result};
+use iterator::{ResidueIter, ResidueIterOut};
+use random::{describe_seed, rng_from_seed, seed_or_random};
+use spatial::CellList;
+use system::*;
+
+use rand;
+use rand::Rng;
+use rand::distributions::{IndependentSample, Range};
+use std::f64::consts::PI;
+
+/// Number of trial positions for every monomer in a random walk.
+const MAX_MONOMER_ATTEMPTS: usize = 100;
+
+/// Number of times that a chain is regrown from its anchor before it is given up on.
+const MAX_CHAIN_ATTEMPTS: usize = 10;
+
+impl_translate![GraftedChains];
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+/// Types of chains to graft onto a surface.
+pub enum ChainType {
+    /// Rigid molecules aligned along the surface normal, as in a self-assembled monolayer.
+    /// The molecule axis points from the anchor atom to the head atom.
+    Rigid { residue: Residue, anchor: usize, head: usize },
+    /// Flexible bead-spring chains of monomers grown by a self-avoiding random walk.
+    RandomWalk { monomer: Residue, num_monomers: usize, bond_length: f64 },
+}
+
+impl ChainType {
+    /// Return the residue of the chain.
+    fn residue(&self) -> &Residue {
+        match *self {
+            ChainType::Rigid { ref residue, .. } => residue,
+            ChainType::RandomWalk { ref monomer, .. } => monomer,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+/// Chains grafted onto a surface component.
+pub struct GraftedChains {
+    /// Name of component.
+    pub name: Option<String>,
+    /// Type of chains.
+    pub chain: ChainType,
+    /// Number of chains per area (1/nm^2).
+    pub grafting_density: f64,
+    /// Minimum distance between chain atoms and surface sites or other chains (nm).
+    /// For rigid chains this is also the distance between the surface site and anchor atom.
+    pub min_distance: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// Seed for the random number generator. Set to the used seed when
+    /// `construct` is called.
+    pub seed: Option<u64>,
+    #[serde(skip)]
+    /// Origin of the component, which is that of the surface.
+    pub origin: Coord,
+    #[serde(skip)]
+    /// Box size of the surface that the chains are grafted onto.
+    pub size: Coord,
+    #[serde(skip)]
+    /// Placed residues of all chains. Relative to the `origin`.
+    pub residues: Vec<PlacedResidue>,
+}
+
+impl GraftedChains {
+    /// Graft chains onto a surface and return the object.
+    ///
+    /// Anchor sites are randomly selected among the surface coordinates. Chains which
+    /// cannot be grown without overlaps are left out, so the final number of chains
+    /// may be lower than that from the grafting density.
+    ///
+    /// # Errors
+    /// Returns an error if the grafting density is not positive, if the surface has
+    /// been replicated or has no sites, if an atom index of a rigid chain is out of bounds
+    /// or if the bond length of a random walk chain is not positive.
+    pub fn construct<'a, T>(self, surface: &T) -> Result<GraftedChains>
+            where T: SurfaceNormal + Component<'a> {
+        if self.grafting_density <= 0.0 {
+            return Err(GrafenError::RunError("the grafting density must be positive".to_string()));
+        }
+
+        if surface.is_replicated() {
+            return Err(GrafenError::RunError(
+                "chains cannot be grafted onto a replicated surface: graft them onto \
+                 the single surface and replicate both".to_string()
+            ));
+        }
+
+        let sites = surface.surface_coords();
+
+        if sites.is_empty() {
+            return Err(GrafenError::RunError("the surface has no sites to graft onto".to_string()));
+        }
+
+        let num_chains = ((self.grafting_density * surface.surface_area()).round() as usize)
+            .min(sites.len());
+
+        let seed = seed_or_random(self.seed);
+        let mut rng = rng_from_seed(seed);
+        let anchors = rand::sample(&mut rng, 0..sites.len(), num_chains);
+
+        let residues = match self.chain {
+            ChainType::Rigid { ref residue, anchor, head } => {
+                self.graft_rigid(surface, &anchors, residue, anchor, head)?
+            },
+            ChainType::RandomWalk { num_monomers, bond_length, .. } => {
+                self.graft_random_walks(surface, &anchors, num_monomers, bond_length, &mut rng)?
+            },
+        };
+
+        Ok(GraftedChains {
+            seed: Some(seed),
+            origin: surface.get_origin(),
+            size: surface.box_size() - surface.get_origin(),
+            residues,
+            .. self
+        })
+    }
+
+    /// Return the number of grafted chains.
+    pub fn num_chains(&self) -> usize {
+        match self.chain {
+            ChainType::Rigid { .. } => self.residues.len(),
+            ChainType::RandomWalk { num_monomers, .. } => {
+                self.residues.len() / num_monomers.max(1)
+            },
+        }
+    }
+
+    /// Calculate the box size.
+    fn calc_box_size(&self) -> Coord {
+        self.size
+    }
+
+    /// Place rigid molecules along the surface normal at every anchor. Molecules with any
+    /// atom closer than the minimum distance to atoms of those already placed are left out.
+    fn graft_rigid<T: SurfaceNormal>(&self, surface: &T, anchors: &[usize], residue: &Residue,
+                                     anchor_atom: usize, head_atom: usize)
+            -> Result<Vec<PlacedResidue>> {
+        let atoms = &residue.atoms;
+
+        if anchor_atom >= atoms.len() || head_atom >= atoms.len() {
+            return Err(GrafenError::RunError(format!(
+                "the anchor or head atom index of '{}' is out of bounds", residue.code
+            )));
+        }
+
+        let anchor_position = atoms[anchor_atom].position;
+        let axis = atoms[head_atom].position - anchor_position;
+        let relative = atoms.iter().map(|atom| atom.position - anchor_position).collect::<Vec<_>>();
+
+        // Placed atoms are indexed in the region which the molecules can reach
+        let reach = relative.iter().map(|c| c.norm()).fold(0.0, f64::max) + self.min_distance;
+        let margin = Coord::new(reach, reach, reach);
+        let (lower, upper) = calc_extent(surface.surface_coords())
+            .unwrap_or((Coord::ORIGO, Coord::ORIGO));
+
+        let mut placed = CellList::with_bounds(lower - margin, upper + margin, self.min_distance);
+        let mut residues = Vec::new();
+
+        for &i in anchors {
+            let site = surface.surface_coords()[i];
+            let normal = surface.normal_at_site(i);
+            let start = site + normal * self.min_distance;
+
+            let atoms = align_coords(&relative, axis, normal)
+                .into_iter()
+                .map(|c| c + start)
+                .collect::<Vec<_>>();
+
+            if atoms.iter().all(|&c| !placed.has_neighbour_within(c, self.min_distance)) {
+                atoms.iter().for_each(|&c| { placed.insert(c); });
+                residues.push(PlacedResidue { residue: 0, atoms });
+            }
+        }
+
+        Ok(residues)
+    }
+
+    /// Grow chains from every anchor by self-avoiding random walks.
+    fn graft_random_walks<T, R>(&self, surface: &T, anchors: &[usize], num_monomers: usize,
+                                bond_length: f64, rng: &mut R) -> Result<Vec<PlacedResidue>>
+            where T: SurfaceNormal, R: Rng {
+        if bond_length <= 0.0 {
+            return Err(GrafenError::RunError("the bond length must be positive".to_string()));
+        }
+
+        let relative = self.chain.residue().atoms
+            .iter()
+            .map(|atom| atom.position)
+            .collect::<Vec<_>>();
+
+        // All monomer positions are checked against these for overlaps
+        let mut occupied = CellList::new(surface.surface_coords(), self.min_distance, None);
+        let mut residues = Vec::new();
+
+        for &i in anchors {
+            let site = surface.surface_coords()[i];
+            let normal = surface.normal_at_site(i);
+
+            let chain = (0..MAX_CHAIN_ATTEMPTS)
+                .filter_map(|_| {
+                    grow_chain(site, normal, num_monomers, bond_length,
+                               self.min_distance, &occupied, rng)
+                })
+                .next();
+
+            if let Some(positions) = chain {
+                positions.iter().for_each(|&position| { occupied.insert(position); });
+
+                residues.extend(positions.iter().map(|&position| PlacedResidue {
+                    residue: 0,
+                    atoms: relative.iter().map(|&c| c + position).collect(),
+                }));
+            }
+        }
+
+        Ok(residues)
+    }
+}
+
+/// Grow a single chain from an anchor site by a self-avoiding random walk.
+///
+/// The first monomer is placed one bond length from the site along the normal.
+/// Returns `None` if a monomer cannot be placed without overlaps.
+fn grow_chain<R: Rng>(site: Coord, normal: Coord, num_monomers: usize, bond_length: f64,
+                      min_distance: f64, occupied: &CellList, rng: &mut R)
+                      -> Option<Vec<Coord>> {
+    let range_cos = Range::new(-1.0, 1.0);
+    let range_phi = Range::new(0.0, 2.0 * PI);
+
+    let mut chain = Vec::with_capacity(num_monomers);
+    let mut previous = site;
+
+    for i in 0..num_monomers {
+        // The directly bonded monomer is allowed to be closer than the minimum distance,
+        // as is the anchor site to the first monomer
+        let num_bonded = chain.len().saturating_sub(1);
+        let is_free = |candidate: Coord| {
+            occupied.within(candidate, min_distance)
+                .into_iter()
+                .map(|j| occupied.coords()[j])
+                .filter(|&c| i > 0 || c != site)
+                .chain(chain[..num_bonded].iter().cloned())
+                .all(|c| c.distance(candidate) >= min_distance)
+        };
+
+        let position = if i == 0 {
+            let first = site + normal * bond_length;
+
+            if is_free(first) { Some(first) } else { None }
+        } else {
+            (0..MAX_MONOMER_ATTEMPTS)
+                .map(|_| {
+                    let cos_theta: f64 = range_cos.ind_sample(rng);
+                    let sin_theta = (1.0 - cos_theta.powi(2)).sqrt();
+                    let phi: f64 = range_phi.ind_sample(rng);
+
+                    let direction = Coord::new(
+                        sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta
+                    );
+
+                    previous + direction * bond_length
+                })
+                .find(|&c| (c - site).dot(normal) > 0.0 && is_free(c))
+        }?;
+
+        chain.push(position);
+        previous = position;
+    }
+
+    Some(chain)
+}
+
+impl<'a> Component<'a> for GraftedChains {
+    fn assign_residues(&mut self, residues: &[ResidueIterOut]) {
+        self.residues = placed_residues_from_iter(&[self.chain.residue()], residues);
+    }
+
+    fn box_size(&self) -> Coord {
+        self.calc_box_size() + self.origin
+    }
+
+    fn get_origin(&self) -> Coord {
+        self.origin
+    }
+
+    fn iter_residues(&self) -> ResidueIter {
+        ResidueIter::Placed(vec![self.chain.residue()], self.residues.iter())
+    }
+
+    fn num_atoms(&self) -> u64 {
+        self.residues.iter().map(|res| res.atoms.len() as u64).sum()
+    }
+
+    fn total_mass(&self) -> Option<f64> {
+        self.chain.residue().mass().map(|mass| mass * self.residues.len() as f64)
+    }
+
+    fn net_charge(&self) -> f64 {
+        self.chain.residue().charge() * self.residues.len() as f64
+    }
+
+    /// Chains are kept whole and not wrapped into the box.
+    fn with_pbc(self) -> Self {
+        self
+    }
+}
+
+impl Rotate for GraftedChains {
+    /// Rotate all chains and set the size to the box which encloses them.
+    fn rotate_in_place(&mut self, rotation: &Rotation, center: Coord) {
+        let extent = rotate_placed_residues(&mut self.residues, self.origin, rotation, center);
+
+        if let Some((origin, size)) = extent {
+            self.origin = origin;
+            self.size = size;
+        }
+    }
+}
+
+impl Describe for GraftedChains {
+    fn describe(&self) -> String {
+        let kind = match self.chain {
+            ChainType::Rigid { .. } => "rigid",
+            ChainType::RandomWalk { .. } => "random walk",
+        };
+
+        format!("{} ({} grafted {} chains of {} at {}{})",
+            unwrap_name(&self.name), self.num_chains(), kind,
+            self.chain.residue().code, self.origin, describe_seed(self.seed))
+    }
+
+    fn describe_short(&self) -> String {
+        format!("{} (Grafted chains)", unwrap_name(&self.name))
+    }
+}
+
+impl Periodic for GraftedChains {
+    /// Clone the chains into PBC multiples of the size.
+    fn pbc_multiply(&self, nx: usize, ny: usize, nz: usize) -> GraftedChains {
+        let shifts = BoxVectors::rectangular(self.size).image_shifts(nx, ny, nz);
+
+        let residues = shifts
+            .iter()
+            .flat_map(|&shift| self.residues.iter().map(move |res| PlacedResidue {
+                residue: res.residue,
+                atoms: res.atoms.iter().map(|&c| c + shift).collect(),
+            }))
+            .collect();
+
+        GraftedChains {
+            size: self.size.pbc_multiply(nx, ny, nz),
+            residues,
+            .. self.clone()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use coord::Direction;
+    use surface::{LatticeType, Sheet, Sphere};
+
+    fn setup_sheet() -> Sheet {
+        Sheet {
+            name: None,
+            residue: Some(resbase!["SUB", ("C", 0.0, 0.0, 0.0)]),
+            lattice: LatticeType::Triclinic { a: 0.5, b: 0.5, gamma: 90.0 },
+            std_z: None,
+            roughness: None,
+            orient_residues: false,
+            seed: None,
+            origin: Coord::new(1.0, 2.0, 3.0),
+            normal: Direction::Z,
+            length: 5.0,
+            width: 5.0,
+            coords: vec![],
+            orientations: vec![],
+            normals: vec![],
+            cell: None,
+            frame: None,
+        }.construct().unwrap()
+    }
+
+    fn setup_grafts(chain: ChainType) -> GraftedChains {
+        GraftedChains {
+            name: None,
+            chain,
+            grafting_density: 1.0,
+            min_distance: 0.3,
+            seed: None,
+            origin: Coord::ORIGO,
+            size: Coord::ORIGO,
+            residues: vec![],
+        }
+    }
+
+    #[test]
+    fn rigid_chains_are_aligned_along_the_surface_normal() {
+        let sheet = setup_sheet();
+
+        // A molecule lying along x with its anchor at the last atom
+        let residue = resbase!["SAM", ("HEAD", 1.0, 0.0, 0.0), ("S", 0.0, 0.0, 0.0)];
+        let chain = ChainType::Rigid { residue, anchor: 1, head: 0 };
+
+        let grafts = setup_grafts(chain).construct(&sheet).unwrap();
+
+        assert_eq!(grafts.num_chains(), 25);
+        assert_eq!(grafts.num_atoms(), 50);
+        assert_eq!(grafts.get_origin(), sheet.get_origin());
+
+        for res in &grafts.residues {
+            let (head, anchor) = (res.atoms[0], res.atoms[1]);
+
+            assert!((anchor.z - 0.3).abs() < 1e-9);
+            assert_eq!(head - anchor, Coord::new(0.0, 0.0, 1.0));
+            assert!(sheet.coords.contains(&(anchor - Coord::new(0.0, 0.0, 0.3))));
+        }
+    }
+
+    #[test]
+    fn rigid_chains_which_overlap_already_placed_chains_are_left_out() {
+        // Anchor sites are closer to each other than the molecules are wide
+        let sheet = Sheet {
+            lattice: LatticeType::Triclinic { a: 0.2, b: 0.2, gamma: 90.0 },
+            .. setup_sheet()
+        }.construct().unwrap();
+
+        let residue = resbase!["SAM",
+            ("HEAD", 1.0, 0.0, 0.0), ("S", 0.0, 0.0, 0.0), ("SIDE", 0.5, 0.4, 0.0)];
+        let chain = ChainType::Rigid { residue, anchor: 1, head: 0 };
+
+        let grafts = GraftedChains {
+            grafting_density: 25.0,
+            .. setup_grafts(chain)
+        }.construct(&sheet).unwrap();
+
+        let num_chains = grafts.num_chains();
+        assert!(num_chains > 0 && num_chains < sheet.coords.len());
+
+        for (i, res1) in grafts.residues.iter().enumerate() {
+            for res2 in grafts.residues.iter().skip(i + 1) {
+                for &c1 in &res1.atoms {
+                    assert!(res2.atoms.iter().all(|&c2| c1.distance(c2) >= 0.3));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn random_walk_chains_are_self_avoiding_and_have_the_bond_length() {
+        let sheet = setup_sheet();
+
+        let monomer = resbase!["MON", ("B", 0.0, 0.0, 0.0)];
+        let chain = ChainType::RandomWalk { monomer, num_monomers: 5, bond_length: 0.5 };
+
+        let grafts = GraftedChains {
+            grafting_density: 0.2,
+            .. setup_grafts(chain)
+        }.construct(&sheet).unwrap();
+
+        let num_chains = grafts.num_chains();
+        assert!(num_chains > 0 && num_chains <= 5);
+
+        let positions = grafts.residues.iter().map(|res| res.atoms[0]).collect::<Vec<_>>();
+
+        for chain in positions.chunks(5) {
+            assert!((chain[0].z - 0.5).abs() < 1e-9);
+
+            for pair in chain.windows(2) {
+                assert!((pair[0].distance(pair[1]) - 0.5).abs() < 1e-9);
+            }
+        }
+
+        // Non-bonded monomers are separated and do not overlap with the surface
+        for (i, &c1) in positions.iter().enumerate() {
+            for (j, &c2) in positions.iter().enumerate().skip(i + 1) {
+                if j != i + 1 || j % 5 == 0 {
+                    assert!(c1.distance(c2) >= 0.3);
+                }
+            }
+
+            for &site in &sheet.coords {
+                assert!(c1.distance(site) >= 0.3);
+            }
+        }
+    }
+
+    #[test]
+    fn grafting_with_the_same_seed_is_reproduced() {
+        let sheet = setup_sheet();
+
+        let monomer = resbase!["MON", ("B", 0.0, 0.0, 0.0)];
+        let chain = ChainType::RandomWalk { monomer, num_monomers: 5, bond_length: 0.5 };
+
+        let grafts = GraftedChains {
+            grafting_density: 0.2,
+            seed: Some(11),
+            .. setup_grafts(chain)
+        };
+
+        let first = grafts.clone().construct(&sheet).unwrap();
+        let second = grafts.construct(&sheet).unwrap();
+
+        assert_eq!(first.seed, Some(11));
+        assert_eq!(first.residues, second.residues);
+    }
+
+    #[test]
+    fn grafting_with_bad_input_returns_error() {
+        let sheet = setup_sheet();
+        let residue = resbase!["SAM", ("HEAD", 1.0, 0.0, 0.0), ("S", 0.0, 0.0, 0.0)];
+
+        let chain = ChainType::Rigid { residue: residue.clone(), anchor: 2, head: 0 };
+        assert!(setup_grafts(chain).construct(&sheet).is_err());
+
+        let chain = ChainType::RandomWalk {
+            monomer: residue.clone(),
+            num_monomers: 2,
+            bond_length: 0.0,
+        };
+        assert!(setup_grafts(chain).construct(&sheet).is_err());
+
+        let chain = ChainType::Rigid { residue: residue.clone(), anchor: 1, head: 0 };
+        let grafts = GraftedChains { grafting_density: 0.0, .. setup_grafts(chain) };
+        assert!(grafts.construct(&sheet).is_err());
+    }
+
+    #[test]
+    fn grafting_onto_a_replicated_surface_returns_error() {
+        let sphere = Sphere {
+            name: None,
+            residue: Some(resbase!["SUB", ("C", 0.0, 0.0, 0.0)]),
+            density: 2.0,
+            orient_residues: false,
+            origin: Coord::ORIGO,
+            radius: 1.0,
+            coords: vec![],
+            orientations: vec![],
+            cell: None,
+            frame: None,
+            images: None,
+        }.construct().unwrap();
+
+        let residue = resbase!["SAM", ("HEAD", 1.0, 0.0, 0.0), ("S", 0.0, 0.0, 0.0)];
+        let chain = ChainType::Rigid { residue, anchor: 1, head: 0 };
+
+        assert!(setup_grafts(chain.clone()).construct(&sphere).is_ok());
+        assert!(setup_grafts(chain).construct(&sphere.pbc_multiply(2, 1, 1)).is_err());
+    }
+}
@@ -2,7 +2,7 @@
 //! information about and grid coordinates of lattices. It comes
 //! with easy-to-use constructors for different lattice types.
 
-use coord::Coord;
+use coord::{BoxVectors, Coord};
 use surface::points::Points;
 
 pub struct Lattice;
@@ -52,11 +52,29 @@ impl LatticeBuilder {
             _ => self.generic(),
         };
 
-        let Spacing(dx, dy, _) = self.crystal.spacing();
+        let Spacing(dx, dy, dx_per_row) = self.crystal.spacing();
         let box_size = Coord::new((self.nx as f64) * dx, (self.ny as f64) * dy, 0.0);
 
+        // The natural cell of the lattice is sheared by the shift along x of all rows.
+        // Since the lattice is periodic along x this shift is reduced to the smallest
+        // equivalent value. Rounding errors (eg. from cos(90°)) must not shear the cell.
+        let shear = (self.ny as f64) * dx_per_row;
+        let shear = if box_size.x > 0.0 {
+            shear - box_size.x * (shear / box_size.x).round()
+        } else {
+            shear
+        };
+        let shear = if shear.abs() < 1e-9 { 0.0 } else { shear };
+
+        let box_vectors = BoxVectors {
+            a: Coord::new(box_size.x, 0.0, 0.0),
+            b: Coord::new(shear, box_size.y, 0.0),
+            c: Coord::ORIGO,
+        };
+
         Points {
             box_size: box_size,
+            box_vectors,
             coords: coords,
         }
     }
@@ -222,6 +240,36 @@ mod tests {
         assert_eq!(None, iter.next());
     }
 
+    #[test]
+    fn triclinic_lattice_has_a_sheared_natural_cell() {
+        let angle = 60f64.to_radians();
+        let lattice = Lattice::triclinic(1.0, 1.0, angle).with_bins(2, 3).finalize();
+
+        // Three rows shift the lattice by 1.5 along x, which is reduced to -0.5
+        assert_eq!(lattice.box_vectors.a, Coord::new(2.0, 0.0, 0.0));
+        assert_eq!(lattice.box_vectors.b, Coord::new(-0.5, lattice.box_size.y, 0.0));
+
+        // A larger lattice is a periodic replication of the cell
+        let extended = Lattice::triclinic(1.0, 1.0, angle).with_bins(2, 6).finalize();
+
+        let wrapped = lattice.coords
+            .iter()
+            .map(|&c| lattice.box_vectors.wrap(c))
+            .collect::<Vec<_>>();
+
+        for &coord in &extended.coords {
+            let image = lattice.box_vectors.wrap(coord);
+            assert!(wrapped.iter().any(|&c| c.distance(image) < 1e-6));
+        }
+    }
+
+    #[test]
+    fn rectangular_lattice_has_a_rectangular_natural_cell() {
+        let lattice = Lattice::triclinic(1.0, 2.0, 90f64.to_radians()).with_bins(2, 2).finalize();
+
+        assert_eq!(lattice.box_vectors.bounding_size(), lattice.box_size);
+    }
+
     #[test]
     fn hexagonal_lattice_has_empty_points() {
         let lattice = Lattice::hexagonal(1.0).with_bins(6, 2).finalize();
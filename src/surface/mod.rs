@@ -1,15 +1,85 @@
 //! Define and construct 2D surface objects.
 
+mod bilayer;
 mod cuboid;
 mod cylinder;
 mod distribution;
+mod graft;
 mod lattice;
 mod points;
+mod pore;
+mod roughness;
 mod sheet;
+mod sphere;
 
+pub use self::bilayer::{Bilayer, Lipid, LipidPacking};
 pub use self::cuboid::{Cuboid, Sides};
+pub use self::pore::Pore;
+pub use self::roughness::Roughness;
 pub use self::sheet::{Circle, Sheet};
-pub use self::cylinder::{Cylinder, CylinderCap};
+pub use self::sphere::Sphere;
+pub use self::cylinder::{CapShape, Cylinder, CylinderCap};
+pub use self::graft::{ChainType, GraftedChains};
+
+use coord::{Coord, Frame, Rotation};
+
+/// Surfaces which residues can be grafted onto.
+pub trait SurfaceNormal {
+    /// Return the positions of all surface sites, relative to the surface origin.
+    fn surface_coords(&self) -> &[Coord];
+
+    /// Return the outward unit normal of the unrotated surface geometry at a position
+    /// relative to its origin.
+    fn unrotated_normal_at(&self, coord: Coord) -> Coord;
+
+    /// Return the rotation frame of the surface, if it has been rotated.
+    fn frame(&self) -> Option<Frame>;
+
+    /// Return the outward unit normal of the surface at a position relative
+    /// to its origin.
+    fn normal_at(&self, coord: Coord) -> Coord {
+        match self.frame() {
+            Some(frame) => frame.to_rotated(self.unrotated_normal_at(frame.to_unrotated(coord))),
+            None => self.unrotated_normal_at(coord),
+        }
+    }
+
+    /// Return the outward unit normal of the surface at the site with an index
+    /// in `surface_coords`.
+    ///
+    /// The default implementation evaluates `normal_at` at the position of the site.
+    fn normal_at_site(&self, index: usize) -> Coord {
+        self.normal_at(self.surface_coords()[index])
+    }
+
+    /// Return the area of the surface.
+    fn surface_area(&self) -> f64;
+
+    /// Return whether the surface has been replicated into periodic images, which
+    /// its geometry and thus its normals and area do not describe.
+    fn is_replicated(&self) -> bool {
+        false
+    }
+}
+
+/// Calculate the rotations which align the z axis of residues with the surface normal
+/// at every surface site.
+fn calc_normal_orientations<T: SurfaceNormal>(surface: &T) -> Vec<Rotation> {
+    surface.surface_coords()
+        .iter()
+        .map(|&coord| Rotation::align(Coord::new(0.0, 0.0, 1.0), surface.normal_at(coord)))
+        .collect()
+}
+
+/// Return the number of periodic images along each axis after replicating an object
+/// with a number of images into multiples, or `None` if it is a single object.
+fn multiply_images(images: Option<[usize; 3]>, nx: usize, ny: usize, nz: usize)
+        -> Option<[usize; 3]> {
+    let images = images.unwrap_or([1, 1, 1]);
+    let multiples = [images[0] * nx, images[1] * ny, images[2] * nz];
+
+    if multiples == [1, 1, 1] { None } else { Some(multiples) }
+}
 
 #[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
 /// Lattice types which a substrate can be constructed from.
@@ -40,3 +110,13 @@ pub enum LatticeType {
         number: u64
     },
 }
+
+impl LatticeType {
+    /// Return whether points of the lattice are generated from random numbers.
+    pub fn is_stochastic(&self) -> bool {
+        match *self {
+            LatticeType::PoissonDisc { .. } | LatticeType::BlueNoise { .. } => true,
+            LatticeType::Hexagonal { .. } | LatticeType::Triclinic { .. } => false,
+        }
+    }
+}
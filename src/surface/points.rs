@@ -3,14 +3,16 @@
 //! using a Lattice or Poisson Disc generator) all transformations
 //! of the points belong in this module.
 
-use rand;
+use rand::Rng;
 
-use coord::Coord;
+use coord::{BoxVectors, Coord};
 
 /// A collection of points to broadcast residues onto.
 pub struct Points {
     /// Box dimensions.
     pub box_size: Coord,
+    /// Vectors of the periodic cell of the points, which for lattices may be triclinic.
+    pub box_vectors: BoxVectors,
     /// Points.
     pub coords: Vec<Coord>,
 }
@@ -20,16 +22,15 @@ impl Points {
     /// have been shifted by a uniform random distribution.
     /// The positions are shifted on a range of (-std_z, +std_z)
     /// where std_z is the input deviation.
-    pub fn uniform_distribution(&self, std_z: f64) -> Points {
-        use rand::distributions::IndependentSample;
+    pub fn uniform_distribution<R: Rng>(&self, std_z: f64, rng: &mut R) -> Points {
+        use rand::distributions::{IndependentSample, Range};
 
-        let range = rand::distributions::Range::new(-std_z, std_z);
-        let mut rng = rand::thread_rng();
+        let range = Range::new(-std_z, std_z);
 
         let coords: Vec<Coord> = self.coords
             .iter()
             .map(|&c| {
-                    let add_z = range.ind_sample(&mut rng);
+                    let add_z = range.ind_sample(rng);
                     //c.add(Coord::new(0.0, 0.0, add_z))
                     c + Coord::new(0.0, 0.0, add_z)
             })
@@ -37,6 +38,7 @@ impl Points {
 
         Points {
             box_size: self.box_size,
+            box_vectors: self.box_vectors,
             coords: coords,
         }
     }
@@ -45,17 +47,19 @@ impl Points {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use random::rng_from_seed;
 
     #[test]
     fn uniform_distribution_of_positions() {
         let z0 = 1.0;
         let points = Points {
             box_size: Coord::new(1.0, 1.0, 1.0),
+            box_vectors: BoxVectors::rectangular(Coord::new(1.0, 1.0, 1.0)),
             coords: vec![Coord::new(0.0, 0.0, z0); 100],
         };
 
         let dz = 0.1;
-        let distributed_points = points.uniform_distribution(dz);
+        let distributed_points = points.uniform_distribution(dz, &mut rng_from_seed(1));
 
         // Assert that the positions are centered around z0 with non-zero variance
         assert!(distributed_points.coords.iter().all(|&c| c.z.abs() - z0 <= dz));
@@ -67,4 +71,20 @@ mod tests {
             .sum::<f64>() / (len as f64);
         assert!(var_z > 0.0);
     }
+
+    #[test]
+    fn uniform_distribution_with_the_same_seed_is_reproduced() {
+        let points = Points {
+            box_size: Coord::new(1.0, 1.0, 1.0),
+            box_vectors: BoxVectors::rectangular(Coord::new(1.0, 1.0, 1.0)),
+            coords: vec![Coord::ORIGO; 10],
+        };
+
+        let first = points.uniform_distribution(0.1, &mut rng_from_seed(5));
+        let second = points.uniform_distribution(0.1, &mut rng_from_seed(5));
+        let third = points.uniform_distribution(0.1, &mut rng_from_seed(6));
+
+        assert_eq!(first.coords, second.coords);
+        assert_ne!(first.coords, third.coords);
+    }
 }
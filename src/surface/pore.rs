@@ -0,0 +1,472 @@
+//! Construct sheets with a pore through them.
+
+use surface::{LatticeType, Sheet, SurfaceNormal, calc_normal_orientations, multiply_images};
+
+use coord::{BoxVectors, Coord, Direction, Frame, Rotate, Rotation, Translate};
+use describe::{unwrap_name, Describe};
+use error::{GrafenError, Result};
+use iterator::{ResidueIter, ResidueIterOut};
+use random::{derive_seed, describe_seed, seed_or_random};
+use system::*;
+
+use std::f64::consts::PI;
+
+/// Minimum height of the box of a pore (nm), as a lower limit of the system.
+const PORE_MARGIN: f64 = 0.1;
+
+impl_component![@oriented Pore];
+impl_translate![Pore];
+impl_rotate![Pore];
+impl_periodic![Pore];
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+/// A sheet in the x-y plane with a cylindrical or conical pore through it. The pore wall
+/// is lined with the lattice of the sheet and directed along the positive z axis.
+///
+/// Optionally a second sheet is added at the top of the wall, forming a slit membrane
+/// of two sheets which are connected by the pore.
+pub struct Pore {
+    /// Name of component.
+    pub name: Option<String>,
+    /// Optional residue placed at each coordinate. If not set the pore describes
+    /// a general collection of coordinates.
+    pub residue: Option<Residue>,
+    /// Lattice type used to construct the sheets and the pore wall.
+    pub lattice: LatticeType,
+    /// Add a sheet at the top of the pore wall.
+    pub slit: bool,
+    #[serde(default)]
+    /// Rotate residues such that their z axis follows the surface normal at their position.
+    pub orient_residues: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// Seed for the random number generator of stochastic lattices. Set to the used
+    /// seed when `construct` is called.
+    pub seed: Option<u64>,
+    #[serde(skip)]
+    /// Origin of the pore. Located in the lower-left position of the bottom sheet.
+    pub origin: Coord,
+    #[serde(skip)]
+    /// Length of the sheets along the x axis.
+    pub length: f64,
+    #[serde(skip)]
+    /// Length of the sheets along the y axis.
+    pub width: f64,
+    #[serde(skip)]
+    /// Height of the pore wall along the z axis.
+    pub height: f64,
+    #[serde(skip)]
+    /// Radius of the pore at the bottom sheet.
+    pub bottom_radius: f64,
+    #[serde(skip)]
+    /// Radius of the pore at the top of the wall.
+    pub top_radius: f64,
+    #[serde(skip)]
+    /// List of coordinates belonging to the pore. Relative to the `origin`.
+    pub coords: Vec<Coord>,
+    #[serde(skip)]
+    /// Rotation of the residue at every coordinate, if residues are oriented along
+    /// the surface normal. Empty if they are not.
+    pub orientations: Vec<Rotation>,
+    #[serde(skip)]
+    /// Periodic cell of the object if it differs from the box calculated from its geometry,
+    /// eg. the box enclosing all atoms after the object has been rotated.
+    pub cell: Option<BoxVectors>,
+    #[serde(skip)]
+    /// Rotation of the object relative to its geometry, if it has been rotated.
+    pub frame: Option<Frame>,
+    #[serde(skip)]
+    /// Number of periodic images along each axis, if the pore has been replicated
+    /// into several pores.
+    pub images: Option<[usize; 3]>,
+}
+
+impl Pore {
+    /// Construct the pore coordinates and return the object.
+    ///
+    /// The pore wall is a sheet which is rolled around the pore axis. Its circumference
+    /// is matched to the lattice spacing, which scales both radii equally. Conical walls
+    /// are rolled such that the lattice spacing is kept along every height, which leaves
+    /// a seam where the sheet is cut. The holes of the sheets match the final radii.
+    ///
+    /// # Errors
+    /// Returns an error if any size or radius is non-positive, or if the pore does not
+    /// fit inside of the sheets.
+    pub fn construct(self) -> Result<Pore> {
+        if self.bottom_radius <= 0.0 || self.top_radius <= 0.0 || self.height <= 0.0 {
+            return Err(GrafenError::RunError(
+                "the pore radii and height must be positive".to_string()
+            ));
+        }
+
+        let seed = if self.lattice.is_stochastic() {
+            Some(seed_or_random(self.seed))
+        } else {
+            self.seed
+        };
+
+        // The wall and the sheets are constructed with their own seeds
+        let part_seed = |i| seed.map(|seed| derive_seed(seed, i));
+
+        let sheet_base = Sheet {
+            name: None,
+            residue: None,
+            lattice: self.lattice,
+            std_z: None,
+            roughness: None,
+            orient_residues: false,
+            seed: None,
+            origin: Coord::ORIGO,
+            normal: Direction::Z,
+            length: 0.0,
+            width: 0.0,
+            coords: Vec::new(),
+            orientations: vec![],
+            normals: vec![],
+            cell: None,
+            frame: None,
+        };
+
+        let max_radius = self.bottom_radius.max(self.top_radius);
+        let slant = (self.height.powi(2) + (self.top_radius - self.bottom_radius).powi(2)).sqrt();
+
+        let wall_sheet = Sheet {
+            seed: part_seed(0),
+            length: 2.0 * PI * max_radius,
+            width: slant,
+            .. sheet_base.clone()
+        }.construct()?;
+
+        let scale = wall_sheet.length / (2.0 * PI * max_radius);
+        let bottom_radius = scale * self.bottom_radius;
+        let top_radius = scale * self.top_radius;
+        let slant = wall_sheet.width;
+
+        let delta_radius = top_radius - bottom_radius;
+        if slant <= delta_radius.abs() {
+            return Err(GrafenError::RunError(
+                "the pore wall is too short for the lattice".to_string()
+            ));
+        }
+        let height = (slant.powi(2) - delta_radius.powi(2)).sqrt();
+
+        let sheet = Sheet {
+            seed: part_seed(1),
+            length: self.length,
+            width: self.width,
+            .. sheet_base.clone()
+        }.construct()?.with_pbc();
+
+        if 2.0 * max_radius * scale > sheet.length.min(sheet.width) {
+            return Err(GrafenError::RunError(
+                "the pore does not fit inside of the sheet".to_string()
+            ));
+        }
+
+        let center = Coord::new(0.5 * sheet.length, 0.5 * sheet.width, 0.0);
+
+        let mut coords = cut_hole(&sheet.coords, center, bottom_radius);
+
+        coords.extend(wall_sheet.coords
+            .iter()
+            .filter_map(|coord| {
+                let fraction = coord.y / slant;
+                let radius = bottom_radius + fraction * delta_radius;
+
+                // The circumference shrinks toward the narrow end: cut the sheet to match
+                if coord.x < 2.0 * PI * radius {
+                    let angle = coord.x / radius;
+
+                    Some(center + Coord::new(
+                        radius * angle.cos(), radius * angle.sin(), fraction * height
+                    ))
+                } else {
+                    None
+                }
+            })
+        );
+
+        if self.slit {
+            let shift = Coord::new(0.0, 0.0, height);
+
+            coords.extend(cut_hole(&sheet.coords, center, top_radius)
+                .into_iter()
+                .map(|coord| coord + shift)
+            );
+        }
+
+        // Sheets of triclinic lattices keep their natural periodic cell, along which
+        // the pore is periodic
+        let cell = sheet.cell.map(|cell| BoxVectors {
+            c: Coord::new(0.0, 0.0, height.max(PORE_MARGIN)),
+            .. cell
+        });
+
+        let mut pore = Pore {
+            seed,
+            length: sheet.length,
+            width: sheet.width,
+            height,
+            bottom_radius,
+            top_radius,
+            coords,
+            orientations: vec![],
+            cell,
+            .. self
+        };
+
+        if pore.orient_residues {
+            pore.orientations = calc_normal_orientations(&pore);
+        }
+
+        Ok(pore)
+    }
+
+    /// Calculate the box size.
+    fn calc_box_size(&self) -> Coord {
+        Coord::new(self.length, self.width, self.height.max(PORE_MARGIN))
+    }
+
+    /// Calculate along which axes the pore is periodic. Its sheets are periodic in-plane,
+    /// unless it has been rotated.
+    fn calc_periodic_axes(&self) -> [bool; 3] {
+        let in_plane = self.frame.is_none();
+
+        [in_plane, in_plane, false]
+    }
+
+    /// Update the geometry after the pore has been replicated. The images are
+    /// several pores.
+    fn replicate_geometry(&mut self, nx: usize, ny: usize, nz: usize) {
+        self.images = multiply_images(self.images, nx, ny, nz);
+    }
+
+    /// Return the position of the pore axis at the bottom sheet, relative to the origin.
+    fn calc_center(&self) -> Coord {
+        Coord::new(0.5 * self.length, 0.5 * self.width, 0.0)
+    }
+
+    /// Return the radius of the pore at a height above the bottom sheet.
+    fn radius_at(&self, height: f64) -> f64 {
+        let fraction = (height / self.height).max(0.0).min(1.0);
+
+        self.bottom_radius + fraction * (self.top_radius - self.bottom_radius)
+    }
+}
+
+/// Remove all coordinates within a radius of a center position in the x-y plane.
+fn cut_hole(coords: &[Coord], center: Coord, radius: f64) -> Vec<Coord> {
+    coords.iter()
+        .filter(|&&coord| {
+            let (dr, _) = center.distance_cylindrical(coord, Direction::Z);
+            dr >= radius
+        })
+        .cloned()
+        .collect()
+}
+
+impl SurfaceNormal for Pore {
+    fn surface_coords(&self) -> &[Coord] {
+        &self.coords
+    }
+
+    fn frame(&self) -> Option<Frame> {
+        self.frame
+    }
+
+    /// The normal of the pore wall points toward the pore axis. The normals of the sheets
+    /// point down from the bottom sheet and up from the top sheet.
+    fn unrotated_normal_at(&self, coord: Coord) -> Coord {
+        let tolerance = 1e-3 * self.bottom_radius.max(1.0);
+        let (dr, dh) = self.calc_center().distance_cylindrical(coord, Direction::Z);
+
+        if dr > self.radius_at(dh) + tolerance {
+            if dh < 0.5 * self.height {
+                Coord::new(0.0, 0.0, -1.0)
+            } else {
+                Coord::new(0.0, 0.0, 1.0)
+            }
+        } else {
+            let radial = coord - self.calc_center() - Coord::new(0.0, 0.0, dh);
+            let slope = (self.top_radius - self.bottom_radius) / self.height;
+            let normal = Coord::new(0.0, 0.0, slope) - radial * (1.0 / radial.norm());
+
+            normal * (1.0 / normal.norm())
+        }
+    }
+
+    fn surface_area(&self) -> f64 {
+        let sheet_area = self.length * self.width;
+        let slant = (self.height.powi(2) + (self.top_radius - self.bottom_radius).powi(2)).sqrt();
+
+        let bottom = sheet_area - PI * self.bottom_radius.powi(2);
+        let wall = PI * (self.bottom_radius + self.top_radius) * slant;
+        let top = if self.slit { sheet_area - PI * self.top_radius.powi(2) } else { 0.0 };
+
+        bottom + wall + top
+    }
+
+    fn is_replicated(&self) -> bool {
+        self.images.is_some()
+    }
+}
+
+impl Describe for Pore {
+    fn describe(&self) -> String {
+        format!("{} (Pore of radii ({:.2}, {:.2}) and height {:.2} in {} of size \
+                 ({:.2}, {:.2}) at {}{})",
+            unwrap_name(&self.name), self.bottom_radius, self.top_radius, self.height,
+            if self.slit { "slit" } else { "sheet" }, self.length, self.width, self.origin,
+            describe_seed(self.seed))
+    }
+
+    fn describe_short(&self) -> String {
+        format!("{} (Pore)", unwrap_name(&self.name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use coord::Periodic;
+
+    fn setup_pore(bottom_radius: f64, top_radius: f64, slit: bool) -> Pore {
+        Pore {
+            name: None,
+            residue: None,
+            lattice: LatticeType::Triclinic { a: 0.1, b: 0.1, gamma: 90.0 },
+            slit,
+            orient_residues: false,
+            seed: None,
+            origin: Coord::ORIGO,
+            length: 5.0,
+            width: 4.0,
+            height: 2.0,
+            bottom_radius,
+            top_radius,
+            coords: vec![],
+            orientations: vec![],
+            cell: None,
+            frame: None,
+            images: None,
+        }
+    }
+
+    fn split_wall_and_sheets(pore: &Pore) -> (Vec<Coord>, Vec<Coord>) {
+        pore.coords
+            .iter()
+            .partition(|&&coord| coord.z > 1e-9 && coord.z < pore.height - 1e-9)
+    }
+
+    #[test]
+    fn cylindrical_pore_wall_lines_the_hole_of_the_sheet() {
+        let pore = setup_pore(1.0, 1.0, false).construct().unwrap();
+        let center = pore.calc_center();
+
+        // The radius is matched to the lattice spacing along the circumference
+        assert!((pore.bottom_radius - 6.3 / (2.0 * PI)).abs() < 1e-9);
+        assert_eq!(pore.bottom_radius, pore.top_radius);
+        assert!((pore.height - 2.0).abs() < 1e-9);
+
+        let (wall, sheet) = split_wall_and_sheets(&pore);
+        assert!(!wall.is_empty() && !sheet.is_empty());
+
+        for coord in wall {
+            let (dr, _) = center.distance_cylindrical(coord, Direction::Z);
+            assert!((dr - pore.bottom_radius).abs() < 1e-9);
+        }
+
+        // No sheet is placed at the top and the hole is cut
+        for coord in sheet {
+            let (dr, _) = center.distance_cylindrical(coord, Direction::Z);
+            assert!(dr >= pore.bottom_radius - 1e-9);
+            assert!(coord.z.abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn conical_pore_wall_narrows_along_its_height() {
+        let pore = setup_pore(1.5, 0.5, true).construct().unwrap();
+        let center = pore.calc_center();
+
+        assert!(pore.top_radius < pore.bottom_radius);
+
+        let (wall, sheets) = split_wall_and_sheets(&pore);
+
+        for &coord in &wall {
+            let (dr, dh) = center.distance_cylindrical(coord, Direction::Z);
+            assert!((dr - pore.radius_at(dh)).abs() < 1e-9);
+        }
+
+        // The wall keeps the lattice density when it narrows
+        let wall_area = pore.surface_area() - 2.0 * pore.length * pore.width
+            + PI * (pore.bottom_radius.powi(2) + pore.top_radius.powi(2));
+        let expected = wall_area / (0.1 * 0.1);
+        assert!((wall.len() as f64 - expected).abs() / expected < 0.05);
+
+        // The slit has sheets at both ends, with holes that match the wall
+        let top = sheets.iter().filter(|c| (c.z - pore.height).abs() < 1e-9).collect::<Vec<_>>();
+        assert!(!top.is_empty());
+
+        for coord in top {
+            let (dr, _) = center.distance_cylindrical(*coord, Direction::Z);
+            assert!(dr >= pore.top_radius - 1e-9);
+        }
+    }
+
+    #[test]
+    fn pores_must_fit_inside_of_the_sheet_and_have_positive_sizes() {
+        assert!(setup_pore(2.1, 1.0, false).construct().is_err());
+        assert!(setup_pore(0.0, 1.0, false).construct().is_err());
+        assert!(Pore { height: 0.0, .. setup_pore(1.0, 1.0, false) }.construct().is_err());
+        assert!(setup_pore(1.9, 1.0, false).construct().is_ok());
+    }
+
+    #[test]
+    fn pore_normals_point_into_the_pore_and_out_of_the_sheets() {
+        let pore = setup_pore(1.0, 1.0, true).construct().unwrap();
+        let center = pore.calc_center();
+
+        let on_wall = center + Coord::new(pore.bottom_radius, 0.0, 1.0);
+        assert!(pore.normal_at(on_wall).distance(Coord::new(-1.0, 0.0, 0.0)) < 1e-9);
+
+        let on_bottom = Coord::new(0.1, 0.1, 0.0);
+        assert_eq!(pore.normal_at(on_bottom), Coord::new(0.0, 0.0, -1.0));
+
+        let on_top = Coord::new(0.1, 0.1, pore.height);
+        assert_eq!(pore.normal_at(on_top), Coord::new(0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn oriented_pore_residues_follow_the_surface_normal() {
+        let pore = Pore { orient_residues: true, .. setup_pore(1.0, 0.5, true) }
+            .construct()
+            .unwrap();
+
+        assert_eq!(pore.orientations.len(), pore.coords.len());
+
+        for (&coord, orientation) in pore.coords.iter().zip(pore.orientations.iter()) {
+            let direction = orientation.apply(Coord::new(0.0, 0.0, 1.0));
+            assert!(direction.distance(pore.normal_at(coord)) < 1e-9);
+        }
+
+        assert!(setup_pore(1.0, 0.5, true).construct().unwrap().orientations.is_empty());
+    }
+    #[test]
+    fn triclinic_pores_keep_the_periodic_cell_of_the_sheet() {
+        let pore = Pore {
+            lattice: LatticeType::Triclinic { a: 0.1, b: 0.1, gamma: 60.0 },
+            .. setup_pore(1.0, 1.0, false)
+        }.construct().unwrap();
+
+        let box_vectors = pore.box_vectors();
+        assert!(!box_vectors.is_rectangular());
+        assert_eq!(box_vectors.c, Coord::new(0.0, 0.0, pore.height));
+        assert_eq!(pore.periodic_axes(), [true, true, false]);
+
+        // Replicas are several pores in the plane of the sheet
+        let replicated = pore.pbc_multiply(2, 2, 1);
+        assert_eq!(replicated.coords.len(), 4 * pore.coords.len());
+        assert_eq!(replicated.images, Some([2, 2, 1]));
+        assert!(replicated.is_replicated());
+    }
+}
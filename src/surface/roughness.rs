@@ -0,0 +1,422 @@
+//! Generate height fields of rough surfaces.
+
+use coord::Coord;
+use error::{GrafenError, Result};
+
+use rand::Rng;
+use rand::distributions::{IndependentSample, Range};
+use std::f64::consts::PI;
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// Maximum number of Fourier modes along each axis of a self-affine height field.
+const MAX_NUM_MODES: usize = 64;
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+/// Roughness of a surface, as a height field which is added along its normal.
+///
+/// Heights are shifted such that the lowest position lies in the surface plane.
+pub enum Roughness {
+    /// A random self-affine (fractal) profile with an input root mean square height `rms`,
+    /// lateral `correlation_length` and Hurst exponent `hurst` in (0, 1].
+    ///
+    /// The profile is a sum of Fourier modes with random phases, which are periodic
+    /// over the surface. Their power spectrum is C(q) ∝ (1 + (q ξ)^2)^-(1 + H),
+    /// which is flat below the correlation length ξ and falls off as a self-affine
+    /// surface with Hurst exponent H above it.
+    ///
+    /// *Roughness spectra and surface plasmons for surfaces of cobalt, nickel,
+    /// and iron*, G. Palasantzas, Physical Review B 48, 14472 (1993)
+    SelfAffine { rms: f64, correlation_length: f64, hurst: f64 },
+    /// A grayscale PGM image (binary or plain) which is stretched over the surface.
+    /// Black pixels are at zero height and white pixels at `max_height`.
+    HeightMap { path: PathBuf, max_height: f64 },
+}
+
+impl Roughness {
+    /// Return whether the height field is generated from random numbers.
+    pub fn is_stochastic(&self) -> bool {
+        match *self {
+            Roughness::SelfAffine { .. } => true,
+            Roughness::HeightMap { .. } => false,
+        }
+    }
+
+    /// Calculate the height of every input position of a surface with the input size.
+    /// The positions are relative to the lower-left corner of the surface in the x-y plane.
+    ///
+    /// # Errors
+    /// Returns an error if the roughness parameters are invalid or if the height map
+    /// could not be read.
+    pub fn calc_heights<R: Rng>(&self, coords: &[Coord], length: f64, width: f64, rng: &mut R)
+            -> Result<Vec<f64>> {
+        self.calc_heights_and_slopes(coords, length, width, rng).map(|(heights, _)| heights)
+    }
+
+    /// Calculate the height of every input position like `calc_heights`, along with
+    /// the slope of the height field at it as (dh/dx, dh/dy, 0). The same random numbers
+    /// are used, which yields identical heights for the same generator.
+    ///
+    /// # Errors
+    /// Returns an error if the roughness parameters are invalid or if the height map
+    /// could not be read.
+    pub fn calc_heights_and_slopes<R: Rng>(&self, coords: &[Coord], length: f64, width: f64,
+                                           rng: &mut R) -> Result<(Vec<f64>, Vec<Coord>)> {
+        let (heights, slopes): (Vec<f64>, Vec<Coord>) = match *self {
+            Roughness::SelfAffine { rms, correlation_length, hurst } => {
+                if rms < 0.0 || correlation_length <= 0.0 || hurst <= 0.0 || hurst > 1.0 {
+                    return Err(GrafenError::RunError(
+                        "a self-affine roughness requires a non-negative rms, a positive \
+                         correlation length and a Hurst exponent in (0, 1]".to_string()
+                    ));
+                }
+
+                calc_self_affine_heights(coords, length, width, rms, correlation_length,
+                                         hurst, rng).into_iter().unzip()
+            },
+            Roughness::HeightMap { ref path, max_height } => {
+                if max_height < 0.0 {
+                    return Err(GrafenError::RunError(
+                        "the maximum height of a height map cannot be negative".to_string()
+                    ));
+                }
+
+                let image = read_pgm(path)?;
+
+                // Slopes are central differences over one pixel in either direction
+                let (dx, dy) = (1.0 / image.width as f64, 1.0 / image.height as f64);
+
+                coords.iter()
+                    .map(|&coord| {
+                        let (x, y) = (coord.x / length, coord.y / width);
+
+                        let height = max_height * image.value_at(x, y);
+                        let slope_x = max_height
+                            * (image.value_at(x + dx, y) - image.value_at(x - dx, y))
+                            / (2.0 * dx * length);
+                        let slope_y = max_height
+                            * (image.value_at(x, y + dy) - image.value_at(x, y - dy))
+                            / (2.0 * dy * width);
+
+                        (height, Coord::new(slope_x, slope_y, 0.0))
+                    })
+                    .unzip()
+            },
+        };
+
+        let min = heights.iter().cloned().fold(::std::f64::INFINITY, f64::min);
+
+        Ok((heights.into_iter().map(|h| h - min).collect(), slopes))
+    }
+}
+
+impl fmt::Display for Roughness {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Roughness::SelfAffine { rms, correlation_length, hurst } => {
+                write!(f, "Self-affine (rms: {:.3} nm, correlation length: {:.3} nm, \
+                           Hurst exponent: {:.2})", rms, correlation_length, hurst)
+            },
+            Roughness::HeightMap { ref path, max_height } => {
+                write!(f, "Height map '{}' (max height: {:.3} nm)", path.display(), max_height)
+            },
+        }
+    }
+}
+
+/// Calculate the heights and slopes of a random self-affine profile at the input positions.
+///
+/// The profile is scaled such that its expected variance over the surface is rms^2.
+fn calc_self_affine_heights<R: Rng>(coords: &[Coord], length: f64, width: f64, rms: f64,
+                                    correlation_length: f64, hurst: f64, rng: &mut R)
+                                    -> Vec<(f64, Coord)> {
+    // Modes are not resolved with shorter wavelengths than twice the point spacing
+    let spacing = (length * width / coords.len().max(1) as f64).sqrt();
+    let num_modes = |size: f64| {
+        ((size / (2.0 * spacing)).floor() as usize).max(1).min(MAX_NUM_MODES) as i64
+    };
+    let (num_x, num_y) = (num_modes(length), num_modes(width));
+
+    let range = Range::new(0.0, 2.0 * PI);
+
+    // Use one half of the wave vectors since the profile is real
+    let modes = (0..(num_x + 1))
+        .flat_map(|m| ((-num_y)..(num_y + 1)).map(move |n| (m, n)))
+        .filter(|&(m, n)| m > 0 || n > 0)
+        .map(|(m, n)| {
+            let qx = 2.0 * PI * m as f64 / length;
+            let qy = 2.0 * PI * n as f64 / width;
+            let q2 = qx * qx + qy * qy;
+
+            let power = (1.0 + q2 * correlation_length.powi(2)).powf(-(1.0 + hurst));
+            let phase = range.ind_sample(rng);
+
+            (qx, qy, power.sqrt(), phase)
+        })
+        .collect::<Vec<_>>();
+
+    // The variance of a sum of cosines with random phases is half their summed power
+    let variance = modes.iter().map(|&(_, _, amplitude, _)| 0.5 * amplitude.powi(2)).sum::<f64>();
+    let scale = if variance > 0.0 { rms / variance.sqrt() } else { 0.0 };
+
+    coords.iter()
+        .map(|coord| {
+            modes.iter()
+                .fold((0.0, Coord::ORIGO), |(height, slope), &(qx, qy, amplitude, phase)| {
+                    let arg = qx * coord.x + qy * coord.y + phase;
+                    let mode_slope = Coord::new(qx, qy, 0.0) * (-amplitude * arg.sin());
+
+                    (height + amplitude * arg.cos(), slope + mode_slope)
+                })
+        })
+        .map(|(height, slope)| (scale * height, slope * scale))
+        .collect()
+}
+
+/// A grayscale image with values normalized to [0, 1], stored row by row from the top.
+struct GrayImage {
+    width: usize,
+    height: usize,
+    values: Vec<f64>,
+}
+
+impl GrayImage {
+    /// Return the value of the pixel at relative position (x, y) in [0, 1], where (0, 0)
+    /// is the lower-left corner of the image.
+    fn value_at(&self, x: f64, y: f64) -> f64 {
+        let to_index = |t: f64, n: usize| ((t * n as f64).floor().max(0.0) as usize).min(n - 1);
+
+        let col = to_index(x, self.width);
+        let row = to_index(1.0 - y, self.height);
+
+        self.values[row * self.width + col]
+    }
+}
+
+/// Read a grayscale image in the PGM format from disk.
+fn read_pgm(path: &Path) -> Result<GrayImage> {
+    let mut bytes = Vec::new();
+
+    File::open(path)
+        .and_then(|mut file| file.read_to_end(&mut bytes))
+        .map_err(|err| GrafenError::RunError(
+            format!("could not read height map '{}' ({})", path.display(), err)
+        ))?;
+
+    parse_pgm(&bytes).map_err(|err| GrafenError::RunError(
+        format!("could not read height map '{}' ({})", path.display(), err)
+    ))
+}
+
+/// Parse a PGM image in either its binary (P5) or plain (P2) format.
+fn parse_pgm(bytes: &[u8]) -> ::std::result::Result<GrayImage, String> {
+    let mut pos = 0;
+    let mut header = Vec::with_capacity(4);
+
+    // The header is the magic number, width, height and maximum value, separated by
+    // whitespace and comments which run from '#' to the end of the line
+    while header.len() < 4 {
+        while pos < bytes.len() && (bytes[pos].is_ascii_whitespace() || bytes[pos] == b'#') {
+            if bytes[pos] == b'#' {
+                while pos < bytes.len() && bytes[pos] != b'\n' {
+                    pos += 1;
+                }
+            } else {
+                pos += 1;
+            }
+        }
+
+        let start = pos;
+        while pos < bytes.len() && !bytes[pos].is_ascii_whitespace() {
+            pos += 1;
+        }
+
+        if start == pos {
+            return Err("the header is incomplete".to_string());
+        }
+
+        header.push(String::from_utf8_lossy(&bytes[start..pos]).into_owned());
+    }
+
+    let parse_value = |value: &str| {
+        value.parse::<usize>().map_err(|_| format!("'{}' is not a valid number", value))
+    };
+
+    let width = parse_value(&header[1])?;
+    let height = parse_value(&header[2])?;
+    let max_value = parse_value(&header[3])?;
+
+    if width == 0 || height == 0 || max_value == 0 || max_value > 65535 {
+        return Err("the image size or maximum value is invalid".to_string());
+    }
+
+    let num_pixels = width * height;
+
+    let pixels = match header[0].as_str() {
+        "P5" => {
+            // A single whitespace separates the header from the binary data
+            let data = &bytes[(pos + 1).min(bytes.len())..];
+            let bytes_per_pixel = if max_value < 256 { 1 } else { 2 };
+
+            if data.len() < num_pixels * bytes_per_pixel {
+                return Err("the image data is incomplete".to_string());
+            }
+
+            data.chunks(bytes_per_pixel)
+                .take(num_pixels)
+                .map(|chunk| chunk.iter().fold(0, |acc, &byte| (acc << 8) + byte as usize))
+                .collect::<Vec<_>>()
+        },
+        "P2" => {
+            let pixels = String::from_utf8_lossy(&bytes[pos..])
+                .split_whitespace()
+                .take(num_pixels)
+                .map(parse_value)
+                .collect::<::std::result::Result<Vec<_>, _>>()?;
+
+            if pixels.len() < num_pixels {
+                return Err("the image data is incomplete".to_string());
+            }
+
+            pixels
+        },
+        magic => {
+            return Err(format!("'{}' is not a grayscale PGM image", magic));
+        },
+    };
+
+    Ok(GrayImage {
+        width,
+        height,
+        values: pixels.into_iter().map(|v| v.min(max_value) as f64 / max_value as f64).collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use random::rng_from_seed;
+
+    fn setup_grid(length: f64, width: f64, num: usize) -> Vec<Coord> {
+        let (dx, dy) = (length / num as f64, width / num as f64);
+
+        (0..num)
+            .flat_map(|i| (0..num).map(move |j| Coord::new(i as f64 * dx, j as f64 * dy, 0.0)))
+            .collect()
+    }
+
+    fn calc_rms(heights: &[f64]) -> f64 {
+        let num = heights.len() as f64;
+        let mean = heights.iter().sum::<f64>() / num;
+
+        (heights.iter().map(|h| (h - mean).powi(2)).sum::<f64>() / num).sqrt()
+    }
+
+    #[test]
+    fn self_affine_heights_have_the_input_rms_and_start_from_zero() {
+        let coords = setup_grid(10.0, 10.0, 60);
+        let roughness = Roughness::SelfAffine { rms: 0.2, correlation_length: 1.0, hurst: 0.8 };
+
+        let heights = roughness.calc_heights(&coords, 10.0, 10.0, &mut rng_from_seed(1)).unwrap();
+
+        assert_eq!(heights.len(), coords.len());
+        assert!((calc_rms(&heights) - 0.2).abs() < 0.2 * 0.1);
+
+        let min = heights.iter().cloned().fold(::std::f64::INFINITY, f64::min);
+        assert_eq!(min, 0.0);
+    }
+
+    #[test]
+    fn self_affine_heights_are_reproduced_with_a_seed_and_periodic() {
+        let coords = vec![Coord::new(0.5, 1.5, 0.0), Coord::new(3.0, 4.0, 0.0)];
+        let shifted = coords.iter().map(|&c| c + Coord::new(5.0, 10.0, 0.0)).collect::<Vec<_>>();
+
+        let roughness = Roughness::SelfAffine { rms: 0.5, correlation_length: 2.0, hurst: 0.5 };
+
+        let first = roughness.calc_heights(&coords, 5.0, 5.0, &mut rng_from_seed(3)).unwrap();
+        let second = roughness.calc_heights(&coords, 5.0, 5.0, &mut rng_from_seed(3)).unwrap();
+        let images = roughness.calc_heights(&shifted, 5.0, 5.0, &mut rng_from_seed(3)).unwrap();
+
+        assert_eq!(first, second);
+        assert!((first[0] - first[1] - (images[0] - images[1])).abs() < 1e-9);
+    }
+
+    #[test]
+    fn self_affine_slopes_match_the_differences_of_heights() {
+        let eps = 1e-6;
+        let coord = Coord::new(1.3, 2.1, 0.0);
+        let coords = vec![
+            coord,
+            coord + Coord::new(eps, 0.0, 0.0),
+            coord + Coord::new(0.0, eps, 0.0)
+        ];
+
+        let roughness = Roughness::SelfAffine { rms: 0.3, correlation_length: 1.0, hurst: 0.7 };
+        let (heights, slopes) = roughness
+            .calc_heights_and_slopes(&coords, 4.0, 4.0, &mut rng_from_seed(5))
+            .unwrap();
+
+        assert_eq!(heights,
+                   roughness.calc_heights(&coords, 4.0, 4.0, &mut rng_from_seed(5)).unwrap());
+
+        assert!(((heights[1] - heights[0]) / eps - slopes[0].x).abs() < 1e-4);
+        assert!(((heights[2] - heights[0]) / eps - slopes[0].y).abs() < 1e-4);
+        assert_eq!(slopes[0].z, 0.0);
+    }
+
+    #[test]
+    fn invalid_self_affine_parameters_are_errors() {
+        let coords = setup_grid(1.0, 1.0, 2);
+        let mut rng = rng_from_seed(1);
+
+        for &(rms, correlation_length, hurst) in &[(-0.1, 1.0, 0.5), (0.1, 0.0, 0.5),
+                                                  (0.1, 1.0, 0.0), (0.1, 1.0, 1.5)] {
+            let roughness = Roughness::SelfAffine { rms, correlation_length, hurst };
+            assert!(roughness.calc_heights(&coords, 1.0, 1.0, &mut rng).is_err());
+        }
+    }
+
+    #[test]
+    fn parse_plain_and_binary_pgm_images() {
+        let plain = b"P2\n# A comment\n2 2\n4\n0 4\n2 1\n";
+        let image = parse_pgm(plain).unwrap();
+
+        assert_eq!((image.width, image.height), (2, 2));
+        assert_eq!(image.values, vec![0.0, 1.0, 0.5, 0.25]);
+
+        let mut binary = b"P5 2 1 255\n".to_vec();
+        binary.extend(&[255, 51]);
+        let image = parse_pgm(&binary).unwrap();
+
+        assert_eq!((image.width, image.height), (2, 1));
+        assert_eq!(image.values, vec![1.0, 0.2]);
+
+        let mut wide = b"P5 1 1 65535\n".to_vec();
+        wide.extend(&[255, 255]);
+        assert_eq!(parse_pgm(&wide).unwrap().values, vec![1.0]);
+    }
+
+    #[test]
+    fn parse_invalid_pgm_images_are_errors() {
+        assert!(parse_pgm(b"P6 1 1 255\n\x00\x00\x00").is_err());
+        assert!(parse_pgm(b"P2 2 2 255\n0 1 2").is_err());
+        assert!(parse_pgm(b"P5 2 2 255\n\x00").is_err());
+        assert!(parse_pgm(b"P2 2").is_err());
+    }
+
+    #[test]
+    fn image_values_are_sampled_with_the_top_row_at_the_upper_edge() {
+        let image = parse_pgm(b"P2 2 2 4 0 4 2 1").unwrap();
+
+        assert_eq!(image.value_at(0.25, 0.75), 0.0);
+        assert_eq!(image.value_at(0.75, 0.75), 1.0);
+        assert_eq!(image.value_at(0.25, 0.25), 0.5);
+        assert_eq!(image.value_at(0.75, 0.25), 0.25);
+
+        // Positions on or outside of the edges are clamped to the image
+        assert_eq!(image.value_at(1.0, 1.0), 1.0);
+        assert_eq!(image.value_at(-0.1, 0.0), 0.5);
+    }
+}
@@ -2,19 +2,24 @@
 
 use surface::distribution::Distribution;
 use surface::lattice::Lattice;
-use surface::LatticeType;
+use surface::roughness::Roughness;
+use surface::{LatticeType, SurfaceNormal};
 use surface::LatticeType::*;
 
-use coord::{Coord, Direction, Periodic, Translate,
-    rotate_planar_coords_to_alignment};
+use coord::{BoxVectors, Coord, Direction, Frame, Periodic, Rotate, Rotation, Translate,
+    align_coords, calc_extent, rotate_planar_coords_to_alignment};
 use describe::{unwrap_name, Describe};
 use error::{GrafenError, Result};
 use iterator::{ResidueIter, ResidueIterOut};
+use random::{describe_seed, rng_from_seed, seed_or_random};
 use system::*;
-use volume::pbc_multiply_volume;
 
-impl_component![Sheet];
+/// Height of the box of a pure sheet (nm), as a lower limit of the system.
+const SHEET_MARGIN: f64 = 0.1;
+
+impl_component![@oriented Sheet];
 impl_translate![Circle, Sheet];
+impl_rotate![Sheet];
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 /// A rectangular sheet.
@@ -29,6 +34,18 @@ pub struct Sheet {
     /// Standard deviation along z of coordinates. Added to the coordinates when `construct`
     /// is called.
     pub std_z: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// Optional roughness of the sheet. Its height field is added along the normal
+    /// when `construct` is called.
+    pub roughness: Option<Roughness>,
+    #[serde(default)]
+    /// Rotate residues such that their z axis follows the normal of the surface at their
+    /// position, which is tilted against the slope of rough sheets.
+    pub orient_residues: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// Seed for the random number generator of stochastic lattices, the deviation
+    /// along z and rough height fields. Set to the used seed when `construct` is called.
+    pub seed: Option<u64>,
     #[serde(skip)]
     /// Origin of the sheet. Located in the lower-left position of it.
     pub origin: Coord,
@@ -43,13 +60,29 @@ pub struct Sheet {
     #[serde(skip)]
     /// List of coordinates belonging to the sheet. Relative to the `origin`.
     pub coords: Vec<Coord>,
+    #[serde(skip)]
+    /// Rotation of the residue at every coordinate, if residues are oriented along
+    /// the surface normal. Empty if they are not.
+    pub orientations: Vec<Rotation>,
+    #[serde(skip)]
+    /// Normal of the unrotated sheet at every coordinate of a rough sheet, which is tilted
+    /// against the slope of its height field. Empty for smooth sheets.
+    pub normals: Vec<Coord>,
+    #[serde(skip)]
+    /// Periodic cell of the object if it differs from the box calculated from its geometry,
+    /// eg. the box enclosing all atoms after the object has been rotated.
+    pub cell: Option<BoxVectors>,
+    #[serde(skip)]
+    /// Rotation of the object relative to its geometry, if it has been rotated.
+    pub frame: Option<Frame>,
 }
 
 impl Sheet {
     /// Construct the sheet coordinates and return the object.
     ///
     /// # Errors
-    /// Returns an error if either the length or width is non-positive.
+    /// Returns an error if either the length or width is non-positive, or if the
+    /// height field of its roughness could not be created.
     pub fn construct(self) -> Result<Sheet> {
         if self.length <= 0.0 || self.width <= 0.0 {
             return Err(
@@ -57,6 +90,13 @@ impl Sheet {
             );
         }
 
+        let seed = if self.is_stochastic() {
+            Some(seed_or_random(self.seed))
+        } else {
+            self.seed
+        };
+        let mut rng = rng_from_seed(seed.unwrap_or(0));
+
         let mut coords_lattice = match self.lattice {
             Hexagonal { a } => {
                 Lattice::hexagonal(a)
@@ -74,47 +114,163 @@ impl Sheet {
                 use std::f64::consts::PI;
                 let rmin = (2.0 / (PI * density)).sqrt();
 
-                Distribution::poisson(rmin, self.length, self.width)
+                Distribution::poisson(rmin, self.length, self.width, &mut rng)
             },
             BlueNoise { number } => {
-                Distribution::blue_noise(number, self.length, self.width)
+                Distribution::blue_noise(number, self.length, self.width, &mut rng)
             },
         };
 
         if let Some(std) = self.std_z {
-            coords_lattice = coords_lattice.uniform_distribution(std);
+            coords_lattice = coords_lattice.uniform_distribution(std, &mut rng);
         };
 
         let (length, width, _) = coords_lattice.box_size.to_tuple();
 
-        let coords = match self.normal {
-            Direction::Z => coords_lattice.coords,
-            direction => {
-                rotate_planar_coords_to_alignment(&coords_lattice.coords, Direction::Z, direction)
+        // Tilted sheets are not periodic in their box, so lattice rows which are shifted
+        // outside of the sheet along x are wrapped back inside it.
+        if let Direction::Vector(_) = self.normal {
+            for coord in coords_lattice.coords.iter_mut() {
+                coord.x -= length * (coord.x / length).floor();
+            }
+        }
+
+        let (heights, slopes) = match self.roughness {
+            Some(ref roughness) => {
+                roughness.calc_heights_and_slopes(&coords_lattice.coords, length, width, &mut rng)?
             },
+            None => (vec![], vec![]),
+        };
+        let height = heights.iter().cloned().fold(0.0, f64::max);
+
+        // Sheets of triclinic lattices in the x-y plane keep their natural periodic cell.
+        let natural_cell = coords_lattice.box_vectors;
+
+        let cell = match (&self.lattice, self.normal) {
+            (&Triclinic { .. }, Direction::Z) if !natural_cell.is_rectangular() => {
+                Some(BoxVectors {
+                    c: Coord::new(0.0, 0.0, SHEET_MARGIN + height),
+                    .. natural_cell
+                })
+            },
+            _ => None,
+        };
+
+        let normal = self.normal.to_unit_vector();
+
+        let rotate = |coords: &[Coord]| match self.normal {
+            Direction::Z => coords.to_vec(),
+            Direction::Vector(_) => align_coords(coords, Coord::new(0.0, 0.0, 1.0), normal),
+            direction => rotate_planar_coords_to_alignment(coords, Direction::Z, direction),
+        };
+
+        let mut coords = rotate(&coords_lattice.coords);
+
+        for (coord, &h) in coords.iter_mut().zip(heights.iter()) {
+            *coord += normal * h;
+        }
+
+        // The surface is tilted against the slope of the height field along
+        // the in-plane axes of the lattice
+        let axes = rotate(&[Coord::new(1.0, 0.0, 0.0), Coord::new(0.0, 1.0, 0.0)]);
+
+        let normals = slopes
+            .iter()
+            .map(|slope| {
+                let local_normal = normal - axes[0] * slope.x - axes[1] * slope.y;
+                local_normal * (1.0 / local_normal.norm())
+            })
+            .collect::<Vec<_>>();
+
+        let orientations = if self.orient_residues {
+            (0..coords.len())
+                .map(|i| {
+                    let local_normal = normals.get(i).cloned().unwrap_or(normal);
+                    Rotation::align(Coord::new(0.0, 0.0, 1.0), local_normal)
+                })
+                .collect()
+        } else {
+            vec![]
         };
 
+        // Tilted sheets are shifted to have their lower corner at the origin
+        if let Direction::Vector(_) = self.normal {
+            let (min, _) = calc_tilted_extent(length, width, height, self.normal);
+            coords.iter_mut().for_each(|coord| *coord -= min);
+        }
+
         Ok(Sheet {
+            seed,
             length,
             width,
             coords,
+            orientations,
+            normals,
+            cell,
             .. self
         })
     }
 
+    /// Return whether the construction of the sheet uses random numbers.
+    pub fn is_stochastic(&self) -> bool {
+        self.lattice.is_stochastic()
+            || self.std_z.is_some()
+            || self.roughness.as_ref().map(|r| r.is_stochastic()).unwrap_or(false)
+    }
+
     /// Calculate the box size. The height of a pure sheet is set to 0.1 (nm)
-    /// as a lower limit of the system.
+    /// as a lower limit of the system, to which the height of a rough sheet is added.
     fn calc_box_size(&self) -> Coord {
-        let margin = 0.1;
+        let height = self.calc_roughness_height();
+        let margin = SHEET_MARGIN + height;
 
         match self.normal {
             Direction::X => Coord::new(margin, self.width, self.length),
             Direction::Y => Coord::new(self.length, margin, self.width),
             Direction::Z => Coord::new(self.length, self.width, margin),
+            Direction::Vector(_) => {
+                let (min, max) = calc_tilted_extent(self.length, self.width, height, self.normal);
+                let size = max - min;
+
+                Coord::new(size.x.max(SHEET_MARGIN), size.y.max(SHEET_MARGIN),
+                           size.z.max(SHEET_MARGIN))
+            },
         }
     }
 
-    /// Cut a circle out of coordinates in the sheet.
+    /// Calculate the height of a rough sheet along its normal from its coordinates.
+    /// Smooth sheets have no height.
+    fn calc_roughness_height(&self) -> f64 {
+        if self.roughness.is_none() || self.coords.is_empty() {
+            return 0.0;
+        }
+
+        let normal = self.normal.to_unit_vector();
+
+        let (min, max) = self.coords
+            .iter()
+            .map(|coord| coord.dot(normal))
+            .fold((::std::f64::INFINITY, ::std::f64::NEG_INFINITY),
+                  |(min, max), h| (min.min(h), max.max(h)));
+
+        max - min
+    }
+
+    /// Return whether a periodic cell is the natural cell of a sheet in the x-y plane,
+    /// which has its lattice vectors along the length and width of the sheet.
+    fn is_natural_cell(&self, cell: BoxVectors) -> bool {
+        let eps = 1e-6;
+
+        self.normal == Direction::Z
+            && cell.a.distance(Coord::new(self.length, 0.0, 0.0)) < eps
+            && (cell.b.y - self.width).abs() < eps
+            && cell.b.z.abs() < eps
+            && cell.c.x.abs() < eps
+            && cell.c.y.abs() < eps
+    }
+
+    /// Cut a circle out of coordinates in the sheet, with the orientations
+    /// of their residues.
     pub fn to_circle(&self, radius: f64) -> Circle {
         let center = Coord::new(radius, radius, 0.0);
         let box_size = Coord::new(self.length, self.width, 0.0);
@@ -125,15 +281,15 @@ impl Sheet {
             (2.0 * radius / self.width).ceil() as usize
         );
 
-        let coords = match pbc_multiples {
+        let (coords, orientations) = match pbc_multiples {
             (1, 1) => {
-                cut_circle(&self.coords, center, box_size, radius)
+                cut_circle(&self.coords, &self.orientations, center, box_size, radius)
             },
             (nx, ny) => {
                 let box_size = box_size.pbc_multiply(nx, ny, 1);
-                let coords = self.pbc_multiply(nx, ny, 1).coords;
+                let sheet = self.pbc_multiply(nx, ny, 1);
 
-                cut_circle(&coords, center, box_size, radius)
+                cut_circle(&sheet.coords, &sheet.orientations, center, box_size, radius)
             },
         };
 
@@ -142,14 +298,16 @@ impl Sheet {
             origin: self.origin,
             radius,
             coords,
+            orientations,
         }
     }
 }
 
 impl Describe for Sheet {
     fn describe(&self) -> String {
-        format!("{} (Rectangular sheet of size ({:.2}, {:.2}) at {})",
-            unwrap_name(&self.name), self.length, self.width, self.origin)
+        format!("{} (Rectangular sheet of size ({:.2}, {:.2}) at {}{})",
+            unwrap_name(&self.name), self.length, self.width, self.origin,
+            describe_seed(self.seed))
     }
 
     fn describe_short(&self) -> String {
@@ -157,19 +315,115 @@ impl Describe for Sheet {
     }
 }
 
+impl SurfaceNormal for Sheet {
+    fn surface_coords(&self) -> &[Coord] {
+        &self.coords
+    }
+
+    fn frame(&self) -> Option<Frame> {
+        self.frame
+    }
+
+    /// The normal of a smooth sheet is its positive normal axis. That of a rough sheet
+    /// is the local normal at the closest of its coordinates.
+    fn unrotated_normal_at(&self, coord: Coord) -> Coord {
+        let unrotated = |c: Coord| self.frame.map(|frame| frame.to_unrotated(c)).unwrap_or(c);
+
+        self.coords
+            .iter()
+            .zip(self.normals.iter())
+            .map(|(&c, &normal)| (unrotated(c).distance(coord), normal))
+            .fold(None, |closest: Option<(f64, Coord)>, (dist, normal)| match closest {
+                Some((min_dist, _)) if min_dist <= dist => closest,
+                _ => Some((dist, normal)),
+            })
+            .map(|(_, normal)| normal)
+            .unwrap_or(self.normal.to_unit_vector())
+    }
+
+    fn normal_at_site(&self, index: usize) -> Coord {
+        match (self.normals.get(index), self.frame) {
+            (Some(&normal), Some(frame)) => frame.to_rotated(normal),
+            (Some(&normal), None) => normal,
+            (None, _) => self.normal_at(self.coords[index]),
+        }
+    }
+
+    fn surface_area(&self) -> f64 {
+        self.length * self.width
+    }
+}
+
 impl Periodic for Sheet {
-    /// Clone sheet coordinates into PBC multiples.
-    fn pbc_multiply(&self, nx: usize, ny: usize, _: usize) -> Sheet {
-        let current_size = Coord::new(self.length, self.width, 0.0);
-        let coords = pbc_multiply_volume(&self.coords, current_size, nx, ny, 1);
+    /// Clone sheet coordinates into PBC multiples along its box vectors. Sheets which are
+    /// only multiplied within their plane are extended in length and width, others have
+    /// their extended box set as their periodic cell.
+    fn pbc_multiply(&self, nx: usize, ny: usize, nz: usize) -> Sheet {
+        let box_vectors = self.box_vectors();
+        let shifts = box_vectors.image_shifts(nx, ny, nz);
+
+        // Coordinates are cloned image by image, which keeps their order within each
+        let coords = shifts
+            .iter()
+            .flat_map(|&shift| self.coords.iter().map(move |&coord| coord + shift))
+            .collect();
 
-        Sheet {
-            length: nx as f64 * self.length,
-            width: ny as f64 * self.width,
-            coords,
-            .. self.clone()
+        let orientations = shifts
+            .iter()
+            .flat_map(|_| self.orientations.iter().cloned())
+            .collect();
+
+        let normals = shifts
+            .iter()
+            .flat_map(|_| self.normals.iter().cloned())
+            .collect::<Vec<_>>();
+
+        // Multiples along the length, width and normal of the sheet
+        let multiples = match self.normal {
+            Direction::X => Some((nz, ny, nx)),
+            Direction::Y => Some((nx, nz, ny)),
+            Direction::Z => Some((nx, ny, nz)),
+            Direction::Vector(_) => None,
+        };
+
+        match multiples {
+            Some((n_length, n_width, 1)) => Sheet {
+                length: n_length as f64 * self.length,
+                width: n_width as f64 * self.width,
+                coords,
+                orientations,
+                normals,
+                cell: self.cell.map(|cell| cell.pbc_multiply(nx, ny, nz)),
+                .. self.clone()
+            },
+            _ => Sheet {
+                coords,
+                orientations,
+                normals,
+                cell: Some(box_vectors.pbc_multiply(nx, ny, nz)),
+                .. self.clone()
+            },
+        }
+    }
+
+    /// A sheet along an axis is periodic within its plane, unless it has been rotated
+    /// or its roughness is read from a height map which is not periodic.
+    fn periodic_axes(&self) -> [bool; 3] {
+        let is_periodic = match (&self.roughness, &self.cell) {
+            (&Some(Roughness::HeightMap { .. }), _) => false,
+            (_, &Some(cell)) => self.is_natural_cell(cell),
+            (_, &None) => true,
+        };
+
+        let mut axes = [false; 3];
+
+        if let Some(normal) = self.normal.axis_index() {
+            for (i, axis) in axes.iter_mut().enumerate() {
+                *axis = is_periodic && i != normal;
+            }
         }
 
+        axes
     }
 }
 
@@ -180,17 +434,47 @@ pub struct Circle {
     origin: Coord,
     radius: f64,
     pub coords: Vec<Coord>,
+    /// Rotations of the residues at every coordinate, if they are oriented.
+    pub orientations: Vec<Rotation>,
+}
+
+/// Calculate the lower and upper corners of the box which encloses a sheet
+/// in the x-y plane after it has been tilted to have the input normal.
+fn calc_tilted_extent(length: f64, width: f64, height: f64, normal: Direction)
+        -> (Coord, Coord) {
+    let corners = vec![
+        Coord::ORIGO,
+        Coord::new(length, 0.0, 0.0),
+        Coord::new(0.0, width, 0.0),
+        Coord::new(length, width, 0.0),
+        Coord::new(0.0, 0.0, height),
+        Coord::new(length, 0.0, height),
+        Coord::new(0.0, width, height),
+        Coord::new(length, width, height)
+    ];
+
+    let tilted = align_coords(&corners, Coord::new(0.0, 0.0, 1.0), normal.to_unit_vector());
+
+    calc_extent(&tilted).unwrap()
 }
 
 /// Cut a set of coordinates into a circle with input radius in the x-y plane.
-fn cut_circle(coords: &[Coord], center: Coord, box_size: Coord, radius: f64) -> Vec<Coord> {
-    coords.iter()
+fn cut_circle(coords: &[Coord], orientations: &[Rotation], center: Coord, box_size: Coord,
+              radius: f64) -> (Vec<Coord>, Vec<Rotation>) {
+    let (indices, coords): (Vec<usize>, Vec<Coord>) = coords.iter()
         .map(|&coord| coord.with_pbc(box_size) - center)
-        .filter(|coord| {
+        .enumerate()
+        .filter(|&(_, coord)| {
             let (dr, _) = coord.distance_cylindrical(Coord::ORIGO, Direction::Z);
             dr <= radius
         })
-        .collect()
+        .unzip();
+
+    let orientations = indices.into_iter()
+        .filter_map(|i| orientations.get(i).cloned())
+        .collect();
+
+    (coords, orientations)
 }
 
 #[cfg(test)]
@@ -204,11 +488,18 @@ mod tests {
             residue: None,
             lattice: lattice.clone(),
             std_z: None,
+            roughness: None,
+            orient_residues: false,
+            seed: None,
             origin: Coord::default(),
             normal: Direction::Z,
             length,
             width,
             coords: vec![],
+            orientations: vec![],
+            normals: vec![],
+            cell: None,
+            frame: None,
         }
     }
 
@@ -301,6 +592,167 @@ mod tests {
         assert_eq!(Coord::new(sheet.length, sheet.width, 0.1), sheet.calc_box_size());
     }
 
+    #[test]
+    fn tilted_sheets_have_their_coordinates_in_the_plane_and_inside_the_box() {
+        let normal = Coord::new(1.0, 0.0, 1.0);
+        let sheet = Sheet {
+            normal: Direction::Vector(normal),
+            .. setup_sheet(4.0, 3.0, &Hexagonal { a: 0.1 })
+        }.construct().unwrap();
+
+        let box_size = sheet.calc_box_size();
+        let (min, _) = calc_tilted_extent(sheet.length, sheet.width, 0.0, sheet.normal);
+
+        assert!((box_size.y - sheet.width).abs() < 1e-9);
+        assert!((box_size.x - sheet.length / 2.0f64.sqrt()).abs() < 1e-9);
+
+        for &coord in &sheet.coords {
+            // All coordinates are in the plane through the tilted sheet origin
+            assert!((coord + min).dot(normal).abs() < 1e-9);
+            assert!(coord.x >= -1e-9 && coord.x <= box_size.x + 1e-9);
+            assert!(coord.z >= -1e-9 && coord.z <= box_size.z + 1e-9);
+        }
+
+        assert_eq!(sheet.normal_at(Coord::ORIGO), normal * (1.0 / 2.0f64.sqrt()));
+    }
+
+    #[test]
+    fn rough_sheets_have_heights_along_their_normal_inside_the_box() {
+        let roughness = Roughness::SelfAffine { rms: 0.2, correlation_length: 1.0, hurst: 0.7 };
+
+        for &normal in &[Direction::X, Direction::Y, Direction::Z] {
+            let sheet = Sheet {
+                normal,
+                roughness: Some(roughness.clone()),
+                seed: Some(5),
+                .. setup_sheet(5.0, 4.0, &Hexagonal { a: 0.142 })
+            }.construct().unwrap();
+
+            let axis = normal.to_unit_vector();
+            let heights = sheet.coords.iter().map(|c| c.dot(axis)).collect::<Vec<_>>();
+            let max_height = heights.iter().cloned().fold(0.0, f64::max);
+
+            assert!(heights.iter().all(|&h| h >= 0.0));
+            assert!(max_height > 0.2);
+
+            // The box encloses the heights, so they are unchanged by periodic wrapping
+            let box_size = sheet.calc_box_size();
+            assert!((box_size.dot(axis) - (max_height + 0.1)).abs() < 1e-9);
+            let wrapped = sheet.clone().with_pbc();
+            let wrapped_heights = wrapped.coords.iter().map(|c| c.dot(axis)).collect::<Vec<_>>();
+            assert_eq!(wrapped_heights, heights);
+        }
+    }
+
+    #[test]
+    fn rough_sheets_are_reproduced_with_a_seed() {
+        let roughness = Roughness::SelfAffine { rms: 0.2, correlation_length: 1.0, hurst: 0.7 };
+        let sheet = Sheet {
+            roughness: Some(roughness),
+            .. setup_sheet(3.0, 3.0, &Hexagonal { a: 0.142 })
+        };
+
+        assert!(sheet.is_stochastic());
+
+        let first = sheet.clone().construct().unwrap();
+        let second = Sheet { seed: first.seed, .. sheet.clone() }.construct().unwrap();
+
+        assert!(first.seed.is_some());
+        assert_eq!(first.coords, second.coords);
+    }
+
+    #[test]
+    fn oriented_residues_follow_the_local_normal_of_rough_sheets() {
+        let ez = Coord::new(0.0, 0.0, 1.0);
+        let base = Sheet {
+            normal: Direction::X,
+            orient_residues: true,
+            seed: Some(3),
+            .. setup_sheet(3.0, 3.0, &Hexagonal { a: 0.142 })
+        };
+
+        // Residues on smooth sheets all point along the normal
+        let smooth = base.clone().construct().unwrap();
+        assert_eq!(smooth.orientations.len(), smooth.coords.len());
+        assert!(smooth.orientations
+            .iter()
+            .all(|r| r.apply(ez).distance(Coord::new(1.0, 0.0, 0.0)) < 1e-9));
+
+        // and are tilted along the slopes of rough sheets
+        let roughness = Roughness::SelfAffine { rms: 0.2, correlation_length: 1.0, hurst: 0.7 };
+        let rough = Sheet { roughness: Some(roughness), .. base.clone() }.construct().unwrap();
+
+        let directions = rough.orientations.iter().map(|r| r.apply(ez)).collect::<Vec<_>>();
+        assert!(directions.iter().all(|d| d.x > 0.0 && (d.norm() - 1.0).abs() < 1e-9));
+        assert!(directions.iter().any(|d| d.x < 1.0 - 1e-3));
+
+        // The surface normals used for grafting follow the slopes as well
+        assert!(smooth.normals.is_empty());
+
+        for (i, (&coord, &direction)) in rough.coords.iter().zip(directions.iter()).enumerate() {
+            assert!(rough.normal_at_site(i).distance(direction) < 1e-9);
+            assert!(rough.normal_at(coord).distance(direction) < 1e-9);
+        }
+
+        // Periodic images keep the orientations of their residues
+        let multiplied = rough.pbc_multiply(2, 2, 1);
+        let num = rough.coords.len();
+
+        assert_eq!(multiplied.orientations.len(), multiplied.coords.len());
+        assert_eq!(&multiplied.orientations[3 * num..], &rough.orientations[..]);
+        assert_eq!(&multiplied.normals[3 * num..], &rough.normals[..]);
+
+        // and so do circles which are cut from the sheet
+        let circle = rough.to_circle(1.0);
+
+        assert_eq!(circle.orientations.len(), circle.coords.len());
+        assert!(circle.orientations.iter().all(|r| rough.orientations.contains(r)));
+
+        // Residues are not oriented unless requested
+        let unset = Sheet { orient_residues: false, .. base }.construct().unwrap();
+        assert!(unset.orientations.is_empty());
+    }
+
+    #[test]
+    fn rough_sheet_with_a_missing_height_map_is_an_error() {
+        let roughness = Roughness::HeightMap {
+            path: ::std::path::PathBuf::from("/this/image/does/not/exist.pgm"),
+            max_height: 1.0,
+        };
+
+        let sheet = Sheet {
+            roughness: Some(roughness),
+            .. setup_sheet(3.0, 3.0, &Hexagonal { a: 0.142 })
+        };
+
+        assert!(!sheet.is_stochastic());
+        assert!(sheet.construct().is_err());
+    }
+
+    #[test]
+    fn sheets_of_oblique_triclinic_lattices_have_a_triclinic_cell() {
+        let lattice = Triclinic { a: 1.0, b: 1.0, gamma: 60.0 };
+        let sheet = setup_sheet(4.0, 3.0, &lattice).construct().unwrap();
+
+        let cell = sheet.cell.unwrap();
+        assert!(!cell.is_rectangular());
+        assert_eq!(cell.a, Coord::new(sheet.length, 0.0, 0.0));
+        assert_eq!(cell.b.y, sheet.width);
+        assert_eq!(sheet.box_vectors(), cell);
+
+        // Wrapping keeps all coordinates as lattice points inside the cell
+        let wrapped = sheet.clone().with_pbc();
+        assert_eq!(wrapped.coords.len(), sheet.coords.len());
+
+        for coord in wrapped.coords {
+            assert!(coord.y >= 0.0 && coord.y < cell.b.y);
+        }
+
+        // A rectangular lattice keeps the rectangular box
+        let lattice = Triclinic { a: 1.0, b: 1.0, gamma: 90.0 };
+        assert!(setup_sheet(4.0, 3.0, &lattice).construct().unwrap().cell.is_none());
+    }
+
     #[test]
     fn cut_a_sheet_into_a_circle() {
         let radius = 4.0;
@@ -379,4 +831,32 @@ mod tests {
             assert_eq!(coord.z, 0.0);
         }
     }
+
+    #[test]
+    fn sheets_are_extended_in_their_plane_and_stacked_along_their_normal() {
+        let sheet = Sheet {
+            normal: Direction::X,
+            .. setup_sheet(1.0, 1.0, &Hexagonal { a: 0.142 })
+        }.construct().unwrap();
+
+        let (length, width) = (sheet.length, sheet.width);
+        assert_eq!(sheet.periodic_axes(), [false, true, true]);
+
+        // The length of a sheet along x is along z and its width along y
+        let extended = sheet.pbc_multiply(1, 2, 3);
+
+        assert_eq!(extended.coords.len(), 6 * sheet.coords.len());
+        assert_eq!((extended.length, extended.width), (3.0 * length, 2.0 * width));
+        assert!(extended.cell.is_none());
+        assert_eq!(extended.periodic_axes(), [false, true, true]);
+
+        // Stacked sheets are separated by the box height along the normal
+        let stacked = sheet.pbc_multiply(2, 1, 1);
+        let num = sheet.coords.len();
+
+        assert_eq!((stacked.length, stacked.width), (length, width));
+        assert_eq!(stacked.box_size().x, 2.0 * SHEET_MARGIN);
+        assert!(stacked.coords[num..].iter().all(|c| c.x == SHEET_MARGIN));
+        assert_eq!(stacked.periodic_axes(), [false; 3]);
+    }
 }
@@ -0,0 +1,248 @@
+//! Construct spheres that are closed shells, not volumes.
+
+use surface::{SurfaceNormal, calc_normal_orientations, multiply_images};
+use surface::cylinder::hemisphere_coords;
+
+use coord::{BoxVectors, Coord, Frame, Rotate, Rotation, Translate};
+use describe::{unwrap_name, Describe};
+use error::{GrafenError, Result};
+use iterator::{ResidueIter, ResidueIterOut};
+use system::*;
+
+use std::f64::consts::PI;
+
+impl_component![@oriented Sphere];
+impl_translate![Sphere];
+impl_rotate![Sphere];
+impl_periodic![Sphere];
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+/// A 2D spherical surface. Its points are evenly spaced along a Fibonacci spiral
+/// from the bottom to the top of the sphere.
+pub struct Sphere {
+    /// Name of sphere in database.
+    pub name: Option<String>,
+    /// Optional residue placed at each coordinate. If not set the sphere describes
+    /// a general collection of coordinates.
+    pub residue: Option<Residue>,
+    /// Number of points per area (1/nm^2).
+    pub density: f64,
+    #[serde(default)]
+    /// Rotate residues such that their z axis follows the surface normal at their position.
+    pub orient_residues: bool,
+    #[serde(skip)]
+    /// Origin of the sphere. Located in the lower corner of the box which encloses it.
+    pub origin: Coord,
+    #[serde(skip)]
+    /// Radius of sphere.
+    pub radius: f64,
+    #[serde(skip)]
+    /// List of coordinates belonging to the sphere. Relative to the `origin`.
+    pub coords: Vec<Coord>,
+    #[serde(skip)]
+    /// Rotation of the residue at every coordinate, if residues are oriented along
+    /// the surface normal. Empty if they are not.
+    pub orientations: Vec<Rotation>,
+    #[serde(skip)]
+    /// Periodic cell of the object if it differs from the box calculated from its geometry,
+    /// eg. the box enclosing all atoms after the object has been rotated.
+    pub cell: Option<BoxVectors>,
+    #[serde(skip)]
+    /// Rotation of the object relative to its geometry, if it has been rotated.
+    pub frame: Option<Frame>,
+    #[serde(skip)]
+    /// Number of periodic images along each axis, if the sphere has been replicated
+    /// into several spheres.
+    pub images: Option<[usize; 3]>,
+}
+
+impl Sphere {
+    /// Construct the sphere coordinates and return the object.
+    ///
+    /// # Errors
+    /// Returns an error if either the radius or density is non-positive.
+    pub fn construct(self) -> Result<Sphere> {
+        if self.radius <= 0.0 || self.density <= 0.0 {
+            return Err(GrafenError::RunError(
+                "the sphere radius and density must be positive".to_string()
+            ));
+        }
+
+        let num_coords = (self.surface_area() * self.density).round() as usize;
+        let center = self.calc_center();
+
+        let coords = hemisphere_coords(self.radius, num_coords, -1.0)
+            .into_iter()
+            .map(|coord| coord + center)
+            .collect();
+
+        let mut sphere = Sphere {
+            coords,
+            orientations: vec![],
+            .. self
+        };
+
+        if sphere.orient_residues {
+            sphere.orientations = calc_normal_orientations(&sphere);
+        }
+
+        Ok(sphere)
+    }
+
+    /// Calculate the box size, which encloses the sphere.
+    fn calc_box_size(&self) -> Coord {
+        let diameter = 2.0 * self.radius;
+        Coord::new(diameter, diameter, diameter)
+    }
+
+    /// Calculate along which axes the sphere is periodic, which it is along none.
+    fn calc_periodic_axes(&self) -> [bool; 3] {
+        [false; 3]
+    }
+
+    /// Update the geometry after the sphere has been replicated. The images are
+    /// several spheres.
+    fn replicate_geometry(&mut self, nx: usize, ny: usize, nz: usize) {
+        self.images = multiply_images(self.images, nx, ny, nz);
+    }
+
+    /// Return the center of the unrotated sphere, relative to its origin.
+    fn calc_center(&self) -> Coord {
+        Coord::new(self.radius, self.radius, self.radius)
+    }
+}
+
+impl SurfaceNormal for Sphere {
+    fn surface_coords(&self) -> &[Coord] {
+        &self.coords
+    }
+
+    fn frame(&self) -> Option<Frame> {
+        self.frame
+    }
+
+    /// The normal points out from the center of the sphere.
+    fn unrotated_normal_at(&self, coord: Coord) -> Coord {
+        let radial = coord - self.calc_center();
+        let dr = radial.norm();
+
+        if dr > 0.0 {
+            radial * (1.0 / dr)
+        } else {
+            Coord::new(0.0, 0.0, 1.0)
+        }
+    }
+
+    fn surface_area(&self) -> f64 {
+        4.0 * PI * self.radius.powi(2)
+    }
+
+    fn is_replicated(&self) -> bool {
+        self.images.is_some()
+    }
+}
+
+impl Describe for Sphere {
+    fn describe(&self) -> String {
+        format!("{} (Sphere surface of radius {:.2} at {})",
+            unwrap_name(&self.name), self.radius, self.origin)
+    }
+
+    fn describe_short(&self) -> String {
+        format!("{} (Sphere)", unwrap_name(&self.name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup_sphere(radius: f64, density: f64) -> Sphere {
+        Sphere {
+            name: None,
+            residue: None,
+            density,
+            orient_residues: false,
+            origin: Coord::default(),
+            radius,
+            coords: vec![],
+            orientations: vec![],
+            cell: None,
+            frame: None,
+            images: None,
+        }
+    }
+
+    #[test]
+    fn sphere_points_are_on_the_shell_at_the_set_density() {
+        let radius = 2.0;
+        let density = 10.0;
+
+        let sphere = setup_sphere(radius, density).construct().unwrap();
+
+        let expected = 4.0 * PI * radius.powi(2) * density;
+        assert_eq!(sphere.coords.len(), expected.round() as usize);
+
+        let center = Coord::new(radius, radius, radius);
+        let box_size = sphere.box_size();
+
+        for &coord in &sphere.coords {
+            assert!((coord.distance(center) - radius).abs() < 1e-9);
+
+            assert!(coord.x >= 0.0 && coord.x <= box_size.x);
+            assert!(coord.y >= 0.0 && coord.y <= box_size.y);
+            assert!(coord.z >= 0.0 && coord.z <= box_size.z);
+        }
+
+        // The points are evenly spread over both halves of the sphere along the spiral
+        let num_above = sphere.coords.iter().filter(|coord| coord.y > radius).count();
+        let num_below = sphere.coords.len() - num_above;
+        assert!((num_above as i64 - num_below as i64).abs() <= 1);
+    }
+
+    #[test]
+    fn constructing_sphere_with_non_positive_radius_or_density_returns_error() {
+        assert!(setup_sphere(0.0, 10.0).construct().is_err());
+        assert!(setup_sphere(-1.0, 10.0).construct().is_err());
+        assert!(setup_sphere(1.0, 0.0).construct().is_err());
+    }
+
+    #[test]
+    fn oriented_residues_point_out_from_the_sphere() {
+        let residue = resbase!["SIO", ("SI", 0.0, 0.0, 0.0), ("O", 0.0, 0.0, 0.2)];
+
+        let sphere = Sphere {
+            residue: Some(residue),
+            orient_residues: true,
+            .. setup_sphere(1.5, 5.0)
+        }.construct().unwrap();
+
+        assert_eq!(sphere.orientations.len(), sphere.coords.len());
+
+        let center = Coord::new(1.5, 1.5, 1.5);
+
+        for (&coord, residue) in sphere.coords.iter().zip(sphere.iter_residues()) {
+            let atoms = residue.get_atoms();
+            let direction = (atoms[1].1 - atoms[0].1) * (1.0 / 0.2);
+            let normal = (coord - center) * (1.0 / 1.5);
+
+            assert!(direction.distance(normal) < 1e-9);
+            assert!(sphere.normal_at(coord).distance(normal) < 1e-9);
+        }
+
+        // Rotating the sphere rotates the residues along with it
+        let rotation = Rotation::from_axis_angle(Coord::new(1.0, 0.0, 0.0), 0.5);
+        let rotated = sphere.clone().rotate(&rotation, Coord::ORIGO);
+
+        let sites = sphere.coords.iter().zip(rotated.coords.iter());
+
+        for ((&coord, &rotated_coord), residue) in sites.zip(rotated.iter_residues()) {
+            let atoms = residue.get_atoms();
+            let direction = (atoms[1].1 - atoms[0].1) * (1.0 / 0.2);
+            let expected = rotation.apply(sphere.normal_at(coord));
+
+            assert!(direction.distance(expected) < 1e-9);
+            assert!(rotated.normal_at(rotated_coord).distance(expected) < 1e-9);
+        }
+    }
+}
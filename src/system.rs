@@ -11,10 +11,12 @@
 //! A proper physical way to look at is that atoms can be
 //! similarly grouped into molecules.
 
-use coord::Coord;
-use describe::{describe_list, Describe};
+use coord::{calc_extent, BoxVectors, Coord, Rotation, Translate};
+use describe::Describe;
 use database::{ComponentEntry, DataBase};
+use element::{get_element, guess_element};
 use iterator::{ResidueIter, ResidueIterOut};
+use random::next_seed;
 
 use colored::*;
 use std::path::PathBuf;
@@ -29,12 +31,58 @@ pub struct System {
     pub database: DataBase,
     /// List of constructed components.
     pub components: Vec<ComponentEntry>,
+    /// Setting for the box size of the system.
+    pub box_setting: BoxSetting,
+    /// Seed from which the seeds of stochastically constructed components are derived.
+    pub seed: Option<u64>,
+    /// Number of component seeds which have been derived from the system seed.
+    pub num_seeds: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+/// How the box size of a `System` is determined.
+pub enum BoxSetting {
+    /// The box encloses all components, with extra padding (nm) added along each axis.
+    Auto { padding: Coord },
+    /// The box has a fixed size (nm).
+    Fixed(Coord),
+    /// The box has fixed (possibly triclinic) box vectors (nm).
+    Vectors(BoxVectors),
+}
+
+impl Default for BoxSetting {
+    fn default() -> BoxSetting {
+        BoxSetting::Auto { padding: Coord::ORIGO }
+    }
 }
 
 impl<'a> System {
-    /// Calculate the total box size of the system as the maximum size along each axis
-    /// from all components.
+    /// Return the seed to construct the next component with, derived from the system
+    /// seed and the number of seeds which have been derived before. Returns `None`
+    /// if the system has no seed.
+    ///
+    /// Every call yields a new seed, which does not depend on the current list of
+    /// components. Removing or reordering components thus does not change the seeds
+    /// of later components.
+    pub fn component_seed(&mut self) -> Option<u64> {
+        next_seed(self.seed, &mut self.num_seeds)
+    }
+
+    /// Calculate the total box size of the system.
+    ///
+    /// For an automatic box this is the maximum size along each axis from all components
+    /// with the padding added. A fixed box has its set size and fixed box vectors
+    /// the size of the rectangular box which encloses them.
     pub fn box_size(&self) -> Coord {
+        match self.box_setting {
+            BoxSetting::Auto { padding } => self.calc_components_box_size() + padding,
+            BoxSetting::Fixed(size) => size,
+            BoxSetting::Vectors(vectors) => vectors.bounding_size(),
+        }
+    }
+
+    /// Calculate the maximum size along each axis from all components.
+    fn calc_components_box_size(&self) -> Coord {
         self.components
             .iter()
             .map(|object| object.box_size())
@@ -47,18 +95,143 @@ impl<'a> System {
             })
     }
 
+    /// Translate all components to have their combined extent centered in the box.
+    ///
+    /// An automatic box is fixed at its box vectors before the translation, since it
+    /// would otherwise follow the components. Returns the translation of the components.
+    pub fn center_components(&mut self) -> Coord {
+        let box_vectors = self.box_vectors();
+
+        let extent = self.components
+            .iter()
+            .map(|object| (object.get_origin(), object.box_size()))
+            .fold(None, |acc: Option<(Coord, Coord)>, (min, max)| {
+                match acc {
+                    Some((acc_min, acc_max)) => Some((
+                        Coord::new(
+                            acc_min.x.min(min.x), acc_min.y.min(min.y), acc_min.z.min(min.z)
+                        ),
+                        Coord::new(
+                            acc_max.x.max(max.x), acc_max.y.max(max.y), acc_max.z.max(max.z)
+                        ),
+                    )),
+                    None => Some((min, max)),
+                }
+            });
+
+        if let Some((min, max)) = extent {
+            let center = (box_vectors.a + box_vectors.b + box_vectors.c) * 0.5;
+            let shift = center - (min + max) * 0.5;
+
+            self.box_setting = match self.box_setting {
+                BoxSetting::Auto { .. } if box_vectors.is_rectangular() => {
+                    BoxSetting::Fixed(box_vectors.bounding_size())
+                },
+                BoxSetting::Auto { .. } => BoxSetting::Vectors(box_vectors),
+                setting => setting,
+            };
+            self.components
+                .iter_mut()
+                .for_each(|object| object.translate_in_place(shift));
+
+            shift
+        } else {
+            Coord::ORIGO
+        }
+    }
+
+    /// Calculate the number of atoms which lie outside of the system box.
+    pub fn num_atoms_outside_box(&self) -> u64 {
+        let box_vectors = self.box_vectors();
+
+        self.components
+            .iter()
+            .map(|object| {
+                let origin = object.get_origin();
+
+                object.iter_residues()
+                    .flat_map(|residue| residue.get_atoms())
+                    .map(|(_, position)| origin + position)
+                    .filter(|&position| box_vectors.wrap(position).distance(position) > 1e-6)
+                    .count() as u64
+            })
+            .sum()
+    }
+
+    /// Calculate the box vectors of the system.
+    ///
+    /// If any component has a triclinic periodic cell the in-plane vectors (a and b)
+    /// of the first such component are used, with the box height set to fit all
+    /// components. The padding of an automatic box is then added to the perpendicular
+    /// heights of this cell. Otherwise the box is rectangular of size `box_size()`.
+    /// Fixed box vectors are returned as they are.
+    pub fn box_vectors(&self) -> BoxVectors {
+        let padding = match self.box_setting {
+            BoxSetting::Auto { padding } => padding,
+            BoxSetting::Fixed(size) => return BoxVectors::rectangular(size),
+            BoxSetting::Vectors(vectors) => return vectors,
+        };
+
+        let size = self.calc_components_box_size();
+
+        self.components
+            .iter()
+            .map(|object| object.box_vectors())
+            .find(|vectors| !vectors.is_rectangular())
+            .map(|vectors| {
+                BoxVectors {
+                    c: Coord { z: vectors.c.z.max(size.z), .. vectors.c },
+                    .. vectors
+                }.padded(padding)
+            })
+            .unwrap_or(BoxVectors::rectangular(size + padding))
+    }
+
     /// Print the system state to standard error.
     pub fn print_state(&self) {
+        let box_vectors = self.box_vectors();
         let (dx, dy, dz) = self.box_size().to_tuple();
 
         eprintln!("{}", "System".underline().color("yellow"));
         eprintln!("Title       '{}'", self.title);
         eprintln!("Output path  {}", self.output_path.to_str().unwrap_or("(Not set)"));
-        eprintln!("Box size     ({:.8}, {:.8}, {:.8})", dx, dy, dz);
+
+        if box_vectors.is_rectangular() {
+            eprintln!("Box size     ({:.8}, {:.8}, {:.8})", dx, dy, dz);
+        } else {
+            let (a, b, c) = box_vectors.lengths().to_tuple();
+            let (alpha, beta, gamma) = box_vectors.angles();
+
+            eprintln!("Box lengths  ({:.8}, {:.8}, {:.8})", a, b, c);
+            eprintln!("Box angles   ({:.2}, {:.2}, {:.2})", alpha, beta, gamma);
+        }
+
+        match self.box_setting {
+            BoxSetting::Auto { padding } if padding != Coord::ORIGO => {
+                eprintln!("Box padding  {}", padding);
+            },
+            BoxSetting::Fixed(_) | BoxSetting::Vectors(_) => eprintln!("Box setting  Fixed"),
+            _ => (),
+        }
+
+        if let Some(seed) = self.seed {
+            eprintln!("Seed         {}", seed);
+        }
+
+        eprintln!("Total mass   {}", describe_mass(self.total_mass()));
+        eprintln!("Net charge   {:.4}", self.net_charge());
+
+        let num_outside = self.num_atoms_outside_box();
+        if num_outside > 0 {
+            eprintln!("{}", format!(
+                "Warning: {} atoms lie outside of the box", num_outside
+            ).color("yellow"));
+        }
+
         eprintln!("");
 
         if self.components.len() > 0 {
-            eprintln!("{}", describe_list("Components", &self.components));
+            eprintln!("{}", self.describe_components());
         } else {
             eprintln!("(no constructed components)\n");
         }
@@ -68,6 +241,35 @@ impl<'a> System {
     pub fn num_atoms(&self) -> u64 {
         self.components.iter().map(|object| object.num_atoms()).sum()
     }
+
+    /// Calculate the total mass of the system (g/mol), or `None` if the mass
+    /// of any component is unknown.
+    pub fn total_mass(&self) -> Option<f64> {
+        self.components
+            .iter()
+            .fold(Some(0.0), |acc, object| {
+                acc.and_then(|sum| object.total_mass().map(|mass| sum + mass))
+            })
+    }
+
+    /// Calculate the net charge of the system (e).
+    pub fn net_charge(&self) -> f64 {
+        self.components.iter().map(|object| object.net_charge()).sum()
+    }
+
+    /// Describe the list of components with their total mass and net charge.
+    fn describe_components(&self) -> String {
+        let mut description = "[ Components ]\n".to_string();
+
+        for (i, object) in self.components.iter().enumerate() {
+            description.push_str(&format!(
+                "{:2}: {} (mass {}, charge {:.4})\n",
+                i, object.describe(), describe_mass(object.total_mass()), object.net_charge()
+            ));
+        }
+
+        description
+    }
 }
 
 /// Methods for yielding atoms and output information from constructed objects.
@@ -95,10 +297,61 @@ pub trait Component<'a> {
     /// Return the number of atoms in the object.
     fn num_atoms(&self) -> u64;
 
+    /// Return the number of atoms which the object holds in memory, which includes
+    /// atoms that are kept to re-derive it.
+    ///
+    /// The default implementation returns the number of atoms in the object.
+    fn num_stored_atoms(&self) -> u64 {
+        self.num_atoms()
+    }
+
+    /// Return the total mass of the object (g/mol), or `None` if the mass of any atom
+    /// is unknown.
+    ///
+    /// The default implementation guesses the element of every atom from its name.
+    fn total_mass(&self) -> Option<f64> {
+        self.iter_residues()
+            .flat_map(|residue| residue.get_atoms())
+            .fold(Some(0.0), |acc, (name, _)| {
+                acc.and_then(|sum| guess_element(&name.borrow()).map(|el| sum + el.mass))
+            })
+    }
+
+    /// Return the net charge of the object (e).
+    ///
+    /// The default implementation has no charge information and returns zero.
+    fn net_charge(&self) -> f64 {
+        0.0
+    }
+
+    /// Return the periodic box vectors of the component, relative to its origin.
+    ///
+    /// The default implementation returns a rectangular box of the component size.
+    fn box_vectors(&self) -> BoxVectors {
+        BoxVectors::rectangular(self.box_size() - self.get_origin())
+    }
+
     /// Return the component with its coordinates adjusted to lie within its box.
     fn with_pbc(self) -> Self;
 }
 
+/// Rotations of the residues at every coordinate of a `Component`.
+///
+/// Implemented by `impl_component`. Objects which are marked as `@oriented` return their
+/// `orientations` field, all other objects keep the orientation of their residue.
+pub trait Oriented {
+    /// Return the rotation of the residue at every coordinate, or an empty slice
+    /// if all residues keep their orientation.
+    fn get_orientations(&self) -> &[Rotation] {
+        &[]
+    }
+
+    /// Return the rotations of the residues to modify, if the object is oriented.
+    fn get_orientations_mut(&mut self) -> Option<&mut Vec<Rotation>> {
+        None
+    }
+}
+
 #[macro_export]
 /// Macro to implement `Component` for an object.
 ///
@@ -106,11 +359,32 @@ pub trait Component<'a> {
 /// {
 ///     residue: Option<Residue>,
 ///     origin: Coord,
-///     coords: [Coord]
+///     coords: [Coord],
+///     cell: Option<BoxVectors>
 /// }
-/// and the method `calc_box_size`.
+/// and the method `calc_box_size`, which is used for the box size unless
+/// a periodic `cell` has been set.
+///
+/// Objects which are marked as `@oriented` additionally contain the field
+/// `orientations: Vec<Rotation>` with a rotation of the residue at every coordinate.
+/// If it is empty all residues keep their orientation.
 macro_rules! impl_component {
-    ( $( $class:path ),+ ) => {
+    ( @oriented $( $class:path ),+ ) => {
+        $(
+            impl $crate::system::Oriented for $class {
+                fn get_orientations(&self) -> &[$crate::coord::Rotation] {
+                    &self.orientations
+                }
+
+                fn get_orientations_mut(&mut self) -> Option<&mut Vec<$crate::coord::Rotation>> {
+                    Some(&mut self.orientations)
+                }
+            }
+        )*
+
+        impl_component!(@impl $( $class ),+);
+    };
+    ( @impl $( $class:path ),+ ) => {
         $(
             impl<'a> Component<'a> for $class {
                 /// Assign a set of input residues to the component.
@@ -132,6 +406,8 @@ macro_rules! impl_component {
                 /// the first atom of every object. That position is subtracted by
                 /// the residue-relative position of the atom of the `Residue` that is set
                 /// to the `Component`, to find the component-relative position of the residue.
+                /// For oriented objects the rotation of every residue is recovered from
+                /// the positions of its atoms.
                 ///
                 /// Furthermore, note that any information about several different residue
                 /// types in the iterating object is lost. This object assumes that the
@@ -146,13 +422,34 @@ macro_rules! impl_component {
                 fn assign_residues(&mut self, residues: &[ResidueIterOut]) {
                     let residue = self.residue.clone().unwrap();
 
+                    let oriented = $crate::system::Oriented::get_orientations_mut(self);
+
+                    if let Some(orientations) = oriented {
+                        if !orientations.is_empty() {
+                            let (coords, rotations) =
+                                $crate::system::assign_oriented_residues(&residue, residues);
+
+                            *orientations = rotations;
+                            self.coords = coords;
+
+                            return;
+                        }
+                    }
+
                     self.coords = residues.iter()
                         .map(|res| res.get_atoms()[0].1 - residue.atoms[0].position)
                         .collect::<Vec<_>>();
                 }
 
                 fn box_size(&self) -> Coord {
-                    self.calc_box_size() + self.origin
+                    self.cell
+                        .map(|cell| cell.bounding_size())
+                        .unwrap_or_else(|| self.calc_box_size())
+                        + self.origin
+                }
+
+                fn box_vectors(&self) -> BoxVectors {
+                    self.cell.unwrap_or_else(|| BoxVectors::rectangular(self.calc_box_size()))
                 }
 
                 fn get_origin(&self) -> Coord {
@@ -162,7 +459,11 @@ macro_rules! impl_component {
                 fn iter_residues(&self) -> ResidueIter {
                     match self.residue {
                         None => ResidueIter::None,
-                        Some(ref code) => ResidueIter::Component(code, self.coords.iter()),
+                        Some(ref code) => ResidueIter::Component(
+                            code,
+                            self.coords.iter().enumerate(),
+                            $crate::system::Oriented::get_orientations(self)
+                        ),
                     }
                 }
 
@@ -175,17 +476,169 @@ macro_rules! impl_component {
                     (residue_len * self.coords.len()) as u64
                 }
 
+                fn total_mass(&self) -> Option<f64> {
+                    match self.residue {
+                        None => Some(0.0),
+                        Some(ref residue) => {
+                            residue.mass().map(|mass| mass * self.coords.len() as f64)
+                        },
+                    }
+                }
+
+                fn net_charge(&self) -> f64 {
+                    self.residue
+                        .as_ref()
+                        .map(|residue| residue.charge() * self.coords.len() as f64)
+                        .unwrap_or(0.0)
+                }
+
                 fn with_pbc(mut self) -> Self {
-                    let box_size = self.calc_box_size();
+                    let box_vectors = self.box_vectors();
 
                     self.coords
                         .iter_mut()
-                        .for_each(|c| *c = c.with_pbc(box_size));
+                        .for_each(|c| *c = box_vectors.wrap(*c));
 
                     self
                 }
             }
         )*
+    };
+    ( $( $class:path ),+ ) => {
+        $( impl $crate::system::Oriented for $class {} )*
+
+        impl_component!(@impl $( $class ),+);
+    };
+}
+
+#[macro_export]
+/// Macro to implement `Rotate` for a `Component` constructed by `impl_component`.
+///
+/// All residues of these objects are copies of a single `residue`, so its atoms are rotated
+/// along with the residue positions in `coords`. The enclosing box of all atoms is set
+/// as the periodic `cell` of the rotated object and the total rotation as its `frame`,
+/// which maps positions to the unrotated geometry. Oriented objects also have
+/// the rotations of their residues at every position updated.
+macro_rules! impl_rotate {
+    ( $( $class:path ),+ ) => {
+        $(
+            impl Rotate for $class {
+                fn rotate_in_place(&mut self, rotation: &Rotation, center: Coord) {
+                    if let Some(ref mut residue) = self.residue {
+                        residue.atoms
+                            .iter_mut()
+                            .for_each(|atom| atom.position = rotation.apply(atom.position));
+                    }
+
+                    // Rotations of the residue at every position are made relative to
+                    // the rotated residue
+                    let oriented = $crate::system::Oriented::get_orientations_mut(self);
+
+                    if let Some(orientations) = oriented {
+                        let inverse = rotation.inverse();
+
+                        orientations
+                            .iter_mut()
+                            .for_each(|r| *r = inverse.then(r).then(rotation));
+                    }
+
+                    let origin = self.origin;
+                    let positions = self.coords
+                        .iter()
+                        .map(|&coord| rotation.apply_around(origin + coord, center))
+                        .collect::<Vec<_>>();
+
+                    let mut atom_positions = self.residue
+                        .as_ref()
+                        .map(|res| res.atoms.iter().map(|atom| atom.position).collect())
+                        .unwrap_or(Vec::new());
+
+                    if atom_positions.is_empty() {
+                        atom_positions.push(Coord::ORIGO);
+                    }
+
+                    let orientations = $crate::system::Oriented::get_orientations(self);
+                    let all_positions = positions
+                        .iter()
+                        .enumerate()
+                        .flat_map(|(i, &position)| {
+                            let orientation = orientations.get(i);
+
+                            atom_positions.iter().map(move |&a| {
+                                position + orientation.map(|r| r.apply(a)).unwrap_or(a)
+                            })
+                        })
+                        .collect::<Vec<_>>();
+
+                    // Objects without coordinates have only their origin rotated
+                    let extent = $crate::coord::calc_extent(&all_positions);
+                    let rotated_origin = extent
+                        .map(|(min, _)| min)
+                        .unwrap_or(rotation.apply_around(origin, center));
+
+                    self.frame = Some($crate::coord::Frame::rotate(
+                        self.frame, rotation, center, origin, rotated_origin
+                    ));
+                    self.origin = rotated_origin;
+
+                    if let Some((min, max)) = extent {
+                        self.coords = positions.into_iter().map(|c| c - min).collect();
+                        self.cell = Some($crate::coord::BoxVectors::rectangular(max - min));
+                    }
+                }
+            }
+        )*
+    }
+}
+
+#[macro_export]
+/// Macro to implement `Periodic` for a `Component` constructed by `impl_component`.
+///
+/// The coordinates are cloned into periodic images along the box vectors of the object,
+/// which are extended and set as its periodic `cell`. Oriented objects also have
+/// the rotations of their residues cloned into the images.
+///
+/// The object has to implement the methods `calc_periodic_axes`, which returns whether
+/// it is periodic along each axis of its box, and `replicate_geometry`, which updates
+/// its geometry after it has been replicated into the images.
+macro_rules! impl_periodic {
+    ( $( $class:path ),+ ) => {
+        $(
+            impl $crate::coord::Periodic for $class {
+                fn pbc_multiply(&self, nx: usize, ny: usize, nz: usize) -> Self {
+                    let box_vectors = $crate::system::Component::box_vectors(self);
+                    let shifts = box_vectors.image_shifts(nx, ny, nz);
+
+                    let mut extended = self.clone();
+
+                    extended.coords = shifts
+                        .iter()
+                        .flat_map(|&shift| self.coords.iter().map(move |&coord| coord + shift))
+                        .collect();
+
+                    if let Some(orientations) =
+                            $crate::system::Oriented::get_orientations_mut(&mut extended) {
+                        *orientations = shifts
+                            .iter()
+                            .flat_map(|_| {
+                                $crate::system::Oriented::get_orientations(self).iter().cloned()
+                            })
+                            .collect();
+                    }
+
+                    extended.cell = Some(
+                        $crate::coord::Periodic::pbc_multiply(&box_vectors, nx, ny, nz)
+                    );
+                    extended.replicate_geometry(nx, ny, nz);
+
+                    extended
+                }
+
+                fn periodic_axes(&self) -> [bool; 3] {
+                    self.calc_periodic_axes()
+                }
+            }
+        )*
     }
 }
 
@@ -197,11 +650,54 @@ pub struct Atom {
     pub code: String,
     /// Relative position.
     pub position: Coord,
+    /// Element symbol of the atom. If not set it is guessed from its code.
+    #[serde(default)]
+    pub element: Option<String>,
+    /// Mass of the atom (g/mol). If not set it is looked up from its element.
+    #[serde(default)]
+    pub mass: Option<f64>,
+    /// Partial charge of the atom (e).
+    #[serde(default)]
+    pub charge: Option<f64>,
+}
+
+impl Atom {
+    /// Return the mass of the atom (g/mol).
+    ///
+    /// Uses the explicitly set mass if available, otherwise the mass of the set element.
+    /// If no element is set it is guessed from the atom code.
+    pub fn get_mass(&self) -> Option<f64> {
+        self.mass.or_else(|| {
+            match self.element {
+                Some(ref symbol) => get_element(symbol),
+                None => guess_element(&self.code),
+            }.map(|element| element.mass)
+        })
+    }
+
+    /// Return the partial charge of the atom (e), which is zero if not set.
+    pub fn get_charge(&self) -> f64 {
+        self.charge.unwrap_or(0.0)
+    }
 }
 
 impl Describe for Atom {
     fn describe(&self) -> String {
-        format!("{} {}", self.code, self.position)
+        let mut description = format!("{} {}", self.code, self.position);
+
+        if let Some(ref element) = self.element {
+            description.push_str(&format!(" element: {}", element));
+        }
+
+        if let Some(mass) = self.mass {
+            description.push_str(&format!(" mass: {}", mass));
+        }
+
+        if let Some(charge) = self.charge {
+            description.push_str(&format!(" charge: {}", charge));
+        }
+
+        description
     }
 
     fn describe_short(&self) -> String {
@@ -216,6 +712,113 @@ pub struct Residue {
     pub atoms: Vec<Atom>,
 }
 
+impl Residue {
+    /// Return the total mass of the residue (g/mol), or `None` if the mass of any atom
+    /// is unknown.
+    pub fn mass(&self) -> Option<f64> {
+        self.atoms
+            .iter()
+            .fold(Some(0.0), |acc, atom| acc.and_then(|sum| atom.get_mass().map(|m| sum + m)))
+    }
+
+    /// Return the net charge of the residue (e). Atoms without a set charge are neutral.
+    pub fn charge(&self) -> f64 {
+        self.atoms.iter().map(|atom| atom.get_charge()).sum()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+/// A residue with explicitly placed atoms, for components in which residues of the same
+/// type are not identical copies of each other (eg. after rotation).
+pub struct PlacedResidue {
+    /// Index of the residue type in the component's list of residues.
+    pub residue: usize,
+    /// Positions of all atoms in the residue, relative to the component origin.
+    pub atoms: Vec<Coord>,
+}
+
+/// Convert residues from an `iter_residues()` call into `PlacedResidue`s.
+///
+/// The residue type of each residue is found by matching its name to the input list.
+/// Residues with a name which is not in the list or with a different number of atoms
+/// than their type are thrown away.
+pub fn placed_residues_from_iter(residue_types: &[&Residue], residues: &[ResidueIterOut])
+        -> Vec<PlacedResidue> {
+    residues
+        .iter()
+        .filter_map(|res| {
+            let name = res.get_residue();
+            let atoms = res.get_atoms();
+
+            residue_types
+                .iter()
+                .position(|residue| residue.code == *name.borrow()
+                    && residue.atoms.len() == atoms.len())
+                .map(|index| PlacedResidue {
+                    residue: index,
+                    atoms: atoms.iter().map(|&(_, position)| position).collect(),
+                })
+        })
+        .collect()
+}
+
+/// Convert residues from an `iter_residues()` call into positions and rotations
+/// of a single residue type, for components with a rotated residue at every position.
+///
+/// The rotation of every residue is recovered from its atom positions relative to
+/// those of the residue type, and its position from that of its first atom.
+pub fn assign_oriented_residues(residue: &Residue, residues: &[ResidueIterOut])
+        -> (Vec<Coord>, Vec<Rotation>) {
+    let reference = residue.atoms.iter().map(|atom| atom.position).collect::<Vec<_>>();
+
+    residues
+        .iter()
+        .map(|res| {
+            let positions = res.get_atoms()
+                .iter()
+                .map(|&(_, position)| position)
+                .collect::<Vec<_>>();
+
+            let rotation = Rotation::from_positions(&reference, &positions);
+
+            (positions[0] - rotation.apply(reference[0]), rotation)
+        })
+        .unzip()
+}
+
+/// Rotate a set of `PlacedResidue`s, with positions relative to an `origin`, around
+/// the absolute position `center`.
+///
+/// The atom positions are updated to be relative to the lower corner of the box which
+/// encloses all of them. That corner and the size of the box are returned, or `None`
+/// if there are no atoms.
+pub fn rotate_placed_residues(residues: &mut [PlacedResidue], origin: Coord,
+                              rotation: &Rotation, center: Coord) -> Option<(Coord, Coord)> {
+    residues
+        .iter_mut()
+        .flat_map(|res| res.atoms.iter_mut())
+        .for_each(|atom| *atom = rotation.apply_around(origin + *atom, center));
+
+    let all_positions = residues
+        .iter()
+        .flat_map(|res| res.atoms.iter().cloned())
+        .collect::<Vec<_>>();
+
+    calc_extent(&all_positions).map(|(min, max)| {
+        residues
+            .iter_mut()
+            .flat_map(|res| res.atoms.iter_mut())
+            .for_each(|atom| *atom -= min);
+
+        (min, max - min)
+    })
+}
+
+/// Format an optional mass for output.
+fn describe_mass(mass: Option<f64>) -> String {
+    mass.map(|m| format!("{:.4} g/mol", m)).unwrap_or("(unknown)".to_string())
+}
+
 impl Describe for Residue {
     fn describe(&self) -> String {
         format!("{} ({} atoms)", self.code, self.atoms.len())
@@ -242,8 +845,20 @@ impl Describe for Residue {
 /// let expect = Residue {
 ///     code: "RES".to_string(),
 ///     atoms: vec![
-///         Atom { code: "A".to_string(), position: Coord::new(0.0, 0.0, 0.0) },
-///         Atom { code: "B".to_string(), position: Coord::new(1.0, 2.0, 3.0) }
+///         Atom {
+///             code: "A".to_string(),
+///             position: Coord::new(0.0, 0.0, 0.0),
+///             element: None,
+///             mass: None,
+///             charge: None,
+///         },
+///         Atom {
+///             code: "B".to_string(),
+///             position: Coord::new(1.0, 2.0, 3.0),
+///             element: None,
+///             mass: None,
+///             charge: None,
+///         }
 ///     ],
 /// };
 ///
@@ -268,6 +883,9 @@ macro_rules! resbase {
                     Atom {
                         code: $atname.to_string(),
                         position: Coord::new($x, $y, $z),
+                        element: None,
+                        mass: None,
+                        charge: None,
                     }
                 );
             )*
@@ -283,7 +901,8 @@ macro_rules! resbase {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use coord::Translate;
+    use coord::{Frame, Rotate, Translate};
+    use random::derive_seed;
     use volume::Cuboid;
 
     #[test]
@@ -291,8 +910,20 @@ mod tests {
         let expect = Residue {
             code: "RES".to_string(),
             atoms: vec![
-                Atom { code: "A1".to_string(), position: Coord::new(0.0, 0.0, 0.0) },
-                Atom { code: "A2".to_string(), position: Coord::new(0.0, 1.0, 2.0) }
+                Atom {
+                    code: "A1".to_string(),
+                    position: Coord::new(0.0, 0.0, 0.0),
+                    element: None,
+                    mass: None,
+                    charge: None,
+                },
+                Atom {
+                    code: "A2".to_string(),
+                    position: Coord::new(0.0, 1.0, 2.0),
+                    element: None,
+                    mass: None,
+                    charge: None,
+                }
             ],
         };
         let result = resbase![
@@ -305,7 +936,13 @@ mod tests {
     }
 
     #[derive(Debug, Deserialize, Serialize)]
-    struct TestObject { residue: Option<Residue>, origin: Coord, coords: Vec<Coord> }
+    struct TestObject {
+        residue: Option<Residue>,
+        origin: Coord,
+        coords: Vec<Coord>,
+        cell: Option<BoxVectors>,
+        frame: Option<Frame>,
+    }
     impl Describe for TestObject {
         fn describe(&self) -> String { "Doesn't matter".to_string() }
         fn describe_short(&self) -> String { "Doesn't matter".to_string() }
@@ -314,6 +951,43 @@ mod tests {
 
     impl_translate![TestObject];
     impl_component![TestObject];
+    impl_rotate![TestObject];
+
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    struct OrientedTestObject {
+        residue: Option<Residue>,
+        origin: Coord,
+        coords: Vec<Coord>,
+        orientations: Vec<Rotation>,
+        cell: Option<BoxVectors>,
+        frame: Option<Frame>,
+    }
+    impl OrientedTestObject {
+        fn calc_box_size(&self) -> Coord { Coord::new(6.0, 1.0, 1.0) }
+        fn calc_periodic_axes(&self) -> [bool; 3] { [true, true, false] }
+        fn replicate_geometry(&mut self, _: usize, _: usize, _: usize) {}
+    }
+
+    impl_component![@oriented OrientedTestObject];
+    impl_rotate![OrientedTestObject];
+    impl_periodic![OrientedTestObject];
+
+    fn setup_oriented_object() -> OrientedTestObject {
+        use std::f64::consts::PI;
+
+        OrientedTestObject {
+            residue: Some(resbase!["RES", ("A", 0.0, 0.0, 0.0), ("B", 0.0, 0.0, 1.0)]),
+            origin: Coord::ORIGO,
+            coords: vec![Coord::ORIGO, Coord::new(2.0, 0.0, 0.0), Coord::new(4.0, 0.0, 0.0)],
+            orientations: vec![
+                Rotation::IDENTITY,
+                Rotation::from_axis_angle(Coord::new(0.0, 1.0, 0.0), PI / 2.0),
+                Rotation::from_axis_angle(Coord::new(1.0, 0.0, 0.0), PI),
+            ],
+            cell: None,
+            frame: None,
+        }
+    }
 
     #[test]
     fn iterate_over_residues_in_macro_generated_impl_object_works_and_ignores_origin() {
@@ -322,6 +996,8 @@ mod tests {
             residue: Some(residue.clone()),
             origin: Coord::new(10.0, 20.0, 30.0), // Will be ignored
             coords: vec![Coord::new(0.0, 2.0, 4.0), Coord::new(1.0, 3.0, 5.0)],
+            cell: None,
+            frame: None,
         };
 
         let mut iter = constructed.iter_residues();
@@ -350,6 +1026,8 @@ mod tests {
             residue: Some(residue.clone()),
             origin: Coord::ORIGO,
             coords: Vec::new(),
+            cell: None,
+            frame: None,
         };
         assert!(empty_component.iter_residues().next().is_none());
 
@@ -357,6 +1035,8 @@ mod tests {
             residue: None,
             origin: Coord::ORIGO,
             coords: Vec::new(),
+            cell: None,
+            frame: None,
         };
         assert!(without_residue.iter_residues().next().is_none());
     }
@@ -369,6 +1049,8 @@ mod tests {
             residue: Some(residue.clone()),
             origin: Coord::ORIGO,
             coords: vec![Coord::new(0.0, 2.0, 4.0), Coord::new(1.0, 3.0, 5.0)],
+            cell: None,
+            frame: None,
         };
 
         assert_eq!(4, constructed.num_atoms());
@@ -378,6 +1060,49 @@ mod tests {
         assert_eq!(0, constructed.num_atoms());
     }
 
+    #[test]
+    fn atom_mass_is_taken_from_set_value_then_element_then_code() {
+        let mut atom = resbase!["RES", ("CA", 0.0, 0.0, 0.0)].atoms[0].clone();
+        assert_eq!(atom.get_mass(), Some(40.078));
+
+        atom.element = Some("C".to_string());
+        assert_eq!(atom.get_mass(), Some(12.011));
+
+        atom.mass = Some(13.0);
+        assert_eq!(atom.get_mass(), Some(13.0));
+
+        atom.mass = None;
+        atom.element = Some("Xx".to_string());
+        assert!(atom.get_mass().is_none());
+    }
+
+    #[test]
+    fn total_mass_and_net_charge_in_macro_generated_impl_objects() {
+        let mut residue = resbase!["RES", ("A", 0.0, 0.1, 0.2), ("X", 0.3, 0.4, 0.5)];
+        residue.atoms[0].mass = Some(1.0);
+        residue.atoms[1].mass = Some(2.5);
+        residue.atoms[0].charge = Some(-0.5);
+
+        let mut constructed = TestObject {
+            residue: Some(residue.clone()),
+            origin: Coord::ORIGO,
+            coords: vec![Coord::new(0.0, 2.0, 4.0), Coord::new(1.0, 3.0, 5.0)],
+            cell: None,
+            frame: None,
+        };
+
+        assert_eq!(constructed.total_mass(), Some(7.0));
+        assert_eq!(constructed.net_charge(), -1.0);
+
+        // An atom of unknown mass makes the total unknown
+        constructed.residue.as_mut().unwrap().atoms[1].mass = None;
+        assert!(constructed.total_mass().is_none());
+
+        constructed.residue = None;
+        assert_eq!(constructed.total_mass(), Some(0.0));
+        assert_eq!(constructed.net_charge(), 0.0);
+    }
+
     #[test]
     fn origin_in_macro_generated_impl_objects() {
         let origin = Coord::new(1.0, 2.0, 3.0);
@@ -385,6 +1110,8 @@ mod tests {
             residue: None,
             origin,
             coords: Vec::new(),
+            cell: None,
+            frame: None,
         };
 
         assert_eq!(component.get_origin(), origin);
@@ -452,7 +1179,10 @@ mod tests {
             size,
             origin,
             density: None,
+            seed: None,
             coords: vec![],
+            cell: None,
+            frame: None,
         };
 
         assert_eq!(origin + size, cuboid.box_size());
@@ -482,6 +1212,185 @@ mod tests {
 
         assert_eq!(cuboid.coords, expected);
     }
+
+    #[test]
+    fn rotating_macro_generated_impl_objects_rotates_residues_and_updates_the_box() {
+        use std::f64::consts::PI;
+
+        let residue = resbase!["RES", ("A", 0.0, 0.0, 0.0), ("B", 1.0, 0.0, 0.0)];
+        let center = Coord::new(1.0, 1.0, 0.0);
+
+        let component = TestObject {
+            residue: Some(residue),
+            origin: Coord::new(1.0, 1.0, 0.0),
+            coords: vec![Coord::ORIGO, Coord::new(0.0, 2.0, 0.0)],
+            cell: None,
+            frame: None,
+        }.rotate(&Rotation::from_axis_angle(Coord::new(0.0, 0.0, 1.0), PI / 2.0), center);
+
+        // The residue now points along y and the residues are placed along -x
+        let atoms = &component.residue.as_ref().unwrap().atoms;
+        assert_eq!(atoms[1].position, Coord::new(0.0, 1.0, 0.0));
+
+        assert_eq!(component.origin, Coord::new(-1.0, 1.0, 0.0));
+        assert_eq!(component.coords, vec![Coord::new(2.0, 0.0, 0.0), Coord::ORIGO]);
+        assert_eq!(component.box_size(), Coord::new(1.0, 2.0, 0.0));
+    }
+
+    #[test]
+    fn residues_of_oriented_objects_are_rotated_at_every_position() {
+        let component = setup_oriented_object();
+
+        let second_atoms = component.iter_residues()
+            .map(|res| res.get_atoms()[1].1)
+            .collect::<Vec<_>>();
+
+        assert!(second_atoms[0].distance(Coord::new(0.0, 0.0, 1.0)) < 1e-9);
+        assert!(second_atoms[1].distance(Coord::new(3.0, 0.0, 0.0)) < 1e-9);
+        assert!(second_atoms[2].distance(Coord::new(4.0, 0.0, -1.0)) < 1e-9);
+
+        // Without orientations all residues keep the orientation of the residue
+        let unoriented = OrientedTestObject { orientations: vec![], .. setup_oriented_object() };
+        assert!(unoriented.iter_residues().all(|res| res.get_atoms()[1].1.z == 1.0));
+    }
+
+    #[test]
+    fn assigning_residues_to_oriented_objects_keeps_their_orientations() {
+        let mut component = setup_oriented_object();
+        let orientations = component.orientations.clone();
+
+        // Remove the middle residue
+        let residues = component.iter_residues()
+            .enumerate()
+            .filter(|&(i, _)| i != 1)
+            .map(|(_, res)| res)
+            .collect::<Vec<_>>();
+
+        let get_positions = |residues: &[ResidueIterOut]| {
+            residues.iter()
+                .flat_map(|res| res.get_atoms().into_iter().map(|(_, position)| position))
+                .collect::<Vec<_>>()
+        };
+
+        let expected = get_positions(&residues);
+        component.assign_residues(&residues);
+
+        assert_eq!(component.coords, vec![Coord::ORIGO, Coord::new(4.0, 0.0, 0.0)]);
+        assert_eq!(component.orientations.len(), 2);
+
+        let assigned = get_positions(&component.iter_residues().collect::<Vec<_>>());
+        assert_eq!(assigned.len(), expected.len());
+
+        for (position, expected) in assigned.into_iter().zip(expected) {
+            assert!(position.distance(expected) < 1e-9);
+        }
+
+        // Rotations of residues with atoms off the line are recovered in full
+        let mut component = OrientedTestObject {
+            residue: Some(resbase!["RES",
+                ("A", 0.0, 0.0, 0.0), ("B", 0.0, 0.0, 1.0), ("C", 1.0, 0.0, 0.0)]),
+            .. setup_oriented_object()
+        };
+
+        // Residues which are moved away from the sites keep their rotations
+        let shift = Coord::new(0.5, 0.5, 0.5);
+        let moved = OrientedTestObject {
+            coords: component.coords.iter().map(|&coord| coord + shift).collect(),
+            .. component.clone()
+        };
+        let residues = moved.iter_residues().collect::<Vec<_>>();
+
+        component.assign_residues(&residues);
+
+        for (&position, &expected) in component.coords.iter().zip(moved.coords.iter()) {
+            assert!(position.distance(expected) < 1e-9);
+        }
+
+        let coord = Coord::new(1.0, 2.0, 3.0);
+        assert_eq!(component.orientations.len(), orientations.len());
+
+        for (rotation, expected) in component.orientations.iter().zip(orientations.iter()) {
+            assert!(rotation.apply(coord).distance(expected.apply(coord)) < 1e-9);
+        }
+    }
+
+    #[test]
+    fn rotating_oriented_objects_rotates_every_residue_around_the_center() {
+        use std::f64::consts::PI;
+
+        let component = setup_oriented_object();
+        let rotation = Rotation::from_axis_angle(Coord::new(0.0, 0.0, 1.0), PI / 2.0);
+
+        let expected = component.iter_residues()
+            .flat_map(|res| res.get_atoms())
+            .map(|(_, position)| rotation.apply(position))
+            .collect::<Vec<_>>();
+
+        let rotated = component.rotate(&rotation, Coord::ORIGO);
+        let origin = rotated.get_origin();
+
+        let positions = rotated.iter_residues()
+            .flat_map(|res| res.get_atoms())
+            .map(|(_, position)| position + origin)
+            .collect::<Vec<_>>();
+
+        assert_eq!(positions.len(), expected.len());
+        for (position, expected) in positions.iter().zip(expected.iter()) {
+            assert!(position.distance(*expected) < 1e-9);
+        }
+    }
+
+    #[test]
+    fn periodic_images_of_objects_are_cloned_along_the_box_with_their_orientations() {
+        use coord::Periodic;
+
+        let object = setup_oriented_object();
+        let extended = object.pbc_multiply(2, 1, 2);
+
+        assert_eq!(extended.coords.len(), 4 * object.coords.len());
+        assert_eq!(extended.orientations.len(), extended.coords.len());
+
+        // Images are added with z varying the fastest
+        let shifts = [
+            Coord::ORIGO,
+            Coord::new(0.0, 0.0, 1.0),
+            Coord::new(6.0, 0.0, 0.0),
+            Coord::new(6.0, 0.0, 1.0),
+        ];
+
+        for (i, &shift) in shifts.iter().enumerate() {
+            let n = object.coords.len();
+            let image = &extended.coords[i * n..(i + 1) * n];
+            let expected = object.coords.iter().map(|&c| c + shift).collect::<Vec<_>>();
+
+            assert_eq!(image, expected.as_slice());
+            assert_eq!(&extended.orientations[i * n..(i + 1) * n], object.orientations.as_slice());
+        }
+
+        // The box is extended as the periodic cell
+        assert_eq!(extended.box_size(), Coord::new(12.0, 1.0, 2.0));
+        assert_eq!(extended.periodic_axes(), [true, true, false]);
+    }
+
+    #[test]
+    fn rotating_placed_residues_returns_the_enclosing_box() {
+        use std::f64::consts::PI;
+
+        let mut residues = vec![
+            PlacedResidue { residue: 0, atoms: vec![Coord::ORIGO, Coord::new(2.0, 0.0, 0.0)] },
+        ];
+
+        let rotation = Rotation::from_axis_angle(Coord::new(0.0, 1.0, 0.0), PI / 2.0);
+        let (origin, size) = rotate_placed_residues(
+            &mut residues, Coord::new(1.0, 0.0, 0.0), &rotation, Coord::ORIGO
+        ).unwrap();
+
+        assert_eq!(origin, Coord::new(0.0, 0.0, -3.0));
+        assert_eq!(size, Coord::new(0.0, 0.0, 2.0));
+        assert_eq!(residues[0].atoms, vec![Coord::new(0.0, 0.0, 2.0), Coord::ORIGO]);
+
+        assert!(rotate_placed_residues(&mut [], Coord::ORIGO, &rotation, Coord::ORIGO).is_none());
+    }
     //
     // #[test]
     // fn iterate_over_atoms_in_whole_system_gives_correct_results() {
@@ -542,13 +1451,19 @@ mod tests {
             origin: Coord::default(),
             size: Coord::default(),
             density: None,
+            seed: None,
             coords: vec![Coord::default(), Coord::default(), Coord::default()],
+            cell: None,
+            frame: None,
         });
 
         let system = System {
             title: String::new(),
             output_path: PathBuf::new(),
             database: DataBase::new(),
+            box_setting: BoxSetting::default(),
+            seed: None,
+            num_seeds: 0,
             components: vec![
                 component.clone(), component.clone()
             ],
@@ -565,7 +1480,10 @@ mod tests {
             origin: Coord::new(0.0, 0.0, 0.0),
             size: Coord::new(5.0, 5.0, 5.0),
             density: None,
+            seed: None,
             coords: vec![],
+            cell: None,
+            frame: None,
         });
 
         let component2 = ComponentEntry::VolumeCuboid(Cuboid {
@@ -574,13 +1492,19 @@ mod tests {
             origin: Coord::new(3.0, 3.0, 3.0),
             size: Coord::new(3.0, 2.0, 1.0),
             density: None,
+            seed: None,
             coords: vec![],
+            cell: None,
+            frame: None,
         });
 
         let system = System {
             title: String::new(),
             output_path: PathBuf::new(),
             database: DataBase::new(),
+            box_setting: BoxSetting::default(),
+            seed: None,
+            num_seeds: 0,
             components: vec![
                 component1.clone(),
                 component2.clone()
@@ -588,5 +1512,204 @@ mod tests {
         };
 
         assert_eq!(Coord::new(6.0, 5.0, 5.0), system.box_size());
+        assert_eq!(BoxVectors::rectangular(Coord::new(6.0, 5.0, 5.0)), system.box_vectors());
+    }
+
+    #[test]
+    fn box_vectors_of_system_uses_triclinic_component_cells() {
+        let cell = BoxVectors {
+            a: Coord::new(4.0, 0.0, 0.0),
+            b: Coord::new(2.0, 3.0, 0.0),
+            c: Coord::new(0.0, 0.0, 0.1),
+        };
+
+        let sheet = ComponentEntry::VolumeCuboid(Cuboid {
+            size: Coord::new(4.0, 3.0, 0.1),
+            cell: Some(cell),
+            .. Cuboid::default()
+        });
+
+        let slab = ComponentEntry::VolumeCuboid(Cuboid {
+            origin: Coord::new(0.0, 0.0, 1.0),
+            size: Coord::new(1.0, 1.0, 2.0),
+            .. Cuboid::default()
+        });
+
+        let system = System {
+            title: String::new(),
+            output_path: PathBuf::new(),
+            database: DataBase::new(),
+            box_setting: BoxSetting::default(),
+            seed: None,
+            num_seeds: 0,
+            components: vec![slab, sheet],
+        };
+
+        let expected = BoxVectors { c: Coord::new(0.0, 0.0, 3.0), .. cell };
+        assert_eq!(expected, system.box_vectors());
+    }
+
+    #[test]
+    fn components_are_described_with_their_mass_and_charge() {
+        let mut residue = resbase!["RES", ("A", 0.0, 0.0, 0.0)];
+        residue.atoms[0].mass = Some(2.0);
+        residue.atoms[0].charge = Some(-1.0);
+
+        let known = ComponentEntry::VolumeCuboid(Cuboid {
+            residue: Some(residue),
+            coords: vec![Coord::ORIGO; 3],
+            .. Cuboid::default()
+        });
+        let unknown = ComponentEntry::VolumeCuboid(Cuboid {
+            residue: Some(resbase!["XXX", ("XX", 0.0, 0.0, 0.0)]),
+            coords: vec![Coord::ORIGO],
+            .. Cuboid::default()
+        });
+
+        let system = setup_system(vec![known, unknown], BoxSetting::default());
+        let lines = system.describe_components()
+            .lines()
+            .map(|line| line.to_string())
+            .collect::<Vec<_>>();
+
+        assert_eq!(lines.len(), 3);
+        assert!(lines[1].ends_with("(mass 6.0000 g/mol, charge -3.0000)"));
+        assert!(lines[2].ends_with("(mass (unknown), charge 0.0000)"));
+    }
+
+    fn setup_system(components: Vec<ComponentEntry>, box_setting: BoxSetting) -> System {
+        System {
+            title: String::new(),
+            output_path: PathBuf::new(),
+            database: DataBase::new(),
+            components,
+            box_setting,
+            seed: None,
+            num_seeds: 0,
+        }
+    }
+
+    #[test]
+    fn box_size_of_system_uses_padding_or_fixed_size() {
+        let component = ComponentEntry::VolumeCuboid(Cuboid {
+            origin: Coord::new(1.0, 1.0, 1.0),
+            size: Coord::new(2.0, 3.0, 4.0),
+            .. Cuboid::default()
+        });
+
+        let padding = Coord::new(1.0, 0.0, 5.0);
+        let system = setup_system(vec![component.clone()], BoxSetting::Auto { padding });
+        assert_eq!(system.box_size(), Coord::new(4.0, 4.0, 10.0));
+
+        let size = Coord::new(10.0, 11.0, 12.0);
+        let system = setup_system(vec![component], BoxSetting::Fixed(size));
+        assert_eq!(system.box_size(), size);
+        assert_eq!(system.box_vectors(), BoxVectors::rectangular(size));
+    }
+
+    #[test]
+    fn center_components_in_system_box() {
+        let component1 = ComponentEntry::VolumeCuboid(Cuboid {
+            origin: Coord::new(1.0, 1.0, 1.0),
+            size: Coord::new(2.0, 2.0, 2.0),
+            .. Cuboid::default()
+        });
+        let component2 = ComponentEntry::VolumeCuboid(Cuboid {
+            origin: Coord::new(2.0, 2.0, 2.0),
+            size: Coord::new(2.0, 2.0, 2.0),
+            .. Cuboid::default()
+        });
+
+        let size = Coord::new(10.0, 10.0, 10.0);
+        let mut system = setup_system(vec![component1, component2], BoxSetting::Fixed(size));
+        assert_eq!(system.center_components(), Coord::new(2.5, 2.5, 2.5));
+
+        // The combined extent is (1, 1, 1) to (4, 4, 4) with its center at 2.5
+        assert_eq!(system.components[0].get_origin(), Coord::new(3.5, 3.5, 3.5));
+        assert_eq!(system.components[1].get_origin(), Coord::new(4.5, 4.5, 4.5));
+        assert_eq!(system.box_size(), size);
+
+        // Automatic boxes are fixed at their padded size
+        let component = ComponentEntry::VolumeCuboid(Cuboid {
+            size: Coord::new(2.0, 2.0, 2.0),
+            .. Cuboid::default()
+        });
+
+        let padding = Coord::new(2.0, 0.0, 0.0);
+        let mut system = setup_system(vec![component], BoxSetting::Auto { padding });
+        system.center_components();
+
+        assert_eq!(system.box_setting, BoxSetting::Fixed(Coord::new(4.0, 2.0, 2.0)));
+        assert_eq!(system.components[0].get_origin(), Coord::new(1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn center_components_in_padded_triclinic_box_keeps_the_box_vectors() {
+        let cell = BoxVectors {
+            a: Coord::new(4.0, 0.0, 0.0),
+            b: Coord::new(2.0, 3.0, 0.0),
+            c: Coord::new(0.0, 0.0, 0.5),
+        };
+
+        let sheet = ComponentEntry::VolumeCuboid(Cuboid {
+            size: Coord::new(4.0, 3.0, 0.5),
+            cell: Some(cell),
+            .. Cuboid::default()
+        });
+
+        // The padding is added to the heights of the cell along y and z
+        let padding = Coord::new(0.0, 3.0, 1.5);
+        let mut system = setup_system(vec![sheet], BoxSetting::Auto { padding });
+
+        let expected = BoxVectors {
+            a: Coord::new(4.0, 0.0, 0.0),
+            b: Coord::new(4.0, 6.0, 0.0),
+            c: Coord::new(0.0, 0.0, 2.0),
+        };
+        assert_eq!(system.box_vectors(), expected);
+
+        // The center (3, 1.5, 0.25) of the component cell is moved to that of the box
+        assert_eq!(system.center_components(), Coord::new(1.0, 1.5, 0.75));
+        assert_eq!(system.box_setting, BoxSetting::Vectors(expected));
+        assert_eq!(system.box_vectors(), expected);
+    }
+
+    #[test]
+    fn count_atoms_outside_of_system_box() {
+        let residue = resbase!["RES", ("A", 0.0, 0.0, 0.0)];
+        let component = ComponentEntry::VolumeCuboid(Cuboid {
+            residue: Some(residue),
+            size: Coord::new(2.0, 2.0, 2.0),
+            coords: vec![
+                Coord::new(1.0, 1.0, 1.0),
+                Coord::new(1.0, 1.5, 1.0),
+                Coord::new(1.0, 1.9, 1.0),
+            ],
+            .. Cuboid::default()
+        });
+
+        let system = setup_system(vec![component.clone()], BoxSetting::default());
+        assert_eq!(system.num_atoms_outside_box(), 0);
+
+        let size = Coord::new(2.0, 1.2, 2.0);
+        let system = setup_system(vec![component], BoxSetting::Fixed(size));
+        assert_eq!(system.num_atoms_outside_box(), 2);
+    }
+
+    #[test]
+    fn component_seeds_are_derived_from_the_system_seed_and_number_of_derived_seeds() {
+        let mut system = setup_system(vec![], BoxSetting::default());
+        system.seed = Some(42);
+
+        let first = system.component_seed();
+        assert_eq!(first, Some(derive_seed(42, 0)));
+
+        // Removing components does not reuse or change the following seeds
+        system.components.push(ComponentEntry::VolumeCuboid(Cuboid::default()));
+        system.components.clear();
+        assert_eq!(system.component_seed(), Some(derive_seed(42, 1)));
+
+        system.seed = None;
+        assert_eq!(system.component_seed(), None);
     }
 }
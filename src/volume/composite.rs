@@ -0,0 +1,284 @@
+//! Composite volume objects.
+
+use coord::{BoxVectors, Coord, Frame, Rotate, Rotation, Translate};
+use describe::{unwrap_name, Describe};
+use iterator::{ResidueIter, ResidueIterOut};
+use random::{describe_seed, rng_from_seed, seed_or_random};
+use system::{Component, Residue};
+use volume::*;
+
+use rand::distributions::{IndependentSample, Range};
+
+/// Maximum number of trial positions for every coordinate when filling with
+/// an absolute number of coordinates.
+const MAX_FILL_ATTEMPTS: u64 = 10000;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+/// A volume of any `Shape`, eg. a combination of primitives.
+pub struct Composite {
+    /// Component name.
+    pub name: Option<String>,
+    /// Component residue.
+    pub residue: Option<Residue>,
+    /// Shape of the volume, with positions relative to the `origin`.
+    pub shape: Shape,
+    #[serde(skip)]
+    /// Origin position of component.
+    pub origin: Coord,
+    /// A density may be set for the component.
+    pub density: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// Seed for the random number generator which is used to fill the component.
+    /// Set to the used seed when the component is filled.
+    pub seed: Option<u64>,
+    #[serde(skip)]
+    /// Residue coordinates of component, relative to its `origin`.
+    pub coords: Vec<Coord>,
+    #[serde(skip)]
+    /// Periodic cell of the object if it differs from the box calculated from its geometry,
+    /// eg. the box enclosing all atoms after the object has been rotated.
+    pub cell: Option<BoxVectors>,
+    #[serde(skip)]
+    /// Rotation of the object relative to its geometry, if it has been rotated.
+    pub frame: Option<Frame>,
+}
+
+impl_component![Composite];
+impl_translate![Composite];
+impl_rotate![Composite];
+impl_periodic![Composite];
+
+impl Composite {
+    /// Calculate the box size, which spans from the origin to the upper corner
+    /// of the shape. Unbounded shapes have no size.
+    fn calc_box_size(&self) -> Coord {
+        self.shape
+            .bounds()
+            .map(|(_, upper)| upper)
+            .unwrap_or(Coord::ORIGO)
+    }
+
+    /// Shapes are in general not periodic along any axis.
+    fn calc_periodic_axes(&self) -> [bool; 3] {
+        [false; 3]
+    }
+
+    /// The shape is kept after the composite has been replicated, so only its
+    /// coordinates and box are replicated.
+    fn replicate_geometry(&mut self, _: usize, _: usize, _: usize) {}
+
+    /// Generate an absolute number of coordinates uniformly inside the shape,
+    /// by sampling its box. Stops early if no free position is found for a coordinate.
+    fn generate_coords(&self, num_coords: u64, lower: Coord, upper: Coord, seed: u64)
+            -> Vec<Coord> {
+        let mut rng = rng_from_seed(seed);
+        let range = Range::new(0.0, 1.0);
+        let size = upper - lower;
+
+        let mut coords = Vec::with_capacity(num_coords as usize);
+
+        for _ in 0..num_coords {
+            let position = (0..MAX_FILL_ATTEMPTS)
+                .map(|_| Coord::new(
+                    lower.x + size.x * range.ind_sample(&mut rng),
+                    lower.y + size.y * range.ind_sample(&mut rng),
+                    lower.z + size.z * range.ind_sample(&mut rng)
+                ))
+                .find(|&coord| self.shape.contains(coord));
+
+            match position {
+                Some(coord) => coords.push(coord),
+                None => break,
+            }
+        }
+
+        coords
+    }
+}
+
+impl Contains for Composite {
+    fn contains(&self, coord: Coord) -> bool {
+        self.shape.contains(unrotate(self.frame, self.origin, coord) - self.origin)
+    }
+}
+
+impl Describe for Composite {
+    fn describe(&self) -> String {
+        format!("{} (Composite volume at {}{})",
+            unwrap_name(&self.name), self.origin, describe_seed(self.seed))
+    }
+
+    fn describe_short(&self) -> String {
+        format!("{} (Composite volume)", unwrap_name(&self.name))
+    }
+}
+
+impl Volume for Composite {
+    /// Fill the shape with coordinates.
+    ///
+    /// The shape and origin are moved such that the lower corner of the box which
+    /// encloses the shape is at the origin, without moving the shape in the system.
+    ///
+    /// Unbounded shapes (eg. a complement which is not intersected with a bounded shape)
+    /// cannot be filled and are returned without coordinates.
+    fn fill(self, fill_type: FillType) -> Composite {
+        let seed = seed_or_random(self.seed);
+
+        let (lower, upper) = match self.shape.bounds() {
+            Some(bounds) => bounds,
+            None => {
+                return Composite { seed: Some(seed), coords: vec![], .. self };
+            },
+        };
+
+        // Place the lower corner of the box which encloses the shape at the origin,
+        // such that the shape is inside of the component box
+        let composite = Composite {
+            shape: self.shape.clone().translate(-lower),
+            origin: self.origin + lower,
+            .. self
+        };
+        let (lower, upper) = (Coord::ORIGO, upper - lower);
+
+        let coords = match fill_type {
+            FillType::NumCoords(num_coords) => {
+                composite.generate_coords(num_coords, lower, upper, seed)
+            },
+            _ => {
+                // Fill the enclosing box at the density and cut the shape from it
+                let cuboid = Cuboid {
+                    residue: composite.residue.clone(),
+                    size: upper - lower,
+                    seed: Some(seed),
+                    .. Cuboid::default()
+                }.fill(fill_type);
+
+                cuboid.coords
+                    .into_iter()
+                    .map(|coord| coord + lower)
+                    .filter(|&coord| composite.shape.contains(coord))
+                    .collect()
+            },
+        };
+
+        let volume = composite.volume();
+        let density = if volume > 0.0 {
+            Some(coords.len() as f64 / volume)
+        } else {
+            composite.density
+        };
+
+        Composite {
+            density,
+            seed: Some(seed),
+            coords,
+            .. composite
+        }
+    }
+
+    /// Return the volume of the shape. Unbounded shapes have a volume of zero.
+    fn volume(&self) -> f64 {
+        self.shape.volume().unwrap_or(0.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use system::Atom;
+
+    fn setup_composite(shape: Shape) -> Composite {
+        Composite {
+            name: None,
+            residue: Some(resbase!["RES", ("A", 0.0, 0.0, 0.0)]),
+            shape,
+            origin: Coord::ORIGO,
+            density: None,
+            seed: Some(3),
+            coords: vec![],
+            cell: None,
+            frame: None,
+        }
+    }
+
+    fn setup_hollow_box() -> Shape {
+        let outer = Shape::Cuboid { origin: Coord::ORIGO, size: Coord::new(4.0, 4.0, 4.0) };
+        let inner = Shape::Sphere { center: Coord::new(2.0, 2.0, 2.0), radius: 1.5 };
+
+        outer.difference(inner)
+    }
+
+    #[test]
+    fn composite_is_filled_with_coordinates_inside_its_shape_only() {
+        let composite = setup_composite(setup_hollow_box());
+
+        let filled = composite.clone().fill(FillType::NumCoords(200));
+        assert_eq!(filled.coords.len(), 200);
+        assert!(filled.coords.iter().all(|&c| composite.shape.contains(c)));
+
+        let density = 10.0;
+        let filled = composite.clone().fill(FillType::Density(density));
+        let expected = composite.volume() * density;
+
+        assert!(filled.coords.iter().all(|&c| composite.shape.contains(c)));
+        assert!((filled.coords.len() as f64 - expected).abs() / expected < 0.05);
+    }
+
+    #[test]
+    fn composite_contains_positions_relative_to_its_origin() {
+        let composite = Composite {
+            origin: Coord::new(10.0, 0.0, 0.0),
+            .. setup_composite(setup_hollow_box())
+        };
+
+        assert!(composite.contains(Coord::new(10.1, 0.1, 0.1)));
+        assert!(!composite.contains(Coord::new(12.0, 2.0, 2.0)));
+        assert!(!composite.contains(Coord::new(0.1, 0.1, 0.1)));
+
+        let translated = composite.translate(Coord::new(-10.0, 0.0, 0.0));
+        assert!(translated.contains(Coord::new(0.1, 0.1, 0.1)));
+    }
+
+    #[test]
+    fn filled_composite_box_encloses_shapes_below_its_origin() {
+        let shape = Shape::Sphere { center: Coord::ORIGO, radius: 1.0 };
+        let composite = Composite {
+            origin: Coord::new(5.0, 5.0, 5.0),
+            .. setup_composite(shape)
+        }.fill(FillType::NumCoords(10));
+
+        assert_eq!(composite.get_origin(), Coord::new(4.0, 4.0, 4.0));
+        assert_eq!(composite.calc_box_size(), Coord::new(2.0, 2.0, 2.0));
+        assert!(composite.contains(Coord::new(5.0, 5.0, 5.0)));
+        assert!(composite.coords.iter().all(|&c| c.x >= 0.0 && c.y >= 0.0 && c.z >= 0.0));
+    }
+
+    #[test]
+    fn unbounded_composite_is_not_filled() {
+        let shape = Shape::Sphere { center: Coord::ORIGO, radius: 1.0 }.complement();
+        let composite = setup_composite(shape).fill(FillType::NumCoords(10));
+
+        assert!(composite.coords.is_empty());
+        assert_eq!(composite.volume(), 0.0);
+    }
+
+    #[test]
+    fn composite_residues_are_pruned_by_its_shape() {
+        let component = Cuboid {
+            residue: Some(resbase!["RES", ("A", 0.0, 0.0, 0.0)]),
+            size: Coord::new(4.0, 4.0, 4.0),
+            coords: vec![Coord::new(0.5, 0.5, 0.5), Coord::new(2.0, 2.0, 2.0)],
+            .. Cuboid::default()
+        };
+
+        let composite = setup_composite(setup_hollow_box());
+
+        let remaining = prune_residues_from_volume(&component, &composite);
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[0].get_atoms()[0].1, Coord::new(2.0, 2.0, 2.0));
+
+        let kept = keep_residues_within_volume(&component, &composite.shape);
+        assert_eq!(kept.len(), 1);
+        assert_eq!(kept[0].get_atoms()[0].1, Coord::new(0.5, 0.5, 0.5));
+    }
+}
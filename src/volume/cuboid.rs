@@ -1,8 +1,10 @@
 //! Cuboid objects.
 
-use coord::{Coord, Direction, Periodic, Translate};
+use coord::{BoxVectors, Coord, Direction, Frame, Periodic, Rotate, Rotation, Translate,
+    calc_cylinder_extent};
 use describe::{unwrap_name, Describe};
 use iterator::{ResidueIter, ResidueIterOut};
+use random::{describe_seed, rng_from_seed, seed_or_random};
 use system::{Component, Residue};
 use volume::*;
 
@@ -23,13 +25,25 @@ pub struct Cuboid {
     pub size: Coord,
     /// A density may be set for the component.
     pub density: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// Seed for the random number generator which is used to fill the component.
+    /// Set to the used seed when the component is filled.
+    pub seed: Option<u64>,
     #[serde(skip)]
     /// Residue coordinates of component, relative to its `origin`.
     pub coords: Vec<Coord>,
+    #[serde(skip)]
+    /// Periodic cell of the object if it differs from the box calculated from its geometry,
+    /// eg. the box enclosing all atoms after the object has been rotated.
+    pub cell: Option<BoxVectors>,
+    #[serde(skip)]
+    /// Rotation of the object relative to its geometry, if it has been rotated.
+    pub frame: Option<Frame>,
 }
 
 impl_component![Cuboid];
 impl_translate![Cuboid];
+impl_rotate![Cuboid];
 
 impl Cuboid {
     /// Calculate the center position of the cuboid, relative to the origin.
@@ -46,32 +60,24 @@ impl Cuboid {
     /// It will be directed along the default cylinder alignment.
     pub fn to_cylinder(&self, radius: f64, height: f64, alignment: Direction) -> Cylinder {
         // Check if we need to extend the cube to create the complete cylinder.
-        let diameter = 2.0 * radius;
-        let pbc_multiples = match alignment {
-            Direction::X => {(
-                (height / self.size.x).ceil() as usize,
-                (diameter / self.size.y).ceil() as usize,
-                (diameter / self.size.z).ceil() as usize
-            )},
-            Direction::Y => {(
-                (diameter / self.size.x).ceil() as usize,
-                (height / self.size.y).ceil() as usize,
-                (diameter / self.size.z).ceil() as usize
-            )},
-            Direction::Z => {(
-                (diameter / self.size.x).ceil() as usize,
-                (diameter / self.size.y).ceil() as usize,
-                (height / self.size.z).ceil() as usize
-            )},
-        };
+        let (min, max) = calc_cylinder_extent(radius, height, alignment);
+        let cylinder_size = max - min;
+
+        let pbc_multiples = (
+            (cylinder_size.x / self.size.x).ceil() as usize,
+            (cylinder_size.y / self.size.y).ceil() as usize,
+            (cylinder_size.z / self.size.z).ceil() as usize
+        );
 
         // Closure to calculate the coordinate in the center of the "bottom"
-        // cuboid face from which the cylinder will be created.
+        // cuboid face from which the cylinder will be created. A tilted cylinder
+        // is instead centered in the cuboid.
         let get_bottom_center = |cuboid: &Cuboid| {
             match alignment {
                     Direction::X => Coord { x: 0.0, .. cuboid.center() },
                     Direction::Y => Coord { y: 0.0, .. cuboid.center() },
                     Direction::Z => Coord { z: 0.0, .. cuboid.center() },
+                    Direction::Vector(_) => cuboid.center() - (min + max) * 0.5,
             }
         };
 
@@ -94,8 +100,11 @@ impl Cuboid {
             radius,
             height,
             density: self.density,
+            seed: self.seed,
             alignment,
             coords,
+            cell: None,
+            frame: None,
         }
     }
 
@@ -130,7 +139,7 @@ impl Cuboid {
 
 impl Contains for Cuboid {
     fn contains(&self, coord: Coord) -> bool {
-        let (x, y, z) = coord.to_tuple();
+        let (x, y, z) = unrotate(self.frame, self.origin, coord).to_tuple();
         let (x0, y0, z0) = self.origin.to_tuple();
         let (x1, y1, z1) = (self.origin + self.size).to_tuple();
 
@@ -146,14 +155,18 @@ impl Default for Cuboid {
             origin: Coord::ORIGO,
             size: Coord::ORIGO,
             density: None,
+            seed: None,
             coords: vec![],
+            cell: None,
+            frame: None,
         }
     }
 }
 
 impl Describe for Cuboid {
     fn describe(&self) -> String {
-        format!("{} (Box of size {} at {})", unwrap_name(&self.name), self.size, self.origin)
+        format!("{} (Box of size {} at {}{})",
+            unwrap_name(&self.name), self.size, self.origin, describe_seed(self.seed))
     }
 
     fn describe_short(&self) -> String {
@@ -162,8 +175,22 @@ impl Describe for Cuboid {
 }
 
 impl Periodic for Cuboid {
-    /// Clone cuboid coordinates into PBC multiples.
+    /// Clone cuboid coordinates into PBC multiples. Rotated cuboids are cloned along
+    /// their periodic cell, which is extended.
     fn pbc_multiply(&self, nx: usize, ny: usize, nz: usize) -> Cuboid {
+        if let Some(cell) = self.cell {
+            let coords = cell.image_shifts(nx, ny, nz)
+                .into_iter()
+                .flat_map(|shift| self.coords.iter().map(move |&coord| coord + shift))
+                .collect();
+
+            return Cuboid {
+                coords,
+                cell: Some(cell.pbc_multiply(nx, ny, nz)),
+                .. self.clone()
+            };
+        }
+
         let coords = pbc_multiply_volume(&self.coords, self.size, nx, ny, nz);
 
         Cuboid {
@@ -174,11 +201,16 @@ impl Periodic for Cuboid {
             .. self.clone()
         }
     }
+
+    /// A cuboid is periodic along all axes, unless it has been rotated.
+    fn periodic_axes(&self) -> [bool; 3] {
+        [self.frame.is_none(); 3]
+    }
 }
 
 impl Volume for Cuboid {
     fn fill(self, fill_type: FillType) -> Cuboid {
-        let num_coords = fill_type.to_num_coords(&self);
+        let num_coords = fill_type.to_num_coords(&self, self.residue.as_ref());
 
         // To fill the cuboid in a uniform manner, construct a lattice grid which can contain
         // the desired number of atoms. Then, select the desired number of cells from this
@@ -192,7 +224,8 @@ impl Volume for Cuboid {
         let nz = (self.size.z / target_cell_length).ceil() as u64;
         let num_cells = nx * ny * nz;
 
-        let mut rng = rand::thread_rng();
+        let seed = seed_or_random(self.seed);
+        let mut rng = rng_from_seed(seed);
         let selected_indices = rand::sample(&mut rng, 0..num_cells, num_coords as usize);
 
         let dx = self.size.x / (nx as f64);
@@ -218,6 +251,7 @@ impl Volume for Cuboid {
 
         Cuboid {
             density,
+            seed: Some(seed),
             coords,
             .. self
         }
@@ -455,6 +489,24 @@ mod tests {
         assert!(ratio >= 0.9 && ratio <= 1.1);
     }
 
+    #[test]
+    fn filling_with_the_same_seed_is_reproduced() {
+        let cuboid = Cuboid {
+            size: Coord::new(2.0, 2.0, 2.0),
+            .. Cuboid::default()
+        };
+
+        let first = cuboid.clone().fill(FillType::NumCoords(100));
+        let seed = first.seed.unwrap();
+
+        let second = Cuboid { seed: Some(seed), .. cuboid.clone() }.fill(FillType::NumCoords(100));
+        assert_eq!(second.seed, Some(seed));
+        assert_eq!(first.coords, second.coords);
+
+        let third = Cuboid { seed: Some(seed + 1), .. cuboid }.fill(FillType::NumCoords(100));
+        assert_ne!(first.coords, third.coords);
+    }
+
     #[test]
     fn cuboid_volume_is_correct() {
         let cuboid = Cuboid {
@@ -1,13 +1,14 @@
 //! Cylinder objects.
 
-use coord::{Coord, Direction, Translate};
+use coord::{BoxVectors, Coord, Direction, Frame, Rotate, Rotation, Translate,
+    align_coords, calc_cylinder_extent};
 use describe::{unwrap_name, Describe};
 use iterator::{ResidueIter, ResidueIterOut};
+use random::{describe_seed, rng_from_seed, seed_or_random};
 use system::{Component, Residue};
 use volume::*;
 
-use rand;
-use rand::distributions::IndependentSample;
+use rand::distributions::{IndependentSample, Range};
 use std::f64::consts::PI;
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -24,28 +25,63 @@ pub struct Cylinder {
     pub height: f64,
     /// A density may be set for the component.
     pub density: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// Seed for the random number generator which is used to fill the component.
+    /// Set to the used seed when the component is filled.
+    pub seed: Option<u64>,
     #[serde(skip)]
     pub coords: Vec<Coord>,
+    #[serde(skip)]
+    /// Periodic cell of the object if it differs from the box calculated from its geometry,
+    /// eg. the box enclosing all atoms after the object has been rotated.
+    pub cell: Option<BoxVectors>,
+    #[serde(skip)]
+    /// Rotation of the object relative to its geometry, if it has been rotated.
+    pub frame: Option<Frame>,
 }
 
 impl_component![Cylinder];
 impl_translate![Cylinder];
+impl_rotate![Cylinder];
+impl_periodic![Cylinder];
 
 impl Cylinder {
     /// Calculate the box size.
     fn calc_box_size(&self) -> Coord {
-        let diameter = 2.0 * self.radius;
+        let (min, max) = calc_cylinder_extent(self.radius, self.height, self.alignment);
+
+        max - min
+    }
+
+    /// Calculate along which axes the cylinder is periodic. A cylinder along one of
+    /// the axes is periodic along it, unless it has been rotated.
+    fn calc_periodic_axes(&self) -> [bool; 3] {
+        let mut periodic = [false; 3];
+
+        if let (None, Some(axis)) = (self.frame, self.alignment.axis_index()) {
+            periodic[axis] = true;
+        }
+
+        periodic
+    }
 
-        match self.alignment {
-            Direction::X => Coord::new(self.height, diameter, diameter),
-            Direction::Y => Coord::new(diameter, self.height, diameter),
-            Direction::Z => Coord::new(diameter, diameter, self.height),
+    /// Update the geometry after the cylinder has been replicated. Images along the axis
+    /// of an unrotated cylinder extend its height.
+    fn replicate_geometry(&mut self, nx: usize, ny: usize, nz: usize) {
+        let multiples = [nx, ny, nz];
+
+        if let (None, Some(axis)) = (self.frame, self.alignment.axis_index()) {
+            if (0..3).all(|i| i == axis || multiples[i] == 1) {
+                self.height *= multiples[axis] as f64;
+                self.cell = None;
+            }
         }
     }
 }
 
 impl Contains for Cylinder {
     fn contains(&self, coord: Coord) -> bool {
+        let coord = unrotate(self.frame, self.origin, coord);
         let (dr, dh) = self.origin.distance_cylindrical(coord, self.alignment);
 
         dr <= self.radius && dh >= 0.0 && dh <= self.height
@@ -54,8 +90,9 @@ impl Contains for Cylinder {
 
 impl Describe for Cylinder {
     fn describe(&self) -> String {
-        format!("{} (Cylinder volume of radius {:.2} and height {:.2} at {})",
-            unwrap_name(&self.name), self.radius, self.height, self.origin)
+        format!("{} (Cylinder volume of radius {:.2} and height {:.2} at {}{})",
+            unwrap_name(&self.name), self.radius, self.height, self.origin,
+            describe_seed(self.seed))
     }
 
     fn describe_short(&self) -> String {
@@ -65,25 +102,24 @@ impl Describe for Cylinder {
 
 impl Volume for Cylinder {
     fn fill(self, fill_type: FillType) -> Cylinder {
+        let seed = seed_or_random(self.seed);
+
         match fill_type {
-            FillType::Density(_) => {
+            FillType::Density(_) | FillType::MassDensity(_) | FillType::Concentration(_) => {
                 // Use the filling function from `Cuboid` to generate coordinates to cut from.
                 // This is slightly inefficient, but for now it is easy to keep the generation
                 // in a single function.
-                let box_height = 1.05 * self.height;
-                let box_side = 2.1 * self.radius;
-
-                let size = match self.alignment {
-                    Direction::X => Coord::new(box_height, box_side, box_side),
-                    Direction::Y => Coord::new(box_side, box_height, box_side),
-                    Direction::Z => Coord::new(box_side, box_side, box_height),
-                };
+                let (min, max) = calc_cylinder_extent(
+                    1.05 * self.radius, 1.05 * self.height, self.alignment
+                );
+                let size = max - min;
 
                 Cuboid {
                     name: self.name,
                     residue: self.residue,
                     origin: self.origin,
                     size,
+                    seed: Some(seed),
                     .. Cuboid::default()
                 }.fill(fill_type).to_cylinder(self.radius, self.height, self.alignment)
             },
@@ -92,11 +128,11 @@ impl Volume for Cylinder {
                 // Note that this currently uses a generation that does not account for
                 // coordinate clustering, which isn't great. The radius generation should
                 // make fewer coordinates appear close to the center.
-                let range_radius = rand::distributions::Range::new(0.0, self.radius);
-                let range_height = rand::distributions::Range::new(0.0, self.height);
-                let range_angle = rand::distributions::Range::new(0.0, 2.0 * PI);
+                let range_radius = Range::new(0.0, self.radius);
+                let range_height = Range::new(0.0, self.height);
+                let range_angle = Range::new(0.0, 2.0 * PI);
 
-                let mut rng = rand::thread_rng();
+                let mut rng = rng_from_seed(seed);
 
                 let mut gen_coord = | | {
                     let radius = range_radius.ind_sample(&mut rng);
@@ -110,13 +146,22 @@ impl Volume for Cylinder {
                     match self.alignment {
                         Direction::X => Coord::new(h, r0, r1),
                         Direction::Y => Coord::new(r0, h, r1),
-                        Direction::Z => Coord::new(r0, r1, h),
+                        Direction::Z | Direction::Vector(_) => Coord::new(r0, r1, h),
                     }
                 };
 
                 let coords = (0..num_coords).map(|_| gen_coord()).collect::<Vec<_>>();
 
+                // Tilted cylinders are generated along z and then aligned
+                let coords = match self.alignment {
+                    Direction::Vector(_) => align_coords(
+                        &coords, Coord::new(0.0, 0.0, 1.0), self.alignment.to_unit_vector()
+                    ),
+                    _ => coords,
+                };
+
                 Cylinder {
+                    seed: Some(seed),
                     coords,
                     .. self.clone()
                 }
@@ -146,8 +191,11 @@ mod tests {
             radius,
             height,
             density: None,
+            seed: None,
             alignment: Direction::Z,
             coords: vec![],
+            cell: None,
+            frame: None,
         };
 
         // Default alignment: Z
@@ -189,8 +237,11 @@ mod tests {
             radius,
             height,
             density: None,
+            seed: None,
             alignment: Direction::X,
             coords: vec![],
+            cell: None,
+            frame: None,
         };
 
         let diameter = 2.0 * radius;
@@ -213,8 +264,11 @@ mod tests {
             radius: 1.0,
             height: 2.0,
             density: None,
+            seed: None,
             alignment: Direction::X,
             coords: vec![],
+            cell: None,
+            frame: None,
         };
 
         let err = 1e-9;
@@ -260,6 +314,69 @@ mod tests {
         assert!(!cylinder.contains(Coord::new(1.0, 2.0 + err, 3.0 - err)));
     }
 
+    #[test]
+    fn tilted_cylinder_contains_coordinates_along_its_axis() {
+        let axis = Coord::new(1.0, 1.0, 1.0);
+        let cylinder = Cylinder {
+            name: None,
+            residue: None,
+            origin: Coord::new(1.0, 1.0, 1.0),
+            radius: 0.5,
+            height: 3.0,
+            density: None,
+            seed: None,
+            alignment: Direction::Vector(axis),
+            coords: vec![],
+            cell: None,
+            frame: None,
+        };
+
+        let top = cylinder.origin + axis * (3.0 / 3.0f64.sqrt());
+
+        assert!(cylinder.contains(cylinder.origin * 0.999 + top * 0.001));
+        assert!(cylinder.contains(top * 0.999 + cylinder.origin * 0.001));
+        assert!(!cylinder.contains(top + axis * 0.01));
+        assert!(!cylinder.contains(cylinder.origin - axis * 0.01));
+
+        // Perpendicular to the axis
+        let side = Coord::new(1.0, -1.0, 0.0) * (1.0 / 2.0f64.sqrt());
+        assert!(cylinder.contains(cylinder.origin + axis + side * 0.49));
+        assert!(!cylinder.contains(cylinder.origin + axis + side * 0.51));
+    }
+
+    #[test]
+    fn tilted_cylinders_are_filled_inside_of_their_volume() {
+        let alignment = Direction::Vector(Coord::new(0.0, 1.0, 2.0));
+        let conf = Cylinder {
+            name: None,
+            residue: None,
+            origin: Coord::ORIGO,
+            radius: 1.5,
+            height: 2.0,
+            density: None,
+            seed: None,
+            alignment,
+            coords: vec![],
+            cell: None,
+            frame: None,
+        };
+
+        let from_num = conf.clone().fill(FillType::NumCoords(100));
+        let from_density = conf.clone().fill(FillType::Density(100.0));
+
+        assert_eq!(from_num.coords.len(), 100);
+
+        let expected_coords = (conf.volume() * 100.0).round() as usize;
+        let ratio = from_density.coords.len() as f64 / expected_coords as f64;
+        assert!(ratio >= 0.95 && ratio <= 1.05);
+
+        for &coord in from_num.coords.iter().chain(from_density.coords.iter()) {
+            let (r, h) = Coord::ORIGO.distance_cylindrical(coord, alignment);
+            assert!(r <= conf.radius + 1e-9);
+            assert!(h >= -1e-9 && h <= conf.height + 1e-9);
+        }
+    }
+
     #[test]
     fn cylinder_volume_is_correct() {
         let radius = 2.0;
@@ -272,8 +389,11 @@ mod tests {
             radius,
             height,
             density: None,
+            seed: None,
             alignment: Direction::X,
             coords: vec![],
+            cell: None,
+            frame: None,
         };
 
         let base = PI * radius * radius;
@@ -294,8 +414,11 @@ mod tests {
             radius,
             height,
             density: None,
+            seed: None,
             alignment: Direction::Y,
             coords: vec![],
+            cell: None,
+            frame: None,
         }.fill(FillType::Density(density));
 
         let expected_coords = (cylinder.volume() * density).round() as usize;
@@ -1,15 +1,20 @@
 //!  Define and construct 3D volume objects.
 
+mod composite;
 mod cuboid;
 mod cylinder;
+mod shape;
 mod sphere;
 
-use coord::{Coord, Direction, Periodic};
+use coord::{Coord, Direction, Frame, Periodic};
+use error::{GrafenError, Result};
 use iterator::ResidueIterOut;
-use system::{Component};
+use system::{Component, Residue};
 
+pub use self::composite::Composite;
 pub use self::cuboid::Cuboid;
 pub use self::cylinder::Cylinder;
+pub use self::shape::Shape;
 pub use self::sphere::Sphere;
 
 /// Volumes can contain coordinates.
@@ -27,22 +32,85 @@ pub trait Volume: Contains {
     fn volume(&self) -> f64;
 }
 
-#[derive(Clone, Copy, Debug)]
+/// Avogadro's constant (1/mol).
+const AVOGADRO: f64 = 6.02214076e23;
+
+/// Number of cubic nanometers in a cubic centimeter.
+const NM3_PER_CM3: f64 = 1e21;
+
+/// Number of cubic nanometers in a liter.
+const NM3_PER_LITER: f64 = 1e24;
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
 /// Variants for how a volume can be filled.
 pub enum FillType {
-    /// An input density from which a number of coordinates to fill with is calculated.
+    /// An input number density (1/nm^3) from which a number of coordinates
+    /// to fill with is calculated.
     Density(f64),
+    /// An input mass density (g/cm^3). Requires that the mass of the filling
+    /// residue is known.
+    MassDensity(f64),
+    /// An input molar concentration (mol/L) of residues.
+    Concentration(f64),
     /// An absolute number of coordinates.
     NumCoords(u64),
 }
 
 impl FillType {
+    /// Return whether the residue mass is required to calculate the number of coordinates.
+    pub fn requires_mass(&self) -> bool {
+        match *self {
+            FillType::MassDensity(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Check that a volume filled with the residue can use the fill type.
+    ///
+    /// # Errors
+    /// Returns an error if a mass density is used and the mass of the residue is not known,
+    /// which would otherwise fill the volume with no coordinates.
+    pub fn check_residue(&self, residue: Option<&Residue>) -> Result<()> {
+        if self.requires_mass() && self.to_number_density(residue).is_none() {
+            return Err(GrafenError::RunError(
+                "cannot use a mass density: the mass of the residue is not known".to_string()
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Calculate the number density (1/nm^3) of residues for the fill type.
+    ///
+    /// Returns `None` if the fill type is an absolute number of coordinates, or if
+    /// a mass density is used and the mass of the residue is not known.
+    pub fn to_number_density(&self, residue: Option<&Residue>) -> Option<f64> {
+        match *self {
+            FillType::Density(density) => Some(density),
+            FillType::MassDensity(density) => {
+                residue
+                    .and_then(|res| res.mass())
+                    .and_then(|mass| if mass > 0.0 { Some(mass) } else { None })
+                    .map(|mass| density * AVOGADRO / (mass * NM3_PER_CM3))
+            },
+            FillType::Concentration(conc) => Some(conc * AVOGADRO / NM3_PER_LITER),
+            FillType::NumCoords(_) => None,
+        }
+    }
+
     /// Unwrap the number of coordinates by either calculating it using the density and volume
     /// of the input object, or return it.
-    fn to_num_coords<T: Volume>(&self, volume: &T) -> u64 {
+    ///
+    /// If a mass density is used and the residue mass is unknown no coordinates will
+    /// be added. Check that the mass is available using `check_residue` before filling.
+    fn to_num_coords<T: Volume>(&self, volume: &T, residue: Option<&Residue>) -> u64 {
         match *self {
-            FillType::Density(density) => (volume.volume() * density).round() as u64,
             FillType::NumCoords(num) => num,
+            _ => {
+                self.to_number_density(residue)
+                    .map(|density| (volume.volume() * density).round() as u64)
+                    .unwrap_or(0)
+            },
         }
     }
 }
@@ -69,6 +137,12 @@ fn cut_to_sphere(coords: &[Coord], center: Coord, radius: f64) -> Vec<Coord> {
         .collect()
 }
 
+/// Helper function to map a position to the unrotated geometry of an object at `origin`,
+/// which has been rotated if it has a `frame`.
+fn unrotate(frame: Option<Frame>, origin: Coord, coord: Coord) -> Coord {
+    frame.map(|frame| origin + frame.to_unrotated(coord - origin)).unwrap_or(coord)
+}
+
 /// Helper function to periodically replicate a set of coordinates for a volume object.
 pub fn pbc_multiply_volume(coords: &[Coord], size: Coord, nx: usize, ny: usize, nz: usize)
         -> Vec<Coord> {
@@ -158,10 +232,53 @@ mod tests {
         let density = 15.6;
         let expected_num_coords = (cuboid.volume() * density).round() as u64;
 
-        assert_eq!(FillType::Density(density).to_num_coords(&cuboid), expected_num_coords);
+        assert_eq!(FillType::Density(density).to_num_coords(&cuboid, None), expected_num_coords);
 
         let num = 11;
-        assert_eq!(FillType::NumCoords(num).to_num_coords(&cuboid), num);
+        assert_eq!(FillType::NumCoords(num).to_num_coords(&cuboid, None), num);
+    }
+
+    #[test]
+    fn fill_type_converts_mass_density_and_concentration_to_numbers() {
+        // 10 nm^3 = 1e-20 cm^3 = 1e-23 L
+        let cuboid = Cuboid {
+            size: Coord::new(1.0, 2.0, 5.0),
+            .. Cuboid::default()
+        };
+
+        // Water with a mass of 18 g/mol and a density of 1 g/cm^3 has
+        // roughly 33.46 molecules per nm^3
+        let mut water = resbase!["SOL", ("OW", 0.0, 0.0, 0.0), ("HW1", 0.0, 0.0, 0.0)];
+        water.atoms[0].mass = Some(16.0);
+        water.atoms[1].mass = Some(2.0);
+
+        let num_coords = FillType::MassDensity(1.0).to_num_coords(&cuboid, Some(&water));
+        assert_eq!(num_coords, 335);
+
+        // 1 mol/L is 0.602 residues per nm^3
+        let num_coords = FillType::Concentration(1.0).to_num_coords(&cuboid, None);
+        assert_eq!(num_coords, 6);
+    }
+
+    #[test]
+    fn fill_type_with_mass_density_and_unknown_mass_is_empty() {
+        let cuboid = Cuboid {
+            size: Coord::new(1.0, 2.0, 5.0),
+            .. Cuboid::default()
+        };
+
+        let residue = resbase!["RES", ("XX", 0.0, 0.0, 0.0)];
+        assert!(residue.mass().is_none());
+
+        let fill_type = FillType::MassDensity(1.0);
+        assert!(fill_type.requires_mass());
+        assert!(fill_type.to_number_density(Some(&residue)).is_none());
+        assert_eq!(fill_type.to_num_coords(&cuboid, Some(&residue)), 0);
+        assert_eq!(fill_type.to_num_coords(&cuboid, None), 0);
+
+        assert!(fill_type.check_residue(Some(&residue)).is_err());
+        assert!(fill_type.check_residue(None).is_err());
+        assert!(FillType::Density(1.0).check_residue(Some(&residue)).is_ok());
     }
 
     #[test]
@@ -205,6 +322,34 @@ mod tests {
         assert_eq!(atoms[1][0].1, coord2_without);
     }
 
+    #[test]
+    fn coordinates_within_rotated_cuboid_are_pruned() {
+        use coord::{Rotate, Rotation};
+        use std::f64::consts::PI;
+
+        // After rotating by 90 degrees around z the cuboid covers (-1..0, 0..2, 0..1)
+        let rotation = Rotation::from_axis_angle(Coord::new(0.0, 0.0, 1.0), PI / 2.0);
+        let pruning_vol = Cuboid {
+            size: Coord::new(2.0, 1.0, 1.0),
+            .. Cuboid::default()
+        }.rotate(&rotation, Coord::ORIGO);
+
+        let coord_within = Coord::new(-0.5, 1.5, 0.5);
+        let coord_without = Coord::new(1.5, 0.5, 0.5);
+
+        let residue = resbase!["RES", ("A", 0.0, 0.0, 0.0)];
+        let component = Cuboid {
+            residue: Some(residue),
+            coords: vec![coord_within, coord_without],
+            .. Cuboid::default()
+        };
+
+        let pruned = prune_residues_from_volume(&component, &pruning_vol);
+        let atoms = pruned.iter().map(|res| res.get_atoms()).collect::<Vec<_>>();
+        assert_eq!(atoms.len(), 1);
+        assert_eq!(atoms[0][0].1, coord_without);
+    }
+
     #[test]
     fn component_residues_are_pruned_if_any_atoms_are_inside_the_pruning_volume() {
         let pruning_vol = Cuboid {
@@ -0,0 +1,546 @@
+//! Composite volumes from constructive solid geometry.
+//!
+//! A `Shape` is either a primitive volume or a combination of other shapes by union,
+//! intersection, difference or complement. Shapes implement `Contains` and can thus
+//! be used to prune residues from components or to keep residues within them.
+
+use coord::{calc_cylinder_extent, Coord, Direction, Translate};
+use random::rng_from_seed;
+use volume::Contains;
+
+use rand::distributions::{IndependentSample, Range};
+use std::f64::consts::PI;
+
+/// Number of random samples used to estimate the volume of composite shapes.
+const NUM_VOLUME_SAMPLES: u64 = 200000;
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+/// A primitive or composite volume shape with absolute positions.
+pub enum Shape {
+    /// A cuboid with its lower corner at the origin.
+    Cuboid { origin: Coord, size: Coord },
+    /// A cylinder with its bottom center at the origin, directed along the alignment.
+    Cylinder { origin: Coord, radius: f64, height: f64, alignment: Direction },
+    /// A sphere around a center position.
+    Sphere { center: Coord, radius: f64 },
+    /// An ellipsoid around a center position, with semi-axes along x, y and z.
+    Ellipsoid { center: Coord, semi_axes: Coord },
+    /// A cone frustum with its bottom center at the origin, directed along the alignment.
+    /// A cone has a top radius of zero.
+    Frustum {
+        origin: Coord,
+        bottom_radius: f64,
+        top_radius: f64,
+        height: f64,
+        alignment: Direction,
+    },
+    /// A torus around a center position, with its symmetry axis along the alignment.
+    /// The major radius is the distance from the center to the tube center.
+    Torus { center: Coord, major_radius: f64, minor_radius: f64, alignment: Direction },
+    /// An annular cylinder with its bottom center at the origin, directed along
+    /// the alignment.
+    HollowCylinder {
+        origin: Coord,
+        inner_radius: f64,
+        outer_radius: f64,
+        height: f64,
+        alignment: Direction,
+    },
+    /// All positions in front of a plane through the origin along its normal,
+    /// within the thickness if it is set or else the half-space. Slabs are unbounded
+    /// and have to be intersected with a bounded shape to be filled.
+    Slab { origin: Coord, normal: Direction, thickness: Option<f64> },
+    /// All positions which are contained by any of the shapes.
+    Union(Vec<Shape>),
+    /// All positions which are contained by every shape.
+    Intersection(Vec<Shape>),
+    /// All positions which are contained by the first shape but not the second.
+    Difference(Box<Shape>, Box<Shape>),
+    /// All positions which are not contained by the shape.
+    Complement(Box<Shape>),
+}
+
+impl Shape {
+    /// Return the shape which contains positions of either shape.
+    pub fn union(self, other: Shape) -> Shape {
+        Shape::Union(vec![self, other])
+    }
+
+    /// Return the shape which contains positions of both shapes.
+    pub fn intersection(self, other: Shape) -> Shape {
+        Shape::Intersection(vec![self, other])
+    }
+
+    /// Return the shape which contains positions of this shape but not the other.
+    pub fn difference(self, other: Shape) -> Shape {
+        Shape::Difference(Box::new(self), Box::new(other))
+    }
+
+    /// Return the shape which contains all positions that this shape does not.
+    pub fn complement(self) -> Shape {
+        Shape::Complement(Box::new(self))
+    }
+
+    /// Return the lower and upper corners of a box which encloses the shape,
+    /// or `None` if the shape is unbounded.
+    ///
+    /// The box of a composite shape is not necessarily tight.
+    ///
+    /// # Examples
+    /// ```
+    /// # use grafen::coord::Coord;
+    /// # use grafen::volume::Shape;
+    /// let sphere = Shape::Sphere { center: Coord::new(1.0, 1.0, 1.0), radius: 1.0 };
+    /// assert_eq!(sphere.bounds(), Some((Coord::ORIGO, Coord::new(2.0, 2.0, 2.0))));
+    /// assert_eq!(sphere.complement().bounds(), None);
+    /// ```
+    pub fn bounds(&self) -> Option<(Coord, Coord)> {
+        match *self {
+            Shape::Cuboid { origin, size } => Some((origin, origin + size)),
+            Shape::Cylinder { origin, radius, height, alignment } => {
+                let (min, max) = calc_cylinder_extent(radius, height, alignment);
+                Some((origin + min, origin + max))
+            },
+            Shape::Sphere { center, radius } => {
+                let extent = Coord::new(radius, radius, radius);
+                Some((center - extent, center + extent))
+            },
+            Shape::Ellipsoid { center, semi_axes } => {
+                let extent = Coord::new(semi_axes.x.abs(), semi_axes.y.abs(), semi_axes.z.abs());
+                Some((center - extent, center + extent))
+            },
+            Shape::Frustum { origin, bottom_radius, top_radius, height, alignment } => {
+                let radius = bottom_radius.max(top_radius);
+                let (min, max) = calc_cylinder_extent(radius, height, alignment);
+                Some((origin + min, origin + max))
+            },
+            Shape::Torus { center, major_radius, minor_radius, alignment } => {
+                // The torus fits in a cylinder centered around the center
+                let bottom = center - alignment.to_unit_vector() * minor_radius;
+                let (min, max) = calc_cylinder_extent(
+                    major_radius + minor_radius, 2.0 * minor_radius, alignment);
+                Some((bottom + min, bottom + max))
+            },
+            Shape::HollowCylinder { origin, outer_radius, height, alignment, .. } => {
+                let (min, max) = calc_cylinder_extent(outer_radius, height, alignment);
+                Some((origin + min, origin + max))
+            },
+            Shape::Slab { .. } => None,
+            Shape::Union(ref shapes) => {
+                shapes.iter()
+                    .map(|shape| shape.bounds())
+                    .fold(Some(None), |acc, bounds| match (acc, bounds) {
+                        (Some(None), Some(bounds)) => Some(Some(bounds)),
+                        (Some(Some(acc)), Some(bounds)) => Some(Some(join_boxes(acc, bounds))),
+                        _ => None,
+                    })
+                    .and_then(|bounds| bounds)
+            },
+            Shape::Intersection(ref shapes) => {
+                // Unbounded shapes do not limit the intersection
+                shapes.iter()
+                    .filter_map(|shape| shape.bounds())
+                    .fold(None, |acc, bounds| match acc {
+                        Some(acc) => Some(intersect_boxes(acc, bounds)),
+                        None => Some(bounds),
+                    })
+            },
+            Shape::Difference(ref shape, _) => shape.bounds(),
+            Shape::Complement(_) => None,
+        }
+    }
+
+    /// Return the volume of the shape if it can be calculated analytically.
+    pub fn analytic_volume(&self) -> Option<f64> {
+        match *self {
+            Shape::Cuboid { size, .. } => Some(size.x.abs() * size.y.abs() * size.z.abs()),
+            Shape::Cylinder { radius, height, .. } => Some(PI * radius.powi(2) * height),
+            Shape::Sphere { radius, .. } => Some(4.0 * PI * radius.powi(3) / 3.0),
+            Shape::Ellipsoid { semi_axes, .. } => {
+                Some(4.0 * PI * (semi_axes.x * semi_axes.y * semi_axes.z).abs() / 3.0)
+            },
+            Shape::Frustum { bottom_radius: r0, top_radius: r1, height, .. } => {
+                Some(PI * height * (r0 * r0 + r0 * r1 + r1 * r1) / 3.0)
+            },
+            Shape::Torus { major_radius, minor_radius, .. } => {
+                Some(2.0 * PI * PI * major_radius * minor_radius.powi(2))
+            },
+            Shape::HollowCylinder { inner_radius, outer_radius, height, .. } => {
+                Some(PI * (outer_radius.powi(2) - inner_radius.powi(2)) * height)
+            },
+            Shape::Union(ref shapes) | Shape::Intersection(ref shapes) if shapes.len() == 1 => {
+                shapes[0].analytic_volume()
+            },
+            _ => None,
+        }
+    }
+
+    /// Return the volume of the shape, or `None` if it is unbounded.
+    ///
+    /// The volume of composite shapes is estimated by Monte Carlo sampling of
+    /// their enclosing box, which is deterministic for a given shape.
+    ///
+    /// # Examples
+    /// ```
+    /// # use grafen::coord::Coord;
+    /// # use grafen::volume::Shape;
+    /// let cuboid = Shape::Cuboid { origin: Coord::ORIGO, size: Coord::new(2.0, 1.0, 1.0) };
+    /// let half = Shape::Cuboid { origin: Coord::ORIGO, size: Coord::new(1.0, 1.0, 1.0) };
+    ///
+    /// let volume = cuboid.difference(half).volume().unwrap();
+    /// assert!((volume - 1.0).abs() < 0.01);
+    /// ```
+    pub fn volume(&self) -> Option<f64> {
+        self.analytic_volume().or_else(|| self.estimate_volume(NUM_VOLUME_SAMPLES))
+    }
+
+    /// Estimate the volume of the shape from a number of random samples in
+    /// its enclosing box. Returns `None` if the shape is unbounded.
+    fn estimate_volume(&self, num_samples: u64) -> Option<f64> {
+        let (lower, upper) = self.bounds()?;
+        let size = upper - lower;
+        let box_volume = size.x * size.y * size.z;
+
+        if box_volume <= 0.0 || num_samples == 0 {
+            return Some(0.0);
+        }
+
+        // The samples are fixed to make the volume a property of the shape
+        let mut rng = rng_from_seed(0);
+        let range = Range::new(0.0, 1.0);
+
+        let num_inside = (0..num_samples)
+            .filter(|_| {
+                let sample = Coord::new(
+                    lower.x + size.x * range.ind_sample(&mut rng),
+                    lower.y + size.y * range.ind_sample(&mut rng),
+                    lower.z + size.z * range.ind_sample(&mut rng)
+                );
+
+                self.contains(sample)
+            })
+            .count();
+
+        Some(box_volume * num_inside as f64 / num_samples as f64)
+    }
+}
+
+impl Contains for Shape {
+    fn contains(&self, coord: Coord) -> bool {
+        match *self {
+            Shape::Cuboid { origin, size } => {
+                let (lower, upper) = (origin, origin + size);
+
+                coord.x >= lower.x && coord.x <= upper.x
+                    && coord.y >= lower.y && coord.y <= upper.y
+                    && coord.z >= lower.z && coord.z <= upper.z
+            },
+            Shape::Cylinder { origin, radius, height, alignment } => {
+                let (dr, dh) = origin.distance_cylindrical(coord, alignment);
+
+                dr <= radius && dh >= 0.0 && dh <= height
+            },
+            Shape::Sphere { center, radius } => center.distance(coord) <= radius,
+            Shape::Ellipsoid { center, semi_axes } => {
+                let dx = (coord - center).to_tuple();
+                let (a, b, c) = semi_axes.to_tuple();
+
+                (dx.0 / a).powi(2) + (dx.1 / b).powi(2) + (dx.2 / c).powi(2) <= 1.0
+            },
+            Shape::Frustum { origin, bottom_radius, top_radius, height, alignment } => {
+                let (dr, dh) = origin.distance_cylindrical(coord, alignment);
+
+                dh >= 0.0 && dh <= height
+                    && dr <= bottom_radius + (top_radius - bottom_radius) * dh / height
+            },
+            Shape::Torus { center, major_radius, minor_radius, alignment } => {
+                let (dr, dh) = center.distance_cylindrical(coord, alignment);
+
+                (dr - major_radius).powi(2) + dh.powi(2) <= minor_radius.powi(2)
+            },
+            Shape::HollowCylinder { origin, inner_radius, outer_radius, height, alignment } => {
+                let (dr, dh) = origin.distance_cylindrical(coord, alignment);
+
+                dr >= inner_radius && dr <= outer_radius && dh >= 0.0 && dh <= height
+            },
+            Shape::Slab { origin, normal, thickness } => {
+                let distance = (coord - origin).dot(normal.to_unit_vector());
+
+                distance >= 0.0 && thickness.map(|t| distance <= t).unwrap_or(true)
+            },
+            Shape::Union(ref shapes) => shapes.iter().any(|shape| shape.contains(coord)),
+            Shape::Intersection(ref shapes) => shapes.iter().all(|shape| shape.contains(coord)),
+            Shape::Difference(ref shape, ref other) => {
+                shape.contains(coord) && !other.contains(coord)
+            },
+            Shape::Complement(ref shape) => !shape.contains(coord),
+        }
+    }
+}
+
+impl Translate for Shape {
+    fn translate(mut self, coord: Coord) -> Self {
+        self.translate_in_place(coord);
+        self
+    }
+
+    fn translate_in_place(&mut self, coord: Coord) {
+        match *self {
+            Shape::Cuboid { ref mut origin, .. } => *origin += coord,
+            Shape::Cylinder { ref mut origin, .. } => *origin += coord,
+            Shape::Sphere { ref mut center, .. } => *center += coord,
+            Shape::Ellipsoid { ref mut center, .. } => *center += coord,
+            Shape::Frustum { ref mut origin, .. } => *origin += coord,
+            Shape::Torus { ref mut center, .. } => *center += coord,
+            Shape::HollowCylinder { ref mut origin, .. } => *origin += coord,
+            Shape::Slab { ref mut origin, .. } => *origin += coord,
+            Shape::Union(ref mut shapes) | Shape::Intersection(ref mut shapes) => {
+                shapes.iter_mut().for_each(|shape| shape.translate_in_place(coord));
+            },
+            Shape::Difference(ref mut shape, ref mut other) => {
+                shape.translate_in_place(coord);
+                other.translate_in_place(coord);
+            },
+            Shape::Complement(ref mut shape) => shape.translate_in_place(coord),
+        }
+    }
+}
+
+/// Return the box which encloses both input boxes.
+fn join_boxes((min1, max1): (Coord, Coord), (min2, max2): (Coord, Coord)) -> (Coord, Coord) {
+    (
+        Coord::new(min1.x.min(min2.x), min1.y.min(min2.y), min1.z.min(min2.z)),
+        Coord::new(max1.x.max(max2.x), max1.y.max(max2.y), max1.z.max(max2.z))
+    )
+}
+
+/// Return the overlap of two boxes. Boxes which do not overlap yield an empty box.
+fn intersect_boxes((min1, max1): (Coord, Coord), (min2, max2): (Coord, Coord))
+        -> (Coord, Coord) {
+    let lower = Coord::new(min1.x.max(min2.x), min1.y.max(min2.y), min1.z.max(min2.z));
+    let upper = Coord::new(max1.x.min(max2.x), max1.y.min(max2.y), max1.z.min(max2.z));
+
+    (lower, Coord::new(upper.x.max(lower.x), upper.y.max(lower.y), upper.z.max(lower.z)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit_cuboid(origin: Coord) -> Shape {
+        Shape::Cuboid { origin, size: Coord::new(1.0, 1.0, 1.0) }
+    }
+
+    #[test]
+    fn boolean_operations_combine_contained_positions() {
+        let first = unit_cuboid(Coord::ORIGO);
+        let second = unit_cuboid(Coord::new(0.5, 0.0, 0.0));
+
+        let only_first = Coord::new(0.25, 0.5, 0.5);
+        let both = Coord::new(0.75, 0.5, 0.5);
+        let only_second = Coord::new(1.25, 0.5, 0.5);
+        let neither = Coord::new(2.0, 0.5, 0.5);
+
+        let union = first.clone().union(second.clone());
+        assert!(union.contains(only_first) && union.contains(both) && union.contains(only_second));
+        assert!(!union.contains(neither));
+
+        let intersection = first.clone().intersection(second.clone());
+        assert!(intersection.contains(both));
+        assert!(!intersection.contains(only_first) && !intersection.contains(only_second));
+
+        let difference = first.clone().difference(second.clone());
+        assert!(difference.contains(only_first));
+        assert!(!difference.contains(both) && !difference.contains(only_second));
+
+        let complement = first.complement();
+        assert!(!complement.contains(only_first));
+        assert!(complement.contains(only_second) && complement.contains(neither));
+    }
+
+    #[test]
+    fn bounds_of_composite_shapes() {
+        let first = unit_cuboid(Coord::ORIGO);
+        let second = unit_cuboid(Coord::new(0.5, 0.0, 0.0));
+
+        assert_eq!(
+            first.clone().union(second.clone()).bounds(),
+            Some((Coord::ORIGO, Coord::new(1.5, 1.0, 1.0)))
+        );
+        assert_eq!(
+            first.clone().intersection(second.clone()).bounds(),
+            Some((Coord::new(0.5, 0.0, 0.0), Coord::new(1.0, 1.0, 1.0)))
+        );
+        assert_eq!(first.clone().difference(second.clone()).bounds(), first.bounds());
+
+        // An unbounded complement is limited by the shape it is intersected with
+        let outside = second.clone().complement();
+        assert_eq!(first.clone().union(outside.clone()).bounds(), None);
+        assert_eq!(first.clone().intersection(outside).bounds(), first.bounds());
+    }
+
+    #[test]
+    fn volume_of_composite_shapes_is_estimated() {
+        let sphere = Shape::Sphere { center: Coord::ORIGO, radius: 1.0 };
+        let expected = 4.0 * PI / 3.0;
+        assert_eq!(sphere.volume(), Some(expected));
+
+        // Cut the sphere into a hemisphere
+        let upper = Shape::Cuboid {
+            origin: Coord::new(-1.0, -1.0, 0.0),
+            size: Coord::new(2.0, 2.0, 1.0),
+        };
+        let hemisphere = sphere.clone().intersection(upper);
+        assert!(hemisphere.analytic_volume().is_none());
+
+        let volume = hemisphere.volume().unwrap();
+        assert!((volume - expected / 2.0).abs() / (expected / 2.0) < 0.02);
+        assert_eq!(hemisphere.volume(), Some(volume));
+
+        assert_eq!(sphere.complement().volume(), None);
+    }
+
+    #[test]
+    fn ellipsoid_contains_positions_within_its_semi_axes() {
+        let ellipsoid = Shape::Ellipsoid {
+            center: Coord::new(1.0, 1.0, 1.0),
+            semi_axes: Coord::new(3.0, 2.0, 1.0),
+        };
+
+        assert!(ellipsoid.contains(Coord::new(3.9, 1.0, 1.0)));
+        assert!(ellipsoid.contains(Coord::new(1.0, -0.9, 1.0)));
+        assert!(!ellipsoid.contains(Coord::new(1.0, 1.0, 2.1)));
+        assert!(!ellipsoid.contains(Coord::new(3.0, 2.5, 1.0)));
+
+        assert_eq!(
+            ellipsoid.bounds(),
+            Some((Coord::new(-2.0, -1.0, 0.0), Coord::new(4.0, 3.0, 2.0)))
+        );
+        assert!((ellipsoid.analytic_volume().unwrap() - 8.0 * PI).abs() < 1e-9);
+    }
+
+    #[test]
+    fn frustum_narrows_from_its_bottom_to_its_top_radius() {
+        let cone = Shape::Frustum {
+            origin: Coord::ORIGO,
+            bottom_radius: 2.0,
+            top_radius: 0.0,
+            height: 4.0,
+            alignment: Direction::Z,
+        };
+
+        assert!(cone.contains(Coord::new(1.9, 0.0, 0.0)));
+        assert!(cone.contains(Coord::new(0.9, 0.0, 2.0)));
+        assert!(!cone.contains(Coord::new(1.1, 0.0, 2.0)));
+        assert!(!cone.contains(Coord::new(0.0, 0.0, -0.1)));
+        assert!(!cone.contains(Coord::new(0.0, 0.0, 4.1)));
+
+        assert_eq!(
+            cone.bounds(),
+            Some((Coord::new(-2.0, -2.0, 0.0), Coord::new(2.0, 2.0, 4.0)))
+        );
+        assert_eq!(cone.analytic_volume(), Some(PI * 4.0 * 4.0 / 3.0));
+
+        let frustum = Shape::Frustum {
+            origin: Coord::ORIGO,
+            bottom_radius: 1.0,
+            top_radius: 2.0,
+            height: 1.0,
+            alignment: Direction::X,
+        };
+
+        assert!(frustum.contains(Coord::new(1.0, 1.9, 0.0)));
+        assert!(!frustum.contains(Coord::new(0.0, 1.1, 0.0)));
+        assert_eq!(frustum.analytic_volume(), Some(PI * 7.0 / 3.0));
+    }
+
+    #[test]
+    fn torus_contains_positions_within_its_tube() {
+        let torus = Shape::Torus {
+            center: Coord::ORIGO,
+            major_radius: 3.0,
+            minor_radius: 1.0,
+            alignment: Direction::Z,
+        };
+
+        assert!(torus.contains(Coord::new(3.0, 0.0, 0.0)));
+        assert!(torus.contains(Coord::new(0.0, -3.0, 0.9)));
+        assert!(torus.contains(Coord::new(2.1, 0.0, 0.0)));
+        assert!(!torus.contains(Coord::ORIGO));
+        assert!(!torus.contains(Coord::new(3.0, 0.0, 1.1)));
+        assert!(!torus.contains(Coord::new(4.1, 0.0, 0.0)));
+
+        assert_eq!(
+            torus.bounds(),
+            Some((Coord::new(-4.0, -4.0, -1.0), Coord::new(4.0, 4.0, 1.0)))
+        );
+        assert!((torus.analytic_volume().unwrap() - 6.0 * PI * PI).abs() < 1e-9);
+    }
+
+    #[test]
+    fn hollow_cylinder_contains_positions_between_its_radii() {
+        let hollow = Shape::HollowCylinder {
+            origin: Coord::ORIGO,
+            inner_radius: 1.0,
+            outer_radius: 2.0,
+            height: 3.0,
+            alignment: Direction::Y,
+        };
+
+        assert!(hollow.contains(Coord::new(1.5, 1.0, 0.0)));
+        assert!(hollow.contains(Coord::new(0.0, 2.9, -1.9)));
+        assert!(!hollow.contains(Coord::new(0.5, 1.0, 0.0)));
+        assert!(!hollow.contains(Coord::new(2.1, 1.0, 0.0)));
+        assert!(!hollow.contains(Coord::new(1.5, 3.1, 0.0)));
+
+        assert_eq!(
+            hollow.bounds(),
+            Some((Coord::new(-2.0, 0.0, -2.0), Coord::new(2.0, 3.0, 2.0)))
+        );
+        assert_eq!(hollow.analytic_volume(), Some(PI * 3.0 * 3.0));
+    }
+
+    #[test]
+    fn slabs_are_unbounded_unless_intersected_with_a_bounded_shape() {
+        let half_space = Shape::Slab {
+            origin: Coord::new(0.0, 0.0, 1.0),
+            normal: Direction::Z,
+            thickness: None,
+        };
+
+        assert!(half_space.contains(Coord::new(100.0, -100.0, 1.1)));
+        assert!(half_space.contains(Coord::new(0.0, 0.0, 100.0)));
+        assert!(!half_space.contains(Coord::new(0.0, 0.0, 0.9)));
+        assert_eq!(half_space.bounds(), None);
+        assert_eq!(half_space.volume(), None);
+
+        let slab = Shape::Slab {
+            origin: Coord::ORIGO,
+            normal: Direction::Vector(Coord::new(1.0, 1.0, 0.0)),
+            thickness: Some(1.0),
+        };
+
+        assert!(slab.contains(Coord::new(0.5, 0.5, 10.0)));
+        assert!(!slab.contains(Coord::new(1.0, 1.0, 0.0)));
+        assert!(!slab.contains(Coord::new(-0.1, 0.0, 0.0)));
+
+        let upper_half = half_space.translate(Coord::new(0.0, 0.0, -0.5));
+        let cut = unit_cuboid(Coord::ORIGO).intersection(upper_half);
+        assert_eq!(cut.bounds(), unit_cuboid(Coord::ORIGO).bounds());
+        assert!(cut.contains(Coord::new(0.5, 0.5, 0.75)));
+        assert!(!cut.contains(Coord::new(0.5, 0.5, 0.25)));
+    }
+
+    #[test]
+    fn translating_composite_shapes_moves_all_parts() {
+        let shape = unit_cuboid(Coord::ORIGO)
+            .difference(Shape::Sphere { center: Coord::ORIGO, radius: 0.5 });
+
+        let shift = Coord::new(1.0, 2.0, 3.0);
+        let translated = shape.clone().translate(shift);
+
+        for &coord in &[Coord::new(0.1, 0.1, 0.1), Coord::new(0.9, 0.9, 0.9)] {
+            assert_eq!(shape.contains(coord), translated.contains(coord + shift));
+        }
+    }
+}